@@ -0,0 +1,34 @@
+-- Quota bypass flags (mirrors admin_overrides.bypass_users / bypass_api_keys)
+ALTER TABLE rate_limits ADD COLUMN quota_bypass BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE api_keys ADD COLUMN quota_bypass BOOLEAN NOT NULL DEFAULT false;
+
+-- Temporary quota boosts
+CREATE TABLE quota_boosts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    multiplier DOUBLE PRECISION NOT NULL CHECK (multiplier >= 1),
+    reason TEXT,
+    granted_by UUID,
+    starts_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (expires_at > starts_at)
+);
+
+-- Admin audit log (actor is not a foreign key so entries survive user deletion)
+CREATE TABLE admin_audit_log (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    actor_id UUID NOT NULL,
+    action VARCHAR(100) NOT NULL,
+    target_type VARCHAR(50) NOT NULL,
+    target_id VARCHAR(255) NOT NULL,
+    details JSONB DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Indexes for performance
+CREATE INDEX idx_rate_limits_user_id ON rate_limits(user_id);
+CREATE INDEX idx_quota_boosts_user_id_expires_at ON quota_boosts(user_id, expires_at DESC);
+CREATE INDEX idx_admin_audit_log_created_at ON admin_audit_log(created_at DESC);
+CREATE INDEX idx_admin_audit_log_target ON admin_audit_log(target_type, target_id);
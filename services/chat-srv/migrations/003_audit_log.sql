@@ -0,0 +1,43 @@
+-- Append-only, hash-chained audit trail. Each row's hash covers its own
+-- contents and the previous row's hash, so edits or gaps are detectable.
+CREATE TABLE audit_log (
+    seq BIGSERIAL PRIMARY KEY,
+    id UUID NOT NULL UNIQUE,
+    event_type VARCHAR(100) NOT NULL,
+    actor_id UUID,
+    session_id VARCHAR(255),
+    api_key_id UUID,
+    model VARCHAR(100),
+    request_hash CHAR(64),
+    prompt_tokens INTEGER,
+    completion_tokens INTEGER,
+    outcome VARCHAR(50) NOT NULL,
+    client_ip VARCHAR(64),
+    details JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL,
+    prev_hash CHAR(64) NOT NULL,
+    hash CHAR(64) NOT NULL UNIQUE
+);
+
+-- Rows can never be updated. Deletes are only allowed by the retention
+-- purge, which opts in for its own transaction.
+CREATE OR REPLACE FUNCTION audit_log_immutable()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP = 'DELETE' AND current_setting('audit.allow_purge', true) = 'on' THEN
+        RETURN OLD;
+    END IF;
+    RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER audit_log_append_only BEFORE UPDATE OR DELETE ON audit_log
+    FOR EACH ROW EXECUTE FUNCTION audit_log_immutable();
+
+CREATE TRIGGER audit_log_no_truncate BEFORE TRUNCATE ON audit_log
+    FOR EACH STATEMENT EXECUTE FUNCTION audit_log_immutable();
+
+-- Indexes for performance
+CREATE INDEX idx_audit_log_created_at ON audit_log(created_at DESC);
+CREATE INDEX idx_audit_log_actor_id ON audit_log(actor_id);
+CREATE INDEX idx_audit_log_event_type ON audit_log(event_type);
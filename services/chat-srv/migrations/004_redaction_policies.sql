@@ -0,0 +1,33 @@
+-- Organizations
+CREATE TABLE organizations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(255) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    metadata JSONB DEFAULT '{}'::jsonb
+);
+
+ALTER TABLE users ADD COLUMN org_id UUID REFERENCES organizations(id) ON DELETE SET NULL;
+
+-- Redaction policies. A NULL org_id row is the global default; org rows override it.
+CREATE TABLE redaction_policies (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    org_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+    detector VARCHAR(100) NOT NULL,
+    action VARCHAR(20) NOT NULL CHECK (action IN ('block', 'mask', 'allow')),
+    pattern TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Indexes for performance
+CREATE INDEX idx_users_org_id ON users(org_id);
+CREATE UNIQUE INDEX idx_redaction_policies_org_detector
+    ON redaction_policies(COALESCE(org_id, '00000000-0000-0000-0000-000000000000'::uuid), detector);
+
+-- Apply updated_at triggers
+CREATE TRIGGER update_organizations_updated_at BEFORE UPDATE ON organizations
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
+
+CREATE TRIGGER update_redaction_policies_updated_at BEFORE UPDATE ON redaction_policies
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
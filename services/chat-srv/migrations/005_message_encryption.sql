@@ -0,0 +1,40 @@
+-- Per-user / per-org data keys, stored wrapped by a master key
+CREATE TABLE data_keys (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    owner_type VARCHAR(20) NOT NULL CHECK (owner_type IN ('user', 'org')),
+    owner_id UUID NOT NULL,
+    version INTEGER NOT NULL,
+    wrapped_key BYTEA NOT NULL,
+    master_key_id VARCHAR(255) NOT NULL,
+    status VARCHAR(20) NOT NULL DEFAULT 'active' CHECK (status IN ('active', 'retired')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    retired_at TIMESTAMPTZ,
+    UNIQUE (owner_type, owner_id, version)
+);
+
+-- Encrypted message columns. Plaintext content is kept nullable so rows
+-- written before encryption was enabled can be migrated in place.
+ALTER TABLE messages ALTER COLUMN content DROP NOT NULL;
+ALTER TABLE messages ADD COLUMN content_ciphertext BYTEA;
+ALTER TABLE messages ADD COLUMN metadata_ciphertext BYTEA;
+ALTER TABLE messages ADD COLUMN data_key_id UUID REFERENCES data_keys(id);
+ALTER TABLE messages ADD CONSTRAINT messages_content_present
+    CHECK (content IS NOT NULL OR (content_ciphertext IS NOT NULL AND data_key_id IS NOT NULL));
+
+-- Opt-in plaintext index for search. Only populated for users who consented.
+ALTER TABLE users ADD COLUMN search_index_consent BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE message_search_index (
+    message_id UUID PRIMARY KEY REFERENCES messages(id) ON DELETE CASCADE,
+    conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    content TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Indexes for performance
+CREATE UNIQUE INDEX idx_data_keys_active_owner ON data_keys(owner_type, owner_id) WHERE status = 'active';
+CREATE INDEX idx_data_keys_master_key_id ON data_keys(master_key_id);
+CREATE INDEX idx_messages_data_key_id ON messages(data_key_id);
+CREATE INDEX idx_messages_plaintext ON messages(created_at) WHERE content_ciphertext IS NULL;
+CREATE INDEX idx_message_search_index_user_id ON message_search_index(user_id);
@@ -0,0 +1,5 @@
+-- Account that titling, summaries and other server-initiated LLM calls are
+-- billed to. It is inactive, so nobody can sign in as it.
+INSERT INTO users (id, email, name, is_active)
+VALUES ('00000000-0000-0000-0000-000000000000', 'system@chat-srv.internal', 'System', false)
+ON CONFLICT (id) DO NOTHING;
@@ -0,0 +1,47 @@
+-- Messages form a tree: editing a prompt or regenerating a reply adds a
+-- sibling instead of overwriting. The conversation remembers which leaf is
+-- active; the active path is that leaf and its ancestors.
+ALTER TABLE messages ADD COLUMN parent_id UUID REFERENCES messages(id) ON DELETE CASCADE;
+ALTER TABLE conversations ADD COLUMN active_leaf_id UUID REFERENCES messages(id) ON DELETE SET NULL;
+
+-- Existing conversations become a single branch in creation order
+UPDATE messages m
+SET parent_id = ordered.previous_id
+FROM (
+    SELECT id, LAG(id) OVER (PARTITION BY conversation_id ORDER BY created_at, id) AS previous_id
+    FROM messages
+) ordered
+WHERE m.id = ordered.id AND ordered.previous_id IS NOT NULL;
+
+UPDATE conversations c
+SET active_leaf_id = (
+    SELECT id FROM messages WHERE conversation_id = c.id ORDER BY created_at DESC, id DESC LIMIT 1
+);
+
+-- A message inserted without a parent continues the active branch, and
+-- becomes its new leaf
+CREATE OR REPLACE FUNCTION attach_message_to_active_leaf()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.parent_id IS NULL THEN
+        SELECT active_leaf_id INTO NEW.parent_id FROM conversations WHERE id = NEW.conversation_id FOR UPDATE;
+    END IF;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+CREATE OR REPLACE FUNCTION advance_active_leaf()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE conversations SET active_leaf_id = NEW.id WHERE id = NEW.conversation_id;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER attach_messages_to_active_leaf BEFORE INSERT ON messages
+    FOR EACH ROW EXECUTE FUNCTION attach_message_to_active_leaf();
+
+CREATE TRIGGER advance_conversations_active_leaf AFTER INSERT ON messages
+    FOR EACH ROW EXECUTE FUNCTION advance_active_leaf();
+
+CREATE INDEX idx_messages_parent_id ON messages(parent_id);
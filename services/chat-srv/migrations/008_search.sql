@@ -0,0 +1,15 @@
+-- Keyword and semantic search over `message_search_index`. The index holds
+-- plaintext, so with encryption at rest it is only filled for users who
+-- consented (see 005_message_encryption).
+CREATE EXTENSION IF NOT EXISTS vector;
+
+ALTER TABLE message_search_index
+    ADD COLUMN content_tsv TSVECTOR GENERATED ALWAYS AS (to_tsvector('english', content)) STORED;
+ALTER TABLE message_search_index ADD COLUMN embedding vector(1536);
+ALTER TABLE message_search_index ADD COLUMN embedding_model VARCHAR(100);
+
+-- Indexes for performance
+CREATE INDEX idx_message_search_index_tsv ON message_search_index USING GIN (content_tsv);
+CREATE INDEX idx_message_search_index_embedding ON message_search_index USING hnsw (embedding vector_cosine_ops);
+CREATE INDEX idx_message_search_index_unembedded ON message_search_index(created_at) WHERE embedding IS NULL;
+CREATE INDEX idx_message_search_index_conversation_id ON message_search_index(conversation_id);
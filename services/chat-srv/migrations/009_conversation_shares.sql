@@ -0,0 +1,25 @@
+-- Read-only share links. A share freezes the ids of the messages it shows
+-- (a range of the active branch when it was created); content is read
+-- through the conversation at view time, so encrypted messages stay
+-- encrypted here and deleting the conversation removes its shares.
+CREATE TABLE conversation_shares (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- 'org' shares are only readable by signed-in members of org_id
+    visibility VARCHAR(20) NOT NULL CHECK (visibility IN ('public', 'org')),
+    org_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+    message_ids UUID[] NOT NULL,
+    title VARCHAR(500),
+    redact BOOLEAN NOT NULL DEFAULT true,
+    expires_at TIMESTAMPTZ,
+    revoked_at TIMESTAMPTZ,
+    view_count BIGINT NOT NULL DEFAULT 0,
+    last_viewed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (visibility = 'public' OR org_id IS NOT NULL)
+);
+
+-- Indexes for performance
+CREATE INDEX idx_conversation_shares_conversation_id ON conversation_shares(conversation_id);
+CREATE INDEX idx_conversation_shares_owner_id ON conversation_shares(owner_id);
@@ -0,0 +1,62 @@
+-- Conversations can have several members. `conversations.user_id` stays the
+-- owner; every conversation also has an 'owner' row here so permission
+-- checks only need this table.
+CREATE TABLE conversation_members (
+    conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('owner', 'editor', 'viewer')),
+    invited_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (conversation_id, user_id)
+);
+
+INSERT INTO conversation_members (conversation_id, user_id, role, created_at)
+SELECT id, user_id, 'owner', created_at FROM conversations;
+
+CREATE OR REPLACE FUNCTION add_conversation_owner()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO conversation_members (conversation_id, user_id, role)
+    VALUES (NEW.id, NEW.user_id, 'owner');
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
+
+CREATE TRIGGER add_conversations_owner AFTER INSERT ON conversations
+    FOR EACH ROW EXECUTE FUNCTION add_conversation_owner();
+
+-- Pending invitations, addressed to a known user or to an email address
+-- that may not have an account yet
+CREATE TABLE conversation_invitations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    conversation_id UUID NOT NULL REFERENCES conversations(id) ON DELETE CASCADE,
+    invitee_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    email VARCHAR(255),
+    role VARCHAR(20) NOT NULL CHECK (role IN ('editor', 'viewer')),
+    invited_by UUID NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    responded_at TIMESTAMPTZ,
+    status VARCHAR(20) NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'accepted', 'declined', 'revoked')),
+    CHECK (invitee_id IS NOT NULL OR email IS NOT NULL)
+);
+
+-- Who sent each user message; assistant replies have no author
+ALTER TABLE messages ADD COLUMN author_id UUID REFERENCES users(id) ON DELETE SET NULL;
+
+UPDATE messages m
+SET author_id = c.user_id
+FROM conversations c
+WHERE m.conversation_id = c.id AND m.role = 'user';
+
+-- Indexes for performance
+CREATE INDEX idx_conversation_members_user_id ON conversation_members(user_id);
+CREATE INDEX idx_conversation_invitations_conversation_id ON conversation_invitations(conversation_id);
+CREATE INDEX idx_conversation_invitations_invitee_id ON conversation_invitations(invitee_id) WHERE status = 'pending';
+CREATE INDEX idx_conversation_invitations_email ON conversation_invitations(LOWER(email)) WHERE status = 'pending';
+
+-- Apply updated_at triggers
+CREATE TRIGGER update_conversation_members_updated_at BEFORE UPDATE ON conversation_members
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,47 @@
+-- Deleting a conversation moves it to the trash. Its owner can restore it
+-- until the restore window passes; then the purge job removes it for good.
+ALTER TABLE conversations ADD COLUMN deleted_at TIMESTAMPTZ;
+ALTER TABLE conversations ADD COLUMN deleted_by UUID;
+
+-- Conversations under legal hold are never purged, whatever the policy says
+ALTER TABLE conversations ADD COLUMN legal_hold BOOLEAN NOT NULL DEFAULT false;
+ALTER TABLE conversations ADD COLUMN legal_hold_reason TEXT;
+ALTER TABLE conversations ADD COLUMN legal_hold_set_by UUID;
+ALTER TABLE conversations ADD COLUMN legal_hold_set_at TIMESTAMPTZ;
+
+-- Retention policies. A NULL org_id row is the global default; org rows
+-- override it field by field. A NULL field falls back to the global row,
+-- then to the deployment default (RESTORE_WINDOW_DAYS, AUDIT_RETENTION_DAYS,
+-- and no limit for messages and token usage).
+CREATE TABLE retention_policies (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    org_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+    -- Conversations with no message newer than this are deleted
+    message_days INTEGER CHECK (message_days > 0),
+    token_usage_days INTEGER CHECK (token_usage_days > 0),
+    audit_days INTEGER CHECK (audit_days > 0),
+    restore_window_days INTEGER CHECK (restore_window_days >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- The merged policy that applies to each user, before deployment defaults
+CREATE VIEW user_retention AS
+SELECT u.id AS user_id,
+       u.org_id,
+       COALESCE(o.message_days, g.message_days) AS message_days,
+       COALESCE(o.token_usage_days, g.token_usage_days) AS token_usage_days,
+       COALESCE(o.audit_days, g.audit_days) AS audit_days,
+       COALESCE(o.restore_window_days, g.restore_window_days) AS restore_window_days
+FROM users u
+LEFT JOIN retention_policies o ON o.org_id = u.org_id
+LEFT JOIN retention_policies g ON g.org_id IS NULL;
+
+-- Indexes for performance
+CREATE UNIQUE INDEX idx_retention_policies_org_id
+    ON retention_policies(COALESCE(org_id, '00000000-0000-0000-0000-000000000000'::uuid));
+CREATE INDEX idx_conversations_deleted_at ON conversations(deleted_at) WHERE deleted_at IS NOT NULL;
+
+-- Apply updated_at triggers
+CREATE TRIGGER update_retention_policies_updated_at BEFORE UPDATE ON retention_policies
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
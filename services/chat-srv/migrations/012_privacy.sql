@@ -0,0 +1,39 @@
+-- Erased users keep their row, pseudonymized, so billing aggregates and
+-- the audit chain still resolve to an account
+ALTER TABLE users ADD COLUMN erased_at TIMESTAMPTZ;
+
+-- Monthly token usage of erased users. The per-request records are deleted
+-- on erasure; these totals stay for billing.
+CREATE TABLE token_usage_rollups (
+    user_id UUID NOT NULL,
+    org_id UUID REFERENCES organizations(id) ON DELETE SET NULL,
+    model VARCHAR(100) NOT NULL,
+    month DATE NOT NULL,
+    prompt_tokens BIGINT NOT NULL,
+    completion_tokens BIGINT NOT NULL,
+    total_tokens BIGINT NOT NULL,
+    requests BIGINT NOT NULL,
+    PRIMARY KEY (user_id, model, month)
+);
+
+-- One receipt per erasure run. Its hash is also written to the audit
+-- chain, so a receipt cannot be altered unnoticed.
+CREATE TABLE erasure_receipts (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL,
+    requested_by UUID NOT NULL,
+    -- Counts of what was deleted or pseudonymized, per store
+    erased JSONB NOT NULL,
+    -- What was kept on purpose, such as billing totals and audit records
+    retained JSONB NOT NULL,
+    -- Rows and keys still linked to the user after erasure; all zero when verified
+    verification JSONB NOT NULL,
+    verified BOOLEAN NOT NULL,
+    receipt_hash VARCHAR(64) NOT NULL,
+    started_at TIMESTAMPTZ NOT NULL,
+    completed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Indexes for performance
+CREATE INDEX idx_token_usage_rollups_org_id ON token_usage_rollups(org_id, month);
+CREATE INDEX idx_erasure_receipts_user_id ON erasure_receipts(user_id, completed_at DESC);
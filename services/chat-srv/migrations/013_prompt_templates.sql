@@ -0,0 +1,38 @@
+-- Prompt templates: reusable system prompts with `{{variable}}` slots.
+-- Personal templates belong to their owner, org templates are shared with
+-- the owner's org, and global templates are curated by admins for everyone.
+CREATE TABLE prompt_templates (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    scope VARCHAR(20) NOT NULL CHECK (scope IN ('personal', 'org', 'global')),
+    -- Who created it; NULL for global templates
+    owner_id UUID REFERENCES users(id) ON DELETE CASCADE,
+    org_id UUID REFERENCES organizations(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    description TEXT,
+    current_version INTEGER NOT NULL DEFAULT 1,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (scope = 'global' OR owner_id IS NOT NULL),
+    CHECK ((scope = 'org') = (org_id IS NOT NULL))
+);
+
+-- Every edit of a template's content is a new version; chats can pin one
+CREATE TABLE prompt_template_versions (
+    template_id UUID NOT NULL REFERENCES prompt_templates(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    -- Variable names used in `content`, in order of first use
+    variables TEXT[] NOT NULL DEFAULT '{}',
+    created_by UUID,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (template_id, version)
+);
+
+-- Indexes for performance
+CREATE INDEX idx_prompt_templates_owner_id ON prompt_templates(owner_id) WHERE scope = 'personal';
+CREATE INDEX idx_prompt_templates_org_id ON prompt_templates(org_id) WHERE scope = 'org';
+CREATE INDEX idx_prompt_templates_global ON prompt_templates(name) WHERE scope = 'global';
+
+-- Apply updated_at triggers
+CREATE TRIGGER update_prompt_templates_updated_at BEFORE UPDATE ON prompt_templates
+    FOR EACH ROW EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,9 @@
+-- Which additional authenticated data message ciphertext was sealed with.
+-- Version 1 bound it to the conversation only; version 2 also binds the
+-- message id. Existing ciphertext is version 1 until the key rotation job
+-- re-seals it.
+ALTER TABLE messages ADD COLUMN aad_version SMALLINT NOT NULL DEFAULT 1;
+
+-- Indexes for performance
+CREATE INDEX idx_messages_legacy_aad ON messages(created_at)
+    WHERE content_ciphertext IS NOT NULL AND aad_version < 2;
@@ -0,0 +1,11 @@
+-- Replies are saved with an explicit parent once they finish streaming. By
+-- then the user may have switched branches or sent another message, so the
+-- active leaf only follows a message saved under it.
+CREATE OR REPLACE FUNCTION advance_active_leaf()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE conversations SET active_leaf_id = NEW.id
+    WHERE id = NEW.conversation_id AND active_leaf_id IS NOT DISTINCT FROM NEW.parent_id;
+    RETURN NEW;
+END;
+$$ language 'plpgsql';
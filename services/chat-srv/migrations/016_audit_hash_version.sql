@@ -0,0 +1,5 @@
+-- Which encoding a record's hash covers. Version 1 hashed serde_json's own
+-- output, which depends on its feature flags and on numbers surviving the
+-- JSONB round trip as written; version 2 uses an explicit canonical form.
+-- Existing records stay version 1 and verify as before.
+ALTER TABLE audit_log ADD COLUMN hash_version SMALLINT NOT NULL DEFAULT 1;
@@ -0,0 +1,250 @@
+use crate::error::ApiError;
+use crate::state::AppState;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, HeaderMap},
+};
+use axum_extra::{
+    headers::{authorization::Bearer, Authorization},
+    TypedHeader,
+};
+use jsonwebtoken::{decode, DecodingKey, Validation};
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub const ADMIN_SCOPE: &str = "admin";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: String,
+    pub exp: usize,
+    #[serde(default)]
+    pub scope: String,
+    // Set when the token was minted from an API key
+    #[serde(default)]
+    pub api_key_id: Option<Uuid>,
+}
+
+impl Claims {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scope.split_whitespace().any(|s| s == scope)
+    }
+}
+
+pub fn decode_token(secret: &str, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
+    let data = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &Validation::default(),
+    )?;
+    
+    Ok(data.claims)
+}
+
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub user_id: Uuid,
+    pub claims: Claims,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AuthUser {
+    type Rejection = ApiError;
+    
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let TypedHeader(Authorization(bearer)) =
+            TypedHeader::<Authorization<Bearer>>::from_request_parts(parts, state)
+                .await
+                .map_err(|_| ApiError::Unauthorized)?;
+        
+        let claims = decode_token(&state.config.jwt_secret, bearer.token())
+            .map_err(|_| ApiError::Unauthorized)?;
+        let user_id = Uuid::parse_str(&claims.sub).map_err(|_| ApiError::Unauthorized)?;
+        
+        // Deactivated users lose access immediately, even with a valid token
+        if state.user_service.get_user(&user_id).await?.is_none() {
+            return Err(ApiError::Unauthorized);
+        }
+        
+        Ok(Self { user_id, claims })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AdminUser {
+    type Rejection = ApiError;
+    
+    async fn from_request_parts(parts: &mut Parts, state: &Arc<AppState>) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        
+        if !user.claims.has_scope(ADMIN_SCOPE) {
+            return Err(ApiError::Forbidden);
+        }
+        
+        Ok(Self(user))
+    }
+}
+
+// Token from an `Authorization: Bearer` header, for requests that are not
+// handled through the `AuthUser` extractor
+pub fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+// Resolves the originating client address behind Cloudflare and Traefik.
+// Forwarding headers are only believed when the connection comes from a
+// trusted proxy; anyone else could set them to whatever they like
+pub fn client_ip(headers: &HeaderMap, remote: SocketAddr, trusted_proxies: &[String]) -> String {
+    let remote = remote.ip();
+    if !is_trusted(remote, trusted_proxies) {
+        return remote.to_string();
+    }
+    
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| v.parse::<IpAddr>().is_ok())
+    };
+    // X-Forwarded-For is appended to by each hop, so the client is the
+    // rightmost address that is not one of our proxies
+    let forwarded_for = || {
+        headers
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| {
+                v.split(',')
+                    .rev()
+                    .filter_map(|hop| hop.trim().parse::<IpAddr>().ok())
+                    .find(|hop| !is_trusted(*hop, trusted_proxies))
+            })
+            .map(|ip| ip.to_string())
+    };
+    
+    header("cf-connecting-ip")
+        .or_else(|| header("x-real-ip"))
+        .or_else(forwarded_for)
+        .unwrap_or_else(|| remote.to_string())
+}
+
+fn is_trusted(ip: IpAddr, trusted_proxies: &[String]) -> bool {
+    trusted_proxies
+        .iter()
+        .filter_map(|proxy| parse_cidr(proxy))
+        .any(|(network, prefix)| in_network(ip, network, prefix))
+}
+
+// `10.0.0.0/8`, `::1` or `192.168.1.7`; a bare address is a single host
+pub fn parse_cidr(range: &str) -> Option<(IpAddr, u8)> {
+    let (address, prefix) = match range.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix)),
+        None => (range, None),
+    };
+    let address: IpAddr = address.trim().parse().ok()?;
+    let max = if address.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(prefix) => prefix.trim().parse::<u8>().ok().filter(|p| *p <= max)?,
+        None => max,
+    };
+    
+    Some((address, prefix))
+}
+
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    // An IPv4 client may reach an IPv6 socket as a mapped address
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        v4 => v4,
+    };
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => {
+            let mask = if prefix == 0 { 0 } else { u128::MAX << (128 - prefix) };
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+    
+    fn remote(ip: &str) -> SocketAddr {
+        SocketAddr::new(ip.parse().unwrap(), 443)
+    }
+    
+    #[test]
+    fn parses_ranges_and_bare_addresses() {
+        assert_eq!(parse_cidr("10.0.0.0/8"), Some(("10.0.0.0".parse().unwrap(), 8)));
+        assert_eq!(parse_cidr("192.168.1.7"), Some(("192.168.1.7".parse().unwrap(), 32)));
+        assert_eq!(parse_cidr("::1"), Some(("::1".parse().unwrap(), 128)));
+        assert_eq!(parse_cidr("10.0.0.0/33"), None);
+        assert_eq!(parse_cidr("proxy.internal"), None);
+    }
+    
+    #[test]
+    fn matches_networks_including_mapped_ipv4() {
+        let (network, prefix) = parse_cidr("10.0.0.0/8").unwrap();
+        
+        assert!(in_network("10.1.2.3".parse().unwrap(), network, prefix));
+        assert!(in_network("::ffff:10.1.2.3".parse().unwrap(), network, prefix));
+        assert!(!in_network("11.0.0.1".parse().unwrap(), network, prefix));
+    }
+    
+    #[test]
+    fn ignores_forwarding_headers_from_untrusted_peers() {
+        let headers = headers(&[("x-forwarded-for", "1.2.3.4"), ("cf-connecting-ip", "5.6.7.8")]);
+        
+        assert_eq!(client_ip(&headers, remote("203.0.113.9"), &[]), "203.0.113.9");
+        assert_eq!(client_ip(&headers, remote("203.0.113.9"), &["10.0.0.0/8".to_string()]), "203.0.113.9");
+    }
+    
+    #[test]
+    fn honors_forwarding_headers_from_trusted_proxies() {
+        let trusted = ["10.0.0.0/8".to_string()];
+        let cf = headers(&[("cf-connecting-ip", "5.6.7.8"), ("x-forwarded-for", "1.2.3.4")]);
+        
+        assert_eq!(client_ip(&cf, remote("10.0.0.2"), &trusted), "5.6.7.8");
+    }
+    
+    #[test]
+    fn takes_the_rightmost_untrusted_forwarded_hop() {
+        let trusted = ["10.0.0.0/8".to_string()];
+        // The client can prepend anything; only our proxies' hops are believed
+        let headers = headers(&[("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.0.0.5")]);
+        
+        assert_eq!(client_ip(&headers, remote("10.0.0.2"), &trusted), "1.2.3.4");
+    }
+    
+    #[test]
+    fn skips_unparseable_header_values() {
+        let trusted = ["10.0.0.0/8".to_string()];
+        let headers = headers(&[("x-real-ip", "not-an-ip"), ("x-forwarded-for", "1.2.3.4")]);
+        
+        assert_eq!(client_ip(&headers, remote("10.0.0.2"), &trusted), "1.2.3.4");
+    }
+}
@@ -1,12 +1,28 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::env;
 
+// Buffered stream chunks are unredacted user content; keep them short-lived
+const MAX_STREAM_RESUME_TTL_SECS: u64 = 3600;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     // Server
     pub host: String,
     pub port: u16,
+    pub shutdown_grace_secs: u64,
+    pub shutdown_readiness_delay_secs: u64,
+    // How long streamed chunks stay in Redis for resuming clients. They hold
+    // the restored (unredacted) text, so this is capped at an hour
+    pub stream_resume_ttl_secs: u64,
+    // Identifies this process in the shared session registry
+    pub replica_id: String,
+    pub session_heartbeat_secs: u64,
+    pub session_stale_secs: u64,
+    // Addresses or CIDR ranges of the proxies in front of this service. Only
+    // their forwarding headers are believed when resolving client IPs
+    pub trusted_proxies: Vec<String>,
     
     // Database
     pub database_url: String,
@@ -26,6 +42,14 @@ pub struct Config {
     // Rate Limiting
     pub rate_limit_requests: u64,
     pub rate_limit_window_secs: u64,
+    pub ws_max_concurrent_requests: usize,
+    pub ws_ping_interval_secs: u64,
+    pub ws_idle_timeout_secs: u64,
+    // Outbound messages / bytes a socket may have queued before it is
+    // considered too slow; see `WS_OUTBOUND_POLICY` for what happens then
+    pub ws_outbound_buffer: usize,
+    pub ws_outbound_max_bytes: usize,
+    pub ws_outbound_policy: String,
     
     // Token Limits
     pub max_tokens_per_request: u32,
@@ -36,6 +60,48 @@ pub struct Config {
     pub default_openai_model: String,
     pub default_claude_model: String,
     pub enable_o3_model: bool,
+    // Further models clients may ask for; anything else is reported as
+    // "other" in metrics. Entries are `name` or `name=context_window`
+    pub models: Vec<String>,
+    // Context windows given in MODELS, over the built-in table
+    pub context_windows: HashMap<String, u32>,
+    // Longest gap between streamed chunks before the upstream is given up on
+    pub llm_stream_timeout_secs: u64,
+    
+    // Context window
+    pub system_prompt: Option<String>,
+    pub context_strategy: String,
+    // Most earlier messages the sliding_window strategy sends
+    pub context_window_messages: usize,
+    // Kept free for the reply when the request does not set max_tokens
+    pub context_reserve_tokens: u32,
+    
+    // Titles & summaries
+    pub auto_title_enabled: bool,
+    pub summary_model: String,
+    // Conversations this long get a rolling summary of all but the latest half
+    pub summary_after_messages: usize,
+    // Account billed for titling and summaries instead of the user
+    pub system_account_id: String,
+    
+    // Search
+    pub search_index_interval_secs: u64,
+    pub search_index_batch_size: i64,
+    // Empty disables semantic search; must produce vectors the size of
+    // `message_search_index.embedding` (1536), which is checked at startup
+    pub embedding_model: String,
+    
+    // Sharing
+    // Signs share links; defaults to JWT_SECRET
+    pub share_link_secret: String,
+    // Public origin share URLs are built on
+    pub share_base_url: String,
+    // Pending conversation invitations lapse after this long
+    pub invitation_ttl_days: u32,
+    
+    // Privacy
+    // Data export archives can be downloaded for this long
+    pub data_export_ttl_secs: u64,
     
     // Security
     pub enable_tls: bool,
@@ -47,11 +113,46 @@ pub struct Config {
     pub metrics_port: u16,
     pub enable_tracing: bool,
     pub otlp_endpoint: Option<String>,
+    
+    // Admin
+    pub admin_max_boost_multiplier: f64,
+    pub admin_max_boost_duration_secs: u64,
+    
+    // Audit
+    pub audit_retention_days: u32,
+    
+    // Retention
+    // How long trashed conversations can be restored, unless a retention
+    // policy says otherwise
+    pub restore_window_days: u32,
+    
+    // Redaction
+    pub redaction_enabled: bool,
+    pub redaction_default_action: String,
+    
+    // Guardrails
+    pub guardrails_enabled: bool,
+    pub guardrails_config_path: Option<String>,
+    
+    // Tiers
+    // token-meter's quotas.yaml; tier features are not enforced without it
+    pub quotas_config_path: Option<String>,
+    
+    // Encryption at rest
+    pub encryption_enabled: bool,
+    pub master_key_source: String,
+    pub master_key_path: Option<String>,
+    pub kms_key_id: String,
+    pub kms_secret: Option<String>,
+    pub data_key_scope: String,
+    pub data_key_rotation_days: u32,
+    pub reencrypt_batch_size: i64,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self> {
         dotenvy::dotenv().ok();
+        let models = parse_models(&env::var("MODELS").unwrap_or_default())?;
         
         Ok(Self {
             host: env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string()),
@@ -59,6 +160,35 @@ impl Config {
                 .unwrap_or_else(|_| "8080".to_string())
                 .parse()
                 .context("Invalid PORT")?,
+            shutdown_grace_secs: env::var("SHUTDOWN_GRACE_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("Invalid SHUTDOWN_GRACE_SECS")?,
+            shutdown_readiness_delay_secs: env::var("SHUTDOWN_READINESS_DELAY_SECS")
+                .unwrap_or_else(|_| "5".to_string())
+                .parse()
+                .context("Invalid SHUTDOWN_READINESS_DELAY_SECS")?,
+            stream_resume_ttl_secs: env::var("STREAM_RESUME_TTL_SECS")
+                .unwrap_or_else(|_| "900".to_string())
+                .parse()
+                .context("Invalid STREAM_RESUME_TTL_SECS")?,
+            replica_id: env::var("REPLICA_ID")
+                .or_else(|_| env::var("HOSTNAME"))
+                .unwrap_or_else(|_| uuid::Uuid::new_v4().to_string()),
+            session_heartbeat_secs: env::var("SESSION_HEARTBEAT_SECS")
+                .unwrap_or_else(|_| "15".to_string())
+                .parse()
+                .context("Invalid SESSION_HEARTBEAT_SECS")?,
+            session_stale_secs: env::var("SESSION_STALE_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .context("Invalid SESSION_STALE_SECS")?,
+            trusted_proxies: env::var("TRUSTED_PROXIES")
+                .unwrap_or_default()
+                .split(',')
+                .map(|proxy| proxy.trim().to_string())
+                .filter(|proxy| !proxy.is_empty())
+                .collect(),
             
             database_url: env::var("DATABASE_URL")
                 .context("DATABASE_URL is required")?,
@@ -88,6 +218,27 @@ impl Config {
                 .unwrap_or_else(|_| "60".to_string())
                 .parse()
                 .context("Invalid RATE_LIMIT_WINDOW_SECS")?,
+            ws_max_concurrent_requests: env::var("WS_MAX_CONCURRENT_REQUESTS")
+                .unwrap_or_else(|_| "4".to_string())
+                .parse()
+                .context("Invalid WS_MAX_CONCURRENT_REQUESTS")?,
+            ws_ping_interval_secs: env::var("WS_PING_INTERVAL_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("Invalid WS_PING_INTERVAL_SECS")?,
+            ws_idle_timeout_secs: env::var("WS_IDLE_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "300".to_string())
+                .parse()
+                .context("Invalid WS_IDLE_TIMEOUT_SECS")?,
+            ws_outbound_buffer: env::var("WS_OUTBOUND_BUFFER")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .context("Invalid WS_OUTBOUND_BUFFER")?,
+            ws_outbound_max_bytes: env::var("WS_OUTBOUND_MAX_BYTES")
+                .unwrap_or_else(|_| "1048576".to_string())
+                .parse()
+                .context("Invalid WS_OUTBOUND_MAX_BYTES")?,
+            ws_outbound_policy: env::var("WS_OUTBOUND_POLICY").unwrap_or_else(|_| "coalesce".to_string()),
             
             max_tokens_per_request: env::var("MAX_TOKENS_PER_REQUEST")
                 .unwrap_or_else(|_| "4096".to_string())
@@ -110,6 +261,62 @@ impl Config {
                 .unwrap_or_else(|_| "false".to_string())
                 .parse()
                 .context("Invalid ENABLE_O3_MODEL")?,
+            models: models.iter().map(|(model, _)| model.clone()).collect(),
+            context_windows: models.into_iter().filter_map(|(model, window)| Some((model, window?))).collect(),
+            llm_stream_timeout_secs: env::var("LLM_STREAM_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "60".to_string())
+                .parse()
+                .context("Invalid LLM_STREAM_TIMEOUT_SECS")?,
+            
+            system_prompt: env::var("SYSTEM_PROMPT").ok(),
+            context_strategy: env::var("CONTEXT_STRATEGY").unwrap_or_else(|_| "summarize_older".to_string()),
+            context_window_messages: env::var("CONTEXT_WINDOW_MESSAGES")
+                .unwrap_or_else(|_| "20".to_string())
+                .parse()
+                .context("Invalid CONTEXT_WINDOW_MESSAGES")?,
+            context_reserve_tokens: env::var("CONTEXT_RESERVE_TOKENS")
+                .unwrap_or_else(|_| "1024".to_string())
+                .parse()
+                .context("Invalid CONTEXT_RESERVE_TOKENS")?,
+            
+            auto_title_enabled: env::var("AUTO_TITLE_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .context("Invalid AUTO_TITLE_ENABLED")?,
+            summary_model: env::var("SUMMARY_MODEL")
+                .unwrap_or_else(|_| "gpt-3.5-turbo".to_string()),
+            summary_after_messages: env::var("SUMMARY_AFTER_MESSAGES")
+                .unwrap_or_else(|_| "40".to_string())
+                .parse()
+                .context("Invalid SUMMARY_AFTER_MESSAGES")?,
+            system_account_id: env::var("SYSTEM_ACCOUNT_ID")
+                .unwrap_or_else(|_| "00000000-0000-0000-0000-000000000000".to_string()),
+            
+            search_index_interval_secs: env::var("SEARCH_INDEX_INTERVAL_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("Invalid SEARCH_INDEX_INTERVAL_SECS")?,
+            search_index_batch_size: env::var("SEARCH_INDEX_BATCH_SIZE")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .context("Invalid SEARCH_INDEX_BATCH_SIZE")?,
+            embedding_model: env::var("EMBEDDING_MODEL")
+                .unwrap_or_else(|_| "text-embedding-3-small".to_string()),
+            
+            share_link_secret: env::var("SHARE_LINK_SECRET")
+                .or_else(|_| env::var("JWT_SECRET"))
+                .context("SHARE_LINK_SECRET or JWT_SECRET is required")?,
+            share_base_url: env::var("SHARE_BASE_URL")
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            invitation_ttl_days: env::var("INVITATION_TTL_DAYS")
+                .unwrap_or_else(|_| "14".to_string())
+                .parse()
+                .context("Invalid INVITATION_TTL_DAYS")?,
+            
+            data_export_ttl_secs: env::var("DATA_EXPORT_TTL_SECS")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .context("Invalid DATA_EXPORT_TTL_SECS")?,
             
             enable_tls: env::var("ENABLE_TLS")
                 .unwrap_or_else(|_| "false".to_string())
@@ -131,9 +338,91 @@ impl Config {
                 .parse()
                 .context("Invalid ENABLE_TRACING")?,
             otlp_endpoint: env::var("OTLP_ENDPOINT").ok(),
+            
+            admin_max_boost_multiplier: env::var("ADMIN_MAX_BOOST_MULTIPLIER")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .context("Invalid ADMIN_MAX_BOOST_MULTIPLIER")?,
+            admin_max_boost_duration_secs: env::var("ADMIN_MAX_BOOST_DURATION_SECS")
+                .unwrap_or_else(|_| "86400".to_string())
+                .parse()
+                .context("Invalid ADMIN_MAX_BOOST_DURATION_SECS")?,
+            
+            audit_retention_days: env::var("AUDIT_RETENTION_DAYS")
+                .unwrap_or_else(|_| "365".to_string())
+                .parse()
+                .context("Invalid AUDIT_RETENTION_DAYS")?,
+            
+            restore_window_days: env::var("RESTORE_WINDOW_DAYS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("Invalid RESTORE_WINDOW_DAYS")?,
+            
+            redaction_enabled: env::var("REDACTION_ENABLED")
+                .unwrap_or_else(|_| "true".to_string())
+                .parse()
+                .context("Invalid REDACTION_ENABLED")?,
+            redaction_default_action: env::var("REDACTION_DEFAULT_ACTION")
+                .unwrap_or_else(|_| "mask".to_string()),
+            
+            guardrails_enabled: env::var("GUARDRAILS_ENABLED")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .context("Invalid GUARDRAILS_ENABLED")?,
+            guardrails_config_path: env::var("GUARDRAILS_CONFIG_PATH").ok(),
+            
+            quotas_config_path: env::var("QUOTAS_CONFIG_PATH").ok(),
+            
+            encryption_enabled: env::var("ENCRYPTION_ENABLED")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .context("Invalid ENCRYPTION_ENABLED")?,
+            master_key_source: env::var("MASTER_KEY_SOURCE")
+                .unwrap_or_else(|_| "file".to_string()),
+            master_key_path: env::var("MASTER_KEY_PATH").ok(),
+            kms_key_id: env::var("KMS_KEY_ID")
+                .unwrap_or_else(|_| "chat-srv-master-1".to_string()),
+            kms_secret: env::var("KMS_SECRET").ok(),
+            data_key_scope: env::var("DATA_KEY_SCOPE")
+                .unwrap_or_else(|_| "user".to_string()),
+            data_key_rotation_days: env::var("DATA_KEY_ROTATION_DAYS")
+                .unwrap_or_else(|_| "90".to_string())
+                .parse()
+                .context("Invalid DATA_KEY_ROTATION_DAYS")?,
+            reencrypt_batch_size: env::var("REENCRYPT_BATCH_SIZE")
+                .unwrap_or_else(|_| "500".to_string())
+                .parse()
+                .context("Invalid REENCRYPT_BATCH_SIZE")?,
         })
     }
     
+    // MODELS plus every model this service is configured to use itself
+    pub fn known_models(&self) -> Vec<String> {
+        let mut models = self.models.clone();
+        let mut defaults = vec![
+            self.default_openai_model.clone(),
+            self.default_claude_model.clone(),
+            self.summary_model.clone(),
+        ];
+        if self.enable_o3_model {
+            defaults.push("o3".to_string());
+        }
+        for model in defaults {
+            if !models.contains(&model) {
+                models.push(model);
+            }
+        }
+        
+        models
+    }
+    
+    pub fn context_window(&self, model: &str) -> u32 {
+        self.context_windows
+            .get(model)
+            .copied()
+            .unwrap_or_else(|| crate::llm::context::context_window(model))
+    }
+    
     pub fn validate(&self) -> Result<()> {
         if self.enable_tls {
             if self.tls_cert_path.is_none() || self.tls_key_path.is_none() {
@@ -145,6 +434,91 @@ impl Config {
             tracing::warn!("O3 model enabled but no custom OpenAI base URL provided");
         }
         
+        if !matches!(self.redaction_default_action.as_str(), "block" | "mask" | "allow") {
+            anyhow::bail!("REDACTION_DEFAULT_ACTION must be one of block, mask, allow");
+        }
+        
+        // Enabled guardrails without a config would silently run unguarded
+        if self.guardrails_enabled && self.guardrails_config_path.is_none() {
+            anyhow::bail!("GUARDRAILS_ENABLED requires GUARDRAILS_CONFIG_PATH");
+        }
+        
+        if !matches!(self.context_strategy.as_str(), "drop_oldest" | "summarize_older" | "sliding_window") {
+            anyhow::bail!("CONTEXT_STRATEGY must be one of drop_oldest, summarize_older, sliding_window");
+        }
+        
+        if let Some(proxy) = self.trusted_proxies.iter().find(|proxy| crate::auth::parse_cidr(proxy).is_none()) {
+            anyhow::bail!("TRUSTED_PROXIES has an invalid address or range: {}", proxy);
+        }
+        
+        if uuid::Uuid::parse_str(&self.system_account_id).is_err() {
+            anyhow::bail!("SYSTEM_ACCOUNT_ID must be a UUID");
+        }
+        
+        if self.search_index_interval_secs == 0 || self.search_index_batch_size <= 0 {
+            anyhow::bail!("SEARCH_INDEX_INTERVAL_SECS and SEARCH_INDEX_BATCH_SIZE must be positive");
+        }
+        
+        if self.admin_max_boost_multiplier < 1.0 {
+            anyhow::bail!("ADMIN_MAX_BOOST_MULTIPLIER must be at least 1");
+        }
+        
+        if self.ws_ping_interval_secs == 0 || self.ws_idle_timeout_secs <= self.ws_ping_interval_secs {
+            anyhow::bail!("WS_IDLE_TIMEOUT_SECS must be greater than a non-zero WS_PING_INTERVAL_SECS");
+        }
+        
+        if self.llm_stream_timeout_secs == 0 {
+            anyhow::bail!("LLM_STREAM_TIMEOUT_SECS must be positive");
+        }
+        
+        if self.stream_resume_ttl_secs <= self.llm_stream_timeout_secs || self.stream_resume_ttl_secs > MAX_STREAM_RESUME_TTL_SECS {
+            anyhow::bail!(
+                "STREAM_RESUME_TTL_SECS must be greater than LLM_STREAM_TIMEOUT_SECS and at most {}",
+                MAX_STREAM_RESUME_TTL_SECS
+            );
+        }
+        
+        if !matches!(self.ws_outbound_policy.as_str(), "coalesce" | "disconnect") {
+            anyhow::bail!("WS_OUTBOUND_POLICY must be one of coalesce, disconnect");
+        }
+        
+        if self.session_stale_secs <= self.session_heartbeat_secs {
+            anyhow::bail!("SESSION_STALE_SECS must be greater than SESSION_HEARTBEAT_SECS");
+        }
+        
+        if self.encryption_enabled {
+            match self.master_key_source.as_str() {
+                "file" if self.master_key_path.is_none() => {
+                    anyhow::bail!("MASTER_KEY_SOURCE=file requires MASTER_KEY_PATH");
+                }
+                "kms" if self.kms_secret.is_none() => {
+                    anyhow::bail!("MASTER_KEY_SOURCE=kms requires KMS_SECRET");
+                }
+                "file" | "kms" => {}
+                _ => anyhow::bail!("MASTER_KEY_SOURCE must be one of file, kms"),
+            }
+            
+            if !matches!(self.data_key_scope.as_str(), "user" | "org") {
+                anyhow::bail!("DATA_KEY_SCOPE must be one of user, org");
+            }
+        }
+        
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+// `gpt-4o,llama-3-70b=8192`: model names, each optionally with its context window
+fn parse_models(raw: &str) -> Result<Vec<(String, Option<u32>)>> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once('=') {
+            Some((model, window)) => {
+                let window = window.trim().parse::<u32>().ok().filter(|w| *w > 0)
+                    .with_context(|| format!("Invalid context window in MODELS entry {}", entry))?;
+                Ok((model.trim().to_string(), Some(window)))
+            }
+            None => Ok((entry.to_string(), None)),
+        })
+        .collect()
+}
@@ -0,0 +1,184 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::{anyhow, bail, Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha2::Sha256;
+use std::collections::HashMap;
+
+// Leading byte of every sealed value, so the format can change without a flag day
+const ENVELOPE_VERSION: u8 = 1;
+const NONCE_LEN: usize = 12;
+pub const DATA_KEY_LEN: usize = 32;
+
+// A plaintext data key. Only ever held in memory; the database stores it wrapped.
+#[derive(Clone)]
+pub struct DataKey([u8; DATA_KEY_LEN]);
+
+impl DataKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; DATA_KEY_LEN];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self(key)
+    }
+    
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let key: [u8; DATA_KEY_LEN] = bytes
+            .try_into()
+            .map_err(|_| anyhow!("Data key must be {} bytes, got {}", DATA_KEY_LEN, bytes.len()))?;
+        Ok(Self(key))
+    }
+    
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for DataKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DataKey(..)")
+    }
+}
+
+// Encrypts with AES-256-GCM. The output is version || nonce || ciphertext+tag.
+// `aad` binds the ciphertext to its context (e.g. the conversation) so it
+// cannot be moved to another row and still decrypt.
+pub fn seal(key: &DataKey, plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    
+    let ciphertext = cipher
+        .encrypt(&nonce, Payload { msg: plaintext, aad })
+        .map_err(|_| anyhow!("Encryption failed"))?;
+    
+    let mut sealed = Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+    sealed.push(ENVELOPE_VERSION);
+    sealed.extend_from_slice(&nonce);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+pub fn open(key: &DataKey, sealed: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() < 1 + NONCE_LEN {
+        bail!("Sealed value is truncated");
+    }
+    if sealed[0] != ENVELOPE_VERSION {
+        bail!("Unsupported envelope version {}", sealed[0]);
+    }
+    
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key.as_bytes()));
+    let nonce = Nonce::from_slice(&sealed[1..1 + NONCE_LEN]);
+    
+    cipher
+        .decrypt(nonce, Payload { msg: &sealed[1 + NONCE_LEN..], aad })
+        .map_err(|_| anyhow!("Decryption failed: wrong key or tampered ciphertext"))
+}
+
+// Wraps and unwraps data keys. Implementations hold the key-encryption keys,
+// which never leave the provider.
+#[async_trait]
+pub trait MasterKeyProvider: Send + Sync {
+    // Key used for new wraps; older ids must stay unwrappable until rewrapped
+    fn active_key_id(&self) -> &str;
+    
+    async fn wrap(&self, data_key: &DataKey) -> Result<Vec<u8>>;
+    
+    async fn unwrap(&self, key_id: &str, wrapped: &[u8]) -> Result<DataKey>;
+}
+
+// Master keys read from a file with one `key_id:base64-key` per line.
+// The last line is the active key; earlier lines are kept so data keys
+// wrapped before a master key rotation can still be unwrapped.
+pub struct FileKeyProvider {
+    keys: HashMap<String, DataKey>,
+    active: String,
+}
+
+impl FileKeyProvider {
+    pub fn from_path(path: &str) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read master key file {}", path))?;
+        
+        let mut keys = HashMap::new();
+        let mut active = None;
+        
+        for line in raw.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let (id, encoded) = line
+                .split_once(':')
+                .context("Master key lines must be key_id:base64")?;
+            let bytes = BASE64
+                .decode(encoded.trim())
+                .with_context(|| format!("Master key {} is not valid base64", id))?;
+            
+            keys.insert(id.trim().to_string(), DataKey::from_bytes(&bytes)?);
+            active = Some(id.trim().to_string());
+        }
+        
+        let active = active.context("Master key file contains no keys")?;
+        Ok(Self { keys, active })
+    }
+}
+
+#[async_trait]
+impl MasterKeyProvider for FileKeyProvider {
+    fn active_key_id(&self) -> &str {
+        &self.active
+    }
+    
+    async fn wrap(&self, data_key: &DataKey) -> Result<Vec<u8>> {
+        seal(&self.keys[&self.active], data_key.as_bytes(), self.active.as_bytes())
+    }
+    
+    async fn unwrap(&self, key_id: &str, wrapped: &[u8]) -> Result<DataKey> {
+        let master = self.keys
+            .get(key_id)
+            .with_context(|| format!("Unknown master key {}", key_id))?;
+        DataKey::from_bytes(&open(master, wrapped, key_id.as_bytes())?)
+    }
+}
+
+// Stand-in for a cloud KMS: key-encryption keys are derived from a root
+// secret and the key id, so rotating the master key is just a new id.
+// Swap for a real KMS client before relying on it in production.
+pub struct LocalKmsProvider {
+    root_secret: Vec<u8>,
+    active: String,
+}
+
+impl LocalKmsProvider {
+    pub fn new(root_secret: &str, active_key_id: &str) -> Result<Self> {
+        if root_secret.len() < 32 {
+            bail!("KMS secret must be at least 32 characters");
+        }
+        
+        Ok(Self {
+            root_secret: root_secret.as_bytes().to_vec(),
+            active: active_key_id.to_string(),
+        })
+    }
+    
+    fn derive(&self, key_id: &str) -> Result<DataKey> {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.root_secret)
+            .map_err(|_| anyhow!("Invalid KMS secret"))?;
+        mac.update(b"chat-srv/kek/");
+        mac.update(key_id.as_bytes());
+        DataKey::from_bytes(&mac.finalize().into_bytes())
+    }
+}
+
+#[async_trait]
+impl MasterKeyProvider for LocalKmsProvider {
+    fn active_key_id(&self) -> &str {
+        &self.active
+    }
+    
+    async fn wrap(&self, data_key: &DataKey) -> Result<Vec<u8>> {
+        seal(&self.derive(&self.active)?, data_key.as_bytes(), self.active.as_bytes())
+    }
+    
+    async fn unwrap(&self, key_id: &str, wrapped: &[u8]) -> Result<DataKey> {
+        DataKey::from_bytes(&open(&self.derive(key_id)?, wrapped, key_id.as_bytes())?)
+    }
+}
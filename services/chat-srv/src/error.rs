@@ -0,0 +1,54 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use tracing::error;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("Unauthorized")]
+    Unauthorized,
+    
+    #[error("Forbidden")]
+    Forbidden,
+    
+    #[error("Not found: {0}")]
+    NotFound(String),
+    
+    #[error("Invalid request: {0}")]
+    BadRequest(String),
+    
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    
+    #[error("Internal error: {0}")]
+    Internal(#[from] anyhow::Error),
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        
+        // Never leak internal error details to clients
+        let message = match &self {
+            ApiError::Internal(e) => {
+                error!("Internal error: {:#}", e);
+                "Internal error".to_string()
+            }
+            other => other.to_string(),
+        };
+        
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+pub type ApiResult<T> = Result<T, ApiError>;
@@ -0,0 +1,330 @@
+use super::{Guardrail, GuardrailChunk, GuardrailError, GuardrailRequest, Verdict};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+// How much previously emitted output is re-scanned with each chunk
+const OUTPUT_SCAN_WINDOW: usize = 512;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DenylistConfig {
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default = "default_true")]
+    pub check_input: bool,
+    #[serde(default = "default_true")]
+    pub check_output: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MaxOutputConfig {
+    pub max_chars: Option<usize>,
+    pub max_chunks: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassifierConfig {
+    #[serde(default = "default_block_threshold")]
+    pub block_threshold: f32,
+    #[serde(default = "default_annotate_threshold")]
+    pub annotate_threshold: f32,
+    #[serde(default)]
+    pub categories: Vec<ClassifierCategory>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassifierCategory {
+    pub name: String,
+    // Term and weight; a text's category score is the sum of matched weights
+    pub terms: Vec<(String, f32)>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_block_threshold() -> f32 {
+    1.0
+}
+
+fn default_annotate_threshold() -> f32 {
+    0.5
+}
+
+pub struct DenylistGuardrail {
+    keywords: Vec<String>,
+    patterns: Vec<Regex>,
+    check_input: bool,
+    check_output: bool,
+}
+
+impl DenylistGuardrail {
+    pub fn new(config: &DenylistConfig) -> Result<Self> {
+        let patterns = config.patterns
+            .iter()
+            .map(|p| Regex::new(p).with_context(|| format!("Invalid denylist pattern {}", p)))
+            .collect::<Result<Vec<_>>>()?;
+        
+        Ok(Self {
+            keywords: config.keywords.iter().map(|k| k.to_lowercase()).collect(),
+            patterns,
+            check_input: config.check_input,
+            check_output: config.check_output,
+        })
+    }
+    
+    fn find(&self, text: &str) -> Option<String> {
+        let lowered = text.to_lowercase();
+        if let Some(keyword) = self.keywords.iter().find(|k| lowered.contains(k.as_str())) {
+            return Some(format!("denied term \"{}\"", keyword));
+        }
+        
+        self.patterns
+            .iter()
+            .find(|p| p.is_match(text))
+            .map(|p| format!("denied pattern /{}/", p.as_str()))
+    }
+}
+
+#[async_trait]
+impl Guardrail for DenylistGuardrail {
+    fn name(&self) -> &str {
+        "denylist"
+    }
+    
+    async fn check_request(&self, request: &GuardrailRequest<'_>) -> Result<Verdict, GuardrailError> {
+        if !self.check_input {
+            return Ok(Verdict::Allow);
+        }
+        
+        Ok(match self.find(request.prompt) {
+            Some(reason) => Verdict::Block { reason },
+            None => Verdict::Allow,
+        })
+    }
+    
+    async fn check_chunk(&self, chunk: &GuardrailChunk<'_>) -> Result<Verdict, GuardrailError> {
+        if !self.check_output {
+            return Ok(Verdict::Allow);
+        }
+        
+        Ok(match self.find(&chunk.tail(OUTPUT_SCAN_WINDOW)) {
+            Some(reason) => Verdict::Block { reason },
+            None => Verdict::Allow,
+        })
+    }
+}
+
+pub struct MaxOutputGuardrail {
+    config: MaxOutputConfig,
+}
+
+impl MaxOutputGuardrail {
+    pub fn new(config: MaxOutputConfig) -> Self {
+        Self { config }
+    }
+}
+
+#[async_trait]
+impl Guardrail for MaxOutputGuardrail {
+    fn name(&self) -> &str {
+        "max_output"
+    }
+    
+    async fn check_chunk(&self, chunk: &GuardrailChunk<'_>) -> Result<Verdict, GuardrailError> {
+        if let Some(max_chars) = self.config.max_chars {
+            if chunk.output_len() > max_chars {
+                return Ok(Verdict::Block {
+                    reason: format!("response exceeded {} characters", max_chars),
+                });
+            }
+        }
+        
+        if let Some(max_chunks) = self.config.max_chunks {
+            if chunk.chunk_index >= max_chunks {
+                return Ok(Verdict::Block {
+                    reason: format!("response exceeded {} chunks", max_chunks),
+                });
+            }
+        }
+        
+        Ok(Verdict::Allow)
+    }
+}
+
+// Weighted keyword scorer standing in for a local ML classifier. It keeps
+// the same contract (per-category scores against thresholds) so a real
+// model can replace it without touching the pipeline.
+pub struct LocalClassifierGuardrail {
+    config: ClassifierConfig,
+}
+
+impl LocalClassifierGuardrail {
+    pub fn new(config: ClassifierConfig) -> Self {
+        Self { config }
+    }
+    
+    fn classify(&self, text: &str) -> Option<(String, f32)> {
+        let lowered = text.to_lowercase();
+        
+        self.config.categories
+            .iter()
+            .map(|category| {
+                let score: f32 = category.terms
+                    .iter()
+                    .filter(|(term, _)| lowered.contains(&term.to_lowercase()))
+                    .map(|(_, weight)| *weight)
+                    .sum();
+                (category.name.clone(), score)
+            })
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+    }
+    
+    fn verdict(&self, text: &str) -> Verdict {
+        match self.classify(text) {
+            Some((category, score)) if score >= self.config.block_threshold => Verdict::Block {
+                reason: format!("classified as {} ({:.2})", category, score),
+            },
+            Some((category, score)) if score >= self.config.annotate_threshold => Verdict::Annotate {
+                label: category,
+                note: format!("classifier score {:.2}", score),
+            },
+            _ => Verdict::Allow,
+        }
+    }
+}
+
+#[async_trait]
+impl Guardrail for LocalClassifierGuardrail {
+    fn name(&self) -> &str {
+        "local_classifier"
+    }
+    
+    async fn check_request(&self, request: &GuardrailRequest<'_>) -> Result<Verdict, GuardrailError> {
+        Ok(self.verdict(request.prompt))
+    }
+    
+    async fn check_chunk(&self, chunk: &GuardrailChunk<'_>) -> Result<Verdict, GuardrailError> {
+        // Annotations are only useful once per response; only blocks matter mid-stream
+        match self.verdict(&chunk.tail(OUTPUT_SCAN_WINDOW)) {
+            Verdict::Annotate { .. } => Ok(Verdict::Allow),
+            verdict => Ok(verdict),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn request(prompt: &str) -> GuardrailRequest<'_> {
+        GuardrailRequest { user_id: "user", model: "gpt-4o", prompt }
+    }
+    
+    fn chunk<'a>(emitted: &'a str, chunk: &'a str, chunk_index: usize) -> GuardrailChunk<'a> {
+        GuardrailChunk { model: "gpt-4o", chunk, emitted, chunk_index }
+    }
+    
+    fn denylist(check_input: bool) -> DenylistGuardrail {
+        DenylistGuardrail::new(&DenylistConfig {
+            keywords: vec!["Forbidden".to_string()],
+            patterns: vec![r"\d{3}-\d{2}-\d{4}".to_string()],
+            check_input,
+            check_output: true,
+        }).unwrap()
+    }
+    
+    #[tokio::test]
+    async fn denylist_matches_keywords_case_insensitively() {
+        let rail = denylist(true);
+        
+        assert_eq!(
+            rail.check_request(&request("this is FORBIDDEN text")).await.unwrap(),
+            Verdict::Block { reason: "denied term \"forbidden\"".to_string() },
+        );
+        assert_eq!(rail.check_request(&request("this is fine")).await.unwrap(), Verdict::Allow);
+    }
+    
+    #[tokio::test]
+    async fn denylist_matches_patterns() {
+        let rail = denylist(true);
+        
+        assert!(matches!(
+            rail.check_request(&request("my ssn is 123-45-6789")).await.unwrap(),
+            Verdict::Block { reason } if reason.starts_with("denied pattern"),
+        ));
+    }
+    
+    #[tokio::test]
+    async fn denylist_can_skip_input() {
+        let rail = denylist(false);
+        
+        assert_eq!(rail.check_request(&request("forbidden")).await.unwrap(), Verdict::Allow);
+        assert!(matches!(rail.check_chunk(&chunk("", "forbidden", 0)).await.unwrap(), Verdict::Block { .. }));
+    }
+    
+    #[test]
+    fn denylist_rejects_invalid_patterns() {
+        let config = DenylistConfig { patterns: vec!["(".to_string()], ..Default::default() };
+        assert!(DenylistGuardrail::new(&config).is_err());
+    }
+    
+    #[tokio::test]
+    async fn max_output_cuts_off_past_the_char_limit() {
+        let rail = MaxOutputGuardrail::new(MaxOutputConfig { max_chars: Some(10), max_chunks: None });
+        
+        assert_eq!(rail.check_chunk(&chunk("hello", "world", 1)).await.unwrap(), Verdict::Allow);
+        assert_eq!(
+            rail.check_chunk(&chunk("helloworld", "!", 2)).await.unwrap(),
+            Verdict::Block { reason: "response exceeded 10 characters".to_string() },
+        );
+    }
+    
+    #[tokio::test]
+    async fn max_output_cuts_off_past_the_chunk_limit() {
+        let rail = MaxOutputGuardrail::new(MaxOutputConfig { max_chars: None, max_chunks: Some(2) });
+        
+        assert_eq!(rail.check_chunk(&chunk("a", "b", 1)).await.unwrap(), Verdict::Allow);
+        assert_eq!(
+            rail.check_chunk(&chunk("ab", "c", 2)).await.unwrap(),
+            Verdict::Block { reason: "response exceeded 2 chunks".to_string() },
+        );
+    }
+    
+    fn classifier() -> LocalClassifierGuardrail {
+        LocalClassifierGuardrail::new(ClassifierConfig {
+            block_threshold: 1.0,
+            annotate_threshold: 0.5,
+            categories: vec![ClassifierCategory {
+                name: "violence".to_string(),
+                terms: vec![("fight".to_string(), 0.5), ("weapon".to_string(), 0.5)],
+            }],
+        })
+    }
+    
+    #[tokio::test]
+    async fn classifier_annotates_then_blocks_as_scores_rise() {
+        let rail = classifier();
+        
+        assert_eq!(rail.check_request(&request("a quiet day")).await.unwrap(), Verdict::Allow);
+        assert_eq!(
+            rail.check_request(&request("a Fight broke out")).await.unwrap(),
+            Verdict::Annotate { label: "violence".to_string(), note: "classifier score 0.50".to_string() },
+        );
+        assert_eq!(
+            rail.check_request(&request("a fight with a weapon")).await.unwrap(),
+            Verdict::Block { reason: "classified as violence (1.00)".to_string() },
+        );
+    }
+    
+    #[tokio::test]
+    async fn classifier_only_blocks_mid_stream() {
+        let rail = classifier();
+        
+        assert_eq!(rail.check_chunk(&chunk("", "a fight", 0)).await.unwrap(), Verdict::Allow);
+        assert!(matches!(rail.check_chunk(&chunk("a fight with a ", "weapon", 1)).await.unwrap(), Verdict::Block { .. }));
+    }
+}
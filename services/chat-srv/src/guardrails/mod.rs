@@ -0,0 +1,383 @@
+pub mod builtin;
+pub mod remote;
+
+pub use builtin::{DenylistGuardrail, LocalClassifierGuardrail, MaxOutputGuardrail};
+pub use remote::RemoteModerationGuardrail;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{error, warn};
+
+pub const CONTENT_FILTER_FINISH_REASON: &str = "content_filter";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "verdict", rename_all = "lowercase")]
+pub enum Verdict {
+    Allow,
+    Block { reason: String },
+    Rewrite { content: String, reason: String },
+    Annotate { label: String, note: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct GuardrailRequest<'a> {
+    pub user_id: &'a str,
+    pub model: &'a str,
+    pub prompt: &'a str,
+}
+
+#[derive(Debug, Clone)]
+pub struct GuardrailChunk<'a> {
+    pub model: &'a str,
+    pub chunk: &'a str,
+    // Everything already emitted before this chunk
+    pub emitted: &'a str,
+    pub chunk_index: usize,
+}
+
+impl GuardrailChunk<'_> {
+    // The chunk plus up to `window` bytes of preceding output, so matches
+    // split across chunk boundaries are still seen
+    pub fn tail(&self, window: usize) -> String {
+        let mut start = self.emitted.len().saturating_sub(window);
+        while !self.emitted.is_char_boundary(start) {
+            start += 1;
+        }
+        format!("{}{}", &self.emitted[start..], self.chunk)
+    }
+    
+    pub fn output_len(&self) -> usize {
+        self.emitted.len() + self.chunk.len()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GuardrailError {
+    #[error("Guardrail unavailable: {0}")]
+    Unavailable(String),
+    
+    #[error("Guardrail misconfigured: {0}")]
+    Misconfigured(String),
+}
+
+#[async_trait]
+pub trait Guardrail: Send + Sync {
+    fn name(&self) -> &str;
+    
+    async fn check_request(&self, _request: &GuardrailRequest<'_>) -> Result<Verdict, GuardrailError> {
+        Ok(Verdict::Allow)
+    }
+    
+    async fn check_chunk(&self, _chunk: &GuardrailChunk<'_>) -> Result<Verdict, GuardrailError> {
+        Ok(Verdict::Allow)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Annotation {
+    pub guardrail: String,
+    pub label: String,
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockedBy {
+    pub guardrail: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestOutcome {
+    pub system_prompt: Option<String>,
+    pub prompt: String,
+    pub annotations: Vec<Annotation>,
+    pub rewritten_by: Vec<String>,
+    pub blocked: Option<BlockedBy>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChunkOutcome {
+    pub content: String,
+    pub annotations: Vec<Annotation>,
+    pub blocked: Option<BlockedBy>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct GuardrailsConfig {
+    #[serde(default)]
+    pub fail_closed: bool,
+    #[serde(default)]
+    pub denylist: Option<builtin::DenylistConfig>,
+    #[serde(default)]
+    pub max_output: Option<builtin::MaxOutputConfig>,
+    #[serde(default)]
+    pub classifier: Option<builtin::ClassifierConfig>,
+    #[serde(default)]
+    pub remote_moderation: Option<remote::RemoteModerationConfig>,
+}
+
+impl GuardrailsConfig {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read guardrails config {}", path))?;
+        serde_yaml::from_str(&raw).with_context(|| format!("Invalid guardrails config {}", path))
+    }
+}
+
+// Runs every configured guardrail in order. The first block wins; rewrites
+// are applied cumulatively so later guardrails see the rewritten text.
+pub struct Guardrails {
+    rails: Vec<Arc<dyn Guardrail>>,
+    fail_closed: bool,
+}
+
+impl Guardrails {
+    pub fn new(rails: Vec<Arc<dyn Guardrail>>, fail_closed: bool) -> Self {
+        Self { rails, fail_closed }
+    }
+    
+    pub fn disabled() -> Self {
+        Self::new(Vec::new(), false)
+    }
+    
+    pub fn from_config(config: &GuardrailsConfig) -> Result<Self> {
+        let mut rails: Vec<Arc<dyn Guardrail>> = Vec::new();
+        
+        if let Some(denylist) = &config.denylist {
+            rails.push(Arc::new(DenylistGuardrail::new(denylist)?));
+        }
+        if let Some(max_output) = &config.max_output {
+            rails.push(Arc::new(MaxOutputGuardrail::new(max_output.clone())));
+        }
+        if let Some(classifier) = &config.classifier {
+            rails.push(Arc::new(LocalClassifierGuardrail::new(classifier.clone())));
+        }
+        if let Some(remote) = &config.remote_moderation {
+            rails.push(Arc::new(RemoteModerationGuardrail::new(remote.clone())));
+        }
+        
+        Ok(Self::new(rails, config.fail_closed))
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.rails.is_empty()
+    }
+    
+    // A custom or template system prompt goes upstream with the message, so
+    // it is held to the same rules; it is checked first
+    pub async fn check_request(&self, user_id: &str, model: &str, system_prompt: Option<&str>, prompt: &str) -> RequestOutcome {
+        let mut outcome = RequestOutcome {
+            system_prompt: None,
+            prompt: String::new(),
+            annotations: Vec::new(),
+            rewritten_by: Vec::new(),
+            blocked: None,
+        };
+        
+        if let Some(system_prompt) = system_prompt {
+            let checked = self.check_text(user_id, model, system_prompt, &mut outcome).await;
+            outcome.system_prompt = Some(checked);
+            if let Some(blocked) = &mut outcome.blocked {
+                blocked.reason = format!("{} (system prompt)", blocked.reason);
+                return outcome;
+            }
+        }
+        outcome.prompt = self.check_text(user_id, model, prompt, &mut outcome).await;
+        
+        outcome
+    }
+    
+    // Runs the input rails over one text, returning it as rewritten. Stops at
+    // the first block, which is recorded in `outcome`
+    async fn check_text(&self, user_id: &str, model: &str, text: &str, outcome: &mut RequestOutcome) -> String {
+        let mut text = text.to_string();
+        for rail in &self.rails {
+            let request = GuardrailRequest {
+                user_id,
+                model,
+                prompt: &text,
+            };
+            let verdict = self.resolve(rail.name(), rail.check_request(&request).await);
+            
+            match verdict {
+                Verdict::Allow => {}
+                Verdict::Block { reason } => {
+                    outcome.blocked = Some(BlockedBy { guardrail: rail.name().to_string(), reason });
+                    break;
+                }
+                Verdict::Rewrite { content, .. } => {
+                    text = content;
+                    outcome.rewritten_by.push(rail.name().to_string());
+                }
+                Verdict::Annotate { label, note } => {
+                    outcome.annotations.push(Annotation { guardrail: rail.name().to_string(), label, note });
+                }
+            }
+        }
+        
+        text
+    }
+    
+    pub fn stream_guard(self: &Arc<Self>, model: &str) -> StreamGuard {
+        StreamGuard {
+            guardrails: self.clone(),
+            model: model.to_string(),
+            output: String::new(),
+            chunk_index: 0,
+        }
+    }
+    
+    fn resolve(&self, name: &str, result: Result<Verdict, GuardrailError>) -> Verdict {
+        match result {
+            Ok(verdict) => verdict,
+            Err(e) if self.fail_closed => {
+                error!("Guardrail {} failed, blocking: {}", name, e);
+                Verdict::Block { reason: "Content policy check unavailable".to_string() }
+            }
+            Err(e) => {
+                warn!("Guardrail {} failed, allowing: {}", name, e);
+                Verdict::Allow
+            }
+        }
+    }
+}
+
+// Per-response state for post-chunk checks
+pub struct StreamGuard {
+    guardrails: Arc<Guardrails>,
+    model: String,
+    output: String,
+    chunk_index: usize,
+}
+
+impl StreamGuard {
+    pub async fn check_chunk(&mut self, chunk: &str) -> ChunkOutcome {
+        let mut outcome = ChunkOutcome {
+            content: chunk.to_string(),
+            annotations: Vec::new(),
+            blocked: None,
+        };
+        
+        if self.guardrails.is_empty() {
+            self.output.push_str(chunk);
+            return outcome;
+        }
+        
+        for rail in &self.guardrails.rails {
+            let view = GuardrailChunk {
+                model: &self.model,
+                chunk: &outcome.content,
+                emitted: &self.output,
+                chunk_index: self.chunk_index,
+            };
+            let verdict = self.guardrails.resolve(rail.name(), rail.check_chunk(&view).await);
+            
+            match verdict {
+                Verdict::Allow => {}
+                Verdict::Block { reason } => {
+                    outcome.content.clear();
+                    outcome.blocked = Some(BlockedBy { guardrail: rail.name().to_string(), reason });
+                    break;
+                }
+                Verdict::Rewrite { content, .. } => outcome.content = content,
+                Verdict::Annotate { label, note } => {
+                    outcome.annotations.push(Annotation { guardrail: rail.name().to_string(), label, note });
+                }
+            }
+        }
+        
+        self.output.push_str(&outcome.content);
+        self.chunk_index += 1;
+        
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn chunk<'a>(emitted: &'a str, chunk: &'a str) -> GuardrailChunk<'a> {
+        GuardrailChunk { model: "gpt-4o", chunk, emitted, chunk_index: 0 }
+    }
+    
+    fn denylist() -> Arc<dyn Guardrail> {
+        let config = builtin::DenylistConfig {
+            keywords: vec!["forbidden".to_string()],
+            check_input: true,
+            check_output: true,
+            ..Default::default()
+        };
+        Arc::new(DenylistGuardrail::new(&config).unwrap())
+    }
+    
+    struct Failing;
+    
+    #[async_trait]
+    impl Guardrail for Failing {
+        fn name(&self) -> &str {
+            "failing"
+        }
+        
+        async fn check_request(&self, _request: &GuardrailRequest<'_>) -> Result<Verdict, GuardrailError> {
+            Err(GuardrailError::Unavailable("down".to_string()))
+        }
+    }
+    
+    #[test]
+    fn tail_reaches_back_into_emitted_output() {
+        assert_eq!(chunk("hello world", "!").tail(5), "world!");
+        assert_eq!(chunk("hi", "!").tail(5), "hi!");
+        assert_eq!(chunk("hello", "!").tail(0), "!");
+    }
+    
+    #[test]
+    fn tail_never_splits_a_character() {
+        // "é" is two bytes; a window ending inside it skips forward
+        assert_eq!(chunk("café", "!").tail(1), "!");
+        assert_eq!(chunk("café", "!").tail(2), "é!");
+    }
+    
+    #[tokio::test]
+    async fn stream_guard_catches_terms_split_across_chunks() {
+        let guardrails = Arc::new(Guardrails::new(vec![denylist()], false));
+        let mut guard = guardrails.stream_guard("gpt-4o");
+        
+        let first = guard.check_chunk("this is forb").await;
+        assert!(first.blocked.is_none());
+        assert_eq!(first.content, "this is forb");
+        
+        let second = guard.check_chunk("idden text").await;
+        assert_eq!(second.blocked.unwrap().guardrail, "denylist");
+        assert!(second.content.is_empty());
+    }
+    
+    #[tokio::test]
+    async fn system_prompt_is_checked_before_the_prompt() {
+        let guardrails = Guardrails::new(vec![denylist()], false);
+        
+        let outcome = guardrails.check_request("user", "gpt-4o", Some("say forbidden things"), "hello").await;
+        assert_eq!(outcome.blocked.unwrap().reason, "denied term \"forbidden\" (system prompt)");
+        
+        let outcome = guardrails.check_request("user", "gpt-4o", Some("be helpful"), "forbidden").await;
+        assert_eq!(outcome.blocked.unwrap().reason, "denied term \"forbidden\"");
+        assert_eq!(outcome.system_prompt.as_deref(), Some("be helpful"));
+        
+        let outcome = guardrails.check_request("user", "gpt-4o", None, "hello").await;
+        assert!(outcome.blocked.is_none());
+        assert_eq!(outcome.prompt, "hello");
+    }
+    
+    #[tokio::test]
+    async fn failures_follow_the_fail_closed_setting() {
+        let open = Guardrails::new(vec![Arc::new(Failing)], false);
+        assert!(open.check_request("user", "gpt-4o", None, "hello").await.blocked.is_none());
+        
+        let closed = Guardrails::new(vec![Arc::new(Failing)], true);
+        let blocked = closed.check_request("user", "gpt-4o", None, "hello").await.blocked.unwrap();
+        assert_eq!(blocked.guardrail, "failing");
+        assert_eq!(blocked.reason, "Content policy check unavailable");
+    }
+}
@@ -0,0 +1,205 @@
+use super::{Guardrail, GuardrailChunk, GuardrailError, GuardrailRequest, Verdict};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteModerationConfig {
+    pub endpoint: String,
+    pub api_key: Option<String>,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    // Output is re-checked each time this many new characters accumulate
+    #[serde(default = "default_output_interval")]
+    pub output_check_interval: usize,
+    // Also block on any category score at or above this, even when the
+    // service did not flag the text itself
+    #[serde(default)]
+    pub block_threshold: Option<f32>,
+}
+
+fn default_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_output_interval() -> usize {
+    1000
+}
+
+#[derive(Debug, Serialize)]
+struct ModerationRequest<'a> {
+    input: &'a str,
+}
+
+// OpenAI moderation response shape, which most moderation services mimic
+#[derive(Debug, Deserialize)]
+struct ModerationResponse {
+    results: Vec<ModerationResult>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationResult {
+    flagged: bool,
+    #[serde(default)]
+    categories: HashMap<String, bool>,
+    #[serde(default)]
+    category_scores: HashMap<String, f32>,
+}
+
+pub struct RemoteModerationGuardrail {
+    client: Client,
+    config: RemoteModerationConfig,
+}
+
+impl RemoteModerationGuardrail {
+    pub fn new(config: RemoteModerationConfig) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_millis(config.timeout_ms))
+            .build()
+            .unwrap_or_else(|_| Client::new());
+        
+        Self { client, config }
+    }
+    
+    async fn moderate(&self, text: &str) -> Result<Verdict, GuardrailError> {
+        let mut request = self.client
+            .post(&self.config.endpoint)
+            .json(&ModerationRequest { input: text });
+        if let Some(key) = &self.config.api_key {
+            request = request.bearer_auth(key);
+        }
+        
+        let response = request
+            .send()
+            .await
+            .map_err(|e| GuardrailError::Unavailable(e.to_string()))?;
+        
+        if !response.status().is_success() {
+            return Err(GuardrailError::Unavailable(format!("moderation endpoint returned {}", response.status())));
+        }
+        
+        let body: ModerationResponse = response
+            .json()
+            .await
+            .map_err(|e| GuardrailError::Misconfigured(format!("unexpected moderation response: {}", e)))?;
+        
+        Ok(verdict(&body, self.config.block_threshold))
+    }
+}
+
+fn verdict(body: &ModerationResponse, block_threshold: Option<f32>) -> Verdict {
+    let mut hits: Vec<String> = Vec::new();
+    for result in &body.results {
+        if result.flagged {
+            hits.extend(result.categories.iter().filter(|(_, hit)| **hit).map(|(name, _)| name.clone()));
+        }
+        if let Some(threshold) = block_threshold {
+            hits.extend(result.category_scores.iter().filter(|(_, score)| **score >= threshold).map(|(name, _)| name.clone()));
+        }
+    }
+    hits.sort();
+    hits.dedup();
+    
+    if hits.is_empty() && !body.results.iter().any(|r| r.flagged) {
+        return Verdict::Allow;
+    }
+    let reason = if hits.is_empty() {
+        "flagged by moderation".to_string()
+    } else {
+        format!("flagged by moderation: {}", hits.join(", "))
+    };
+    Verdict::Block { reason }
+}
+
+#[async_trait]
+impl Guardrail for RemoteModerationGuardrail {
+    fn name(&self) -> &str {
+        "remote_moderation"
+    }
+    
+    async fn check_request(&self, request: &GuardrailRequest<'_>) -> Result<Verdict, GuardrailError> {
+        self.moderate(request.prompt).await
+    }
+    
+    async fn check_chunk(&self, chunk: &GuardrailChunk<'_>) -> Result<Verdict, GuardrailError> {
+        // Calling out per chunk would dominate latency; check at intervals instead
+        let interval = self.config.output_check_interval.max(1);
+        if chunk.emitted.len() / interval == chunk.output_len() / interval {
+            return Ok(Verdict::Allow);
+        }
+        
+        self.moderate(&chunk.tail(interval)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::guardrails::Guardrails;
+    use std::sync::Arc;
+    
+    fn response(body: serde_json::Value) -> ModerationResponse {
+        serde_json::from_value(body).unwrap()
+    }
+    
+    #[test]
+    fn flagged_result_blocks_with_its_categories() {
+        let body = response(serde_json::json!({
+            "results": [{ "flagged": true, "categories": { "violence": true, "hate": false } }],
+        }));
+        assert_eq!(verdict(&body, None), Verdict::Block { reason: "flagged by moderation: violence".to_string() });
+    }
+    
+    #[test]
+    fn scores_block_only_at_the_threshold() {
+        let body = response(serde_json::json!({
+            "results": [{ "flagged": false, "category_scores": { "violence": 0.7, "hate": 0.2 } }],
+        }));
+        
+        assert_eq!(verdict(&body, None), Verdict::Allow);
+        assert_eq!(verdict(&body, Some(0.8)), Verdict::Allow);
+        assert_eq!(verdict(&body, Some(0.7)), Verdict::Block { reason: "flagged by moderation: violence".to_string() });
+        assert_eq!(
+            verdict(&body, Some(0.1)),
+            Verdict::Block { reason: "flagged by moderation: hate, violence".to_string() },
+        );
+    }
+    
+    // An endpoint nothing listens on, so every call fails
+    async fn unreachable() -> RemoteModerationGuardrail {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}/moderate", listener.local_addr().unwrap());
+        drop(listener);
+        
+        RemoteModerationGuardrail::new(RemoteModerationConfig {
+            endpoint,
+            api_key: None,
+            timeout_ms: 500,
+            output_check_interval: 10,
+            block_threshold: None,
+        })
+    }
+    
+    #[tokio::test]
+    async fn outage_fails_open_by_default() {
+        let guardrails = Guardrails::new(vec![Arc::new(unreachable().await)], false);
+        let outcome = guardrails.check_request("user", "gpt-4o", None, "hello").await;
+        
+        assert!(outcome.blocked.is_none());
+        assert_eq!(outcome.prompt, "hello");
+    }
+    
+    #[tokio::test]
+    async fn outage_fails_closed_when_configured() {
+        let guardrails = Arc::new(Guardrails::new(vec![Arc::new(unreachable().await)], true));
+        let outcome = guardrails.check_request("user", "gpt-4o", None, "hello").await;
+        assert_eq!(outcome.blocked.unwrap().reason, "Content policy check unavailable");
+        
+        // Output is only sent once a full interval has accumulated
+        let mut guard = guardrails.stream_guard("gpt-4o");
+        assert!(guard.check_chunk("short").await.blocked.is_none());
+        assert!(guard.check_chunk(" and then more").await.blocked.is_some());
+    }
+}
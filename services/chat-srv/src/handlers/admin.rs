@@ -0,0 +1,866 @@
+use crate::auth::AdminUser;
+use crate::error::{ApiError, ApiResult};
+use crate::services::admin::{
+    AdminAuditEntry, GrantBoostRequest, QuotaBoost, RateLimits, UpdateRateLimitsRequest,
+};
+use crate::services::audit::{AuditQuery, AuditRecord, ChainAnchor, ChainVerification};
+use crate::services::encryption::{EncryptionService, ReencryptReport, RotationReport};
+use crate::handlers::{privacy, templates};
+use crate::services::policy::{is_builtin_detector, RedactionRule, UpsertRedactionRuleRequest};
+use crate::services::privacy::ErasureReceipt;
+use crate::services::retention::{LegalHold, RetentionPolicy, RetentionReport, UpsertRetentionPolicyRequest};
+use crate::services::user::{CreateUserRequest, UpdateUserRequest, User};
+use crate::services::session_registry::RegisteredSession;
+use crate::services::templates::{CreateTemplateRequest, PromptTemplate, TemplateScope, UpdateTemplateRequest};
+use crate::state::{AppState, SessionControl};
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        // Users
+        .route("/users", get(list_users).post(create_user))
+        .route("/users/:id", get(get_user).patch(update_user).delete(delete_user))
+        .route("/users/:id/deactivate", post(deactivate_user))
+        .route("/users/:id/activate", post(activate_user))
+        .route("/users/:id/disconnect", post(disconnect_user))
+        .route("/users/:id/sessions", get(list_sessions))
+        .route("/users/:id/notify", post(notify_user))
+        .route("/users/:id/erase", post(erase_user))
+        .route("/users/:id/erasure", get(get_erasure_receipt))
+        // Quotas
+        .route("/users/:id/limits", get(get_limits).put(update_limits))
+        .route("/users/:id/boosts", get(list_boosts).post(grant_boost))
+        .route("/users/:id/bypass", put(set_user_bypass))
+        .route("/boosts/:id", delete(revoke_boost))
+        .route("/api-keys/:id/bypass", put(set_api_key_bypass))
+        // Redaction
+        .route("/redaction-rules", get(list_redaction_rules).put(upsert_redaction_rule))
+        .route("/redaction-rules/:id", delete(delete_redaction_rule))
+        // Audit
+        .route("/audit", get(query_audit))
+        .route("/audit/export", get(export_audit))
+        .route("/audit/verify", get(verify_audit))
+        .route("/audit/admin-actions", get(list_admin_actions))
+        // Encryption
+        .route("/users/:id/data-key/rotate", post(rotate_user_data_key))
+        .route("/encryption/rewrap", post(rewrap_data_keys))
+        .route("/encryption/reencrypt", post(reencrypt_messages))
+        // Retention
+        .route("/retention-policies", get(list_retention_policies).put(upsert_retention_policy))
+        .route("/retention-policies/:id", delete(delete_retention_policy))
+        .route("/retention/purge", post(run_retention_purge))
+        .route("/conversations/:id/legal-hold", put(set_legal_hold))
+        // Prompt templates
+        .route("/prompt-templates", get(list_global_templates).post(create_global_template))
+        .route("/prompt-templates/:id", delete(delete_template).patch(update_template))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Pagination {
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
+}
+
+fn default_limit() -> i64 {
+    50
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListUsersQuery {
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+    #[serde(default)]
+    pub offset: i64,
+    #[serde(default)]
+    pub include_inactive: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListBoostsQuery {
+    #[serde(default)]
+    pub include_expired: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BypassRequest {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RedactionRulesQuery {
+    pub org_id: Option<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReencryptQuery {
+    #[serde(default = "default_reencrypt_batch")]
+    pub batch_size: i64,
+}
+
+fn default_reencrypt_batch() -> i64 {
+    500
+}
+
+// The last_seq and last_hash of an earlier verification, kept outside the database
+#[derive(Debug, Deserialize)]
+pub struct VerifyAuditQuery {
+    pub anchor_seq: Option<i64>,
+    pub anchor_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LegalHoldRequest {
+    pub enabled: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct DisconnectRequest {
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotifyRequest {
+    #[serde(default = "default_notify_topic")]
+    pub topic: String,
+    pub data: Value,
+}
+
+fn default_notify_topic() -> String {
+    "system".to_string()
+}
+
+async fn list_users(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ListUsersQuery>,
+) -> ApiResult<Json<Vec<User>>> {
+    let users = state.user_service
+        .list_users(query.limit.clamp(1, 500), query.offset.max(0), query.include_inactive)
+        .await?;
+    
+    Ok(Json(users))
+}
+
+async fn create_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CreateUserRequest>,
+) -> ApiResult<(StatusCode, Json<User>)> {
+    if state.user_service.get_user_by_email(&request.email).await?.is_some() {
+        return Err(ApiError::Conflict(format!("User {} already exists", request.email)));
+    }
+    
+    let mut tx = state.admin_service.begin().await?;
+    let user = state.user_service.create_user(&mut tx, request).await?;
+    
+    state.admin_service.commit_action(
+        tx,
+        &admin.user_id,
+        "user.create",
+        "user",
+        &user.id.to_string(),
+        json!({ "email": user.email, "name": user.name }),
+    ).await?;
+    
+    Ok((StatusCode::CREATED, Json(user)))
+}
+
+async fn get_user(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<User>> {
+    let user = state.user_service.get_user_including_inactive(&user_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("User {}", user_id)))?;
+    
+    Ok(Json(user))
+}
+
+async fn update_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<UpdateUserRequest>,
+) -> ApiResult<Json<User>> {
+    if let Some(email) = &request.email {
+        if state.user_service.email_in_use(email, &user_id).await? {
+            return Err(ApiError::Conflict(format!("User {} already exists", email)));
+        }
+    }
+    
+    let details = json!({
+        "email": request.email,
+        "name": request.name,
+        "metadata": request.metadata,
+    });
+    
+    let mut tx = state.admin_service.begin().await?;
+    let user = state.user_service.update_user(&mut tx, &user_id, request).await?
+        .ok_or_else(|| ApiError::NotFound(format!("User {}", user_id)))?;
+    
+    state.admin_service.commit_action(tx, &admin.user_id, "user.update", "user", &user_id.to_string(), details).await?;
+    
+    Ok(Json(user))
+}
+
+async fn delete_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let mut tx = state.admin_service.begin().await?;
+    if !state.user_service.delete_user(&mut tx, &user_id).await? {
+        return Err(ApiError::NotFound(format!("User {}", user_id)));
+    }
+    state.admin_service.commit_action(tx, &admin.user_id, "user.delete", "user", &user_id.to_string(), json!({})).await?;
+    
+    disconnect_after(&state, &admin.user_id, &user_id, "Account deleted").await;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// For erasure requests that come in through support rather than the app
+async fn erase_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<ErasureReceipt>> {
+    let user = state.user_service.get_user_including_inactive(&user_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("User {}", user_id)))?;
+    
+    let receipt = privacy::erase_user(&state, &user, &admin.user_id).await?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "user.erase",
+        "user",
+        &user_id.to_string(),
+        json!({ "receipt_id": receipt.id, "receipt_hash": receipt.receipt_hash, "verified": receipt.verified }),
+    ).await?;
+    
+    Ok(Json(receipt))
+}
+
+async fn get_erasure_receipt(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<ErasureReceipt>> {
+    let receipt = state.privacy.latest_receipt(&user_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Erasure receipt for user {}", user_id)))?;
+    
+    Ok(Json(receipt))
+}
+
+async fn deactivate_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let mut tx = state.admin_service.begin().await?;
+    if !state.user_service.set_user_active(&mut tx, &user_id, false).await? {
+        return Err(ApiError::NotFound(format!("User {}", user_id)));
+    }
+    state.admin_service.commit_action(tx, &admin.user_id, "user.deactivate", "user", &user_id.to_string(), json!({})).await?;
+    
+    // A deactivated user must not keep streaming on an existing socket
+    disconnect_after(&state, &admin.user_id, &user_id, "Account deactivated").await;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn activate_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let mut tx = state.admin_service.begin().await?;
+    if !state.user_service.set_user_active(&mut tx, &user_id, true).await? {
+        return Err(ApiError::NotFound(format!("User {}", user_id)));
+    }
+    state.admin_service.commit_action(tx, &admin.user_id, "user.activate", "user", &user_id.to_string(), json!({})).await?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Ends the sessions of a user whose account change is already committed and
+// audited; failing to log the disconnect does not fail the request
+async fn disconnect_after(state: &AppState, admin_id: &Uuid, user_id: &Uuid, reason: &str) {
+    let disconnected = state.disconnect_user(&user_id.to_string(), reason).await;
+    let recorded = state.admin_service.record_action(
+        admin_id,
+        "session.disconnect",
+        "user",
+        &user_id.to_string(),
+        json!({ "reason": reason, "disconnected_sessions": disconnected }),
+    ).await;
+    if let Err(e) = recorded {
+        tracing::error!("Failed to record disconnect of user {}: {}", user_id, e);
+    }
+}
+
+async fn disconnect_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    request: Option<Json<DisconnectRequest>>,
+) -> ApiResult<Json<Value>> {
+    let reason = request
+        .and_then(|Json(r)| r.reason)
+        .unwrap_or_else(|| "Disconnected by administrator".to_string());
+    
+    let disconnected = state.disconnect_user(&user_id.to_string(), &reason).await;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "session.disconnect",
+        "user",
+        &user_id.to_string(),
+        json!({ "reason": reason, "disconnected_sessions": disconnected }),
+    ).await?;
+    
+    Ok(Json(json!({ "disconnected_sessions": disconnected })))
+}
+
+// Live sockets of the user across all replicas
+async fn list_sessions(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<Vec<RegisteredSession>>> {
+    let sessions = state.session_registry.sessions_for_user(&user_id.to_string()).await?;
+    Ok(Json(sessions))
+}
+
+async fn notify_user(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<NotifyRequest>,
+) -> ApiResult<Json<Value>> {
+    let delivered = state.control_user(&user_id.to_string(), SessionControl::Push {
+        topic: request.topic.clone(),
+        data: request.data,
+    }).await;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "session.notify",
+        "user",
+        &user_id.to_string(),
+        json!({ "topic": request.topic, "delivered_sessions": delivered }),
+    ).await?;
+    
+    Ok(Json(json!({ "delivered_sessions": delivered })))
+}
+
+async fn get_limits(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<Value>> {
+    let limits = state.admin_service.get_rate_limits(&user_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Rate limits for user {}", user_id)))?;
+    let boost_multiplier = state.admin_service.active_boost_multiplier(&user_id).await?;
+    
+    Ok(Json(json!({
+        "limits": limits,
+        "boost_multiplier": boost_multiplier,
+        "effective_daily_token_limit": (limits.daily_token_limit as f64 * boost_multiplier) as i64,
+        "effective_monthly_token_limit": (limits.monthly_token_limit as f64 * boost_multiplier) as i64,
+    })))
+}
+
+async fn update_limits(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<UpdateRateLimitsRequest>,
+) -> ApiResult<Json<RateLimits>> {
+    let negative = [request.daily_token_limit, request.monthly_token_limit]
+        .iter()
+        .flatten()
+        .any(|v| *v < 0)
+        || request.requests_per_minute.map_or(false, |v| v < 0);
+    if negative {
+        return Err(ApiError::BadRequest("Limits must not be negative".to_string()));
+    }
+    
+    if state.user_service.get_user_including_inactive(&user_id).await?.is_none() {
+        return Err(ApiError::NotFound(format!("User {}", user_id)));
+    }
+    
+    let limits = state.admin_service.update_rate_limits(&user_id, request, &admin.user_id).await?;
+    
+    Ok(Json(limits))
+}
+
+async fn list_boosts(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    Query(query): Query<ListBoostsQuery>,
+) -> ApiResult<Json<Vec<QuotaBoost>>> {
+    let boosts = state.admin_service.list_boosts(&user_id, query.include_expired).await?;
+    
+    Ok(Json(boosts))
+}
+
+async fn grant_boost(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<GrantBoostRequest>,
+) -> ApiResult<(StatusCode, Json<QuotaBoost>)> {
+    // Enforce admin_overrides.temporary_boosts bounds
+    let max_multiplier = state.config.admin_max_boost_multiplier;
+    let max_duration = state.config.admin_max_boost_duration_secs;
+    if !(request.multiplier > 1.0 && request.multiplier <= max_multiplier) {
+        return Err(ApiError::BadRequest(format!(
+            "Boost multiplier must be greater than 1 and at most {}",
+            max_multiplier
+        )));
+    }
+    if request.duration_secs == 0 || request.duration_secs > max_duration {
+        return Err(ApiError::BadRequest(format!(
+            "Boost duration must be between 1 and {} seconds",
+            max_duration
+        )));
+    }
+    
+    if state.user_service.get_user(&user_id).await?.is_none() {
+        return Err(ApiError::NotFound(format!("User {}", user_id)));
+    }
+    
+    let boost = state.admin_service.grant_boost(&user_id, &admin.user_id, request).await?;
+    
+    Ok((StatusCode::CREATED, Json(boost)))
+}
+
+async fn revoke_boost(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(boost_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    state.admin_service.revoke_boost(&boost_id, &admin.user_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Active boost {}", boost_id)))?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_user_bypass(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<BypassRequest>,
+) -> ApiResult<StatusCode> {
+    if state.user_service.get_user_including_inactive(&user_id).await?.is_none() {
+        return Err(ApiError::NotFound(format!("User {}", user_id)));
+    }
+    
+    state.admin_service.set_user_bypass(&user_id, request.enabled, &admin.user_id).await?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn set_api_key_bypass(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(key_id): Path<Uuid>,
+    Json(request): Json<BypassRequest>,
+) -> ApiResult<StatusCode> {
+    if !state.admin_service.set_api_key_bypass(&key_id, request.enabled, &admin.user_id).await? {
+        return Err(ApiError::NotFound(format!("API key {}", key_id)));
+    }
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_redaction_rules(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RedactionRulesQuery>,
+) -> ApiResult<Json<Vec<RedactionRule>>> {
+    let rules = state.policy_service.list_redaction_rules(query.org_id).await?;
+    
+    Ok(Json(rules))
+}
+
+async fn upsert_redaction_rule(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<UpsertRedactionRuleRequest>,
+) -> ApiResult<Json<RedactionRule>> {
+    match &request.pattern {
+        Some(pattern) => {
+            if is_builtin_detector(&request.detector) {
+                return Err(ApiError::BadRequest(format!(
+                    "{} is a built-in detector and cannot be given a custom pattern",
+                    request.detector
+                )));
+            }
+            regex::Regex::new(pattern)
+                .map_err(|e| ApiError::BadRequest(format!("Invalid pattern: {}", e)))?;
+        }
+        None if !is_builtin_detector(&request.detector) => {
+            return Err(ApiError::BadRequest(format!(
+                "Unknown detector {}; custom detectors need a pattern",
+                request.detector
+            )));
+        }
+        None => {}
+    }
+    if let Some(org_id) = &request.org_id {
+        if !state.policy_service.org_exists(org_id).await? {
+            return Err(ApiError::NotFound(format!("Organization {}", org_id)));
+        }
+    }
+    
+    let rule = state.policy_service.upsert_redaction_rule(request).await?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "redaction.upsert",
+        "redaction_rule",
+        &rule.id.to_string(),
+        json!({
+            "org_id": rule.org_id,
+            "detector": rule.detector,
+            "action": rule.action,
+            "pattern": rule.pattern,
+        }),
+    ).await?;
+    
+    Ok(Json(rule))
+}
+
+async fn delete_redaction_rule(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(rule_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let rule = state.policy_service.delete_redaction_rule(&rule_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Redaction rule {}", rule_id)))?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "redaction.delete",
+        "redaction_rule",
+        &rule_id.to_string(),
+        json!({ "org_id": rule.org_id, "detector": rule.detector }),
+    ).await?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn query_audit(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> ApiResult<Json<Vec<AuditRecord>>> {
+    let records = state.audit_service.query(&query).await?;
+    
+    Ok(Json(records))
+}
+
+async fn export_audit(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> ApiResult<Response> {
+    // Exports are themselves audited
+    state.admin_service.record_action(
+        &admin.user_id,
+        "audit.export",
+        "audit_log",
+        "*",
+        json!({ "query": query }),
+    ).await?;
+    
+    let body = Body::from_stream(state.audit_service.export_jsonl(query));
+    
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-ndjson"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"audit-log.jsonl\""),
+        ],
+        body,
+    ).into_response())
+}
+
+async fn verify_audit(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<VerifyAuditQuery>,
+) -> ApiResult<Json<ChainVerification>> {
+    let anchor = match (query.anchor_seq, query.anchor_hash) {
+        (Some(seq), Some(hash)) => Some(ChainAnchor { seq, hash }),
+        (None, None) => None,
+        _ => return Err(ApiError::BadRequest("anchor_seq and anchor_hash go together".to_string())),
+    };
+    let report = state.audit_service.verify_chain(anchor.as_ref()).await?;
+    
+    Ok(Json(report))
+}
+
+async fn list_admin_actions(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+    Query(page): Query<Pagination>,
+) -> ApiResult<Json<Vec<AdminAuditEntry>>> {
+    let entries = state.admin_service
+        .list_actions(page.limit.clamp(1, 500), page.offset.max(0))
+        .await?;
+    
+    Ok(Json(entries))
+}
+
+fn encryption(state: &AppState) -> ApiResult<&Arc<EncryptionService>> {
+    state.encryption_service
+        .as_ref()
+        .ok_or_else(|| ApiError::BadRequest("Encryption at rest is not enabled".to_string()))
+}
+
+async fn rotate_user_data_key(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<Value>> {
+    let key_id = encryption(&state)?.rotate_user_key(&user_id).await?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "data_key.rotate",
+        "user",
+        &user_id.to_string(),
+        json!({ "data_key_id": key_id }),
+    ).await?;
+    
+    Ok(Json(json!({ "data_key_id": key_id })))
+}
+
+async fn rewrap_data_keys(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<RotationReport>> {
+    let rewrapped = encryption(&state)?.rewrap_data_keys().await?;
+    let report = RotationReport { rotated: 0, rewrapped };
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "data_key.rewrap",
+        "encryption",
+        "master_key",
+        json!(report),
+    ).await?;
+    
+    Ok(Json(report))
+}
+
+async fn reencrypt_messages(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ReencryptQuery>,
+) -> ApiResult<Json<ReencryptReport>> {
+    let report = encryption(&state)?
+        .reencrypt_batch(query.batch_size.clamp(1, 5000), &[])
+        .await?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "messages.reencrypt",
+        "encryption",
+        "messages",
+        json!(report),
+    ).await?;
+    
+    Ok(Json(report))
+}
+
+async fn list_retention_policies(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<Vec<RetentionPolicy>>> {
+    let policies = state.retention.list_policies().await?;
+    
+    Ok(Json(policies))
+}
+
+async fn upsert_retention_policy(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<UpsertRetentionPolicyRequest>,
+) -> ApiResult<Json<RetentionPolicy>> {
+    let periods = [
+        ("message_days", request.message_days),
+        ("token_usage_days", request.token_usage_days),
+        ("audit_days", request.audit_days),
+    ];
+    if let Some((field, _)) = periods.iter().find(|(_, days)| days.map_or(false, |days| days <= 0)) {
+        return Err(ApiError::BadRequest(format!("{} must be positive", field)));
+    }
+    if request.restore_window_days.map_or(false, |days| days < 0) {
+        return Err(ApiError::BadRequest("restore_window_days cannot be negative".to_string()));
+    }
+    
+    let policy = state.retention.upsert_policy(&request).await?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "retention.upsert",
+        "retention_policy",
+        &policy.id.to_string(),
+        json!(request),
+    ).await?;
+    
+    Ok(Json(policy))
+}
+
+async fn delete_retention_policy(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(policy_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let policy = state.retention.delete_policy(&policy_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Retention policy {}", policy_id)))?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "retention.delete",
+        "retention_policy",
+        &policy_id.to_string(),
+        json!({ "org_id": policy.org_id }),
+    ).await?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Runs the daily purge now and returns what it removed
+async fn run_retention_purge(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<RetentionReport>> {
+    let report = state.retention.purge().await;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "retention.purge",
+        "retention",
+        "*",
+        json!(report),
+    ).await?;
+    
+    Ok(Json(report))
+}
+
+async fn set_legal_hold(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(conversation_id): Path<Uuid>,
+    Json(request): Json<LegalHoldRequest>,
+) -> ApiResult<Json<LegalHold>> {
+    let hold = state.retention
+        .set_legal_hold(&conversation_id, request.enabled, request.reason.as_deref(), &admin.user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Conversation {}", conversation_id)))?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        if request.enabled { "legal_hold.set" } else { "legal_hold.release" },
+        "conversation",
+        &conversation_id.to_string(),
+        json!({ "reason": request.reason }),
+    ).await?;
+    
+    Ok(Json(hold))
+}
+
+async fn list_global_templates(
+    _admin: AdminUser,
+    State(state): State<Arc<AppState>>,
+) -> ApiResult<Json<Vec<PromptTemplate>>> {
+    Ok(Json(state.templates.list_global().await?))
+}
+
+// Admins only create global templates; personal and org ones belong to users
+async fn create_global_template(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<CreateTemplateRequest>,
+) -> ApiResult<(StatusCode, Json<PromptTemplate>)> {
+    templates::validate_template(Some(&request.name), Some(&request.content))?;
+    if request.scope != TemplateScope::Global {
+        return Err(ApiError::BadRequest("Admins create global templates only".to_string()));
+    }
+    
+    let template = state.templates.create(None, None, &admin.user_id, &request).await?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "template.create",
+        "prompt_template",
+        &template.id.to_string(),
+        json!({ "name": template.name, "version": template.current_version }),
+    ).await?;
+    
+    Ok((StatusCode::CREATED, Json(template)))
+}
+
+// Any template, so admins can also take down org and personal ones
+async fn update_template(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(template_id): Path<Uuid>,
+    Json(request): Json<UpdateTemplateRequest>,
+) -> ApiResult<Json<PromptTemplate>> {
+    templates::validate_template(request.name.as_deref(), request.content.as_deref())?;
+    
+    let template = state.templates
+        .update(&template_id, &request, &admin.user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Template {}", template_id)))?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "template.update",
+        "prompt_template",
+        &template_id.to_string(),
+        json!({ "scope": template.scope, "version": template.current_version }),
+    ).await?;
+    
+    Ok(Json(template))
+}
+
+async fn delete_template(
+    AdminUser(admin): AdminUser,
+    State(state): State<Arc<AppState>>,
+    Path(template_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let template = state.templates.get(&template_id).await?
+        .ok_or_else(|| ApiError::NotFound(format!("Template {}", template_id)))?;
+    state.templates.delete(&template_id).await?;
+    
+    state.admin_service.record_action(
+        &admin.user_id,
+        "template.delete",
+        "prompt_template",
+        &template_id.to_string(),
+        json!({ "scope": template.scope, "owner_id": template.owner_id }),
+    ).await?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
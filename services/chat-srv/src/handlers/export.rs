@@ -0,0 +1,59 @@
+use crate::auth::AuthUser;
+use crate::error::ApiResult;
+use crate::handlers::members::conversation_as;
+use crate::services::members::MemberRole;
+use crate::services::export::{render, ExportDocument, ExportFormat};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default = "default_format")]
+    pub format: ExportFormat,
+}
+
+fn default_format() -> ExportFormat {
+    ExportFormat::Json
+}
+
+pub async fn export_conversation(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<ExportQuery>,
+) -> ApiResult<Response> {
+    let conversation = conversation_as(&state, &user, &id, MemberRole::Viewer).await?;
+    
+    let document = state.export_service.export_conversation(&conversation).await?;
+    
+    attachment(query.format, &document, false, &format!("conversation-{}", conversation.id))
+}
+
+pub async fn export_account(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Query(query): Query<ExportQuery>,
+) -> ApiResult<Response> {
+    let document = state.export_service.export_account(&user.user_id).await?;
+    
+    attachment(query.format, &document, true, "conversations")
+}
+
+fn attachment(format: ExportFormat, document: &ExportDocument, whole_account: bool, name: &str) -> ApiResult<Response> {
+    let body = render(format, document, whole_account)?;
+    let disposition = format!("attachment; filename=\"{}.{}\"", name, format.extension());
+    
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ).into_response())
+}
@@ -0,0 +1,34 @@
+use crate::state::AppState;
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+pub struct HealthResponse {
+    pub status: &'static str,
+    pub version: &'static str,
+    pub active_sessions: usize,
+    pub in_flight_streams: usize,
+}
+
+// Readiness: turns 503 as soon as shutdown starts so the load balancer
+// stops routing new connections here
+pub async fn health_check(State(state): State<Arc<AppState>>) -> (StatusCode, Json<HealthResponse>) {
+    let (status_code, status) = if state.lifecycle.is_draining() {
+        (StatusCode::SERVICE_UNAVAILABLE, "draining")
+    } else {
+        (StatusCode::OK, "ok")
+    };
+    
+    (status_code, Json(HealthResponse {
+        status,
+        version: env!("CARGO_PKG_VERSION"),
+        active_sessions: state.active_sessions.len(),
+        in_flight_streams: state.lifecycle.in_flight(),
+    }))
+}
+
+// Liveness stays green while draining so the process is not killed early
+pub async fn liveness() -> StatusCode {
+    StatusCode::OK
+}
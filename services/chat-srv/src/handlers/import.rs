@@ -0,0 +1,49 @@
+use crate::auth::AuthUser;
+use crate::error::{ApiError, ApiResult};
+use crate::services::import::{parse, ImportJob, ImportSource};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    // Detected from the document when absent
+    #[serde(default)]
+    pub source: Option<ImportSource>,
+}
+
+// Runs in the background; progress is published on the `imports` topic
+pub async fn start_import(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Query(query): Query<ImportQuery>,
+    Json(body): Json<Value>,
+) -> ApiResult<(StatusCode, Json<ImportJob>)> {
+    let (source, conversations) = parse(query.source, &body).map_err(ApiError::BadRequest)?;
+    if conversations.is_empty() {
+        return Err(ApiError::BadRequest("Nothing to import".to_string()));
+    }
+    
+    let job = state.import_service.start(&user.user_id, source, conversations).await?;
+    
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+pub async fn get_import(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<Json<ImportJob>> {
+    state.import_service
+        .get(&id)
+        .await?
+        .filter(|job| job.user_id == user.user_id.to_string())
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound("Import not found".to_string()))
+}
@@ -0,0 +1,238 @@
+use crate::auth::AuthUser;
+use crate::error::{ApiError, ApiResult};
+use crate::models::Conversation;
+use crate::services::members::{Invitation, InviteMemberRequest, Member, MemberRole};
+use crate::services::pubsub::{session_topic, user_topic, TopicEvent};
+use crate::state::{AppState, SessionControl};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateMemberRequest {
+    pub role: MemberRole,
+}
+
+// Loads a conversation for a member with at least `required`. Non-members
+// are told it does not exist; members without the role are forbidden
+pub async fn conversation_as(
+    state: &AppState,
+    user: &AuthUser,
+    conversation_id: &str,
+    required: MemberRole,
+) -> ApiResult<Conversation> {
+    match state.conversation_access(conversation_id, &user.user_id).await? {
+        Some((conversation, role)) if role.allows(required) => Ok(conversation),
+        Some(_) => Err(ApiError::Forbidden),
+        None => Err(ApiError::NotFound("Conversation not found".to_string())),
+    }
+}
+
+pub async fn list_members(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<Json<Vec<Member>>> {
+    conversation_as(&state, &user, &id, MemberRole::Viewer).await?;
+    
+    Ok(Json(state.members.members(&id).await?))
+}
+
+// Invites by user id or email. The invitee becomes a member once they accept
+pub async fn invite_member(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(request): Json<InviteMemberRequest>,
+) -> ApiResult<(StatusCode, Json<Invitation>)> {
+    conversation_as(&state, &user, &id, MemberRole::Owner).await?;
+    
+    if request.role == MemberRole::Owner {
+        return Err(ApiError::BadRequest("Conversations have exactly one owner".to_string()));
+    }
+    let invitee = match (&request.user_id, &request.email) {
+        (Some(user_id), None) => Some(
+            state.user_service
+                .get_user(user_id)
+                .await?
+                .ok_or_else(|| ApiError::NotFound(format!("User {}", user_id)))?,
+        ),
+        (None, Some(email)) if email.contains('@') => state.user_service.get_user_by_email(email).await?,
+        (None, Some(_)) => return Err(ApiError::BadRequest("Invalid email address".to_string())),
+        _ => return Err(ApiError::BadRequest("Exactly one of user_id or email is required".to_string())),
+    };
+    if let Some(invitee) = &invitee {
+        if state.members.role(&id, &invitee.id).await?.is_some() {
+            return Err(ApiError::Conflict("User is already a member".to_string()));
+        }
+    }
+    
+    let invitation = state.members
+        .invite(&id, &user.user_id, invitee.as_ref().map(|u| u.id), request.email.as_deref(), request.role)
+        .await?;
+    
+    if let Some(invitee) = &invitee {
+        state.pubsub.publish(TopicEvent {
+            topic: user_topic(&invitee.id.to_string(), "invitations"),
+            origin: None,
+            data: json!({ "type": "invitationReceived", "invitation": invitation }),
+        }).await;
+    }
+    
+    Ok((StatusCode::CREATED, Json(invitation)))
+}
+
+pub async fn update_member(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path((id, member_id)): Path<(String, Uuid)>,
+    Json(request): Json<UpdateMemberRequest>,
+) -> ApiResult<Json<Value>> {
+    conversation_as(&state, &user, &id, MemberRole::Owner).await?;
+    
+    if request.role == MemberRole::Owner {
+        return Err(ApiError::BadRequest("Conversations have exactly one owner".to_string()));
+    }
+    if !state.members.set_role(&id, &member_id, request.role).await? {
+        return Err(ApiError::NotFound("Member not found".to_string()));
+    }
+    
+    publish_members_changed(&state, &id).await;
+    
+    Ok(Json(json!({ "user_id": member_id, "role": request.role })))
+}
+
+// Owners remove others; any other member may remove themselves to leave
+pub async fn remove_member(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path((id, member_id)): Path<(String, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let required = if member_id == user.user_id { MemberRole::Viewer } else { MemberRole::Owner };
+    conversation_as(&state, &user, &id, required).await?;
+    
+    if !state.members.remove(&id, &member_id).await? {
+        return Err(ApiError::NotFound("Member not found".to_string()));
+    }
+    
+    // Their open sockets stop following the conversation right away
+    state.control_user(&member_id.to_string(), SessionControl::Revoke { topic: session_topic(&id) }).await;
+    state.pubsub.publish(TopicEvent {
+        topic: user_topic(&member_id.to_string(), "sessions"),
+        origin: None,
+        data: json!({ "type": "sessionDeleted", "sessionId": id }),
+    }).await;
+    publish_members_changed(&state, &id).await;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_conversation_invitations(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<Json<Vec<Invitation>>> {
+    conversation_as(&state, &user, &id, MemberRole::Owner).await?;
+    
+    Ok(Json(state.members.pending_for_conversation(&id).await?))
+}
+
+pub async fn list_my_invitations(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> ApiResult<Json<Vec<Invitation>>> {
+    let me = state.user_service
+        .get_user(&user.user_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+    
+    Ok(Json(state.members.pending_for_user(&me).await?))
+}
+
+pub async fn accept_invitation(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(invitation_id): Path<Uuid>,
+) -> ApiResult<Json<Invitation>> {
+    respond(&state, &user, &invitation_id, true).await
+}
+
+pub async fn decline_invitation(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(invitation_id): Path<Uuid>,
+) -> ApiResult<Json<Invitation>> {
+    respond(&state, &user, &invitation_id, false).await
+}
+
+// Owners take back invitations that were not answered yet
+pub async fn revoke_invitation(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(invitation_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let invitation = state.members
+        .get_invitation(&invitation_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Invitation not found".to_string()))?;
+    conversation_as(&state, &user, &invitation.conversation_id.to_string(), MemberRole::Owner).await?;
+    
+    if !state.members.revoke_invitation(&invitation_id).await? {
+        return Err(ApiError::Conflict("Invitation was already answered".to_string()));
+    }
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn respond(state: &AppState, user: &AuthUser, invitation_id: &Uuid, accept: bool) -> ApiResult<Json<Invitation>> {
+    let me = state.user_service
+        .get_user(&user.user_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+    
+    let invitation = state.members
+        .respond(invitation_id, &me, accept)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Invitation not found".to_string()))?;
+    
+    if accept {
+        let conversation_id = invitation.conversation_id.to_string();
+        state.pubsub.publish(TopicEvent {
+            topic: user_topic(&me.id.to_string(), "sessions"),
+            origin: None,
+            data: json!({ "type": "sessionShared", "sessionId": conversation_id, "role": invitation.role }),
+        }).await;
+        publish_members_changed(state, &conversation_id).await;
+    }
+    
+    Ok(Json(invitation))
+}
+
+async fn publish_members_changed(state: &AppState, conversation_id: &str) {
+    let members = match state.members.members(conversation_id).await {
+        Ok(members) => members,
+        Err(e) => {
+            tracing::warn!("Failed to load members of conversation {}: {}", conversation_id, e);
+            return;
+        }
+    };
+    
+    state.pubsub.publish(TopicEvent {
+        topic: session_topic(conversation_id),
+        origin: None,
+        data: json!({
+            "type": "membersChanged",
+            "sessionId": conversation_id,
+            "members": members
+                .iter()
+                .map(|m| json!({ "userId": m.user_id, "name": m.name, "role": m.role }))
+                .collect::<Vec<_>>(),
+        }),
+    }).await;
+}
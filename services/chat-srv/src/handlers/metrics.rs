@@ -0,0 +1,20 @@
+use crate::error::ApiResult;
+use crate::state::AppState;
+use axum::{
+    extract::State,
+    http::header,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use std::sync::Arc;
+
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/metrics", get(metrics_handler))
+}
+
+pub async fn metrics_handler(State(state): State<Arc<AppState>>) -> ApiResult<impl IntoResponse> {
+    let body = state.metrics.encode()?;
+    
+    Ok(([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body))
+}
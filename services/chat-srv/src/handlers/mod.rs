@@ -0,0 +1,11 @@
+pub mod admin;
+pub mod export;
+pub mod health;
+pub mod import;
+pub mod members;
+pub mod metrics;
+pub mod privacy;
+pub mod retention;
+pub mod search;
+pub mod share;
+pub mod templates;
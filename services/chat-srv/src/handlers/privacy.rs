@@ -0,0 +1,114 @@
+use crate::auth::AuthUser;
+use crate::error::{ApiError, ApiResult};
+use crate::services::audit::AuditEvent;
+use crate::services::privacy::{DataExportJob, DataExportStatus, ErasureReceipt};
+use crate::services::user::User;
+use crate::state::AppState;
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct EraseAccountRequest {
+    // Must match the account's email, so an erasure is never a misclick
+    pub confirm_email: String,
+}
+
+// Runs in the background; completion is published on the `exports` topic
+pub async fn start_export(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> ApiResult<(StatusCode, Json<DataExportJob>)> {
+    state.user_service
+        .get_user(&user.user_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+    
+    let job = state.privacy.start_export(&user.user_id).await?;
+    
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: "privacy.export".to_string(),
+        actor_id: Some(user.user_id),
+        api_key_id: user.claims.api_key_id,
+        outcome: "success".to_string(),
+        details: json!({ "job_id": job.id }),
+        ..Default::default()
+    });
+    
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+pub async fn get_export(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<Json<DataExportJob>> {
+    state.privacy
+        .get_export(&user.user_id, &id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| ApiError::NotFound("Export not found".to_string()))
+}
+
+pub async fn download_export(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<Response> {
+    let not_found = || ApiError::NotFound("Export not found".to_string());
+    let job = state.privacy.get_export(&user.user_id, &id).await?.ok_or_else(not_found)?;
+    if job.status != DataExportStatus::Completed {
+        return Err(ApiError::Conflict("Export is not ready".to_string()));
+    }
+    let archive = state.privacy.archive(&user.user_id, &id).await?.ok_or_else(not_found)?;
+    
+    let disposition = format!("attachment; filename=\"data-export-{}.zip\"", job.id);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        archive,
+    ).into_response())
+}
+
+// Erases the caller's own account. The receipt is the last thing they get;
+// afterwards the account cannot sign in
+pub async fn erase_account(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(request): Json<EraseAccountRequest>,
+) -> ApiResult<Json<ErasureReceipt>> {
+    let me = state.user_service
+        .get_user(&user.user_id)
+        .await?
+        .ok_or(ApiError::Unauthorized)?;
+    if !me.email.eq_ignore_ascii_case(request.confirm_email.trim()) {
+        return Err(ApiError::BadRequest("confirm_email does not match the account".to_string()));
+    }
+    
+    Ok(Json(erase_user(&state, &me, &user.user_id).await?))
+}
+
+// Shared with the admin endpoint
+pub async fn erase_user(state: &AppState, user: &User, requested_by: &Uuid) -> ApiResult<ErasureReceipt> {
+    if user.id.to_string() == state.config.system_account_id {
+        return Err(ApiError::BadRequest("The system account cannot be erased".to_string()));
+    }
+    let held = state.privacy.held_conversations(&user.id).await?;
+    if held > 0 {
+        return Err(ApiError::Conflict(format!("{} conversations are under legal hold", held)));
+    }
+    
+    // Before erasing, while the session registry can still find the sockets
+    state.disconnect_user(&user.id.to_string(), "Account erased").await;
+    
+    Ok(state.privacy.erase(user, requested_by).await?)
+}
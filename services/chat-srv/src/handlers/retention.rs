@@ -0,0 +1,84 @@
+use crate::auth::AuthUser;
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::members::conversation_as;
+use crate::services::members::MemberRole;
+use crate::services::pubsub::{user_topic, TopicEvent};
+use crate::services::retention::TrashedConversation;
+use crate::state::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Deleting moves the conversation to the owner's trash; the purge job
+// removes it once the restore window has passed
+pub async fn delete_conversation(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    conversation_as(&state, &user, &id, MemberRole::Owner).await?;
+    
+    move_to_trash(&state, &id, &user.user_id, None).await?;
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn restore_conversation(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<StatusCode> {
+    if !state.retention.restore(&id, &user.user_id).await? {
+        return Err(ApiError::NotFound("No restorable conversation with this id".to_string()));
+    }
+    
+    let restored = json!({ "type": "sessionRestored", "sessionId": id });
+    for member in state.members.member_ids(&id).await? {
+        publish_sessions_event(&state, &member, None, restored.clone()).await;
+    }
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_trash(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> ApiResult<Json<Vec<TrashedConversation>>> {
+    Ok(Json(state.retention.trash(&user.user_id).await?))
+}
+
+// Soft-deletes a conversation the caller owns and tells every member. The
+// owner's event skips `origin`, the socket that asked for the delete
+pub async fn move_to_trash(
+    state: &AppState,
+    conversation_id: &str,
+    deleted_by: &Uuid,
+    origin: Option<String>,
+) -> anyhow::Result<()> {
+    let members = state.members.member_ids(conversation_id).await?;
+    if !state.retention.soft_delete(conversation_id, deleted_by).await? {
+        return Ok(());
+    }
+    
+    let deleted = json!({ "type": "sessionDeleted", "sessionId": conversation_id });
+    let owner = deleted_by.to_string();
+    for member in members {
+        let origin = if member == owner { origin.clone() } else { None };
+        publish_sessions_event(state, &member, origin, deleted.clone()).await;
+    }
+    
+    Ok(())
+}
+
+async fn publish_sessions_event(state: &AppState, user_id: &str, origin: Option<String>, data: Value) {
+    state.pubsub.publish(TopicEvent {
+        topic: user_topic(user_id, "sessions"),
+        origin,
+        data,
+    }).await;
+}
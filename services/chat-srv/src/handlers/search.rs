@@ -0,0 +1,50 @@
+use crate::auth::AuthUser;
+use crate::error::{ApiError, ApiResult};
+use crate::services::search::{SearchMode, SearchQuery, SearchResults};
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchConsentRequest {
+    pub enabled: bool,
+}
+
+pub async fn search(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Query(mut query): Query<SearchQuery>,
+) -> ApiResult<Json<SearchResults>> {
+    if query.q.len() > 1000 {
+        return Err(ApiError::BadRequest("Query is too long".to_string()));
+    }
+    if matches!(query.mode, Some(SearchMode::Semantic | SearchMode::Hybrid)) && !state.search.semantic_enabled() {
+        return Err(ApiError::BadRequest("Semantic search is not enabled".to_string()));
+    }
+    query.limit = query.limit.clamp(1, MAX_LIMIT);
+    
+    Ok(Json(state.search.search(&user.user_id, &query).await?))
+}
+
+// Only meaningful with encryption at rest, where the plaintext search index
+// is opt-in; turning it off drops everything indexed for the user
+pub async fn set_consent(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(request): Json<SearchConsentRequest>,
+) -> ApiResult<Json<Value>> {
+    let Some(encryption) = &state.encryption_service else {
+        return Err(ApiError::BadRequest("Messages are not encrypted; search needs no consent".to_string()));
+    };
+    
+    encryption.set_search_consent(&user.user_id, request.enabled).await?;
+    
+    Ok(Json(json!({ "enabled": request.enabled })))
+}
@@ -0,0 +1,153 @@
+use crate::auth::{self, AuthUser};
+use crate::error::{ApiError, ApiResult};
+use crate::handlers::members::conversation_as;
+use crate::services::members::MemberRole;
+use crate::services::audit::AuditEvent;
+use crate::services::share::{CreateShareRequest, Share, SharedConversation, ShareVisibility};
+use crate::state::AppState;
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Longest a share may be set to live; links without expiry are still allowed
+const MAX_EXPIRY_SECS: i64 = 365 * 24 * 60 * 60;
+
+pub async fn create_share(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(request): Json<CreateShareRequest>,
+) -> ApiResult<(StatusCode, Json<Share>)> {
+    // Sharing reaches beyond the members, so only the owner may do it
+    let conversation = conversation_as(&state, &user, &id, MemberRole::Owner).await?;
+    
+    if let Some(secs) = request.expires_in_secs {
+        if secs <= 0 || secs > MAX_EXPIRY_SECS {
+            return Err(ApiError::BadRequest(format!("expires_in_secs must be between 1 and {}", MAX_EXPIRY_SECS)));
+        }
+    }
+    let org_id = match request.visibility {
+        ShareVisibility::Public => None,
+        ShareVisibility::Org => Some(
+            state.policy_service
+                .user_org(&user.user_id)
+                .await?
+                .ok_or_else(|| ApiError::BadRequest("You are not in an org".to_string()))?,
+        ),
+    };
+    
+    let share = state.share_service
+        .create(&user.user_id, org_id, &conversation, &request)
+        .await?
+        .ok_or_else(|| ApiError::BadRequest("Messages are not a range of the active branch".to_string()))?;
+    
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: "share.create".to_string(),
+        actor_id: Some(user.user_id),
+        api_key_id: user.claims.api_key_id,
+        outcome: "success".to_string(),
+        details: json!({
+            "share_id": share.record.id,
+            "conversation_id": conversation.id,
+            "visibility": share.record.visibility,
+            "messages": share.record.message_ids.len(),
+            "redact": share.record.redact,
+            "expires_at": share.record.expires_at,
+        }),
+        ..Default::default()
+    });
+    
+    Ok((StatusCode::CREATED, Json(share)))
+}
+
+pub async fn list_shares(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> ApiResult<Json<Vec<Share>>> {
+    if Uuid::parse_str(&id).is_err() {
+        return Err(ApiError::NotFound("Conversation not found".to_string()));
+    }
+    
+    Ok(Json(state.share_service.list(&user.user_id, &id).await?))
+}
+
+pub async fn revoke_share(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(share_id): Path<Uuid>,
+) -> ApiResult<Json<Share>> {
+    let share = state.share_service
+        .revoke(&user.user_id, &share_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Share not found".to_string()))?;
+    
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: "share.revoke".to_string(),
+        actor_id: Some(user.user_id),
+        api_key_id: user.claims.api_key_id,
+        outcome: "success".to_string(),
+        details: json!({
+            "share_id": share.record.id,
+            "conversation_id": share.record.conversation_id,
+        }),
+        ..Default::default()
+    });
+    
+    Ok(Json(share))
+}
+
+// Public: needs no token for public shares, and a signed-in member of the
+// owner's org for org shares. Revoked, expired and unknown links all look
+// missing, so the endpoint does not reveal which shares exist
+pub async fn view_share(
+    State(state): State<Arc<AppState>>,
+    viewer: Option<AuthUser>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Path(token): Path<String>,
+) -> ApiResult<Json<SharedConversation>> {
+    let not_found = || ApiError::NotFound("Share not found".to_string());
+    let Some(share) = state.share_service.resolve(&token).await? else {
+        return Err(not_found());
+    };
+    
+    let viewer_id = viewer.as_ref().map(|v| v.user_id);
+    let outcome = if !share.is_active() {
+        "inactive"
+    } else if share.visibility == "org" {
+        match viewer_id {
+            None => "unauthenticated",
+            Some(viewer_id) if state.policy_service.user_org(&viewer_id).await? != share.org_id => "forbidden",
+            Some(_) => "success",
+        }
+    } else {
+        "success"
+    };
+    
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: "share.view".to_string(),
+        actor_id: viewer_id,
+        api_key_id: viewer.as_ref().and_then(|v| v.claims.api_key_id),
+        outcome: outcome.to_string(),
+        client_ip: Some(auth::client_ip(&headers, remote, &state.config.trusted_proxies)),
+        details: json!({
+            "share_id": share.id,
+            "conversation_id": share.conversation_id,
+            "owner_id": share.owner_id,
+        }),
+        ..Default::default()
+    });
+    
+    match outcome {
+        "success" => Ok(Json(state.share_service.view(&share).await?)),
+        "unauthenticated" => Err(ApiError::Unauthorized),
+        _ => Err(not_found()),
+    }
+}
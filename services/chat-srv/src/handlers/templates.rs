@@ -0,0 +1,202 @@
+use crate::auth::AuthUser;
+use crate::error::{ApiError, ApiResult};
+use crate::services::audit::AuditEvent;
+use crate::services::templates::{
+    self, CreateTemplateRequest, PromptTemplate, TemplateScope, TemplateVersion, UpdateTemplateRequest, MAX_TEMPLATE_CHARS,
+};
+use crate::state::AppState;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Deserialize)]
+pub struct RenderTemplateRequest {
+    // The current version when absent
+    #[serde(default)]
+    pub version: Option<i32>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RenderTemplateResponse {
+    pub version: i32,
+    pub prompt: String,
+}
+
+pub async fn list_templates(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+) -> ApiResult<Json<Vec<PromptTemplate>>> {
+    let org_id = state.policy_service.user_org(&user.user_id).await?;
+    
+    Ok(Json(state.templates.list_visible(&user.user_id, org_id).await?))
+}
+
+pub async fn get_template(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<PromptTemplate>> {
+    Ok(Json(visible_template(&state, &user, &id).await?))
+}
+
+pub async fn list_versions(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<Vec<TemplateVersion>>> {
+    visible_template(&state, &user, &id).await?;
+    
+    Ok(Json(state.templates.versions(&id).await?))
+}
+
+// Personal and org templates are custom prompts, so the tier must allow them;
+// global templates are created by admins
+pub async fn create_template(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Json(request): Json<CreateTemplateRequest>,
+) -> ApiResult<(StatusCode, Json<PromptTemplate>)> {
+    validate_template(Some(&request.name), Some(&request.content))?;
+    if !state.templates.access(&user.user_id).await?.custom_prompts {
+        return Err(ApiError::Forbidden);
+    }
+    let org_id = match request.scope {
+        TemplateScope::Personal => None,
+        TemplateScope::Org => Some(
+            state.policy_service
+                .user_org(&user.user_id)
+                .await?
+                .ok_or_else(|| ApiError::BadRequest("You are not in an org".to_string()))?,
+        ),
+        TemplateScope::Global => return Err(ApiError::Forbidden),
+    };
+    
+    let template = state.templates
+        .create(Some(&user.user_id), org_id, &user.user_id, &request)
+        .await?;
+    
+    record(&state, &user, "template.create", &template);
+    
+    Ok((StatusCode::CREATED, Json(template)))
+}
+
+// Only the creator edits a personal or org template
+pub async fn update_template(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(request): Json<UpdateTemplateRequest>,
+) -> ApiResult<Json<PromptTemplate>> {
+    validate_template(request.name.as_deref(), request.content.as_deref())?;
+    owned_template(&state, &user, &id).await?;
+    if !state.templates.access(&user.user_id).await?.custom_prompts {
+        return Err(ApiError::Forbidden);
+    }
+    
+    let template = state.templates
+        .update(&id, &request, &user.user_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Template not found".to_string()))?;
+    
+    record(&state, &user, "template.update", &template);
+    
+    Ok(Json(template))
+}
+
+pub async fn delete_template(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let template = owned_template(&state, &user, &id).await?;
+    
+    state.templates.delete(&id).await?;
+    
+    record(&state, &user, "template.delete", &template);
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Previews what a chat referencing this template would send
+pub async fn render_template(
+    State(state): State<Arc<AppState>>,
+    user: AuthUser,
+    Path(id): Path<Uuid>,
+    Json(request): Json<RenderTemplateRequest>,
+) -> ApiResult<Json<RenderTemplateResponse>> {
+    let template = visible_template(&state, &user, &id).await?;
+    let (version, content) = match request.version {
+        Some(version) if version != template.current_version => {
+            let version = state.templates
+                .version(&id, version)
+                .await?
+                .ok_or_else(|| ApiError::NotFound(format!("Template has no version {}", version)))?;
+            (version.version, version.content)
+        }
+        _ => (template.current_version, template.content),
+    };
+    
+    let prompt = templates::render(&content, &request.variables)
+        .map_err(|missing| ApiError::BadRequest(format!("Missing template variables: {}", missing.join(", "))))?;
+    
+    Ok(Json(RenderTemplateResponse { version, prompt }))
+}
+
+// Shared with the admin endpoints
+pub fn validate_template(name: Option<&str>, content: Option<&str>) -> ApiResult<()> {
+    if name.map_or(false, |name| name.trim().is_empty()) {
+        return Err(ApiError::BadRequest("name cannot be empty".to_string()));
+    }
+    if let Some(content) = content {
+        if content.trim().is_empty() {
+            return Err(ApiError::BadRequest("content cannot be empty".to_string()));
+        }
+        if content.chars().count() > MAX_TEMPLATE_CHARS {
+            return Err(ApiError::BadRequest(format!("content is longer than {} characters", MAX_TEMPLATE_CHARS)));
+        }
+    }
+    
+    Ok(())
+}
+
+async fn visible_template(state: &AppState, user: &AuthUser, id: &Uuid) -> ApiResult<PromptTemplate> {
+    let org_id = state.policy_service.user_org(&user.user_id).await?;
+    
+    state.templates
+        .get_visible(id, &user.user_id, org_id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound("Template not found".to_string()))
+}
+
+async fn owned_template(state: &AppState, user: &AuthUser, id: &Uuid) -> ApiResult<PromptTemplate> {
+    let template = visible_template(state, user, id).await?;
+    if !template.is_custom() || template.owner_id != Some(user.user_id) {
+        return Err(ApiError::Forbidden);
+    }
+    
+    Ok(template)
+}
+
+fn record(state: &AppState, user: &AuthUser, event_type: &str, template: &PromptTemplate) {
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: event_type.to_string(),
+        actor_id: Some(user.user_id),
+        api_key_id: user.claims.api_key_id,
+        outcome: "success".to_string(),
+        details: json!({
+            "template_id": template.id,
+            "scope": template.scope,
+            "version": template.current_version,
+        }),
+        ..Default::default()
+    });
+}
@@ -0,0 +1,122 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{watch, Notify};
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Serving,
+    // Not ready for new work; in-flight streams may finish
+    Draining,
+    // Grace period is over; remaining streams are cut and sockets closed
+    Closing,
+}
+
+// Process-wide shutdown state shared by the HTTP server, sockets and streams
+pub struct Lifecycle {
+    phase: watch::Sender<Phase>,
+    in_flight: AtomicUsize,
+    idle: Notify,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        let (phase, _) = watch::channel(Phase::Serving);
+        Self {
+            phase,
+            in_flight: AtomicUsize::new(0),
+            idle: Notify::new(),
+        }
+    }
+    
+    pub fn phase(&self) -> Phase {
+        *self.phase.borrow()
+    }
+    
+    pub fn is_draining(&self) -> bool {
+        self.phase() != Phase::Serving
+    }
+    
+    pub fn subscribe(&self) -> watch::Receiver<Phase> {
+        self.phase.subscribe()
+    }
+    
+    pub fn start_draining(&self) {
+        self.phase.send_replace(Phase::Draining);
+    }
+    
+    pub fn close(&self) {
+        self.phase.send_replace(Phase::Closing);
+    }
+    
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+    
+    // Held by a streaming task for as long as it has output or usage to flush
+    pub fn track_stream(self: &Arc<Self>) -> InFlightStream {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlightStream { lifecycle: self.clone() }
+    }
+    
+    // Returns true if every stream finished within `timeout`
+    pub async fn wait_for_streams(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        
+        loop {
+            let notified = self.idle.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+            
+            if self.in_flight() == 0 {
+                return true;
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return self.in_flight() == 0;
+            }
+        }
+    }
+}
+
+pub struct InFlightStream {
+    lifecycle: Arc<Lifecycle>,
+}
+
+impl Drop for InFlightStream {
+    fn drop(&mut self) {
+        if self.lifecycle.in_flight.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.lifecycle.idle.notify_waiters();
+        }
+    }
+}
+
+// Resolves on SIGTERM or Ctrl-C after flipping readiness, then waits
+// `readiness_delay` so load balancers stop routing before the listener closes
+pub async fn shutdown_signal(lifecycle: Arc<Lifecycle>, readiness_delay: Duration) {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to install Ctrl-C handler");
+    };
+    
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to install SIGTERM handler")
+            .recv()
+            .await;
+    };
+    
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    
+    info!("Shutdown signal received; draining {} in-flight streams", lifecycle.in_flight());
+    lifecycle.start_draining();
+    tokio::time::sleep(readiness_delay).await;
+}
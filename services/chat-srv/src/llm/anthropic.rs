@@ -1,4 +1,5 @@
 use super::{ChatCompletionRequest, ChatCompletionResponse, ChatMessage, ChatChoice, TokenUsage, LLMClient, LLMError, LLMProvider, Model, ChatStream};
+use crate::telemetry;
 use async_trait::async_trait;
 use eventsource_stream::Eventsource;
 use futures::{Stream, StreamExt};
@@ -23,6 +24,8 @@ struct AnthropicRequest {
     messages: Vec<AnthropicMessage>,
     max_tokens: u32,
     #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream: Option<bool>,
@@ -107,16 +110,19 @@ impl LLMClient for AnthropicClient {
             model: request.model.clone(),
             messages: anthropic_messages,
             max_tokens: request.max_tokens.unwrap_or(4096),
+            system: None,
             temperature: request.temperature,
             stream: Some(false),
         };
         
-        let response = self.client
+        let request = self.client
             .post(format!("{}/v1/messages", self.base_url))
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", ANTHROPIC_API_VERSION)
             .header("content-type", "application/json")
-            .json(&anthropic_request)
+            .json(&anthropic_request);
+        
+        let response = telemetry::inject_trace_headers(request)
             .send()
             .await
             .map_err(|e| LLMError::NetworkError(e.to_string()))?;
@@ -166,24 +172,46 @@ impl LLMClient for AnthropicClient {
         temperature: Option<f32>,
         max_tokens: Option<u32>,
     ) -> Result<ChatStream, LLMError> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.stream_chat(model, messages, temperature, max_tokens).await
+    }
+    
+    async fn stream_chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> Result<ChatStream, LLMError> {
+        // System prompt and summary both go in the top-level `system` field
+        let system: Vec<&str> = messages
+            .iter()
+            .filter(|m| m.role == "system")
+            .map(|m| m.content.as_str())
+            .collect();
+        let system = Some(system.join("\n\n")).filter(|s| !s.is_empty());
+        
         let anthropic_request = AnthropicRequest {
             model: model.to_string(),
-            messages: vec![AnthropicMessage {
-                role: "user".to_string(),
-                content: prompt.to_string(),
-            }],
+            messages: self.convert_messages(messages),
             max_tokens: max_tokens.unwrap_or(4096),
+            system,
             temperature,
             stream: Some(true),
         };
         
-        let response = self.client
+        let request = self.client
             .post(format!("{}/v1/messages", self.base_url))
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", ANTHROPIC_API_VERSION)
             .header("content-type", "application/json")
             .header("accept", "text/event-stream")
-            .json(&anthropic_request)
+            .json(&anthropic_request);
+        
+        let response = telemetry::inject_trace_headers(request)
             .send()
             .await
             .map_err(|e| LLMError::NetworkError(e.to_string()))?;
@@ -223,6 +251,11 @@ impl LLMClient for AnthropicClient {
         Ok(Box::pin(stream))
     }
     
+    async fn embed(&self, model: &str, _inputs: Vec<String>) -> Result<Vec<Vec<f32>>, LLMError> {
+        // Anthropic has no embeddings endpoint
+        Err(LLMError::ModelNotFound(model.to_string()))
+    }
+    
     async fn list_models(&self) -> Result<Vec<Model>, LLMError> {
         // Anthropic doesn't have a models endpoint, so we return a static list
         Ok(vec![
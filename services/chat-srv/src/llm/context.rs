@@ -0,0 +1,386 @@
+use super::ChatMessage;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tiktoken_rs::CoreBPE;
+
+// Claude's tokenizer is not published; cl100k counts within a few percent of it
+static CL100K: Lazy<CoreBPE> = Lazy::new(|| tiktoken_rs::cl100k_base().expect("cl100k ships with tiktoken-rs"));
+
+// Each chat message costs a few tokens of framing on top of its content,
+// and the reply is primed with a few more
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+const REPLY_PRIMING_TOKENS: u32 = 3;
+
+pub fn count_tokens(text: &str) -> u32 {
+    CL100K.encode_ordinary(text).len() as u32
+}
+
+// Built-in windows; MODELS can set others (see Config::context_window)
+pub fn context_window(model: &str) -> u32 {
+    match model {
+        "o3" => 200_000,
+        m if m.starts_with("claude") => 200_000,
+        m if m.starts_with("gpt-4o") || m.starts_with("gpt-4-turbo") => 128_000,
+        m if m.starts_with("gpt-4-0125") || m.starts_with("gpt-4-1106") => 128_000,
+        m if m.starts_with("gpt-4-32k") => 32_768,
+        m if m.starts_with("gpt-4") => 8_192,
+        m if m.starts_with("gpt-3.5-turbo") => 16_385,
+        _ => 4_096,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    // As many of the latest messages as fit
+    DropOldest,
+    // The rolling summary in place of the messages it covers, then the latest that fit
+    SummarizeOlder,
+    // At most a fixed number of the latest messages, fewer if they do not fit
+    SlidingWindow,
+}
+
+impl ContextStrategy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "drop_oldest" => Some(ContextStrategy::DropOldest),
+            "summarize_older" => Some(ContextStrategy::SummarizeOlder),
+            "sliding_window" => Some(ContextStrategy::SlidingWindow),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryMessage {
+    pub id: String,
+    pub role: String,
+    pub content: String,
+}
+
+// Summary of the oldest `covers` messages of the history
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub text: String,
+    pub covers: usize,
+}
+
+// What went into the prompt, reported back with the response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextReport {
+    pub strategy: ContextStrategy,
+    // Earlier messages sent verbatim, oldest first; pinned ones included
+    pub included: Vec<String>,
+    pub pinned: Vec<String>,
+    // Messages represented by the summary instead
+    pub summarized: usize,
+    pub dropped: usize,
+    pub prompt_tokens: u32,
+    pub budget: u32,
+}
+
+pub struct BuiltContext {
+    pub messages: Vec<ChatMessage>,
+    pub report: ContextReport,
+}
+
+// Assembles the prompt for one turn within the model's context window:
+// system prompt, pinned messages, the running summary and recent turns,
+// then the new message. Anything that does not fit is left out, oldest first
+pub struct ContextBuilder {
+    model: String,
+    window: u32,
+    reserved: u32,
+    budget: u32,
+    strategy: ContextStrategy,
+    window_messages: usize,
+    system_prompt: Option<String>,
+    pinned: HashSet<String>,
+    summary: Option<Summary>,
+    history: Vec<HistoryMessage>,
+}
+
+impl ContextBuilder {
+    // `reserved` tokens of the `window` are kept free for the reply
+    pub fn new(model: &str, window: u32, strategy: ContextStrategy, reserved: u32) -> Self {
+        let reserved = reserved.min(window);
+        Self {
+            model: model.to_string(),
+            window,
+            reserved,
+            budget: window - reserved,
+            strategy,
+            window_messages: usize::MAX,
+            system_prompt: None,
+            pinned: HashSet::new(),
+            summary: None,
+            history: Vec::new(),
+        }
+    }
+    
+    pub fn system_prompt(mut self, prompt: Option<String>) -> Self {
+        self.system_prompt = prompt.filter(|p| !p.trim().is_empty());
+        self
+    }
+    
+    pub fn window_messages(mut self, messages: usize) -> Self {
+        self.window_messages = messages;
+        self
+    }
+    
+    pub fn pin(mut self, message_ids: impl IntoIterator<Item = String>) -> Self {
+        self.pinned.extend(message_ids);
+        self
+    }
+    
+    pub fn summary(mut self, summary: Option<Summary>) -> Self {
+        self.summary = summary;
+        self
+    }
+    
+    // Earlier messages of the conversation, oldest first
+    pub fn history(mut self, history: Vec<HistoryMessage>) -> Self {
+        self.history = history;
+        self
+    }
+    
+    pub fn build(self, message: &str) -> Result<BuiltContext, String> {
+        let cost = |content: &str| count_tokens(content) + MESSAGE_OVERHEAD_TOKENS;
+        let history = &self.history;
+        
+        // Blame the reply size, not the message, when it alone fills the window
+        if self.reserved == self.window {
+            return Err(format!(
+                "max_tokens of {} leaves no room for the prompt in the {}-token context window of {}",
+                self.reserved, self.window, self.model,
+            ));
+        }
+        
+        // The system prompt and the new message always go in
+        let mut used = REPLY_PRIMING_TOKENS + cost(message);
+        if let Some(prompt) = &self.system_prompt {
+            used += cost(prompt);
+        }
+        if used > self.budget {
+            return Err(format!(
+                "Message is too long for {}: {} tokens with {} available after reserving {} for the reply",
+                self.model, used, self.budget, self.reserved,
+            ));
+        }
+        
+        let mut verbatim = vec![false; history.len()];
+        let mut pinned = Vec::new();
+        for (i, entry) in history.iter().enumerate() {
+            if !self.pinned.contains(&entry.id) {
+                continue;
+            }
+            let needed = cost(&entry.content);
+            if used + needed <= self.budget {
+                used += needed;
+                verbatim[i] = true;
+                pinned.push(entry.id.clone());
+            }
+        }
+        
+        let summary_text = match (&self.summary, self.strategy) {
+            (Some(summary), ContextStrategy::SummarizeOlder) => {
+                let text = format!("Summary of the earlier conversation:\n{}", summary.text);
+                let needed = cost(&text);
+                if used + needed <= self.budget {
+                    used += needed;
+                    Some((text, summary.covers.min(history.len())))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        };
+        let summarized = summary_text.as_ref().map(|(_, covers)| *covers).unwrap_or(0);
+        
+        // Walk back from the latest message until the budget runs out, so
+        // what is kept stays contiguous
+        let floor = match self.strategy {
+            ContextStrategy::SlidingWindow => history.len().saturating_sub(self.window_messages),
+            _ => summarized,
+        };
+        for i in (floor..history.len()).rev() {
+            if verbatim[i] {
+                continue;
+            }
+            let needed = cost(&history[i].content);
+            if used + needed > self.budget {
+                break;
+            }
+            used += needed;
+            verbatim[i] = true;
+        }
+        
+        // Some providers want the user to speak first after the system prompt
+        if let Some(first) = (0..history.len()).find(|&i| verbatim[i]) {
+            if history[first].role == "assistant" && !self.pinned.contains(&history[first].id) {
+                used -= cost(&history[first].content);
+                verbatim[first] = false;
+            }
+        }
+        
+        let mut messages = Vec::new();
+        if let Some(prompt) = self.system_prompt {
+            messages.push(ChatMessage { role: "system".to_string(), content: prompt });
+        }
+        if let Some((text, _)) = summary_text {
+            messages.push(ChatMessage { role: "system".to_string(), content: text });
+        }
+        let mut included = Vec::new();
+        let mut dropped = 0;
+        for (i, entry) in history.iter().enumerate() {
+            if verbatim[i] {
+                included.push(entry.id.clone());
+                messages.push(ChatMessage { role: entry.role.clone(), content: entry.content.clone() });
+            } else if i >= summarized {
+                dropped += 1;
+            }
+        }
+        messages.push(ChatMessage { role: "user".to_string(), content: message.to_string() });
+        
+        Ok(BuiltContext {
+            messages,
+            report: ContextReport {
+                strategy: self.strategy,
+                included,
+                pinned,
+                summarized,
+                dropped,
+                prompt_tokens: used,
+                budget: self.budget,
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn message(id: &str, role: &str, content: &str) -> HistoryMessage {
+        HistoryMessage { id: id.to_string(), role: role.to_string(), content: content.to_string() }
+    }
+    
+    // Alternating user/assistant turns of roughly `words` tokens each
+    fn history(turns: usize, words: usize) -> Vec<HistoryMessage> {
+        (0..turns)
+            .map(|i| {
+                let role = if i % 2 == 0 { "user" } else { "assistant" };
+                message(&format!("m{}", i), role, &"word ".repeat(words))
+            })
+            .collect()
+    }
+    
+    #[test]
+    fn keeps_everything_that_fits() {
+        let built = ContextBuilder::new("gpt-4o", 128_000, ContextStrategy::DropOldest, 1_000)
+            .system_prompt(Some("Be brief".to_string()))
+            .history(history(4, 10))
+            .build("hello")
+            .unwrap();
+        
+        assert_eq!(built.messages.len(), 6);
+        assert_eq!(built.messages[0].role, "system");
+        assert_eq!(built.messages[5].content, "hello");
+        assert_eq!(built.report.included, vec!["m0", "m1", "m2", "m3"]);
+        assert_eq!(built.report.dropped, 0);
+        assert_eq!(built.report.budget, 127_000);
+        assert!(built.report.prompt_tokens <= built.report.budget);
+    }
+    
+    #[test]
+    fn drops_oldest_beyond_the_budget() {
+        let built = ContextBuilder::new("m", 400, ContextStrategy::DropOldest, 100)
+            .history(history(10, 100))
+            .build("hello")
+            .unwrap();
+        
+        assert_eq!(built.report.included, vec!["m8", "m9"]);
+        assert_eq!(built.report.dropped, 8);
+        assert!(built.report.prompt_tokens <= 300);
+    }
+    
+    #[test]
+    fn pinned_messages_survive_the_budget() {
+        let built = ContextBuilder::new("m", 400, ContextStrategy::DropOldest, 100)
+            .history(history(10, 100))
+            .pin(vec!["m0".to_string()])
+            .build("hello")
+            .unwrap();
+        
+        assert_eq!(built.report.pinned, vec!["m0"]);
+        assert_eq!(built.report.included, vec!["m0", "m9"]);
+        assert_eq!(built.messages[0].role, "user");
+        assert!(built.report.prompt_tokens <= 300);
+    }
+    
+    #[test]
+    fn summary_replaces_the_messages_it_covers() {
+        let summary = Summary { text: "They talked about words".to_string(), covers: 6 };
+        let built = ContextBuilder::new("m", 128_000, ContextStrategy::SummarizeOlder, 1_000)
+            .history(history(8, 10))
+            .summary(Some(summary.clone()))
+            .build("hello")
+            .unwrap();
+        
+        assert_eq!(built.report.summarized, 6);
+        assert_eq!(built.report.included, vec!["m6", "m7"]);
+        assert!(built.messages[0].content.contains("They talked about words"));
+        
+        // Other strategies ignore the summary
+        let built = ContextBuilder::new("m", 128_000, ContextStrategy::DropOldest, 1_000)
+            .history(history(8, 10))
+            .summary(Some(summary))
+            .build("hello")
+            .unwrap();
+        assert_eq!(built.report.summarized, 0);
+        assert_eq!(built.report.included.len(), 8);
+    }
+    
+    #[test]
+    fn sliding_window_limits_the_message_count() {
+        let built = ContextBuilder::new("m", 128_000, ContextStrategy::SlidingWindow, 1_000)
+            .history(history(10, 10))
+            .window_messages(4)
+            .build("hello")
+            .unwrap();
+        
+        assert_eq!(built.report.included, vec!["m6", "m7", "m8", "m9"]);
+        assert_eq!(built.report.dropped, 6);
+    }
+    
+    #[test]
+    fn leading_assistant_message_is_dropped() {
+        let built = ContextBuilder::new("m", 128_000, ContextStrategy::SlidingWindow, 1_000)
+            .history(history(10, 10))
+            .window_messages(3)
+            .build("hello")
+            .unwrap();
+        
+        assert_eq!(built.report.included, vec!["m8", "m9"]);
+        assert_eq!(built.messages[0].role, "user");
+    }
+    
+    #[test]
+    fn message_over_the_budget_is_rejected() {
+        let err = ContextBuilder::new("m", 200, ContextStrategy::DropOldest, 100)
+            .build(&"word ".repeat(150))
+            .err()
+            .unwrap();
+        assert!(err.starts_with("Message is too long for m"));
+    }
+    
+    #[test]
+    fn reserve_larger_than_the_window_is_reported() {
+        let err = ContextBuilder::new("m", 4_096, ContextStrategy::DropOldest, 10_000)
+            .build("hello")
+            .err()
+            .unwrap();
+        assert!(err.starts_with("max_tokens of 4096 leaves no room"), "{}", err);
+    }
+}
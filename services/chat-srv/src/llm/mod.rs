@@ -1,4 +1,5 @@
 pub mod anthropic;
+pub mod context;
 pub mod openai;
 
 pub use anthropic::AnthropicClient;
@@ -15,6 +16,15 @@ pub enum LLMProvider {
     Anthropic,
 }
 
+impl LLMProvider {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LLMProvider::OpenAI => "openai",
+            LLMProvider::Anthropic => "anthropic",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: String,
@@ -88,6 +98,21 @@ pub enum LLMError {
     InternalError(String),
 }
 
+impl LLMError {
+    // Stable label for metrics and logs
+    pub fn kind(&self) -> &'static str {
+        match self {
+            LLMError::ApiError(_) => "api_error",
+            LLMError::NetworkError(_) => "network_error",
+            LLMError::RateLimitExceeded => "rate_limit_exceeded",
+            LLMError::InvalidRequest(_) => "invalid_request",
+            LLMError::ModelNotFound(_) => "model_not_found",
+            LLMError::AuthenticationFailed => "authentication_failed",
+            LLMError::InternalError(_) => "internal_error",
+        }
+    }
+}
+
 #[async_trait]
 pub trait LLMClient: Send + Sync {
     async fn chat_completion(&self, request: ChatCompletionRequest) -> Result<ChatCompletionResponse, LLMError>;
@@ -100,5 +125,18 @@ pub trait LLMClient: Send + Sync {
         max_tokens: Option<u32>,
     ) -> Result<ChatStream, LLMError>;
     
+    // Multi-turn variant of `stream_completion`; system messages carry the
+    // system prompt and any conversation summary
+    async fn stream_chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+    ) -> Result<ChatStream, LLMError>;
+    
+    // One vector per input, in input order
+    async fn embed(&self, model: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, LLMError>;
+    
     async fn list_models(&self) -> Result<Vec<Model>, LLMError>;
 }
\ No newline at end of file
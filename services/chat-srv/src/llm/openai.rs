@@ -1,4 +1,5 @@
 use super::{ChatCompletionRequest, ChatCompletionResponse, ChatMessage, ChatChoice, TokenUsage, LLMClient, LLMError, LLMProvider, Model, ChatStream, StreamResult};
+use crate::telemetry;
 use async_openai::{
     Client,
     config::OpenAIConfig,
@@ -6,6 +7,8 @@ use async_openai::{
         CreateChatCompletionRequest,
         CreateChatCompletionResponse,
         CreateChatCompletionStreamResponse,
+        CreateEmbeddingRequestArgs,
+        CreateEmbeddingResponse,
         ChatCompletionRequestMessage,
         ChatCompletionRequestUserMessage,
         ChatCompletionRequestAssistantMessage,
@@ -14,33 +17,72 @@ use async_openai::{
     },
 };
 use async_trait::async_trait;
+use eventsource_stream::Eventsource;
 use futures::{Stream, StreamExt};
+use reqwest::StatusCode;
+use serde::Serialize;
 use std::pin::Pin;
 use tracing::{error, debug};
 
+const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
+
+// Chat and embedding calls are sent with reqwest, using async-openai's
+// request and response types, so the trace context reaches OpenAI like it
+// does every other upstream. async-openai has no per-request headers.
 pub struct OpenAIClient {
     client: Client<OpenAIConfig>,
+    http: reqwest::Client,
+    api_key: String,
+    api_base: String,
     org_id: Option<String>,
 }
 
 impl OpenAIClient {
     pub fn new(api_key: String, org_id: Option<String>, base_url: Option<String>) -> Self {
-        let mut config = OpenAIConfig::new().with_api_key(api_key);
+        let mut config = OpenAIConfig::new().with_api_key(api_key.clone());
         
         if let Some(org) = &org_id {
             config = config.with_org_id(org);
         }
         
-        if let Some(url) = base_url {
+        if let Some(url) = &base_url {
             config = config.with_api_base(url);
         }
         
         Self {
             client: Client::with_config(config),
+            http: reqwest::Client::new(),
+            api_key,
+            api_base: base_url.unwrap_or_else(|| OPENAI_API_BASE.to_string()),
             org_id,
         }
     }
     
+    async fn post<T: Serialize>(&self, path: &str, body: &T) -> Result<reqwest::Response, LLMError> {
+        let mut request = self.http
+            .post(format!("{}{}", self.api_base.trim_end_matches('/'), path))
+            .bearer_auth(&self.api_key)
+            .json(body);
+        if let Some(org) = &self.org_id {
+            request = request.header("OpenAI-Organization", org);
+        }
+        
+        let response = telemetry::inject_trace_headers(request)
+            .send()
+            .await
+            .map_err(|e| LLMError::NetworkError(e.to_string()))?;
+        
+        match response.status() {
+            status if status.is_success() => Ok(response),
+            StatusCode::TOO_MANY_REQUESTS => Err(LLMError::RateLimitExceeded),
+            StatusCode::UNAUTHORIZED => Err(LLMError::AuthenticationFailed),
+            status => {
+                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                Err(LLMError::ApiError(format!("API error ({}): {}", status, error_text)))
+            }
+        }
+    }
+    
     fn convert_messages(&self, messages: Vec<ChatMessage>) -> Vec<ChatCompletionRequestMessage> {
         messages.into_iter().map(|msg| {
             match msg.role.as_str() {
@@ -87,7 +129,14 @@ impl LLMClient for OpenAIClient {
             ..Default::default()
         };
         
-        match self.client.chat().create(openai_request).await {
+        let response = self.post("/chat/completions", &openai_request).await;
+        let response = match response {
+            Ok(response) => response.json::<CreateChatCompletionResponse>().await
+                .map_err(|e| LLMError::ApiError(format!("Failed to parse response: {}", e))),
+            Err(e) => Err(e),
+        };
+        
+        match response {
             Ok(response) => {
                 Ok(ChatCompletionResponse {
                     id: response.id,
@@ -109,7 +158,7 @@ impl LLMClient for OpenAIClient {
             }
             Err(e) => {
                 error!("OpenAI API error: {}", e);
-                Err(LLMError::ApiError(e.to_string()))
+                Err(e)
             }
         }
     }
@@ -120,26 +169,38 @@ impl LLMClient for OpenAIClient {
         prompt: &str,
         temperature: Option<f32>,
         max_tokens: Option<u32>,
+    ) -> Result<ChatStream, LLMError> {
+        let messages = vec![ChatMessage {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+        }];
+        self.stream_chat(model, messages, temperature, max_tokens).await
+    }
+    
+    async fn stream_chat(
+        &self,
+        model: &str,
+        messages: Vec<ChatMessage>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
     ) -> Result<ChatStream, LLMError> {
         let request = CreateChatCompletionRequest {
             model: model.to_string(),
-            messages: vec![
-                ChatCompletionRequestMessage::User(ChatCompletionRequestUserMessage {
-                    content: async_openai::types::ChatCompletionRequestUserMessageContent::Text(prompt.to_string()),
-                    name: None,
-                })
-            ],
+            messages: self.convert_messages(messages),
             temperature,
             max_tokens,
             stream: Some(true),
             ..Default::default()
         };
         
-        let stream = self.client.chat().create_stream(request).await
-            .map_err(|e| LLMError::ApiError(e.to_string()))?;
+        let response = self.post("/chat/completions", &request).await?;
         
-        let mapped_stream = stream.map(|result| match result {
-            Ok(response) => {
+        // The stream ends with a `[DONE]` event, which carries no content
+        let mapped_stream = response.bytes_stream().eventsource().map(|result| match result {
+            Ok(event) if event.data == "[DONE]" => Ok(String::new()),
+            Ok(event) => {
+                let response: CreateChatCompletionStreamResponse = serde_json::from_str(&event.data)
+                    .map_err(|e| LLMError::ApiError(format!("Failed to parse stream event: {}", e)))?;
                 if let Some(choice) = response.choices.first() {
                     Ok(choice.delta.content.clone().unwrap_or_default())
                 } else {
@@ -155,6 +216,33 @@ impl LLMClient for OpenAIClient {
         Ok(Box::pin(mapped_stream))
     }
     
+    async fn embed(&self, model: &str, inputs: Vec<String>) -> Result<Vec<Vec<f32>>, LLMError> {
+        let request = CreateEmbeddingRequestArgs::default()
+            .model(model)
+            .input(inputs)
+            .build()
+            .map_err(|e| LLMError::InvalidRequest(e.to_string()))?;
+        
+        let response = self.post("/embeddings", &request).await;
+        let response = match response {
+            Ok(response) => response.json::<CreateEmbeddingResponse>().await
+                .map_err(|e| LLMError::ApiError(format!("Failed to parse response: {}", e))),
+            Err(e) => Err(e),
+        };
+        
+        match response {
+            Ok(response) => {
+                let mut data = response.data;
+                data.sort_by_key(|embedding| embedding.index);
+                Ok(data.into_iter().map(|embedding| embedding.embedding).collect())
+            }
+            Err(e) => {
+                error!("OpenAI embeddings error: {}", e);
+                Err(e)
+            }
+        }
+    }
+    
     async fn list_models(&self) -> Result<Vec<Model>, LLMError> {
         match self.client.models().list().await {
             Ok(response) => {
@@ -1,70 +1,161 @@
 use anyhow::Result;
 use axum::{
-    extract::{State, WebSocketUpgrade},
-    response::IntoResponse,
-    routing::{get, post},
+    extract::{ConnectInfo, State, WebSocketUpgrade},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tower_http::{
     compression::CompressionLayer,
     cors::{Any, CorsLayer},
     limit::RequestBodyLimitLayer,
     trace::TraceLayer,
 };
-use tracing::{info, Level};
+use tracing::{info, warn, Level};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod auth;
 mod config;
+mod crypto;
+mod error;
+mod guardrails;
 mod handlers;
+mod lifecycle;
 mod llm;
+mod metrics;
 mod models;
+mod redaction;
 mod services;
 mod state;
+mod telemetry;
+mod tiers;
 mod websocket;
 
 use crate::config::Config;
 use crate::state::AppState;
 
+const STREAM_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    // Initialize tracing
-    init_tracing()?;
-
     // Load configuration
     let config = Config::from_env()?;
+    
+    // Initialize tracing
+    init_tracing(&config)?;
     info!("Starting Chat Service v{}", env!("CARGO_PKG_VERSION"));
-
+    
     // Initialize application state
     let state = Arc::new(AppState::new(config.clone()).await?);
-
+    
+    // Background jobs
+    services::retention::spawn_purge_job(state.retention.clone());
+    services::pubsub::spawn_listener(state.pubsub.clone(), redis::Client::open(config.redis_url.as_str())?);
+    services::session_registry::spawn_registry_jobs(state.session_registry.clone(), config.session_heartbeat_secs);
+    services::session_registry::spawn_control_listener(
+        state.session_registry.clone(),
+        redis::Client::open(config.redis_url.as_str())?,
+    );
+    services::search::spawn_indexer(state.search.clone(), config.search_index_interval_secs, config.search_index_batch_size);
+    if let Some(encryption) = &state.encryption_service {
+        services::encryption::spawn_key_rotation_job(
+            encryption.clone(),
+            config.data_key_rotation_days,
+            config.reencrypt_batch_size,
+        );
+    }
+    
     // Build router
-    let app = Router::new()
-        // Health & Metrics
+    let mut app = Router::new()
+        // Health
         .route("/health", get(handlers::health::health_check))
-        .route("/metrics", get(handlers::metrics::metrics_handler))
+        .route("/health/live", get(handlers::health::liveness))
         // Chat endpoints
         .route("/api/v1/chat/completions", post(handlers::chat::chat_completion))
         .route("/api/v1/chat/stream", post(handlers::chat::chat_stream))
         .route("/api/v1/chat/ws", get(websocket_handler))
+        // Path the Flutter bridge connects to
+        .route("/ws/chat", get(websocket_handler))
         // Conversation management
         .route("/api/v1/conversations", get(handlers::conversations::list_conversations))
         .route("/api/v1/conversations", post(handlers::conversations::create_conversation))
         .route("/api/v1/conversations/:id", get(handlers::conversations::get_conversation))
-        .route("/api/v1/conversations/:id", delete(handlers::conversations::delete_conversation))
+        .route("/api/v1/conversations/:id", delete(handlers::retention::delete_conversation))
+        .route("/api/v1/conversations/:id/restore", post(handlers::retention::restore_conversation))
         .route("/api/v1/conversations/:id/messages", get(handlers::conversations::get_messages))
+        .route("/api/v1/conversations/:id/export", get(handlers::export::export_conversation))
+        .route("/api/v1/trash", get(handlers::retention::list_trash))
+        // Share links
+        .route("/api/v1/conversations/:id/shares", get(handlers::share::list_shares))
+        .route("/api/v1/conversations/:id/shares", post(handlers::share::create_share))
+        .route("/api/v1/shares/:id", delete(handlers::share::revoke_share))
+        .route("/api/v1/shared/:token", get(handlers::share::view_share))
+        // Members and invitations
+        .route("/api/v1/conversations/:id/members", get(handlers::members::list_members))
+        .route("/api/v1/conversations/:id/members", post(handlers::members::invite_member))
+        .route("/api/v1/conversations/:id/members/:user_id", put(handlers::members::update_member))
+        .route("/api/v1/conversations/:id/members/:user_id", delete(handlers::members::remove_member))
+        .route("/api/v1/conversations/:id/invitations", get(handlers::members::list_conversation_invitations))
+        .route("/api/v1/invitations", get(handlers::members::list_my_invitations))
+        .route("/api/v1/invitations/:id", delete(handlers::members::revoke_invitation))
+        .route("/api/v1/invitations/:id/accept", post(handlers::members::accept_invitation))
+        .route("/api/v1/invitations/:id/decline", post(handlers::members::decline_invitation))
+        // Export and import
+        .route("/api/v1/export", get(handlers::export::export_account))
+        .route("/api/v1/import", post(handlers::import::start_import))
+        .route("/api/v1/import/:id", get(handlers::import::get_import))
+        // Personal data
+        .route("/api/v1/me/export", post(handlers::privacy::start_export))
+        .route("/api/v1/me/export/:id", get(handlers::privacy::get_export))
+        .route("/api/v1/me/export/:id/archive", get(handlers::privacy::download_export))
+        .route("/api/v1/me/erasure", post(handlers::privacy::erase_account))
+        // Prompt templates
+        .route("/api/v1/prompt-templates", get(handlers::templates::list_templates))
+        .route("/api/v1/prompt-templates", post(handlers::templates::create_template))
+        .route("/api/v1/prompt-templates/:id", get(handlers::templates::get_template))
+        .route("/api/v1/prompt-templates/:id", patch(handlers::templates::update_template))
+        .route("/api/v1/prompt-templates/:id", delete(handlers::templates::delete_template))
+        .route("/api/v1/prompt-templates/:id/versions", get(handlers::templates::list_versions))
+        .route("/api/v1/prompt-templates/:id/render", post(handlers::templates::render_template))
         // Model management
         .route("/api/v1/models", get(handlers::models::list_models))
         .route("/api/v1/models/:id/status", get(handlers::models::model_status))
         // Token usage
         .route("/api/v1/usage", get(handlers::usage::get_usage))
         .route("/api/v1/usage/limits", get(handlers::usage::get_limits))
+        // Search
+        .route("/api/v1/search", get(handlers::search::search))
+        .route("/api/v1/search/consent", put(handlers::search::set_consent))
+        // Administration
+        .nest("/admin/v1", handlers::admin::router());
+    
+    // Metrics share the main listener unless a separate port is configured
+    if config.enable_metrics {
+        if config.metrics_port == config.port {
+            app = app.merge(handlers::metrics::router());
+        } else {
+            let metrics_app = handlers::metrics::router().with_state(state.clone());
+            let metrics_addr = SocketAddr::from(([0, 0, 0, 0], config.metrics_port));
+            let metrics_listener = tokio::net::TcpListener::bind(metrics_addr).await?;
+            info!("Metrics listening on {}", metrics_addr);
+            tokio::spawn(async move {
+                if let Err(e) = axum::serve(metrics_listener, metrics_app).await {
+                    tracing::error!("Metrics server failed: {}", e);
+                }
+            });
+        }
+    }
+    
+    let app = app
         // State
-        .with_state(state)
+        .with_state(state.clone())
         // Middleware
         .layer(CompressionLayer::new())
-        .layer(TraceLayer::new_for_http())
+        .layer(TraceLayer::new_for_http().make_span_with(telemetry::make_http_span))
         .layer(RequestBodyLimitLayer::new(10 * 1024 * 1024)) // 10MB limit
         .layer(
             CorsLayer::new()
@@ -72,30 +163,72 @@ async fn main() -> Result<()> {
                 .allow_methods(Any)
                 .allow_headers(Any),
         );
-
+    
     // Start server
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
     info!("Chat Service listening on {}", addr);
     
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
-
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(lifecycle::shutdown_signal(
+            state.lifecycle.clone(),
+            Duration::from_secs(config.shutdown_readiness_delay_secs),
+        ))
+        .await?;
+    
+    // Let in-flight streams finish, then cut the rest and give them a moment
+    // to persist partial output and usage
+    let lifecycle = &state.lifecycle;
+    if !lifecycle.wait_for_streams(Duration::from_secs(config.shutdown_grace_secs)).await {
+        warn!("Interrupting {} streams still running after grace period", lifecycle.in_flight());
+    }
+    lifecycle.close();
+    if !lifecycle.wait_for_streams(STREAM_FLUSH_TIMEOUT).await {
+        warn!("{} streams did not flush before exit", lifecycle.in_flight());
+    }
+    info!("Chat Service stopped");
+    
+    telemetry::shutdown();
+    
     Ok(())
 }
 
 async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
-) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| websocket::handle_socket(socket, state))
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Response {
+    // New sockets go to another instance while this one drains
+    if state.lifecycle.is_draining() {
+        return (StatusCode::SERVICE_UNAVAILABLE, "Server is shutting down").into_response();
+    }
+    
+    let client_ip = auth::client_ip(&headers, remote, &state.config.trusted_proxies);
+    let token = auth::bearer_token(&headers);
+    let trace_parent = telemetry::context_from_headers(&headers);
+    // Bridge clients ask for their subprotocol; everyone else gets plain JSON
+    ws.protocols([websocket::BRIDGE_SUBPROTOCOL])
+        .on_upgrade(move |socket| websocket::handle_socket(socket, state, Some(client_ip), token, trace_parent))
+        .into_response()
 }
 
-fn init_tracing() -> Result<()> {
+fn init_tracing(config: &Config) -> Result<()> {
     let env_filter = tracing_subscriber::EnvFilter::try_from_default_env()
         .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
-
+    
+    // Export spans over OTLP only when tracing is enabled and a collector is configured
+    telemetry::init_propagator();
+    let otel_layer = match &config.otlp_endpoint {
+        Some(endpoint) if config.enable_tracing => {
+            Some(tracing_opentelemetry::layer().with_tracer(telemetry::init_tracer(endpoint)?))
+        }
+        _ => None,
+    };
+    
     tracing_subscriber::registry()
         .with(env_filter)
+        .with(otel_layer)
         .with(
             tracing_subscriber::fmt::layer()
                 .with_target(false)
@@ -104,6 +237,6 @@ fn init_tracing() -> Result<()> {
                 .json(),
         )
         .init();
-
+    
     Ok(())
 }
\ No newline at end of file
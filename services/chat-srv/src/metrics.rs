@@ -0,0 +1,157 @@
+use anyhow::Result;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::collections::HashSet;
+
+// Time to first token is usually sub-second; total latency runs to minutes for long answers
+const TTFT_BUCKETS: &[f64] = &[0.1, 0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 3.0, 5.0, 10.0];
+const LATENCY_BUCKETS: &[f64] = &[0.5, 1.0, 2.5, 5.0, 10.0, 20.0, 30.0, 60.0, 120.0, 300.0];
+
+pub struct Metrics {
+    registry: Registry,
+    pub llm_requests: IntCounterVec,
+    pub llm_time_to_first_token: HistogramVec,
+    pub llm_latency: HistogramVec,
+    pub llm_tokens: IntCounterVec,
+    pub llm_upstream_errors: IntCounterVec,
+    pub stream_cancellations: IntCounterVec,
+    pub quota_rejections: IntCounterVec,
+    pub active_sessions: IntGauge,
+    pub ws_outbound_queued_bytes: IntGauge,
+    pub ws_outbound_limit_bytes: IntGauge,
+    pub ws_outbound_coalesced: IntCounter,
+    pub ws_outbound_dropped: IntCounter,
+    pub ws_disconnects: IntCounterVec,
+    // Model labels are client-supplied; only these are kept as they are
+    known_models: HashSet<String>,
+}
+
+impl Metrics {
+    pub fn new(known_models: &[String]) -> Result<Self> {
+        let registry = Registry::new_custom(Some("chat_srv".to_string()), None)?;
+        
+        let llm_requests = IntCounterVec::new(
+            Opts::new("llm_requests_total", "LLM requests by provider, model and outcome"),
+            &["provider", "model", "status"],
+        )?;
+        let llm_time_to_first_token = HistogramVec::new(
+            HistogramOpts::new("llm_time_to_first_token_seconds", "Time from request to first streamed token")
+                .buckets(TTFT_BUCKETS.to_vec()),
+            &["provider", "model"],
+        )?;
+        let llm_latency = HistogramVec::new(
+            HistogramOpts::new("llm_request_duration_seconds", "Total LLM request duration")
+                .buckets(LATENCY_BUCKETS.to_vec()),
+            &["provider", "model", "status"],
+        )?;
+        let llm_tokens = IntCounterVec::new(
+            Opts::new("llm_tokens_total", "Tokens sent to and received from LLM providers"),
+            &["provider", "model", "direction"],
+        )?;
+        let llm_upstream_errors = IntCounterVec::new(
+            Opts::new("llm_upstream_errors_total", "Upstream LLM errors by kind"),
+            &["provider", "model", "kind"],
+        )?;
+        let stream_cancellations = IntCounterVec::new(
+            Opts::new("stream_cancellations_total", "Streams ended early by the client"),
+            &["provider", "model"],
+        )?;
+        let quota_rejections = IntCounterVec::new(
+            Opts::new("quota_rejections_total", "Requests rejected for exceeding token quota"),
+            &["provider", "model"],
+        )?;
+        let active_sessions = IntGauge::new("websocket_active_sessions", "Authenticated WebSocket sessions")?;
+        let ws_outbound_queued_bytes = IntGauge::new(
+            "websocket_outbound_queued_bytes",
+            "Bytes queued for WebSocket clients, across all sessions",
+        )?;
+        let ws_outbound_limit_bytes = IntGauge::new(
+            "websocket_outbound_limit_bytes",
+            "Per-session cap on queued outbound bytes",
+        )?;
+        let ws_outbound_coalesced = IntCounter::new(
+            "websocket_outbound_coalesced_total",
+            "Chunks merged into a queued chunk for a slow client",
+        )?;
+        let ws_outbound_dropped = IntCounter::new(
+            "websocket_outbound_dropped_total",
+            "Live events dropped for a slow client",
+        )?;
+        let ws_disconnects = IntCounterVec::new(
+            Opts::new("websocket_server_disconnects_total", "Sessions closed by the server, by reason"),
+            &["reason"],
+        )?;
+        
+        registry.register(Box::new(llm_requests.clone()))?;
+        registry.register(Box::new(llm_time_to_first_token.clone()))?;
+        registry.register(Box::new(llm_latency.clone()))?;
+        registry.register(Box::new(llm_tokens.clone()))?;
+        registry.register(Box::new(llm_upstream_errors.clone()))?;
+        registry.register(Box::new(stream_cancellations.clone()))?;
+        registry.register(Box::new(quota_rejections.clone()))?;
+        registry.register(Box::new(active_sessions.clone()))?;
+        registry.register(Box::new(ws_outbound_queued_bytes.clone()))?;
+        registry.register(Box::new(ws_outbound_limit_bytes.clone()))?;
+        registry.register(Box::new(ws_outbound_coalesced.clone()))?;
+        registry.register(Box::new(ws_outbound_dropped.clone()))?;
+        registry.register(Box::new(ws_disconnects.clone()))?;
+        
+        Ok(Self {
+            registry,
+            llm_requests,
+            llm_time_to_first_token,
+            llm_latency,
+            llm_tokens,
+            llm_upstream_errors,
+            stream_cancellations,
+            quota_rejections,
+            active_sessions,
+            ws_outbound_queued_bytes,
+            ws_outbound_limit_bytes,
+            ws_outbound_coalesced,
+            ws_outbound_dropped,
+            ws_disconnects,
+            known_models: known_models.iter().cloned().collect(),
+        })
+    }
+    
+    // Bounds label cardinality: unknown models share one series
+    pub fn model_label<'a>(&self, model: &'a str) -> &'a str {
+        if self.known_models.contains(model) {
+            model
+        } else {
+            "other"
+        }
+    }
+    
+    pub fn record_request(&self, provider: &str, model: &str, status: &str, duration_secs: f64) {
+        self.llm_requests.with_label_values(&[provider, model, status]).inc();
+        self.llm_latency.with_label_values(&[provider, model, status]).observe(duration_secs);
+    }
+    
+    pub fn record_tokens(&self, provider: &str, model: &str, prompt_tokens: u32, completion_tokens: u32) {
+        self.llm_tokens.with_label_values(&[provider, model, "in"]).inc_by(prompt_tokens as u64);
+        self.llm_tokens.with_label_values(&[provider, model, "out"]).inc_by(completion_tokens as u64);
+    }
+    
+    pub fn encode(&self) -> Result<String> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8(buffer)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn unknown_models_share_the_other_label() {
+        let metrics = Metrics::new(&["gpt-4o".to_string()]).unwrap();
+        
+        assert_eq!(metrics.model_label("gpt-4o"), "gpt-4o");
+        assert_eq!(metrics.model_label("gpt-4o-8f3a1c"), "other");
+        assert_eq!(metrics.model_label(""), "other");
+    }
+}
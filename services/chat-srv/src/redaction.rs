@@ -0,0 +1,402 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+// Longest placeholder we ever emit; bounds how much streamed text is held back
+const MAX_PLACEHOLDER_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RedactionAction {
+    Block,
+    Mask,
+    Allow,
+}
+
+impl RedactionAction {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "block" => Some(Self::Block),
+            "mask" => Some(Self::Mask),
+            "allow" => Some(Self::Allow),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Detector {
+    pub name: String,
+    pub label: String,
+    pub regex: Regex,
+}
+
+impl Detector {
+    pub fn new(name: &str, label: &str, pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            name: name.to_string(),
+            label: label.to_string(),
+            regex: Regex::new(pattern)?,
+        })
+    }
+}
+
+// Ordered by priority: when matches overlap, the earlier detector wins
+static BUILTIN_DETECTORS: Lazy<Vec<Detector>> = Lazy::new(|| {
+    [
+        ("private_key", "PRIVATE_KEY", r"-----BEGIN [A-Z ]*PRIVATE KEY-----[\s\S]*?-----END [A-Z ]*PRIVATE KEY-----"),
+        ("anthropic_api_key", "API_KEY", r"\bsk-ant-[A-Za-z0-9_\-]{20,}"),
+        ("openai_api_key", "API_KEY", r"\bsk-(?:proj-)?[A-Za-z0-9_\-]{20,}"),
+        ("github_token", "API_KEY", r"\bgh[pousr]_[A-Za-z0-9]{36,}\b"),
+        ("aws_access_key_id", "AWS_KEY", r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b"),
+        ("aws_secret_access_key", "AWS_SECRET", r#"(?i)aws_?secret_?access_?key["']?\s*[:=]\s*["']?[A-Za-z0-9/+=]{40}"#),
+        ("jwt", "JWT", r"\beyJ[A-Za-z0-9_\-]+\.eyJ[A-Za-z0-9_\-]+\.[A-Za-z0-9_\-]+"),
+        ("email", "EMAIL", r"\b[A-Za-z0-9._%+\-]+@[A-Za-z0-9.\-]+\.[A-Za-z]{2,}\b"),
+    ]
+    .iter()
+    .map(|(name, label, pattern)| Detector::new(name, label, pattern).expect("builtin detector pattern"))
+    .collect()
+});
+
+static PLACEHOLDER: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\[\[REDACTED_[A-Z_]+_\d+\]\]").expect("placeholder pattern")
+});
+
+pub fn builtin_detector_names() -> Vec<&'static str> {
+    BUILTIN_DETECTORS.iter().map(|d| d.name.as_str()).collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct RedactionPolicy {
+    pub default_action: RedactionAction,
+    pub actions: HashMap<String, RedactionAction>,
+    pub custom_detectors: Vec<Detector>,
+}
+
+impl RedactionPolicy {
+    pub fn new(default_action: RedactionAction) -> Self {
+        Self {
+            default_action,
+            actions: HashMap::new(),
+            custom_detectors: Vec::new(),
+        }
+    }
+    
+    pub fn action_for(&self, detector: &str) -> RedactionAction {
+        self.actions.get(detector).copied().unwrap_or(self.default_action)
+    }
+    
+    // Same policy with every block action turned into mask, for text that
+    // is shown rather than sent upstream
+    pub fn masking_blocked(&self) -> Self {
+        let mask = |action: RedactionAction| match action {
+            RedactionAction::Block => RedactionAction::Mask,
+            other => other,
+        };
+        
+        Self {
+            default_action: mask(self.default_action),
+            actions: self.actions.iter().map(|(name, action)| (name.clone(), mask(*action))).collect(),
+            custom_detectors: self.custom_detectors.clone(),
+        }
+    }
+    
+    fn detectors(&self) -> impl Iterator<Item = &Detector> {
+        BUILTIN_DETECTORS.iter().chain(self.custom_detectors.iter())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RedactionFinding {
+    pub detector: String,
+    pub action: RedactionAction,
+    pub count: usize,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Prompt blocked: contains {detector}")]
+pub struct RedactionBlocked {
+    pub detector: String,
+    pub findings: Vec<RedactionFinding>,
+}
+
+// Maps placeholders back to the original values for the lifetime of one request
+#[derive(Debug, Clone, Default)]
+pub struct RedactionVault {
+    originals: HashMap<String, String>,
+}
+
+impl RedactionVault {
+    pub fn is_empty(&self) -> bool {
+        self.originals.is_empty()
+    }
+    
+    pub fn restore(&self, text: &str) -> String {
+        if self.originals.is_empty() {
+            return text.to_string();
+        }
+        
+        PLACEHOLDER
+            .replace_all(text, |caps: &regex::Captures| {
+                self.originals
+                    .get(&caps[0])
+                    .cloned()
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .into_owned()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Redacted {
+    pub texts: Vec<String>,
+    pub vault: RedactionVault,
+    pub findings: Vec<RedactionFinding>,
+}
+
+impl Redacted {
+    // What was actually done to the texts; allowed findings leave them as they were
+    pub fn outcome(&self) -> &'static str {
+        if self.findings.iter().any(|f| f.action == RedactionAction::Mask) {
+            "masked"
+        } else {
+            "allowed"
+        }
+    }
+}
+
+// Redacts several texts that go upstream together, such as a conversation's
+// history, so a value gets the same placeholder wherever it appears
+pub fn redact(texts: &[&str], policy: &RedactionPolicy) -> Result<Redacted, RedactionBlocked> {
+    // Collect non-overlapping matches per text, giving priority to earlier detectors
+    let matches: Vec<Vec<(usize, usize, &Detector)>> = texts
+        .iter()
+        .map(|text| {
+            let mut matches: Vec<(usize, usize, &Detector)> = Vec::new();
+            for detector in policy.detectors() {
+                for m in detector.regex.find_iter(text) {
+                    let overlaps = matches.iter().any(|(start, end, _)| m.start() < *end && *start < m.end());
+                    if !overlaps {
+                        matches.push((m.start(), m.end(), detector));
+                    }
+                }
+            }
+            matches.sort_by_key(|(start, _, _)| *start);
+            matches
+        })
+        .collect();
+    
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for (_, _, detector) in matches.iter().flatten() {
+        *counts.entry(detector.name.as_str()).or_default() += 1;
+    }
+    let findings: Vec<RedactionFinding> = counts
+        .iter()
+        .map(|(name, count)| RedactionFinding {
+            detector: name.to_string(),
+            action: policy.action_for(name),
+            count: *count,
+        })
+        .collect();
+    
+    if let Some(blocked) = findings.iter().find(|f| f.action == RedactionAction::Block) {
+        return Err(RedactionBlocked {
+            detector: blocked.detector.clone(),
+            findings: findings.clone(),
+        });
+    }
+    
+    let mut outputs = Vec::with_capacity(texts.len());
+    let mut vault = RedactionVault::default();
+    let mut by_value: HashMap<&str, String> = HashMap::new();
+    let mut per_label: HashMap<&str, usize> = HashMap::new();
+    
+    for (text, matches) in texts.iter().zip(matches) {
+        let mut output = String::with_capacity(text.len());
+        let mut cursor = 0;
+        
+        for (start, end, detector) in matches {
+            if policy.action_for(&detector.name) != RedactionAction::Mask {
+                continue;
+            }
+            
+            let value = &text[start..end];
+            // Repeated values share a placeholder so the model can still reason about them
+            let placeholder = by_value
+                .entry(value)
+                .or_insert_with(|| {
+                    let n = per_label.entry(detector.label.as_str()).or_default();
+                    *n += 1;
+                    format!("[[REDACTED_{}_{}]]", detector.label, n)
+                })
+                .clone();
+            
+            output.push_str(&text[cursor..start]);
+            output.push_str(&placeholder);
+            vault.originals.insert(placeholder, value.to_string());
+            cursor = end;
+        }
+        output.push_str(&text[cursor..]);
+        outputs.push(output);
+    }
+    
+    Ok(Redacted {
+        texts: outputs,
+        vault,
+        findings,
+    })
+}
+
+// Restores placeholders in a streamed response. A placeholder can be split
+// across chunks, so a possible partial placeholder is held back until the
+// next chunk (or the end of the stream) resolves it.
+#[derive(Debug, Default)]
+pub struct StreamRestorer {
+    vault: RedactionVault,
+    pending: String,
+}
+
+impl StreamRestorer {
+    pub fn new(vault: RedactionVault) -> Self {
+        Self {
+            vault,
+            pending: String::new(),
+        }
+    }
+    
+    pub fn push(&mut self, chunk: &str) -> String {
+        if self.vault.is_empty() {
+            return chunk.to_string();
+        }
+        
+        self.pending.push_str(chunk);
+        let hold_from = partial_placeholder_start(&self.pending);
+        let ready: String = self.pending.drain(..hold_from).collect();
+        
+        self.vault.restore(&ready)
+    }
+    
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.vault.restore(&rest)
+    }
+}
+
+fn partial_placeholder_start(text: &str) -> usize {
+    let Some(open) = text.rfind('[') else {
+        return text.len();
+    };
+    // Step back over a "[[" opener
+    let open = if open > 0 && text.as_bytes()[open - 1] == b'[' { open - 1 } else { open };
+    let tail = &text[open..];
+    
+    let could_be_placeholder = tail.len() < MAX_PLACEHOLDER_LEN
+        && !tail.contains("]]")
+        && tail.get(..tail.len().min(11)).map_or(false, |prefix| "[[REDACTED_".starts_with(prefix))
+        && tail.chars().skip(2).all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_' || c == ']');
+    
+    if could_be_placeholder { open } else { text.len() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn masking() -> RedactionPolicy {
+        RedactionPolicy::new(RedactionAction::Mask)
+    }
+    
+    fn stream(restorer: &mut StreamRestorer, chunks: &[&str]) -> String {
+        let mut out: String = chunks.iter().map(|chunk| restorer.push(chunk)).collect();
+        out.push_str(&restorer.finish());
+        out
+    }
+    
+    #[test]
+    fn repeated_values_share_a_placeholder_across_texts() {
+        let texts = ["mail a@example.com", "again a@example.com or b@example.com"];
+        let redacted = redact(&texts, &masking()).unwrap();
+        
+        assert_eq!(redacted.texts[0], "mail [[REDACTED_EMAIL_1]]");
+        assert_eq!(redacted.texts[1], "again [[REDACTED_EMAIL_1]] or [[REDACTED_EMAIL_2]]");
+        assert_eq!(redacted.findings.len(), 1);
+        assert_eq!(redacted.findings[0].count, 3);
+        assert_eq!(redacted.outcome(), "masked");
+        assert_eq!(redacted.vault.restore(&redacted.texts[1]), texts[1]);
+    }
+    
+    #[test]
+    fn allowed_findings_leave_text_unchanged() {
+        let mut policy = masking();
+        policy.actions.insert("email".to_string(), RedactionAction::Allow);
+        let redacted = redact(&["mail a@example.com"], &policy).unwrap();
+        
+        assert_eq!(redacted.texts[0], "mail a@example.com");
+        assert_eq!(redacted.findings[0].action, RedactionAction::Allow);
+        assert!(redacted.vault.is_empty());
+        assert_eq!(redacted.outcome(), "allowed");
+    }
+    
+    #[test]
+    fn block_action_rejects_the_texts() {
+        let mut policy = masking();
+        policy.actions.insert("github_token".to_string(), RedactionAction::Block);
+        let token = format!("ghp_{}", "a".repeat(36));
+        
+        let blocked = redact(&[token.as_str()], &policy).unwrap_err();
+        assert_eq!(blocked.detector, "github_token");
+    }
+    
+    #[test]
+    fn earlier_detectors_win_overlapping_matches() {
+        let key = format!("sk-ant-{}", "x".repeat(24));
+        let redacted = redact(&[key.as_str()], &masking()).unwrap();
+        
+        assert_eq!(redacted.texts[0], "[[REDACTED_API_KEY_1]]");
+        assert_eq!(redacted.findings[0].detector, "anthropic_api_key");
+    }
+    
+    #[test]
+    fn restores_placeholders_split_across_chunks() {
+        let redacted = redact(&["a@example.com"], &masking()).unwrap();
+        let mut restorer = StreamRestorer::new(redacted.vault);
+        
+        let out = stream(&mut restorer, &["Write to [", "[REDAC", "TED_EMAIL", "_1]", "] today"]);
+        assert_eq!(out, "Write to a@example.com today");
+    }
+    
+    #[test]
+    fn holds_back_only_a_possible_placeholder() {
+        let redacted = redact(&["a@example.com"], &masking()).unwrap();
+        let mut restorer = StreamRestorer::new(redacted.vault);
+        
+        assert_eq!(restorer.push("see [docs] and [[REDACTED_EM"), "see [docs] and ");
+        assert_eq!(restorer.push("AIL_1]]!"), "a@example.com!");
+    }
+    
+    #[test]
+    fn unknown_placeholders_and_unfinished_tails_pass_through() {
+        let redacted = redact(&["a@example.com"], &masking()).unwrap();
+        let mut restorer = StreamRestorer::new(redacted.vault);
+        
+        let out = stream(&mut restorer, &["[[REDACTED_EMAIL_9]] then [[REDACTED_EM"]);
+        assert_eq!(out, "[[REDACTED_EMAIL_9]] then [[REDACTED_EM");
+    }
+    
+    #[test]
+    fn empty_vault_streams_unchanged() {
+        let mut restorer = StreamRestorer::new(RedactionVault::default());
+        
+        assert_eq!(restorer.push("[[REDACTED_"), "[[REDACTED_");
+        assert_eq!(restorer.finish(), "");
+    }
+    
+    #[test]
+    fn builtin_names_follow_the_detector_table() {
+        let names = builtin_detector_names();
+        assert_eq!(names.len(), BUILTIN_DETECTORS.len());
+        assert_eq!(names.first(), Some(&"private_key"));
+        assert!(names.contains(&"email"));
+    }
+}
@@ -0,0 +1,494 @@
+use super::audit::{AuditEvent, AuditService};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use redis::{aio::ConnectionManager, AsyncCommands};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RateLimits {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub daily_token_limit: i64,
+    pub monthly_token_limit: i64,
+    pub requests_per_minute: i32,
+    pub quota_bypass: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateRateLimitsRequest {
+    pub daily_token_limit: Option<i64>,
+    pub monthly_token_limit: Option<i64>,
+    pub requests_per_minute: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct QuotaBoost {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub multiplier: f64,
+    pub reason: Option<String>,
+    pub granted_by: Option<Uuid>,
+    pub starts_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GrantBoostRequest {
+    pub multiplier: f64,
+    pub duration_secs: u64,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AdminAuditEntry {
+    pub id: Uuid,
+    pub actor_id: Uuid,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+pub struct AdminService {
+    db: PgPool,
+    redis: ConnectionManager,
+    audit: Arc<AuditService>,
+}
+
+impl AdminService {
+    pub fn new(db: PgPool, redis: ConnectionManager, audit: Arc<AuditService>) -> Self {
+        Self { db, redis, audit }
+    }
+    
+    pub async fn get_rate_limits(&self, user_id: &Uuid) -> Result<Option<RateLimits>> {
+        let limits = sqlx::query_as::<_, RateLimits>(
+            r#"
+            SELECT * FROM rate_limits
+            WHERE user_id = $1
+            ORDER BY created_at
+            LIMIT 1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(limits)
+    }
+    
+    // The admin audit row is written in the same transaction as each change,
+    // so a change is logged exactly when it commits
+    pub async fn update_rate_limits(&self, user_id: &Uuid, request: UpdateRateLimitsRequest, actor_id: &Uuid) -> Result<RateLimits> {
+        // Users created before rate limits were seeded may not have a row yet
+        self.ensure_rate_limits(user_id).await?;
+        
+        let mut tx = self.db.begin().await?;
+        let before = sqlx::query_as::<_, RateLimits>("SELECT * FROM rate_limits WHERE user_id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+        let limits = sqlx::query_as::<_, RateLimits>(
+            r#"
+            UPDATE rate_limits
+            SET daily_token_limit = COALESCE($2, daily_token_limit),
+                monthly_token_limit = COALESCE($3, monthly_token_limit),
+                requests_per_minute = COALESCE($4, requests_per_minute)
+            WHERE user_id = $1
+            RETURNING *
+            "#
+        )
+        .bind(user_id)
+        .bind(request.daily_token_limit)
+        .bind(request.monthly_token_limit)
+        .bind(request.requests_per_minute)
+        .fetch_one(&mut *tx)
+        .await?;
+        
+        let details = serde_json::json!({ "before": before, "after": limits });
+        log_action(&mut tx, actor_id, "limits.update", "user", &user_id.to_string(), &details).await?;
+        tx.commit().await?;
+        self.chain_action(actor_id, "limits.update", "user", &user_id.to_string(), details).await;
+        
+        Ok(limits)
+    }
+    
+    pub async fn grant_boost(&self, user_id: &Uuid, granted_by: &Uuid, request: GrantBoostRequest) -> Result<QuotaBoost> {
+        let expires_at = Utc::now() + chrono::Duration::seconds(request.duration_secs as i64);
+        
+        let mut tx = self.db.begin().await?;
+        let boost = sqlx::query_as::<_, QuotaBoost>(
+            r#"
+            INSERT INTO quota_boosts (user_id, multiplier, reason, granted_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING *
+            "#
+        )
+        .bind(user_id)
+        .bind(request.multiplier)
+        .bind(request.reason)
+        .bind(granted_by)
+        .bind(expires_at)
+        .fetch_one(&mut *tx)
+        .await?;
+        
+        let details = serde_json::json!({
+            "boost_id": boost.id,
+            "multiplier": boost.multiplier,
+            "expires_at": boost.expires_at,
+            "reason": boost.reason,
+        });
+        log_action(&mut tx, granted_by, "boost.grant", "user", &user_id.to_string(), &details).await?;
+        tx.commit().await?;
+        self.chain_action(granted_by, "boost.grant", "user", &user_id.to_string(), details).await;
+        
+        // The boost is granted either way; a stale cache entry lapses with its TTL
+        if let Err(e) = self.sync_boost_cache(user_id).await {
+            tracing::error!("Failed to cache quota boost of user {}: {}", user_id, e);
+        }
+        
+        Ok(boost)
+    }
+    
+    pub async fn list_boosts(&self, user_id: &Uuid, include_expired: bool) -> Result<Vec<QuotaBoost>> {
+        let boosts = sqlx::query_as::<_, QuotaBoost>(
+            r#"
+            SELECT * FROM quota_boosts
+            WHERE user_id = $1
+              AND ($2 OR (revoked_at IS NULL AND expires_at > NOW()))
+            ORDER BY created_at DESC
+            "#
+        )
+        .bind(user_id)
+        .bind(include_expired)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(boosts)
+    }
+    
+    pub async fn revoke_boost(&self, boost_id: &Uuid, revoked_by: &Uuid) -> Result<Option<QuotaBoost>> {
+        let mut tx = self.db.begin().await?;
+        let boost = sqlx::query_as::<_, QuotaBoost>(
+            r#"
+            UPDATE quota_boosts
+            SET revoked_at = NOW()
+            WHERE id = $1 AND revoked_at IS NULL
+            RETURNING *
+            "#
+        )
+        .bind(boost_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(boost) = boost else {
+            return Ok(None);
+        };
+        
+        let details = serde_json::json!({ "boost_id": boost.id });
+        log_action(&mut tx, revoked_by, "boost.revoke", "user", &boost.user_id.to_string(), &details).await?;
+        tx.commit().await?;
+        self.chain_action(revoked_by, "boost.revoke", "user", &boost.user_id.to_string(), details).await;
+        
+        if let Err(e) = self.sync_boost_cache(&boost.user_id).await {
+            tracing::error!("Failed to clear cached quota boost of user {}: {}", boost.user_id, e);
+        }
+        
+        Ok(Some(boost))
+    }
+    
+    pub async fn active_boost_multiplier(&self, user_id: &Uuid) -> Result<f64> {
+        let multiplier = sqlx::query_scalar::<_, Option<f64>>(
+            r#"
+            SELECT MAX(multiplier) FROM quota_boosts
+            WHERE user_id = $1
+              AND revoked_at IS NULL
+              AND starts_at <= NOW()
+              AND expires_at > NOW()
+            "#
+        )
+        .bind(user_id)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(multiplier.unwrap_or(1.0))
+    }
+    
+    // The boost the quota check applies: the cached one, which also carries
+    // boosts granted through the token meter, else the database's
+    pub async fn effective_boost(&self, user_id: &Uuid) -> Result<f64> {
+        let mut conn = self.redis.clone();
+        let cached: Option<f64> = conn.get(boost_key(user_id)).await?;
+        match cached {
+            Some(multiplier) => Ok(multiplier),
+            None => self.active_boost_multiplier(user_id).await,
+        }
+    }
+    
+    // Whether today's and this month's usage fit the user's limits scaled by
+    // `multiplier`. None when the user has no limits row, so the token meter
+    // decides with its defaults
+    pub async fn within_boosted_limits(&self, user_id: &Uuid, multiplier: f64) -> Result<Option<bool>> {
+        let usage = sqlx::query_as::<_, (i64, i64, i64, i64)>(
+            r#"
+            SELECT r.daily_token_limit, r.monthly_token_limit,
+                   (SELECT COALESCE(SUM(total_tokens), 0) FROM token_usage
+                    WHERE user_id = $1 AND created_at >= date_trunc('day', NOW()))::BIGINT,
+                   (SELECT COALESCE(SUM(total_tokens), 0) FROM token_usage
+                    WHERE user_id = $1 AND created_at >= date_trunc('month', NOW()))::BIGINT
+            FROM rate_limits r
+            WHERE r.user_id = $1
+            ORDER BY r.created_at
+            LIMIT 1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(usage.map(|(daily_limit, monthly_limit, daily_used, monthly_used)| {
+            (daily_used as f64) < daily_limit as f64 * multiplier
+                && (monthly_used as f64) < monthly_limit as f64 * multiplier
+        }))
+    }
+    
+    pub async fn set_user_bypass(&self, user_id: &Uuid, enabled: bool, actor_id: &Uuid) -> Result<()> {
+        self.ensure_rate_limits(user_id).await?;
+        
+        let mut tx = self.db.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE rate_limits
+            SET quota_bypass = $2
+            WHERE user_id = $1
+            "#
+        )
+        .bind(user_id)
+        .bind(enabled)
+        .execute(&mut *tx)
+        .await?;
+        
+        let details = serde_json::json!({ "enabled": enabled });
+        log_action(&mut tx, actor_id, "bypass.user", "user", &user_id.to_string(), &details).await?;
+        tx.commit().await?;
+        self.chain_action(actor_id, "bypass.user", "user", &user_id.to_string(), details).await;
+        
+        Ok(())
+    }
+    
+    pub async fn set_api_key_bypass(&self, key_id: &Uuid, enabled: bool, actor_id: &Uuid) -> Result<bool> {
+        let mut tx = self.db.begin().await?;
+        let result = sqlx::query(
+            r#"
+            UPDATE api_keys
+            SET quota_bypass = $2
+            WHERE id = $1
+            "#
+        )
+        .bind(key_id)
+        .bind(enabled)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        
+        let details = serde_json::json!({ "enabled": enabled });
+        log_action(&mut tx, actor_id, "bypass.api_key", "api_key", &key_id.to_string(), &details).await?;
+        tx.commit().await?;
+        self.chain_action(actor_id, "bypass.api_key", "api_key", &key_id.to_string(), details).await;
+        
+        Ok(true)
+    }
+    
+    // A bypass on the user, or on the API key the request came in with
+    pub async fn has_quota_bypass(&self, user_id: &Uuid, api_key_id: Option<&Uuid>) -> Result<bool> {
+        let bypass = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM rate_limits
+                WHERE user_id = $1 AND quota_bypass = true
+            ) OR EXISTS (
+                SELECT 1 FROM api_keys
+                WHERE id = $2 AND user_id = $1 AND is_active = true AND quota_bypass = true
+            )
+            "#
+        )
+        .bind(user_id)
+        .bind(api_key_id)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(bypass)
+    }
+    
+    // For changes made elsewhere that must be audited atomically: pass the
+    // transaction they ran in to `commit_action`
+    pub async fn begin(&self) -> Result<Transaction<'static, Postgres>> {
+        Ok(self.db.begin().await?)
+    }
+    
+    // Logs the action in the change's own transaction and commits both
+    pub async fn commit_action(
+        &self,
+        mut tx: Transaction<'_, Postgres>,
+        actor_id: &Uuid,
+        action: &str,
+        target_type: &str,
+        target_id: &str,
+        details: serde_json::Value,
+    ) -> Result<()> {
+        log_action(&mut tx, actor_id, action, target_type, target_id, &details).await?;
+        tx.commit().await?;
+        self.chain_action(actor_id, action, target_type, target_id, details).await;
+        
+        Ok(())
+    }
+    
+    pub async fn record_action(
+        &self,
+        actor_id: &Uuid,
+        action: &str,
+        target_type: &str,
+        target_id: &str,
+        details: serde_json::Value,
+    ) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+        log_action(&mut tx, actor_id, action, target_type, target_id, &details).await?;
+        tx.commit().await?;
+        
+        // Also chain the action into the tamper-evident audit trail
+        self.audit.record(AuditEvent {
+            event_type: format!("admin.{}", action),
+            actor_id: Some(*actor_id),
+            outcome: "success".to_string(),
+            details: serde_json::json!({
+                "target_type": target_type,
+                "target_id": target_id,
+                "details": details,
+            }),
+            ..Default::default()
+        }).await?;
+        
+        Ok(())
+    }
+    
+    // Chains an action whose admin audit row is already committed; a failure
+    // is logged rather than undoing the committed change
+    async fn chain_action(&self, actor_id: &Uuid, action: &str, target_type: &str, target_id: &str, details: serde_json::Value) {
+        let recorded = self.audit.record(AuditEvent {
+            event_type: format!("admin.{}", action),
+            actor_id: Some(*actor_id),
+            outcome: "success".to_string(),
+            details: serde_json::json!({
+                "target_type": target_type,
+                "target_id": target_id,
+                "details": details,
+            }),
+            ..Default::default()
+        }).await;
+        if let Err(e) = recorded {
+            tracing::error!("Failed to chain admin action {}: {}", action, e);
+        }
+    }
+    
+    pub async fn list_actions(&self, limit: i64, offset: i64) -> Result<Vec<AdminAuditEntry>> {
+        let entries = sqlx::query_as::<_, AdminAuditEntry>(
+            r#"
+            SELECT * FROM admin_audit_log
+            ORDER BY created_at DESC
+            LIMIT $1 OFFSET $2
+            "#
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(entries)
+    }
+    
+    async fn ensure_rate_limits(&self, user_id: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO rate_limits (user_id)
+            SELECT $1
+            WHERE NOT EXISTS (SELECT 1 FROM rate_limits WHERE user_id = $1)
+            "#
+        )
+        .bind(user_id)
+        .execute(&self.db)
+        .await?;
+        
+        Ok(())
+    }
+    
+    async fn sync_boost_cache(&self, user_id: &Uuid) -> Result<()> {
+        // Cache the strongest active boost with a TTL matching its expiry, so
+        // the boost lapses on its own without a cleanup job
+        let active = sqlx::query_as::<_, (f64, DateTime<Utc>)>(
+            r#"
+            SELECT multiplier, expires_at FROM quota_boosts
+            WHERE user_id = $1
+              AND revoked_at IS NULL
+              AND starts_at <= NOW()
+              AND expires_at > NOW()
+            ORDER BY multiplier DESC
+            LIMIT 1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        let mut conn = self.redis.clone();
+        let key = boost_key(user_id);
+        match active {
+            Some((multiplier, expires_at)) => {
+                let ttl = (expires_at - Utc::now()).num_seconds().max(1) as u64;
+                conn.set_ex::<_, _, ()>(&key, multiplier, ttl).await?;
+            }
+            None => {
+                conn.del::<_, ()>(&key).await?;
+            }
+        }
+        
+        Ok(())
+    }
+}
+
+async fn log_action(
+    tx: &mut Transaction<'_, Postgres>,
+    actor_id: &Uuid,
+    action: &str,
+    target_type: &str,
+    target_id: &str,
+    details: &serde_json::Value,
+) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO admin_audit_log (actor_id, action, target_type, target_id, details)
+        VALUES ($1, $2, $3, $4, $5)
+        "#
+    )
+    .bind(actor_id)
+    .bind(action)
+    .bind(target_type)
+    .bind(target_id)
+    .bind(details)
+    .execute(&mut **tx)
+    .await?;
+    
+    Ok(())
+}
+
+// Shared with the token meter, which sets it when it grants a boost
+fn boost_key(user_id: &Uuid) -> String {
+    format!("boost:{}", user_id)
+}
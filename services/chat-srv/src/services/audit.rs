@@ -0,0 +1,719 @@
+use anyhow::Result;
+use chrono::{DateTime, Timelike, Utc};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use tracing::error;
+use uuid::Uuid;
+
+// prev_hash of the very first record in the chain
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+// Serializes appends across replicas so the chain never forks
+const AUDIT_CHAIN_LOCK: i64 = 0x6175_6469_745f_6c6f;
+
+const PAGE_SIZE: i64 = 1000;
+
+// Encoding new records are hashed with; see 016_audit_hash_version
+pub const HASH_VERSION: i16 = 2;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub event_type: String,
+    pub actor_id: Option<Uuid>,
+    pub session_id: Option<String>,
+    pub api_key_id: Option<Uuid>,
+    pub model: Option<String>,
+    pub request_hash: Option<String>,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub outcome: String,
+    pub client_ip: Option<String>,
+    pub details: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditRecord {
+    pub seq: i64,
+    pub id: Uuid,
+    pub event_type: String,
+    pub actor_id: Option<Uuid>,
+    pub session_id: Option<String>,
+    pub api_key_id: Option<Uuid>,
+    pub model: Option<String>,
+    pub request_hash: Option<String>,
+    pub prompt_tokens: Option<i32>,
+    pub completion_tokens: Option<i32>,
+    pub outcome: String,
+    pub client_ip: Option<String>,
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+    pub prev_hash: String,
+    pub hash: String,
+    pub hash_version: i16,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub actor_id: Option<Uuid>,
+    pub event_type: Option<String>,
+    pub session_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub after_seq: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainVerification {
+    pub valid: bool,
+    pub records_checked: u64,
+    pub first_seq: Option<i64>,
+    pub last_seq: Option<i64>,
+    // Keep this outside the database and pass it to the next verification,
+    // so a truncated tail is noticed
+    pub last_hash: Option<String>,
+    pub broken_at_seq: Option<i64>,
+    pub reason: Option<String>,
+}
+
+// A record known to be in the chain, from an earlier verification or export
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainAnchor {
+    pub seq: i64,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PurgeReport {
+    pub deleted: u64,
+    pub cutoff: DateTime<Utc>,
+    pub last_purged_seq: Option<i64>,
+    pub last_purged_hash: Option<String>,
+}
+
+pub struct AuditService {
+    db: PgPool,
+}
+
+impl AuditService {
+    pub fn new(db: PgPool) -> Self {
+        Self { db }
+    }
+    
+    pub async fn record(&self, event: AuditEvent) -> Result<AuditRecord> {
+        let mut tx = self.db.begin().await?;
+        
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(AUDIT_CHAIN_LOCK)
+            .execute(&mut *tx)
+            .await?;
+        
+        let record = append(&mut tx, &event).await?;
+        
+        tx.commit().await?;
+        
+        Ok(record)
+    }
+    
+    // Audit writes must never fail the request they describe
+    pub fn record_in_background(self: &Arc<Self>, event: AuditEvent) {
+        let audit = self.clone();
+        tokio::spawn(async move {
+            let event_type = event.event_type.clone();
+            if let Err(e) = audit.record(event).await {
+                error!("Failed to write audit record {}: {}", event_type, e);
+            }
+        });
+    }
+    
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditRecord>> {
+        let records = sqlx::query_as::<_, AuditRecord>(
+            r#"
+            SELECT * FROM audit_log
+            WHERE ($1::uuid IS NULL OR actor_id = $1)
+              AND ($2::text IS NULL OR event_type = $2)
+              AND ($3::text IS NULL OR session_id = $3)
+              AND ($4::timestamptz IS NULL OR created_at >= $4)
+              AND ($5::timestamptz IS NULL OR created_at < $5)
+              AND seq > $6
+            ORDER BY seq
+            LIMIT $7
+            "#
+        )
+        .bind(query.actor_id)
+        .bind(&query.event_type)
+        .bind(&query.session_id)
+        .bind(query.since)
+        .bind(query.until)
+        .bind(query.after_seq.unwrap_or(0))
+        .bind(query.limit.unwrap_or(100).clamp(1, PAGE_SIZE))
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(records)
+    }
+    
+    // Streams every matching record as one JSON document per line, paging by seq
+    pub fn export_jsonl(&self, query: AuditQuery) -> impl Stream<Item = Result<String>> + Send + 'static {
+        let audit = AuditService::new(self.db.clone());
+        
+        stream::try_unfold(
+            (audit, query, false),
+            |(audit, mut query, done)| async move {
+                if done {
+                    return Ok(None);
+                }
+                
+                query.limit = Some(PAGE_SIZE);
+                let records = audit.query(&query).await?;
+                let done = (records.len() as i64) < PAGE_SIZE;
+                query.after_seq = records.last().map(|r| r.seq).or(query.after_seq);
+                
+                let mut chunk = String::new();
+                for record in &records {
+                    chunk.push_str(&serde_json::to_string(record)?);
+                    chunk.push('\n');
+                }
+                
+                Ok(Some((chunk, (audit, query, done))))
+            },
+        )
+    }
+    
+    // Checks every surviving record and that the chain starts where the last
+    // purge left it. Truncating the tail is only caught with an anchor from an
+    // earlier verification
+    pub async fn verify_chain(&self, anchor: Option<&ChainAnchor>) -> Result<ChainVerification> {
+        let mut report = ChainVerification {
+            valid: true,
+            records_checked: 0,
+            first_seq: None,
+            last_seq: None,
+            last_hash: None,
+            broken_at_seq: None,
+            reason: None,
+        };
+        let purged = self.last_purge().await?;
+        let mut expected_prev = purged
+            .as_ref()
+            .and_then(|purge| purge.last_purged_hash.clone())
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+        let mut anchor_seen = false;
+        let mut after_seq = 0i64;
+        
+        loop {
+            let records = sqlx::query_as::<_, AuditRecord>(
+                r#"
+                SELECT * FROM audit_log
+                WHERE seq > $1
+                ORDER BY seq
+                LIMIT $2
+                "#
+            )
+            .bind(after_seq)
+            .bind(PAGE_SIZE)
+            .fetch_all(&self.db)
+            .await?;
+            
+            for record in &records {
+                if let Err(reason) = check_record(record, &expected_prev) {
+                    report.fail(record.seq, reason);
+                    return Ok(report);
+                }
+                if let Some(anchor) = anchor.filter(|anchor| anchor.seq == record.seq) {
+                    if anchor.hash != record.hash {
+                        report.fail(record.seq, "record does not match the anchor hash");
+                        return Ok(report);
+                    }
+                    anchor_seen = true;
+                }
+                
+                report.first_seq.get_or_insert(record.seq);
+                report.last_seq = Some(record.seq);
+                report.records_checked += 1;
+                expected_prev = record.hash.clone();
+            }
+            
+            match records.last() {
+                Some(last) if records.len() as i64 == PAGE_SIZE => after_seq = last.seq,
+                _ => break,
+            }
+        }
+        
+        // An anchor older than the last purge was legitimately deleted
+        if let Some(anchor) = anchor {
+            let purged_past = purged
+                .and_then(|purge| purge.last_purged_seq)
+                .map_or(false, |seq| anchor.seq <= seq);
+            if !anchor_seen && !purged_past {
+                report.fail(anchor.seq, "anchored record is missing");
+                return Ok(report);
+            }
+        }
+        
+        report.last_hash = (report.records_checked > 0).then_some(expected_prev);
+        
+        Ok(report)
+    }
+    
+    // The purge report recorded with the newest purge, if any
+    async fn last_purge(&self) -> Result<Option<PurgeReport>> {
+        let details = sqlx::query_scalar::<_, serde_json::Value>(
+            r#"
+            SELECT details FROM audit_log
+            WHERE event_type = 'audit.purge'
+            ORDER BY seq DESC
+            LIMIT 1
+            "#
+        )
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(details.and_then(|details| serde_json::from_value(details).ok()))
+    }
+    
+    // The purge event is appended in the same transaction, so the chain is
+    // never left without a record of where it now starts
+    pub async fn purge_older_than(&self, retention_days: u32) -> Result<PurgeReport> {
+        let cutoff = Utc::now() - chrono::Duration::days(retention_days as i64);
+        let mut tx = self.db.begin().await?;
+        
+        sqlx::query("SELECT pg_advisory_xact_lock($1)")
+            .bind(AUDIT_CHAIN_LOCK)
+            .execute(&mut *tx)
+            .await?;
+        
+        sqlx::query("SET LOCAL audit.allow_purge = 'on'")
+            .execute(&mut *tx)
+            .await?;
+        
+        // Only a contiguous prefix is purged, so the remaining chain stays verifiable
+        let last_purged = sqlx::query_as::<_, (i64, String)>(
+            r#"
+            SELECT seq, hash FROM audit_log
+            WHERE created_at < $1
+            ORDER BY seq DESC
+            LIMIT 1
+            "#
+        )
+        .bind(cutoff)
+        .fetch_optional(&mut *tx)
+        .await?;
+        
+        let Some((last_purged_seq, last_purged_hash)) = last_purged else {
+            return Ok(PurgeReport {
+                deleted: 0,
+                cutoff,
+                last_purged_seq: None,
+                last_purged_hash: None,
+            });
+        };
+        
+        let deleted = sqlx::query("DELETE FROM audit_log WHERE seq <= $1")
+            .bind(last_purged_seq)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        
+        let report = PurgeReport {
+            deleted,
+            cutoff,
+            last_purged_seq: Some(last_purged_seq),
+            last_purged_hash: Some(last_purged_hash),
+        };
+        
+        append(&mut tx, &AuditEvent {
+            event_type: "audit.purge".to_string(),
+            outcome: "success".to_string(),
+            details: json!(report),
+            ..Default::default()
+        }).await?;
+        
+        tx.commit().await?;
+        
+        Ok(report)
+    }
+}
+
+impl ChainVerification {
+    fn fail(&mut self, seq: i64, reason: &str) {
+        self.valid = false;
+        self.broken_at_seq = Some(seq);
+        self.reason = Some(reason.to_string());
+    }
+}
+
+// Checks a record's link to its predecessor and its own hash
+fn check_record(record: &AuditRecord, expected_prev: &str) -> std::result::Result<(), &'static str> {
+    if record.prev_hash != expected_prev {
+        return Err("prev_hash does not match preceding record");
+    }
+    
+    let event = AuditEvent {
+        event_type: record.event_type.clone(),
+        actor_id: record.actor_id,
+        session_id: record.session_id.clone(),
+        api_key_id: record.api_key_id,
+        model: record.model.clone(),
+        request_hash: record.request_hash.clone(),
+        prompt_tokens: record.prompt_tokens,
+        completion_tokens: record.completion_tokens,
+        outcome: record.outcome.clone(),
+        client_ip: record.client_ip.clone(),
+        details: record.details.clone(),
+    };
+    let hash = chain_hash(record.hash_version, &record.prev_hash, &record.id, &record.created_at, &event, &event.details);
+    if hash != record.hash {
+        return Err("record contents do not match its hash");
+    }
+    
+    Ok(())
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+fn chain_hash(
+    version: i16,
+    prev_hash: &str,
+    id: &Uuid,
+    created_at: &DateTime<Utc>,
+    event: &AuditEvent,
+    details: &serde_json::Value,
+) -> String {
+    let fields = json!({
+        "prev_hash": prev_hash,
+        "id": id,
+        "created_at": created_at.to_rfc3339_opts(chrono::SecondsFormat::Micros, true),
+        "event_type": event.event_type,
+        "actor_id": event.actor_id,
+        "session_id": event.session_id,
+        "api_key_id": event.api_key_id,
+        "model": event.model,
+        "request_hash": event.request_hash,
+        "prompt_tokens": event.prompt_tokens,
+        "completion_tokens": event.completion_tokens,
+        "outcome": event.outcome,
+        "client_ip": event.client_ip,
+        "details": details,
+    });
+    
+    let encoded = match version {
+        // Whatever serde_json produced; sorted keys only without `preserve_order`
+        1 => fields.to_string(),
+        _ => {
+            let mut out = String::new();
+            write_canonical(&fields, &mut out);
+            out
+        }
+    };
+    sha256_hex(encoded.as_bytes())
+}
+
+// Compact JSON with object keys sorted bytewise and numbers in one fixed
+// form, so the encoding does not depend on serde_json features or on how
+// JSONB hands the details back
+fn write_canonical(value: &serde_json::Value, out: &mut String) {
+    use serde_json::Value;
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) => write_number(n, out),
+        Value::String(s) => write_string(s, out),
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(item, out);
+            }
+            out.push(']');
+        }
+        Value::Object(map) => {
+            let mut entries: Vec<(&String, &Value)> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.as_bytes().cmp(b.0.as_bytes()));
+            out.push('{');
+            for (i, (key, item)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_string(key, out);
+                out.push(':');
+                write_canonical(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+// Integers as digits; anything else as the shortest decimal that round-trips,
+// never in exponent form. 100, 100.0 and 1e2 all come out as `100`, which is
+// what JSONB may turn any of them into
+fn write_number(n: &serde_json::Number, out: &mut String) {
+    if let Some(i) = n.as_i64() {
+        out.push_str(&i.to_string());
+    } else if let Some(u) = n.as_u64() {
+        out.push_str(&u.to_string());
+    } else {
+        out.push_str(&n.as_f64().unwrap_or(0.0).to_string());
+    }
+}
+
+// Escapes only what JSON requires, with lowercase `\u` escapes for the rest
+// of the control characters
+fn write_string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+// Appends to the chain; the caller holds AUDIT_CHAIN_LOCK in `tx`
+async fn append(tx: &mut Transaction<'_, Postgres>, event: &AuditEvent) -> Result<AuditRecord> {
+    let prev_hash = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT hash FROM audit_log
+        ORDER BY seq DESC
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .unwrap_or_else(|| GENESIS_HASH.to_string());
+    
+    let id = Uuid::new_v4();
+    // Postgres stores microseconds; truncate so the hash survives a round trip
+    let now = Utc::now();
+    let created_at = now.with_nanosecond(now.nanosecond() / 1_000 * 1_000).unwrap_or(now);
+    let details = if event.details.is_null() { json!({}) } else { event.details.clone() };
+    let hash = chain_hash(HASH_VERSION, &prev_hash, &id, &created_at, event, &details);
+    
+    let record = sqlx::query_as::<_, AuditRecord>(
+        r#"
+        INSERT INTO audit_log (
+            id, event_type, actor_id, session_id, api_key_id, model, request_hash,
+            prompt_tokens, completion_tokens, outcome, client_ip, details,
+            created_at, prev_hash, hash, hash_version
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+        RETURNING *
+        "#
+    )
+    .bind(id)
+    .bind(&event.event_type)
+    .bind(event.actor_id)
+    .bind(&event.session_id)
+    .bind(event.api_key_id)
+    .bind(&event.model)
+    .bind(&event.request_hash)
+    .bind(event.prompt_tokens)
+    .bind(event.completion_tokens)
+    .bind(&event.outcome)
+    .bind(&event.client_ip)
+    .bind(&details)
+    .bind(created_at)
+    .bind(&prev_hash)
+    .bind(&hash)
+    .bind(HASH_VERSION)
+    .fetch_one(&mut **tx)
+    .await?;
+    
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn record(seq: i64, prev_hash: &str, event_type: &str) -> AuditRecord {
+        let event = AuditEvent {
+            event_type: event_type.to_string(),
+            outcome: "success".to_string(),
+            details: json!({ "seq": seq }),
+            ..Default::default()
+        };
+        let id = Uuid::new_v4();
+        let created_at = Utc::now().with_nanosecond(0).unwrap();
+        let hash = chain_hash(HASH_VERSION, prev_hash, &id, &created_at, &event, &event.details);
+        
+        AuditRecord {
+            seq,
+            id,
+            event_type: event.event_type,
+            actor_id: None,
+            session_id: None,
+            api_key_id: None,
+            model: None,
+            request_hash: None,
+            prompt_tokens: None,
+            completion_tokens: None,
+            outcome: event.outcome,
+            client_ip: None,
+            details: event.details,
+            created_at,
+            prev_hash: prev_hash.to_string(),
+            hash,
+            hash_version: HASH_VERSION,
+        }
+    }
+    
+    fn chain(len: i64) -> Vec<AuditRecord> {
+        let mut records: Vec<AuditRecord> = Vec::new();
+        for seq in 1..=len {
+            let prev = records.last().map_or(GENESIS_HASH.to_string(), |r| r.hash.clone());
+            records.push(record(seq, &prev, "chat.completion"));
+        }
+        records
+    }
+    
+    fn verify(records: &[AuditRecord], start: &str) -> std::result::Result<(), (i64, &'static str)> {
+        let mut expected_prev = start.to_string();
+        for record in records {
+            check_record(record, &expected_prev).map_err(|reason| (record.seq, reason))?;
+            expected_prev = record.hash.clone();
+        }
+        Ok(())
+    }
+    
+    #[test]
+    fn intact_chain_verifies_from_genesis() {
+        assert_eq!(verify(&chain(5), GENESIS_HASH), Ok(()));
+    }
+    
+    #[test]
+    fn edited_record_breaks_its_hash() {
+        let mut records = chain(3);
+        records[1].details = json!({ "seq": 99 });
+        
+        assert_eq!(verify(&records, GENESIS_HASH), Err((2, "record contents do not match its hash")));
+    }
+    
+    #[test]
+    fn deleted_record_breaks_the_link() {
+        let mut records = chain(3);
+        records.remove(1);
+        
+        assert_eq!(verify(&records, GENESIS_HASH), Err((3, "prev_hash does not match preceding record")));
+    }
+    
+    #[test]
+    fn purged_prefix_verifies_from_the_purge_anchor() {
+        let records = chain(5);
+        let purge = PurgeReport {
+            deleted: 2,
+            cutoff: Utc::now(),
+            last_purged_seq: Some(2),
+            last_purged_hash: Some(records[1].hash.clone()),
+        };
+        
+        let start = purge.last_purged_hash.unwrap();
+        assert_eq!(verify(&records[2..], &start), Ok(()));
+    }
+    
+    #[test]
+    fn prefix_deleted_beyond_the_purge_is_caught() {
+        let records = chain(5);
+        let last_purged_hash = records[1].hash.clone();
+        
+        assert_eq!(
+            verify(&records[3..], &last_purged_hash),
+            Err((4, "prev_hash does not match preceding record")),
+        );
+    }
+    
+    #[test]
+    fn prefix_deleted_without_a_purge_is_caught() {
+        let records = chain(4);
+        
+        assert_eq!(
+            verify(&records[1..], GENESIS_HASH),
+            Err((2, "prev_hash does not match preceding record")),
+        );
+    }
+    
+    #[test]
+    fn purge_report_round_trips_through_details() {
+        let report = PurgeReport {
+            deleted: 7,
+            cutoff: Utc::now(),
+            last_purged_seq: Some(7),
+            last_purged_hash: Some("ab".repeat(32)),
+        };
+        let parsed: PurgeReport = serde_json::from_value(json!(report)).unwrap();
+        
+        assert_eq!(parsed.last_purged_seq, Some(7));
+        assert_eq!(parsed.last_purged_hash, report.last_purged_hash);
+    }
+    
+    fn canonical(value: &serde_json::Value) -> String {
+        let mut out = String::new();
+        write_canonical(value, &mut out);
+        out
+    }
+    
+    #[test]
+    fn canonical_form_sorts_keys_and_fixes_numbers() {
+        let value = json!({ "z": [1.5, 2.0, -3], "a": { "y": 1e2, "b": "q\"\n\u{1}" }, "m": null });
+        assert_eq!(
+            canonical(&value),
+            r#"{"a":{"b":"q\"\n\u0001","y":100},"m":null,"z":[1.5,2,-3]}"#,
+        );
+    }
+    
+    #[test]
+    fn details_read_back_from_jsonb_hash_the_same() {
+        let details = json!({
+            "multiplier": 2.0,
+            "ratio": 1.5e-7,
+            "big": 5e15,
+            "huge": 1e300,
+            "nested": { "zeta": [0.1, 3], "alpha": "x" },
+        });
+        // How Postgres prints that JSONB: keys reordered, exponents expanded
+        let jsonb = format!(
+            r#"{{"big": 5000000000000000, "huge": 1{}, "ratio": 0.00000015, "nested": {{"alpha": "x", "zeta": [0.1, 3]}}, "multiplier": 2.0}}"#,
+            "0".repeat(300),
+        );
+        let read_back: serde_json::Value = serde_json::from_str(&jsonb).unwrap();
+        
+        assert_eq!(canonical(&read_back), canonical(&details));
+        
+        let event = AuditEvent { event_type: "admin.boost.grant".to_string(), ..Default::default() };
+        let id = Uuid::new_v4();
+        let created_at = Utc::now().with_nanosecond(0).unwrap();
+        assert_eq!(
+            chain_hash(HASH_VERSION, GENESIS_HASH, &id, &created_at, &event, &read_back),
+            chain_hash(HASH_VERSION, GENESIS_HASH, &id, &created_at, &event, &details),
+        );
+    }
+    
+    #[test]
+    fn version_one_records_still_verify() {
+        let mut record = record(1, GENESIS_HASH, "chat.completion");
+        let event = AuditEvent {
+            event_type: record.event_type.clone(),
+            outcome: record.outcome.clone(),
+            details: record.details.clone(),
+            ..Default::default()
+        };
+        record.hash = chain_hash(1, GENESIS_HASH, &record.id, &record.created_at, &event, &event.details);
+        record.hash_version = 1;
+        
+        assert_eq!(check_record(&record, GENESIS_HASH), Ok(()));
+    }
+}
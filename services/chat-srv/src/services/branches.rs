@@ -0,0 +1,355 @@
+use crate::models::Message;
+use crate::services::encryption::AAD_VERSION;
+use crate::services::EncryptionService;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct BranchNode {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// One leaf of the tree and the branch ending in it
+#[derive(Debug, Clone, Serialize)]
+pub struct Branch {
+    pub leaf_id: String,
+    pub message_count: usize,
+    pub updated_at: DateTime<Utc>,
+    pub active: bool,
+}
+
+// Alternatives at one point of the active path, oldest first
+#[derive(Debug, Clone, Serialize)]
+pub struct Fork {
+    pub parent_id: Option<String>,
+    pub message_ids: Vec<String>,
+    pub active_id: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BranchTree {
+    pub active_leaf_id: Option<String>,
+    pub path: Vec<String>,
+    pub forks: Vec<Fork>,
+    pub branches: Vec<Branch>,
+}
+
+// Messages form a tree through `parent_id`; the conversation's
+// `active_leaf_id` picks the branch that is shown and sent as context.
+// Inserts without a parent attach to the active leaf in the database
+// (007_message_branches), so forking is a matter of moving the leaf before
+// the next message is saved. The leaf only advances to a message saved under
+// it (015_explicit_message_parents). With encryption at rest on, content
+// and metadata are sealed on the way in and opened on the way out
+pub struct BranchService {
+    db: PgPool,
+    encryption: Option<Arc<EncryptionService>>,
+}
+
+impl BranchService {
+    pub fn new(db: PgPool, encryption: Option<Arc<EncryptionService>>) -> Self {
+        Self { db, encryption }
+    }
+    
+    pub async fn node(&self, conversation_id: &str, message_id: &str) -> Result<Option<BranchNode>> {
+        let (Ok(conversation_id), Ok(message_id)) = (Uuid::parse_str(conversation_id), Uuid::parse_str(message_id)) else {
+            return Ok(None);
+        };
+        let row = sqlx::query_as::<_, (Uuid, Option<Uuid>, String, DateTime<Utc>)>(
+            "SELECT id, parent_id, role, created_at FROM messages WHERE id = $1 AND conversation_id = $2",
+        )
+        .bind(message_id)
+        .bind(conversation_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(row.map(node))
+    }
+    
+    pub async fn active_leaf(&self, conversation_id: &str) -> Result<Option<String>> {
+        let leaf = sqlx::query_scalar::<_, Option<Uuid>>(
+            "SELECT active_leaf_id FROM conversations WHERE id = $1",
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_optional(&self.db)
+        .await?
+        .flatten();
+        
+        Ok(leaf.map(|id| id.to_string()))
+    }
+    
+    // Ids from the root down to `leaf`, or to the active leaf when None
+    pub async fn path(&self, conversation_id: &str, leaf: Option<&str>) -> Result<Vec<String>> {
+        let leaf = match leaf {
+            Some(leaf) => Some(leaf.to_string()),
+            None => self.active_leaf(conversation_id).await?,
+        };
+        let Some(leaf) = leaf else {
+            return Ok(Vec::new());
+        };
+        
+        let ids = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            WITH RECURSIVE path AS (
+                SELECT id, parent_id, 0 AS depth FROM messages WHERE id = $1 AND conversation_id = $2
+                UNION ALL
+                SELECT m.id, m.parent_id, path.depth + 1 FROM messages m JOIN path ON m.id = path.parent_id
+            )
+            SELECT id FROM path ORDER BY depth DESC
+            "#,
+        )
+        .bind(Uuid::parse_str(&leaf)?)
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(ids.into_iter().map(|id| id.to_string()).collect())
+    }
+    
+    // Saves a message under `parent_id`, or under the active leaf when None.
+    // A reply names its prompt, so it lands there even if the branch moved
+    // while it streamed. User messages carry the member who sent them;
+    // replies have no author. Metadata is stored with the content, in the
+    // same row write. Sealed messages are bound to their id, so it is chosen
+    // here rather than by the database. Returns the new message's id
+    pub async fn add_message(
+        &self,
+        conversation_id: &str,
+        parent_id: Option<&str>,
+        author_id: Option<&str>,
+        role: &str,
+        content: &str,
+        metadata: &Value,
+    ) -> Result<String> {
+        let id = Uuid::new_v4();
+        let conversation_id = Uuid::parse_str(conversation_id)?;
+        let parent_id = parent_id.map(Uuid::parse_str).transpose()?;
+        let author_id = author_id.map(Uuid::parse_str).transpose()?;
+        
+        let query = match &self.encryption {
+            // Sealed with the owner's key, whoever sent it, so every member reads the same rows
+            Some(encryption) => {
+                let owner = sqlx::query_scalar::<_, Uuid>("SELECT user_id FROM conversations WHERE id = $1")
+                    .bind(conversation_id)
+                    .fetch_optional(&self.db)
+                    .await?
+                    .ok_or_else(|| anyhow!("Conversation {} not found", conversation_id))?;
+                let sealed = encryption.encrypt_message(&owner, &conversation_id, &id, content, metadata).await?;
+                
+                sqlx::query(
+                    r#"
+                    INSERT INTO messages (id, conversation_id, parent_id, author_id, role, content, metadata,
+                                          content_ciphertext, metadata_ciphertext, data_key_id, aad_version)
+                    VALUES ($1, $2, $3, $4, $5, NULL, '{}'::jsonb, $6, $7, $8, $9)
+                    "#,
+                )
+                .bind(id)
+                .bind(conversation_id)
+                .bind(parent_id)
+                .bind(author_id)
+                .bind(role)
+                .bind(sealed.content)
+                .bind(sealed.metadata)
+                .bind(sealed.data_key_id)
+                .bind(AAD_VERSION)
+            }
+            None => sqlx::query(
+                r#"
+                INSERT INTO messages (id, conversation_id, parent_id, author_id, role, content, metadata)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                "#,
+            )
+            .bind(id)
+            .bind(conversation_id)
+            .bind(parent_id)
+            .bind(author_id)
+            .bind(role)
+            .bind(content)
+            .bind(metadata),
+        };
+        query.execute(&self.db).await?;
+        
+        Ok(id.to_string())
+    }
+    
+    // Replaces the content of sealed messages with their plaintext. Applied
+    // to whatever the conversation store loaded, which only sees `content`
+    pub async fn decrypt(&self, conversation_id: &str, mut messages: Vec<Message>) -> Result<Vec<Message>> {
+        let ids: Vec<Uuid> = messages.iter().filter_map(|m| Uuid::parse_str(&m.id).ok()).collect();
+        let conversation_id = Uuid::parse_str(conversation_id)?;
+        let sealed = sqlx::query_as::<_, (Uuid, Vec<u8>, Option<Vec<u8>>, Uuid, i16)>(
+            r#"
+            SELECT id, content_ciphertext, metadata_ciphertext, data_key_id, aad_version
+            FROM messages
+            WHERE conversation_id = $1 AND id = ANY($2) AND content_ciphertext IS NOT NULL
+            "#,
+        )
+        .bind(conversation_id)
+        .bind(&ids)
+        .fetch_all(&self.db)
+        .await?;
+        if sealed.is_empty() {
+            return Ok(messages);
+        }
+        let encryption = self.encryption
+            .as_ref()
+            .context("Conversation has encrypted messages but encryption at rest is disabled")?;
+        
+        let mut contents = HashMap::new();
+        for (id, content, metadata, data_key_id, aad_version) in sealed {
+            let (content, _) = encryption
+                .decrypt_message(&conversation_id, &id, aad_version, &data_key_id, &content, metadata.as_deref())
+                .await
+                .with_context(|| format!("Failed to decrypt message {}", id))?;
+            contents.insert(id.to_string(), content);
+        }
+        for message in &mut messages {
+            if let Some(content) = contents.remove(&message.id) {
+                message.content = content;
+            }
+        }
+        
+        Ok(messages)
+    }
+    
+    // None rewinds to before the first message, so the next one starts a new root
+    pub async fn set_active_leaf(&self, conversation_id: &str, leaf: Option<&str>) -> Result<()> {
+        let leaf = leaf.map(Uuid::parse_str).transpose()?;
+        sqlx::query("UPDATE conversations SET active_leaf_id = $2 WHERE id = $1")
+            .bind(Uuid::parse_str(conversation_id)?)
+            .bind(leaf)
+            .execute(&self.db)
+            .await?;
+        
+        Ok(())
+    }
+    
+    // Makes the branch through `message_id` active, continuing to its most
+    // recent leaf. Returns that leaf, or None if the message is not in the
+    // conversation
+    pub async fn switch(&self, conversation_id: &str, message_id: &str) -> Result<Option<String>> {
+        let Ok(message_id) = Uuid::parse_str(message_id) else {
+            return Ok(None);
+        };
+        // Children are newer than their parent, so the newest descendant is a leaf
+        let leaf = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            WITH RECURSIVE below AS (
+                SELECT id, created_at FROM messages WHERE id = $1 AND conversation_id = $2
+                UNION ALL
+                SELECT m.id, m.created_at FROM messages m JOIN below ON m.parent_id = below.id
+            )
+            SELECT id FROM below ORDER BY created_at DESC, id DESC LIMIT 1
+            "#,
+        )
+        .bind(message_id)
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        let Some(leaf) = leaf.map(|id| id.to_string()) else {
+            return Ok(None);
+        };
+        self.set_active_leaf(conversation_id, Some(&leaf)).await?;
+        
+        Ok(Some(leaf))
+    }
+    
+    pub async fn tree(&self, conversation_id: &str) -> Result<BranchTree> {
+        let nodes: Vec<BranchNode> = sqlx::query_as::<_, (Uuid, Option<Uuid>, String, DateTime<Utc>)>(
+            "SELECT id, parent_id, role, created_at FROM messages WHERE conversation_id = $1 ORDER BY created_at, id",
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(node)
+        .collect();
+        let active_leaf_id = self.active_leaf(conversation_id).await?;
+        
+        let parents: HashMap<&str, Option<&str>> = nodes
+            .iter()
+            .map(|n| (n.id.as_str(), n.parent_id.as_deref()))
+            .collect();
+        let mut children: HashMap<Option<&str>, Vec<&str>> = HashMap::new();
+        for n in &nodes {
+            children.entry(n.parent_id.as_deref()).or_default().push(&n.id);
+        }
+        
+        let ancestry = |leaf: &str| {
+            let mut path = vec![leaf.to_string()];
+            let mut current = parents.get(leaf).copied().flatten();
+            while let Some(id) = current {
+                path.push(id.to_string());
+                current = parents.get(id).copied().flatten();
+            }
+            path.reverse();
+            path
+        };
+        let path = active_leaf_id.as_deref().map(ancestry).unwrap_or_default();
+        
+        let mut forks = Vec::new();
+        for (i, id) in path.iter().enumerate() {
+            let parent_id = i.checked_sub(1).map(|p| path[p].clone());
+            let siblings: Vec<String> = children
+                .get(&parent_id.as_deref())
+                .map(|siblings| siblings.iter().map(|s| s.to_string()).collect())
+                .unwrap_or_default();
+            if siblings.len() > 1 {
+                forks.push(Fork {
+                    parent_id,
+                    message_ids: siblings,
+                    active_id: id.clone(),
+                });
+            }
+        }
+        
+        let branches = nodes
+            .iter()
+            .filter(|n| !children.contains_key(&Some(n.id.as_str())))
+            .map(|leaf| Branch {
+                leaf_id: leaf.id.clone(),
+                message_count: ancestry(&leaf.id).len(),
+                updated_at: leaf.created_at,
+                active: active_leaf_id.as_deref() == Some(leaf.id.as_str()),
+            })
+            .collect();
+        
+        Ok(BranchTree {
+            active_leaf_id,
+            path,
+            forks,
+            branches,
+        })
+    }
+}
+
+// Keeps the messages on `path`, in path order
+pub fn on_path(messages: Vec<Message>, path: &[String]) -> Vec<Message> {
+    let wanted: HashSet<&str> = path.iter().map(String::as_str).collect();
+    let mut by_id: HashMap<String, Message> = messages
+        .into_iter()
+        .filter(|m| wanted.contains(m.id.as_str()))
+        .map(|m| (m.id.clone(), m))
+        .collect();
+    
+    path.iter().filter_map(|id| by_id.remove(id)).collect()
+}
+
+fn node((id, parent_id, role, created_at): (Uuid, Option<Uuid>, String, DateTime<Utc>)) -> BranchNode {
+    BranchNode {
+        id: id.to_string(),
+        parent_id: parent_id.map(|id| id.to_string()),
+        role,
+        created_at,
+    }
+}
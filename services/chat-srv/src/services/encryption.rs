@@ -0,0 +1,650 @@
+use crate::crypto::{self, DataKey, MasterKeyProvider};
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+// How long an owner's active key id is trusted before re-checking after a rotation elsewhere
+const ACTIVE_KEY_CACHE_TTL: Duration = Duration::from_secs(300);
+
+// Version 1 bound ciphertext to its conversation only; version 2 also binds
+// the message, so sealed values cannot be swapped within a conversation.
+// Stored next to the ciphertext in `messages.aad_version`
+pub const AAD_VERSION: i16 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyScope {
+    User,
+    Org,
+}
+
+impl KeyScope {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "user" => Some(Self::User),
+            "org" => Some(Self::Org),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EncryptedMessage {
+    pub data_key_id: Uuid,
+    pub content: Vec<u8>,
+    pub metadata: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RotationReport {
+    pub rotated: u64,
+    pub rewrapped: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReencryptReport {
+    pub scanned: u64,
+    pub reencrypted: u64,
+    pub failed: u64,
+    pub failed_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct DataKeyRow {
+    id: Uuid,
+    wrapped_key: Vec<u8>,
+    master_key_id: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct PendingMessage {
+    id: Uuid,
+    conversation_id: Uuid,
+    user_id: Uuid,
+    content_ciphertext: Vec<u8>,
+    metadata_ciphertext: Option<Vec<u8>>,
+    data_key_id: Uuid,
+    aad_version: i16,
+}
+
+// Envelope encryption for message content. Each user (or org, depending on
+// scope) has one active data key; data keys are stored wrapped by the master
+// key provider and only unwrapped in memory.
+pub struct EncryptionService {
+    db: PgPool,
+    provider: Arc<dyn MasterKeyProvider>,
+    scope: KeyScope,
+    data_keys: DashMap<Uuid, DataKey>,
+    active_keys: DashMap<(&'static str, Uuid), (Instant, Uuid)>,
+}
+
+impl EncryptionService {
+    pub fn new(db: PgPool, provider: Arc<dyn MasterKeyProvider>, scope: KeyScope) -> Self {
+        Self {
+            db,
+            provider,
+            scope,
+            data_keys: DashMap::new(),
+            active_keys: DashMap::new(),
+        }
+    }
+    
+    // Always seals with the current AAD_VERSION
+    pub async fn encrypt_message(
+        &self,
+        user_id: &Uuid,
+        conversation_id: &Uuid,
+        message_id: &Uuid,
+        content: &str,
+        metadata: &Value,
+    ) -> Result<EncryptedMessage> {
+        let (data_key_id, key) = self.active_key_for_user(user_id).await?;
+        let (content, metadata) = seal_message(&key, conversation_id, message_id, content, metadata)?;
+        
+        Ok(EncryptedMessage {
+            data_key_id,
+            content,
+            metadata,
+        })
+    }
+    
+    pub async fn decrypt_message(
+        &self,
+        conversation_id: &Uuid,
+        message_id: &Uuid,
+        aad_version: i16,
+        data_key_id: &Uuid,
+        content: &[u8],
+        metadata: Option<&[u8]>,
+    ) -> Result<(String, Value)> {
+        let key = self.data_key(data_key_id).await?;
+        open_message(&key, conversation_id, message_id, aad_version, content, metadata)
+    }
+    
+    // Retires the owner's current data key and starts a new one. Existing
+    // messages stay readable and are moved over by the re-encryption job.
+    pub async fn rotate_user_key(&self, user_id: &Uuid) -> Result<Uuid> {
+        let (owner_type, owner_id) = self.owner_for_user(user_id).await?;
+        self.rotate_data_key(owner_type, &owner_id).await
+    }
+    
+    pub async fn rotate_data_key(&self, owner_type: &'static str, owner_id: &Uuid) -> Result<Uuid> {
+        let mut tx = self.db.begin().await?;
+        
+        sqlx::query(
+            r#"
+            UPDATE data_keys
+            SET status = 'retired', retired_at = NOW()
+            WHERE owner_type = $1 AND owner_id = $2 AND status = 'active'
+            "#
+        )
+        .bind(owner_type)
+        .bind(owner_id)
+        .execute(&mut *tx)
+        .await?;
+        
+        let key = DataKey::generate();
+        let wrapped = self.provider.wrap(&key).await?;
+        
+        let id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO data_keys (owner_type, owner_id, version, wrapped_key, master_key_id)
+            VALUES (
+                $1, $2,
+                (SELECT COALESCE(MAX(version), 0) + 1 FROM data_keys WHERE owner_type = $1 AND owner_id = $2),
+                $3, $4
+            )
+            RETURNING id
+            "#
+        )
+        .bind(owner_type)
+        .bind(owner_id)
+        .bind(&wrapped)
+        .bind(self.provider.active_key_id())
+        .fetch_one(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        
+        self.data_keys.insert(id, key);
+        self.active_keys.insert((owner_type, *owner_id), (Instant::now(), id));
+        
+        Ok(id)
+    }
+    
+    // Rotates every active data key older than `max_age_days`
+    pub async fn rotate_expired_keys(&self, max_age_days: u32) -> Result<u64> {
+        let owners = sqlx::query_as::<_, (String, Uuid)>(
+            r#"
+            SELECT owner_type, owner_id FROM data_keys
+            WHERE status = 'active'
+              AND created_at < NOW() - make_interval(days => $1)
+            "#
+        )
+        .bind(max_age_days as i32)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let mut rotated = 0;
+        for (owner_type, owner_id) in owners {
+            let owner_type = if owner_type == "org" { "org" } else { "user" };
+            match self.rotate_data_key(owner_type, &owner_id).await {
+                Ok(_) => rotated += 1,
+                Err(e) => error!("Failed to rotate data key for {} {}: {}", owner_type, owner_id, e),
+            }
+        }
+        
+        Ok(rotated)
+    }
+    
+    // Re-wraps data keys still wrapped by an older master key. Message
+    // ciphertext is untouched; only the wrapped key changes.
+    pub async fn rewrap_data_keys(&self) -> Result<u64> {
+        let active_master = self.provider.active_key_id().to_string();
+        
+        let rows = sqlx::query_as::<_, DataKeyRow>(
+            r#"
+            SELECT id, wrapped_key, master_key_id FROM data_keys
+            WHERE master_key_id <> $1
+            "#
+        )
+        .bind(&active_master)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let mut rewrapped = 0;
+        for row in rows {
+            let key = self.provider.unwrap(&row.master_key_id, &row.wrapped_key).await
+                .with_context(|| format!("Failed to unwrap data key {}", row.id))?;
+            let wrapped = self.provider.wrap(&key).await?;
+            
+            sqlx::query(
+                r#"
+                UPDATE data_keys
+                SET wrapped_key = $2, master_key_id = $3
+                WHERE id = $1 AND master_key_id = $4
+                "#
+            )
+            .bind(row.id)
+            .bind(&wrapped)
+            .bind(&active_master)
+            .bind(&row.master_key_id)
+            .execute(&self.db)
+            .await?;
+            
+            rewrapped += 1;
+        }
+        
+        Ok(rewrapped)
+    }
+    
+    // Re-seals ciphertext that is on a retired data key or an older AAD
+    // version, one batch at a time. Plaintext rows from before encryption was
+    // enabled are left alone. Rows in `skip` (earlier failures) are passed
+    // over so they cannot hold up the rest of the backlog.
+    pub async fn reencrypt_batch(&self, limit: i64, skip: &[Uuid]) -> Result<ReencryptReport> {
+        let rows = sqlx::query_as::<_, PendingMessage>(
+            r#"
+            SELECT m.id, m.conversation_id, c.user_id, m.content_ciphertext,
+                   m.metadata_ciphertext, m.data_key_id, m.aad_version
+            FROM messages m
+            JOIN conversations c ON c.id = m.conversation_id
+            JOIN data_keys k ON k.id = m.data_key_id
+            WHERE m.content_ciphertext IS NOT NULL
+              AND (k.status = 'retired' OR m.aad_version < $2)
+              AND m.id <> ALL($3)
+            ORDER BY m.created_at, m.id
+            LIMIT $1
+            "#
+        )
+        .bind(limit)
+        .bind(AAD_VERSION)
+        .bind(skip)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let mut report = ReencryptReport {
+            scanned: rows.len() as u64,
+            ..Default::default()
+        };
+        
+        for row in rows {
+            match self.reencrypt_row(&row).await {
+                Ok(()) => report.reencrypted += 1,
+                Err(e) => {
+                    warn!("Failed to re-encrypt message {}: {}", row.id, e);
+                    report.failed += 1;
+                    report.failed_ids.push(row.id);
+                }
+            }
+        }
+        
+        Ok(report)
+    }
+    
+    async fn reencrypt_row(&self, row: &PendingMessage) -> Result<()> {
+        let (content, metadata) = self.decrypt_message(
+            &row.conversation_id,
+            &row.id,
+            row.aad_version,
+            &row.data_key_id,
+            &row.content_ciphertext,
+            row.metadata_ciphertext.as_deref(),
+        ).await?;
+        
+        let sealed = self.encrypt_message(&row.user_id, &row.conversation_id, &row.id, &content, &metadata).await?;
+        
+        // Only replace the ciphertext that was read, in case the row changed meanwhile
+        sqlx::query(
+            r#"
+            UPDATE messages
+            SET content_ciphertext = $2,
+                metadata_ciphertext = $3,
+                data_key_id = $4,
+                aad_version = $5
+            WHERE id = $1 AND data_key_id = $6 AND aad_version = $7
+            "#
+        )
+        .bind(row.id)
+        .bind(&sealed.content)
+        .bind(&sealed.metadata)
+        .bind(sealed.data_key_id)
+        .bind(AAD_VERSION)
+        .bind(row.data_key_id)
+        .bind(row.aad_version)
+        .execute(&self.db)
+        .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn has_search_consent(&self, user_id: &Uuid) -> Result<bool> {
+        let consent = sqlx::query_scalar::<_, bool>(
+            r#"
+            SELECT search_index_consent FROM users
+            WHERE id = $1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(consent.unwrap_or(false))
+    }
+    
+    // Revoking consent drops everything already indexed for the user
+    pub async fn set_search_consent(&self, user_id: &Uuid, consent: bool) -> Result<()> {
+        let mut tx = self.db.begin().await?;
+        
+        sqlx::query(
+            r#"
+            UPDATE users SET search_index_consent = $2
+            WHERE id = $1
+            "#
+        )
+        .bind(user_id)
+        .bind(consent)
+        .execute(&mut *tx)
+        .await?;
+        
+        if !consent {
+            sqlx::query(
+                r#"
+                DELETE FROM message_search_index
+                WHERE user_id = $1
+                "#
+            )
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        tx.commit().await?;
+        
+        Ok(())
+    }
+    
+    // Writes plaintext to the search index, but only for users who opted in
+    pub async fn index_for_search(
+        &self,
+        user_id: &Uuid,
+        conversation_id: &Uuid,
+        message_id: &Uuid,
+        content: &str,
+    ) -> Result<bool> {
+        if !self.has_search_consent(user_id).await? {
+            return Ok(false);
+        }
+        
+        sqlx::query(
+            r#"
+            INSERT INTO message_search_index (message_id, conversation_id, user_id, content)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (message_id) DO UPDATE SET content = EXCLUDED.content
+            "#
+        )
+        .bind(message_id)
+        .bind(conversation_id)
+        .bind(user_id)
+        .bind(content)
+        .execute(&self.db)
+        .await?;
+        
+        Ok(true)
+    }
+    
+    async fn owner_for_user(&self, user_id: &Uuid) -> Result<(&'static str, Uuid)> {
+        if self.scope == KeyScope::Org {
+            let org_id = sqlx::query_scalar::<_, Option<Uuid>>(
+                r#"
+                SELECT org_id FROM users
+                WHERE id = $1
+                "#
+            )
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .flatten();
+            
+            if let Some(org_id) = org_id {
+                return Ok(("org", org_id));
+            }
+        }
+        
+        // Users without an org fall back to a personal key
+        Ok(("user", *user_id))
+    }
+    
+    async fn active_key_for_user(&self, user_id: &Uuid) -> Result<(Uuid, DataKey)> {
+        let owner = self.owner_for_user(user_id).await?;
+        
+        if let Some(entry) = self.active_keys.get(&owner) {
+            let (cached_at, key_id) = *entry.value();
+            if cached_at.elapsed() < ACTIVE_KEY_CACHE_TTL {
+                drop(entry);
+                return Ok((key_id, self.data_key(&key_id).await?));
+            }
+        }
+        
+        let existing = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT id FROM data_keys
+            WHERE owner_type = $1 AND owner_id = $2 AND status = 'active'
+            "#
+        )
+        .bind(owner.0)
+        .bind(owner.1)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        let key_id = match existing {
+            Some(id) => id,
+            None => self.create_first_key(owner.0, &owner.1).await?,
+        };
+        
+        self.active_keys.insert(owner, (Instant::now(), key_id));
+        Ok((key_id, self.data_key(&key_id).await?))
+    }
+    
+    async fn create_first_key(&self, owner_type: &'static str, owner_id: &Uuid) -> Result<Uuid> {
+        let key = DataKey::generate();
+        let wrapped = self.provider.wrap(&key).await?;
+        
+        // Two replicas may race to create the first key; the partial unique
+        // index lets exactly one win and the other picks up the winner
+        let inserted = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO data_keys (owner_type, owner_id, version, wrapped_key, master_key_id)
+            VALUES ($1, $2, 1, $3, $4)
+            ON CONFLICT (owner_type, owner_id) WHERE status = 'active' DO NOTHING
+            RETURNING id
+            "#
+        )
+        .bind(owner_type)
+        .bind(owner_id)
+        .bind(&wrapped)
+        .bind(self.provider.active_key_id())
+        .fetch_optional(&self.db)
+        .await?;
+        
+        if let Some(id) = inserted {
+            self.data_keys.insert(id, key);
+            return Ok(id);
+        }
+        
+        sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT id FROM data_keys
+            WHERE owner_type = $1 AND owner_id = $2 AND status = 'active'
+            "#
+        )
+        .bind(owner_type)
+        .bind(owner_id)
+        .fetch_one(&self.db)
+        .await
+        .context("Failed to load active data key")
+    }
+    
+    async fn data_key(&self, id: &Uuid) -> Result<DataKey> {
+        if let Some(key) = self.data_keys.get(id) {
+            return Ok(key.clone());
+        }
+        
+        let row = sqlx::query_as::<_, DataKeyRow>(
+            r#"
+            SELECT id, wrapped_key, master_key_id FROM data_keys
+            WHERE id = $1
+            "#
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await?
+        .with_context(|| format!("Data key {} not found", id))?;
+        
+        let key = self.provider.unwrap(&row.master_key_id, &row.wrapped_key).await?;
+        self.data_keys.insert(row.id, key.clone());
+        
+        Ok(key)
+    }
+}
+
+// Daily key maintenance: rotate aged data keys, re-wrap keys after a master
+// key change, then re-seal ciphertext left on retired keys or old AAD versions
+pub fn spawn_key_rotation_job(encryption: Arc<EncryptionService>, rotation_days: u32, batch_size: i64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            
+            let mut report = RotationReport::default();
+            if rotation_days > 0 {
+                match encryption.rotate_expired_keys(rotation_days).await {
+                    Ok(rotated) => report.rotated = rotated,
+                    Err(e) => error!("Data key rotation failed: {}", e),
+                }
+            }
+            match encryption.rewrap_data_keys().await {
+                Ok(rewrapped) => report.rewrapped = rewrapped,
+                Err(e) => error!("Data key re-wrap failed: {}", e),
+            }
+            if report.rotated > 0 || report.rewrapped > 0 {
+                info!("Rotated {} and re-wrapped {} data keys", report.rotated, report.rewrapped);
+            }
+            
+            // Every batch either re-seals its rows or skips them from then on,
+            // so this ends once the backlog is drained; failures retry tomorrow
+            let mut total = 0;
+            let mut failed = Vec::new();
+            loop {
+                match encryption.reencrypt_batch(batch_size, &failed).await {
+                    Ok(batch) if batch.scanned == 0 => break,
+                    Ok(batch) => {
+                        total += batch.reencrypted;
+                        failed.extend(batch.failed_ids);
+                    }
+                    Err(e) => {
+                        error!("Message re-encryption failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            if total > 0 || !failed.is_empty() {
+                info!("Re-encrypted {} messages, {} failed", total, failed.len());
+            }
+        }
+    });
+}
+
+fn seal_message(
+    key: &DataKey,
+    conversation_id: &Uuid,
+    message_id: &Uuid,
+    content: &str,
+    metadata: &Value,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let aad = |field| aad(AAD_VERSION, conversation_id, message_id, field);
+    
+    Ok((
+        crypto::seal(key, content.as_bytes(), &aad("content"))?,
+        crypto::seal(key, &serde_json::to_vec(metadata)?, &aad("metadata"))?,
+    ))
+}
+
+fn open_message(
+    key: &DataKey,
+    conversation_id: &Uuid,
+    message_id: &Uuid,
+    aad_version: i16,
+    content: &[u8],
+    metadata: Option<&[u8]>,
+) -> Result<(String, Value)> {
+    let aad = |field| aad(aad_version, conversation_id, message_id, field);
+    
+    let content = crypto::open(key, content, &aad("content"))?;
+    let content = String::from_utf8(content).context("Decrypted content is not UTF-8")?;
+    
+    let metadata = match metadata {
+        Some(sealed) => serde_json::from_slice(&crypto::open(key, sealed, &aad("metadata"))?)?,
+        None => Value::Object(Default::default()),
+    };
+    
+    Ok((content, metadata))
+}
+
+fn aad(version: i16, conversation_id: &Uuid, message_id: &Uuid, field: &str) -> Vec<u8> {
+    match version {
+        1 => format!("{}:{}", conversation_id, field),
+        _ => format!("{}:{}:{}", conversation_id, message_id, field),
+    }
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn ciphertext_is_bound_to_its_message() {
+        let key = DataKey::generate();
+        let (conversation, message, other) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let sealed = crypto::seal(&key, b"hello", &aad(AAD_VERSION, &conversation, &message, "content")).unwrap();
+        
+        assert!(crypto::open(&key, &sealed, &aad(AAD_VERSION, &conversation, &message, "content")).is_ok());
+        assert!(crypto::open(&key, &sealed, &aad(AAD_VERSION, &conversation, &other, "content")).is_err());
+        assert!(crypto::open(&key, &sealed, &aad(AAD_VERSION, &conversation, &message, "metadata")).is_err());
+    }
+    
+    #[test]
+    fn version_one_ignores_the_message() {
+        let conversation = Uuid::new_v4();
+        
+        assert_eq!(
+            aad(1, &conversation, &Uuid::new_v4(), "content"),
+            aad(1, &conversation, &Uuid::new_v4(), "content"),
+        );
+        assert_eq!(aad(1, &conversation, &Uuid::nil(), "content"), format!("{}:content", conversation).into_bytes());
+    }
+    
+    #[test]
+    fn messages_round_trip_without_storing_plaintext() {
+        let key = DataKey::generate();
+        let (conversation, message) = (Uuid::new_v4(), Uuid::new_v4());
+        let content = "my api key is sk-live-1234";
+        let metadata = serde_json::json!({ "system_prompt": "You are a pirate" });
+        
+        let (sealed_content, sealed_metadata) = seal_message(&key, &conversation, &message, content, &metadata).unwrap();
+        assert_ne!(sealed_content, content.as_bytes());
+        assert!(!sealed_content.windows(content.len()).any(|w| w == content.as_bytes()));
+        assert!(!sealed_metadata.windows(6).any(|w| w == b"pirate"));
+        
+        let opened = open_message(&key, &conversation, &message, AAD_VERSION, &sealed_content, Some(&sealed_metadata)).unwrap();
+        assert_eq!(opened, (content.to_string(), metadata));
+        assert!(open_message(&key, &conversation, &Uuid::new_v4(), AAD_VERSION, &sealed_content, None).is_err());
+    }
+}
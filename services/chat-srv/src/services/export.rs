@@ -0,0 +1,316 @@
+use crate::models::Conversation;
+use crate::services::{BranchService, ConversationService};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Identifies our own export documents, which import accepts back
+pub const DOCUMENT_FORMAT: &str = "chat-srv";
+pub const DOCUMENT_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    // Our schema: every branch, models and token counts
+    Json,
+    // Transcript of the active branch
+    Markdown,
+    // Chat Completions `messages` of the active branch
+    Openai,
+    // Messages API `system` + `messages` of the active branch
+    Anthropic,
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+            _ => "application/json",
+        }
+    }
+    
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Markdown => "md",
+            _ => "json",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedMessage {
+    pub id: String,
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    pub role: String,
+    pub content: String,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub tokens_used: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportedConversation {
+    pub id: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub model: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub active_leaf_id: Option<String>,
+    // Every branch, oldest first; parents always precede their children
+    pub messages: Vec<ExportedMessage>,
+}
+
+impl ExportedConversation {
+    // Messages of the active branch, root first
+    pub fn active_path(&self) -> Vec<&ExportedMessage> {
+        let by_id: HashMap<&str, &ExportedMessage> = self.messages.iter().map(|m| (m.id.as_str(), m)).collect();
+        let Some(mut current) = self.active_leaf_id.as_deref().and_then(|id| by_id.get(id).copied()) else {
+            return self.messages.iter().collect();
+        };
+        
+        let mut path = vec![current];
+        while let Some(parent) = current.parent_id.as_deref().and_then(|id| by_id.get(id).copied()) {
+            path.push(parent);
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelUsage {
+    pub model: String,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportDocument {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: DateTime<Utc>,
+    pub conversations: Vec<ExportedConversation>,
+    // Account-wide token usage by model; only in whole-account exports
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub usage: Vec<ModelUsage>,
+}
+
+impl ExportDocument {
+    fn new(conversations: Vec<ExportedConversation>, usage: Vec<ModelUsage>) -> Self {
+        Self {
+            format: DOCUMENT_FORMAT.to_string(),
+            version: DOCUMENT_VERSION,
+            exported_at: Utc::now(),
+            conversations,
+            usage,
+        }
+    }
+}
+
+// Exports conversations with their full message trees. Content comes from
+// `ConversationService`, so encrypted messages are exported decrypted
+pub struct ExportService {
+    db: PgPool,
+    conversations: Arc<ConversationService>,
+    branches: Arc<BranchService>,
+}
+
+impl ExportService {
+    pub fn new(db: PgPool, conversations: Arc<ConversationService>, branches: Arc<BranchService>) -> Self {
+        Self {
+            db,
+            conversations,
+            branches,
+        }
+    }
+    
+    pub async fn export_conversation(&self, conversation: &Conversation) -> Result<ExportDocument> {
+        let exported = self.conversation(conversation).await?;
+        
+        Ok(ExportDocument::new(vec![exported], Vec::new()))
+    }
+    
+    pub async fn export_account(&self, user_id: &Uuid) -> Result<ExportDocument> {
+        let mut exported = Vec::new();
+        for conversation in self.conversations.list_conversations(&user_id.to_string()).await? {
+            exported.push(self.conversation(&conversation).await?);
+        }
+        
+        let usage = sqlx::query_as::<_, (String, i64, i64)>(
+            r#"
+            SELECT model, SUM(prompt_tokens)::bigint, SUM(completion_tokens)::bigint
+            FROM token_usage
+            WHERE user_id = $1
+            GROUP BY model
+            ORDER BY model
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|(model, prompt_tokens, completion_tokens)| ModelUsage {
+            model,
+            prompt_tokens,
+            completion_tokens,
+        })
+        .collect();
+        
+        Ok(ExportDocument::new(exported, usage))
+    }
+    
+    async fn conversation(&self, conversation: &Conversation) -> Result<ExportedConversation> {
+        let tree: HashMap<String, (Option<String>, Option<i32>)> = sqlx::query_as::<_, (Uuid, Option<Uuid>, Option<i32>)>(
+            r#"
+            SELECT id, parent_id, tokens_used FROM messages
+            WHERE conversation_id = $1
+            "#
+        )
+        .bind(Uuid::parse_str(&conversation.id)?)
+        .fetch_all(&self.db)
+        .await?
+        .into_iter()
+        .map(|(id, parent_id, tokens_used)| (id.to_string(), (parent_id.map(|p| p.to_string()), tokens_used)))
+        .collect();
+        
+        let messages = self.conversations.get_messages(&conversation.id).await?;
+        let mut messages: Vec<ExportedMessage> = self.branches
+            .decrypt(&conversation.id, messages)
+            .await?
+            .into_iter()
+            .map(|m| {
+                let (parent_id, tokens_used) = tree.get(&m.id).cloned().unwrap_or_default();
+                ExportedMessage {
+                    id: m.id,
+                    parent_id,
+                    role: m.role,
+                    content: m.content,
+                    model: m.model,
+                    tokens_used,
+                    created_at: m.created_at,
+                }
+            })
+            .collect();
+        messages.sort_by_key(|m| m.created_at);
+        
+        Ok(ExportedConversation {
+            id: conversation.id.clone(),
+            title: conversation.title.clone(),
+            model: conversation.model.clone(),
+            created_at: conversation.created_at,
+            active_leaf_id: self.branches.active_leaf(&conversation.id).await?,
+            messages,
+        })
+    }
+}
+
+// A single conversation renders as one transcript or message array; a whole
+// account as all of them
+pub fn render(format: ExportFormat, document: &ExportDocument, whole_account: bool) -> Result<String> {
+    let rendered = match format {
+        ExportFormat::Json => serde_json::to_string_pretty(document)?,
+        ExportFormat::Markdown => document.conversations
+            .iter()
+            .map(markdown)
+            .collect::<Vec<_>>()
+            .join("\n---\n\n"),
+        ExportFormat::Openai | ExportFormat::Anthropic => {
+            let mut rendered: Vec<Value> = document.conversations
+                .iter()
+                .map(|conversation| match format {
+                    ExportFormat::Openai => openai_messages(conversation),
+                    _ => anthropic_messages(conversation),
+                })
+                .collect();
+            if whole_account || rendered.len() != 1 {
+                serde_json::to_string_pretty(&rendered)?
+            } else {
+                serde_json::to_string_pretty(&rendered.remove(0))?
+            }
+        }
+    };
+    
+    Ok(rendered)
+}
+
+fn markdown(conversation: &ExportedConversation) -> String {
+    let mut out = format!(
+        "# {}\n\n_{} · {}_\n\n",
+        conversation.title.as_deref().unwrap_or("Untitled conversation"),
+        conversation.model,
+        conversation.created_at.format("%Y-%m-%d %H:%M UTC"),
+    );
+    
+    for message in conversation.active_path() {
+        let speaker = match message.role.as_str() {
+            "user" => "User".to_string(),
+            "assistant" => match &message.model {
+                Some(model) => format!("Assistant ({})", model),
+                None => "Assistant".to_string(),
+            },
+            "system" => "System".to_string(),
+            other => other.to_string(),
+        };
+        out.push_str(&format!("### {}\n\n{}\n\n", speaker, message.content.trim_end()));
+    }
+    
+    out
+}
+
+fn openai_messages(conversation: &ExportedConversation) -> Value {
+    let messages: Vec<Value> = conversation
+        .active_path()
+        .into_iter()
+        .map(|m| json!({ "role": m.role, "content": m.content }))
+        .collect();
+    
+    json!({
+        "title": conversation.title,
+        "model": conversation.model,
+        "messages": messages,
+    })
+}
+
+// The Messages API takes system text separately and wants user and
+// assistant turns to alternate, so consecutive turns of one role are merged
+fn anthropic_messages(conversation: &ExportedConversation) -> Value {
+    let mut system = Vec::new();
+    let mut messages: Vec<(String, String)> = Vec::new();
+    
+    for message in conversation.active_path() {
+        match message.role.as_str() {
+            "system" => system.push(message.content.clone()),
+            role @ ("user" | "assistant") => match messages.last_mut() {
+                Some((last_role, content)) if last_role == role => {
+                    content.push_str("\n\n");
+                    content.push_str(&message.content);
+                }
+                _ => messages.push((role.to_string(), message.content.clone())),
+            },
+            _ => {}
+        }
+    }
+    
+    let mut rendered = json!({
+        "title": conversation.title,
+        "model": conversation.model,
+        "messages": messages
+            .into_iter()
+            .map(|(role, content)| json!({ "role": role, "content": content }))
+            .collect::<Vec<_>>(),
+    });
+    if !system.is_empty() {
+        rendered["system"] = json!(system.join("\n\n"));
+    }
+    rendered
+}
@@ -0,0 +1,494 @@
+use crate::services::export::{ExportDocument, DOCUMENT_FORMAT};
+use crate::services::pubsub::{user_topic, TopicEvent};
+use crate::services::{BranchService, ConversationService, PubSubService};
+use anyhow::Result;
+use chrono::{DateTime, TimeZone, Utc};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+// Finished jobs stay visible this long
+const JOB_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportSource {
+    // Our own JSON export
+    Native,
+    // `conversations.json` from a ChatGPT data export
+    Chatgpt,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportJob {
+    pub id: String,
+    pub user_id: String,
+    pub source: ImportSource,
+    pub status: ImportStatus,
+    pub total: usize,
+    pub imported: usize,
+    pub failed: usize,
+    pub conversation_ids: Vec<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// One message to import; `key` and `parent` are ids in the source document
+#[derive(Debug, Clone)]
+pub struct ImportedMessage {
+    pub key: String,
+    pub parent: Option<String>,
+    pub role: String,
+    pub content: String,
+    pub model: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportedConversation {
+    pub title: Option<String>,
+    pub model: Option<String>,
+    pub created_at: Option<DateTime<Utc>>,
+    // Parents always precede their children
+    pub messages: Vec<ImportedMessage>,
+    pub active_leaf: Option<String>,
+}
+
+// Imports conversation histories as background jobs. Progress is kept in
+// Redis, so any replica can report it, and published on the user's
+// `imports` topic for WebSocket clients
+pub struct ImportService {
+    db: PgPool,
+    redis: ConnectionManager,
+    conversations: Arc<ConversationService>,
+    branches: Arc<BranchService>,
+    pubsub: Arc<PubSubService>,
+    default_model: String,
+}
+
+impl ImportService {
+    pub fn new(
+        db: PgPool,
+        redis: ConnectionManager,
+        conversations: Arc<ConversationService>,
+        branches: Arc<BranchService>,
+        pubsub: Arc<PubSubService>,
+        default_model: String,
+    ) -> Self {
+        Self {
+            db,
+            redis,
+            conversations,
+            branches,
+            pubsub,
+            default_model,
+        }
+    }
+    
+    pub async fn start(self: &Arc<Self>, user_id: &Uuid, source: ImportSource, conversations: Vec<ImportedConversation>) -> Result<ImportJob> {
+        let now = Utc::now();
+        let job = ImportJob {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            source,
+            status: ImportStatus::Running,
+            total: conversations.len(),
+            imported: 0,
+            failed: 0,
+            conversation_ids: Vec::new(),
+            error: None,
+            created_at: now,
+            updated_at: now,
+        };
+        self.save(&job).await?;
+        
+        let importer = self.clone();
+        let running = job.clone();
+        tokio::spawn(async move {
+            importer.run(running, conversations).await;
+        });
+        
+        Ok(job)
+    }
+    
+    pub async fn get(&self, job_id: &str) -> Result<Option<ImportJob>> {
+        let mut conn = self.redis.clone();
+        let stored: Option<String> = conn.get(job_key(job_id)).await?;
+        
+        Ok(stored.map(|s| serde_json::from_str(&s)).transpose()?)
+    }
+    
+    async fn run(&self, mut job: ImportJob, conversations: Vec<ImportedConversation>) {
+        for conversation in conversations {
+            match self.import_conversation(&job.user_id, conversation).await {
+                Ok(conversation_id) => {
+                    job.imported += 1;
+                    job.conversation_ids.push(conversation_id);
+                }
+                Err(e) => {
+                    warn!("Import {}: failed to import a conversation: {}", job.id, e);
+                    job.failed += 1;
+                }
+            }
+            self.progress(&job).await;
+        }
+        
+        job.status = if job.imported == 0 && job.failed > 0 {
+            job.error = Some("No conversation could be imported".to_string());
+            ImportStatus::Failed
+        } else {
+            ImportStatus::Completed
+        };
+        self.progress(&job).await;
+        info!("Import {} finished: {} imported, {} failed", job.id, job.imported, job.failed);
+        
+        if job.imported > 0 {
+            self.pubsub.publish(TopicEvent {
+                topic: user_topic(&job.user_id, "sessions"),
+                origin: None,
+                data: json!({ "type": "sessionsImported", "sessionIds": job.conversation_ids }),
+            }).await;
+        }
+    }
+    
+    // Recreates the message tree by saving each message under its parent.
+    // User messages are attributed to the importing user
+    async fn import_conversation(&self, user_id: &str, conversation: ImportedConversation) -> Result<String> {
+        let model = conversation.model.unwrap_or_else(|| self.default_model.clone());
+        let created = self.conversations.create_conversation(user_id, &model).await?;
+        if let Some(title) = conversation.title.filter(|t| !t.trim().is_empty()) {
+            self.conversations.set_title(&created.id, &title).await?;
+        }
+        
+        let mut ids: HashMap<String, String> = HashMap::new();
+        let mut last = None;
+        for message in conversation.messages {
+            let parent = message.parent.as_ref().and_then(|key| ids.get(key)).cloned();
+            let author = (message.role == "user").then_some(user_id);
+            let id = self.branches
+                .add_message(&created.id, parent.as_deref(), author, &message.role, &message.content, &json!({}))
+                .await?;
+            
+            sqlx::query(
+                r#"
+                UPDATE messages SET created_at = COALESCE($2, created_at), model = COALESCE($3, model)
+                WHERE id = $1
+                "#
+            )
+            .bind(Uuid::parse_str(&id)?)
+            .bind(message.created_at)
+            .bind(message.model)
+            .execute(&self.db)
+            .await?;
+            
+            ids.insert(message.key, id.clone());
+            last = Some(id);
+        }
+        
+        let leaf = conversation.active_leaf.and_then(|key| ids.get(&key).cloned()).or(last);
+        self.branches.set_active_leaf(&created.id, leaf.as_deref()).await?;
+        if let Some(created_at) = conversation.created_at {
+            sqlx::query("UPDATE conversations SET created_at = $2 WHERE id = $1")
+                .bind(Uuid::parse_str(&created.id)?)
+                .bind(created_at)
+                .execute(&self.db)
+                .await?;
+        }
+        
+        Ok(created.id)
+    }
+    
+    async fn progress(&self, job: &ImportJob) {
+        let mut job = job.clone();
+        job.updated_at = Utc::now();
+        if let Err(e) = self.save(&job).await {
+            error!("Failed to save progress of import {}: {}", job.id, e);
+        }
+        
+        self.pubsub.publish(TopicEvent {
+            topic: user_topic(&job.user_id, "imports"),
+            origin: None,
+            data: json!({ "type": "importProgress", "job": job }),
+        }).await;
+    }
+    
+    async fn save(&self, job: &ImportJob) -> Result<()> {
+        let mut conn = self.redis.clone();
+        conn.set_ex::<_, _, ()>(job_key(&job.id), serde_json::to_string(job)?, JOB_TTL_SECS).await?;
+        
+        Ok(())
+    }
+}
+
+// Detects the source when it is not given
+pub fn parse(source: Option<ImportSource>, body: &Value) -> Result<(ImportSource, Vec<ImportedConversation>), String> {
+    let source = match source {
+        Some(source) => source,
+        None if body["format"] == DOCUMENT_FORMAT => ImportSource::Native,
+        None if body.as_array().is_some_and(|items| items.iter().all(|c| c.get("mapping").is_some())) => ImportSource::Chatgpt,
+        None => return Err("Unrecognized import format".to_string()),
+    };
+    
+    let conversations = match source {
+        ImportSource::Native => parse_native(body)?,
+        ImportSource::Chatgpt => parse_chatgpt(body)?,
+    };
+    
+    Ok((source, conversations))
+}
+
+fn parse_native(body: &Value) -> Result<Vec<ImportedConversation>, String> {
+    let document: ExportDocument = serde_json::from_value(body.clone()).map_err(|e| format!("Invalid export document: {}", e))?;
+    
+    Ok(document.conversations
+        .into_iter()
+        .map(|conversation| {
+            let mut messages = conversation.messages;
+            messages.sort_by_key(|m| m.created_at);
+            ImportedConversation {
+                title: conversation.title,
+                model: Some(conversation.model),
+                created_at: Some(conversation.created_at),
+                messages: messages
+                    .into_iter()
+                    .filter(|m| is_importable_role(&m.role))
+                    .map(|m| ImportedMessage {
+                        key: m.id,
+                        parent: m.parent_id,
+                        role: m.role,
+                        content: m.content,
+                        model: m.model,
+                        created_at: Some(m.created_at),
+                    })
+                    .collect(),
+                active_leaf: conversation.active_leaf_id,
+            }
+        })
+        .collect())
+}
+
+// ChatGPT exports store each conversation as a tree in `mapping`, including
+// nodes that were never shown (empty system prompts, tool calls). Those are
+// skipped and their children attached to the nearest shown ancestor
+fn parse_chatgpt(body: &Value) -> Result<Vec<ImportedConversation>, String> {
+    let items = body.as_array().ok_or("Expected an array of conversations")?;
+    
+    let mut conversations = Vec::with_capacity(items.len());
+    for item in items {
+        let mapping = item["mapping"].as_object().ok_or("Conversation without a mapping")?;
+        
+        let roots: Vec<&String> = mapping
+            .iter()
+            .filter(|(_, node)| node["parent"].as_str().map_or(true, |parent| !mapping.contains_key(parent)))
+            .map(|(id, _)| id)
+            .collect();
+        
+        // Depth-first from the roots, carrying the nearest shown ancestor
+        let mut messages = Vec::new();
+        let mut shown_ancestor: HashMap<&str, Option<String>> = HashMap::new();
+        let mut seen = HashSet::new();
+        let mut stack: Vec<(&str, Option<String>)> = roots.into_iter().rev().map(|id| (id.as_str(), None)).collect();
+        while let Some((id, parent)) = stack.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            let node = &mapping[id];
+            
+            let shown = match chatgpt_message(id, &node["message"], parent.clone()) {
+                Some(message) => {
+                    messages.push(message);
+                    Some(id.to_string())
+                }
+                None => parent,
+            };
+            shown_ancestor.insert(id, shown.clone());
+            
+            if let Some(children) = node["children"].as_array() {
+                for child in children.iter().rev().filter_map(Value::as_str) {
+                    if mapping.contains_key(child) {
+                        stack.push((child, shown.clone()));
+                    }
+                }
+            }
+        }
+        
+        let model = messages.iter().rev().find_map(|m| m.model.clone());
+        conversations.push(ImportedConversation {
+            title: item["title"].as_str().map(str::to_string),
+            model,
+            created_at: item["create_time"].as_f64().and_then(timestamp),
+            active_leaf: item["current_node"].as_str().and_then(|id| shown_ancestor.get(id).cloned().flatten()),
+            messages,
+        });
+    }
+    
+    Ok(conversations)
+}
+
+fn chatgpt_message(id: &str, message: &Value, parent: Option<String>) -> Option<ImportedMessage> {
+    let role = message["author"]["role"].as_str()?;
+    if !is_importable_role(role) || message["metadata"]["is_visually_hidden_from_conversation"] == true {
+        return None;
+    }
+    
+    // Text parts only; images and other attachments are left out
+    let content = message["content"]["parts"]
+        .as_array()?
+        .iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>()
+        .join("\n");
+    if content.trim().is_empty() {
+        return None;
+    }
+    
+    Some(ImportedMessage {
+        key: id.to_string(),
+        parent,
+        role: role.to_string(),
+        content,
+        model: message["metadata"]["model_slug"].as_str().map(str::to_string),
+        created_at: message["create_time"].as_f64().and_then(timestamp),
+    })
+}
+
+fn is_importable_role(role: &str) -> bool {
+    matches!(role, "user" | "assistant" | "system")
+}
+
+fn timestamp(secs: f64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_opt(secs.trunc() as i64, (secs.fract() * 1e9) as u32).single()
+}
+
+fn job_key(job_id: &str) -> String {
+    format!("import:job:{}", job_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn node(parent: Option<&str>, role: Option<&str>, text: &str, children: &[&str]) -> Value {
+        let message = match role {
+            Some(role) => json!({
+                "author": { "role": role },
+                "content": { "parts": [text] },
+                "metadata": { "model_slug": (role == "assistant").then_some("gpt-4o") },
+                "create_time": 1_700_000_000.5,
+            }),
+            None => Value::Null,
+        };
+        json!({ "parent": parent, "children": children, "message": message })
+    }
+    
+    // root -> empty system -> prompt, answered twice; the first answer goes
+    // on through a tool call, the second ends in a hidden message
+    fn export() -> Value {
+        let mut hidden = node(Some("a2"), Some("assistant"), "Thinking", &[]);
+        hidden["message"]["metadata"]["is_visually_hidden_from_conversation"] = json!(true);
+        json!([{
+            "title": "Branches",
+            "create_time": 1_700_000_000.0,
+            "current_node": "h",
+            "mapping": {
+                "root": node(None, None, "", &["s"]),
+                "s": node(Some("root"), Some("system"), "", &["u1"]),
+                "u1": node(Some("s"), Some("user"), "Hi", &["a1", "a2"]),
+                "a1": node(Some("u1"), Some("assistant"), "Hello", &["t1"]),
+                "t1": node(Some("a1"), Some("tool"), "search results", &["u2"]),
+                "u2": node(Some("t1"), Some("user"), "More", &[]),
+                "a2": node(Some("u1"), Some("assistant"), "Hey", &["h"]),
+                "h": hidden,
+            },
+        }])
+    }
+    
+    fn shape(conversation: &ImportedConversation) -> Vec<(&str, Option<&str>, &str)> {
+        conversation.messages
+            .iter()
+            .map(|m| (m.key.as_str(), m.parent.as_deref(), m.role.as_str()))
+            .collect()
+    }
+    
+    #[test]
+    fn detects_chatgpt_exports() {
+        let (source, conversations) = parse(None, &export()).unwrap();
+        assert_eq!(source, ImportSource::Chatgpt);
+        assert_eq!(conversations.len(), 1);
+        
+        assert!(parse(None, &json!({ "conversations": [] })).is_err());
+    }
+    
+    #[test]
+    fn hidden_nodes_are_skipped_and_children_reattached() {
+        let conversations = parse_chatgpt(&export()).unwrap();
+        let conversation = &conversations[0];
+        
+        assert_eq!(
+            shape(conversation),
+            vec![
+                ("u1", None, "user"),
+                ("a1", Some("u1"), "assistant"),
+                ("u2", Some("a1"), "user"),
+                ("a2", Some("u1"), "assistant"),
+            ],
+        );
+        assert_eq!(conversation.title.as_deref(), Some("Branches"));
+        assert_eq!(conversation.model.as_deref(), Some("gpt-4o"));
+        assert_eq!(conversation.messages[0].content, "Hi");
+    }
+    
+    #[test]
+    fn parents_precede_children() {
+        let conversations = parse_chatgpt(&export()).unwrap();
+        let mut seen = HashSet::new();
+        for message in &conversations[0].messages {
+            if let Some(parent) = &message.parent {
+                assert!(seen.contains(parent), "{} before its parent {}", message.key, parent);
+            }
+            seen.insert(message.key.clone());
+        }
+    }
+    
+    #[test]
+    fn hidden_current_node_resolves_to_shown_ancestor() {
+        let conversations = parse_chatgpt(&export()).unwrap();
+        assert_eq!(conversations[0].active_leaf.as_deref(), Some("a2"));
+    }
+    
+    #[test]
+    fn dangling_parents_and_cycles_do_not_break_the_walk() {
+        let body = json!([{
+            "title": null,
+            "mapping": {
+                "u1": node(Some("missing"), Some("user"), "Hi", &["a1"]),
+                "a1": node(Some("u1"), Some("assistant"), "Hello", &["u1"]),
+            },
+        }]);
+        let conversations = parse_chatgpt(&body).unwrap();
+        
+        assert_eq!(shape(&conversations[0]), vec![("u1", None, "user"), ("a1", Some("u1"), "assistant")]);
+        assert_eq!(conversations[0].active_leaf, None);
+    }
+    
+    #[test]
+    fn conversation_without_mapping_is_rejected() {
+        assert!(parse_chatgpt(&json!([{ "title": "x" }])).is_err());
+        assert!(parse_chatgpt(&json!({})).is_err());
+    }
+}
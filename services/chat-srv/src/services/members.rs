@@ -0,0 +1,342 @@
+use crate::services::user::User;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Ordered by what a role may do: each role can do everything the ones
+// before it can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberRole {
+    // Reads the conversation and follows it live
+    Viewer,
+    // Also sends messages and switches branches
+    Editor,
+    // Also manages members, shares and deletes the conversation
+    Owner,
+}
+
+impl MemberRole {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "viewer" => Some(Self::Viewer),
+            "editor" => Some(Self::Editor),
+            "owner" => Some(Self::Owner),
+            _ => None,
+        }
+    }
+    
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MemberRole::Viewer => "viewer",
+            MemberRole::Editor => "editor",
+            MemberRole::Owner => "owner",
+        }
+    }
+    
+    pub fn allows(&self, required: MemberRole) -> bool {
+        *self >= required
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Member {
+    pub user_id: Uuid,
+    pub name: String,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub invitee_id: Option<Uuid>,
+    pub email: Option<String>,
+    pub role: String,
+    pub invited_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InviteMemberRequest {
+    // One of user_id or email
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+    #[serde(default)]
+    pub email: Option<String>,
+    pub role: MemberRole,
+}
+
+// Membership of shared conversations. The owner is a member like any other
+// (010_conversation_members adds the row), so every permission check is a
+// role lookup here
+pub struct MemberService {
+    db: PgPool,
+    invitation_ttl: chrono::Duration,
+}
+
+impl MemberService {
+    pub fn new(db: PgPool, invitation_ttl_days: u32) -> Self {
+        Self {
+            db,
+            invitation_ttl: chrono::Duration::days(invitation_ttl_days as i64),
+        }
+    }
+    
+    // None when the user is not a member or the conversation does not exist
+    // or is in the trash
+    pub async fn role(&self, conversation_id: &str, user_id: &Uuid) -> Result<Option<MemberRole>> {
+        let Ok(conversation_id) = Uuid::parse_str(conversation_id) else {
+            return Ok(None);
+        };
+        let role = sqlx::query_scalar::<_, String>(
+            r#"
+            SELECT m.role FROM conversation_members m
+            JOIN conversations c ON c.id = m.conversation_id
+            WHERE m.conversation_id = $1 AND m.user_id = $2 AND c.deleted_at IS NULL
+            "#
+        )
+        .bind(conversation_id)
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(role.as_deref().and_then(MemberRole::parse))
+    }
+    
+    pub async fn members(&self, conversation_id: &str) -> Result<Vec<Member>> {
+        let members = sqlx::query_as::<_, Member>(
+            r#"
+            SELECT m.user_id, u.name, u.email, m.role, m.invited_by, m.created_at
+            FROM conversation_members m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.conversation_id = $1
+            ORDER BY m.created_at
+            "#
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(members)
+    }
+    
+    pub async fn member_ids(&self, conversation_id: &str) -> Result<Vec<String>> {
+        let ids = sqlx::query_scalar::<_, Uuid>(
+            "SELECT user_id FROM conversation_members WHERE conversation_id = $1",
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(ids.into_iter().map(|id| id.to_string()).collect())
+    }
+    
+    // Conversations other users shared with `user_id`, most recent first
+    pub async fn shared_with(&self, user_id: &str) -> Result<Vec<String>> {
+        let ids = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            SELECT m.conversation_id FROM conversation_members m
+            JOIN conversations c ON c.id = m.conversation_id
+            WHERE m.user_id = $1 AND m.role <> 'owner' AND c.deleted_at IS NULL
+            ORDER BY c.updated_at DESC
+            "#
+        )
+        .bind(Uuid::parse_str(user_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(ids.into_iter().map(|id| id.to_string()).collect())
+    }
+    
+    pub async fn invite(
+        &self,
+        conversation_id: &str,
+        invited_by: &Uuid,
+        invitee_id: Option<Uuid>,
+        email: Option<&str>,
+        role: MemberRole,
+    ) -> Result<Invitation> {
+        let invitation = sqlx::query_as::<_, Invitation>(
+            r#"
+            INSERT INTO conversation_invitations (conversation_id, invitee_id, email, role, invited_by, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .bind(invitee_id)
+        .bind(email.map(str::to_lowercase))
+        .bind(role.as_str())
+        .bind(invited_by)
+        .bind(Utc::now() + self.invitation_ttl)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(invitation)
+    }
+    
+    pub async fn get_invitation(&self, invitation_id: &Uuid) -> Result<Option<Invitation>> {
+        let invitation = sqlx::query_as::<_, Invitation>(
+            "SELECT * FROM conversation_invitations WHERE id = $1",
+        )
+        .bind(invitation_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(invitation)
+    }
+    
+    pub async fn pending_for_conversation(&self, conversation_id: &str) -> Result<Vec<Invitation>> {
+        let invitations = sqlx::query_as::<_, Invitation>(
+            r#"
+            SELECT * FROM conversation_invitations
+            WHERE conversation_id = $1 AND status = 'pending' AND expires_at > NOW()
+            ORDER BY created_at DESC
+            "#
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(invitations)
+    }
+    
+    // Invitations addressed to the user directly or to their email address
+    pub async fn pending_for_user(&self, user: &User) -> Result<Vec<Invitation>> {
+        let invitations = sqlx::query_as::<_, Invitation>(
+            r#"
+            SELECT * FROM conversation_invitations
+            WHERE (invitee_id = $1 OR LOWER(email) = LOWER($2))
+              AND status = 'pending' AND expires_at > NOW()
+            ORDER BY created_at DESC
+            "#
+        )
+        .bind(user.id)
+        .bind(&user.email)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(invitations)
+    }
+    
+    // Accepts or declines an invitation addressed to `user`. None when there
+    // is no such pending invitation. Accepting never demotes the owner
+    pub async fn respond(&self, invitation_id: &Uuid, user: &User, accept: bool) -> Result<Option<Invitation>> {
+        let mut tx = self.db.begin().await?;
+        
+        let invitation = sqlx::query_as::<_, Invitation>(
+            r#"
+            UPDATE conversation_invitations
+            SET status = $4, responded_at = NOW()
+            WHERE id = $1
+              AND (invitee_id = $2 OR LOWER(email) = LOWER($3))
+              AND status = 'pending' AND expires_at > NOW()
+            RETURNING *
+            "#
+        )
+        .bind(invitation_id)
+        .bind(user.id)
+        .bind(&user.email)
+        .bind(if accept { "accepted" } else { "declined" })
+        .fetch_optional(&mut *tx)
+        .await?;
+        
+        if let Some(invitation) = invitation.as_ref().filter(|_| accept) {
+            sqlx::query(
+                r#"
+                INSERT INTO conversation_members (conversation_id, user_id, role, invited_by)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (conversation_id, user_id)
+                DO UPDATE SET role = EXCLUDED.role, invited_by = EXCLUDED.invited_by
+                WHERE conversation_members.role <> 'owner'
+                "#
+            )
+            .bind(invitation.conversation_id)
+            .bind(user.id)
+            .bind(&invitation.role)
+            .bind(invitation.invited_by)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        tx.commit().await?;
+        
+        Ok(invitation)
+    }
+    
+    pub async fn revoke_invitation(&self, invitation_id: &Uuid) -> Result<bool> {
+        let revoked = sqlx::query(
+            r#"
+            UPDATE conversation_invitations
+            SET status = 'revoked', responded_at = NOW()
+            WHERE id = $1 AND status = 'pending'
+            "#
+        )
+        .bind(invitation_id)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        
+        Ok(revoked > 0)
+    }
+    
+    // Changes a non-owner's role; false when they are not such a member
+    pub async fn set_role(&self, conversation_id: &str, user_id: &Uuid, role: MemberRole) -> Result<bool> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE conversation_members SET role = $3
+            WHERE conversation_id = $1 AND user_id = $2 AND role <> 'owner'
+            "#
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .bind(user_id)
+        .bind(role.as_str())
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        
+        Ok(updated > 0)
+    }
+    
+    // The owner cannot be removed; deleting the conversation is their way out
+    pub async fn remove(&self, conversation_id: &str, user_id: &Uuid) -> Result<bool> {
+        let removed = sqlx::query(
+            r#"
+            DELETE FROM conversation_members
+            WHERE conversation_id = $1 AND user_id = $2 AND role <> 'owner'
+            "#
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .bind(user_id)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        
+        Ok(removed > 0)
+    }
+    
+    // Author of each user message, by message id
+    pub async fn authors(&self, conversation_id: &str) -> Result<HashMap<String, String>> {
+        let rows = sqlx::query_as::<_, (Uuid, Uuid)>(
+            r#"
+            SELECT id, author_id FROM messages
+            WHERE conversation_id = $1 AND author_id IS NOT NULL
+            "#
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(rows.into_iter().map(|(id, author)| (id.to_string(), author.to_string())).collect())
+    }
+}
@@ -1,7 +1,41 @@
+pub mod admin;
+pub mod audit;
+pub mod branches;
 pub mod conversation;
+pub mod encryption;
+pub mod export;
+pub mod import;
+pub mod members;
+pub mod policy;
+pub mod privacy;
+pub mod pubsub;
+pub mod retention;
+pub mod search;
+pub mod session_registry;
+pub mod share;
+pub mod stream_buffer;
+pub mod summarizer;
+pub mod templates;
 pub mod token_meter;
 pub mod user;
 
+pub use admin::AdminService;
+pub use audit::AuditService;
+pub use branches::BranchService;
 pub use conversation::ConversationService;
+pub use encryption::EncryptionService;
+pub use export::ExportService;
+pub use import::ImportService;
+pub use members::MemberService;
+pub use policy::PolicyService;
+pub use privacy::PrivacyService;
+pub use pubsub::PubSubService;
+pub use retention::RetentionService;
+pub use search::SearchService;
+pub use session_registry::SessionRegistryService;
+pub use share::ShareService;
+pub use stream_buffer::StreamBufferService;
+pub use summarizer::SummarizerService;
+pub use templates::TemplateService;
 pub use token_meter::TokenMeterService;
 pub use user::UserService;
\ No newline at end of file
@@ -0,0 +1,208 @@
+use crate::redaction::{builtin_detector_names, Detector, RedactionAction, RedactionPolicy};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+const POLICY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RedactionRule {
+    pub id: Uuid,
+    pub org_id: Option<Uuid>,
+    pub detector: String,
+    pub action: String,
+    pub pattern: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpsertRedactionRuleRequest {
+    pub org_id: Option<Uuid>,
+    pub detector: String,
+    pub action: RedactionAction,
+    pub pattern: Option<String>,
+}
+
+pub struct PolicyService {
+    db: PgPool,
+    redaction_enabled: bool,
+    default_action: RedactionAction,
+    redaction_cache: DashMap<Option<Uuid>, (Instant, Arc<RedactionPolicy>)>,
+}
+
+impl PolicyService {
+    pub fn new(db: PgPool, redaction_enabled: bool, default_action: RedactionAction) -> Self {
+        Self {
+            db,
+            redaction_enabled,
+            default_action,
+            redaction_cache: DashMap::new(),
+        }
+    }
+    
+    pub async fn user_org(&self, user_id: &Uuid) -> Result<Option<Uuid>> {
+        let org_id = sqlx::query_scalar::<_, Option<Uuid>>(
+            r#"
+            SELECT org_id FROM users
+            WHERE id = $1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .flatten();
+        
+        Ok(org_id)
+    }
+    
+    // Returns None when redaction is switched off for this deployment
+    pub async fn redaction_policy_for_user(&self, user_id: &Uuid) -> Result<Option<Arc<RedactionPolicy>>> {
+        if !self.redaction_enabled {
+            return Ok(None);
+        }
+        
+        let org_id = self.user_org(user_id).await?;
+        self.redaction_policy_for_org(org_id).await.map(Some)
+    }
+    
+    pub async fn redaction_policy_for_org(&self, org_id: Option<Uuid>) -> Result<Arc<RedactionPolicy>> {
+        if let Some(entry) = self.redaction_cache.get(&org_id) {
+            let (loaded_at, policy) = entry.value();
+            if loaded_at.elapsed() < POLICY_CACHE_TTL {
+                return Ok(policy.clone());
+            }
+        }
+        
+        // Global rules first so org rules override them
+        let rules = sqlx::query_as::<_, RedactionRule>(
+            r#"
+            SELECT * FROM redaction_policies
+            WHERE org_id IS NULL OR org_id = $1
+            ORDER BY org_id NULLS FIRST, created_at
+            "#
+        )
+        .bind(org_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let mut policy = RedactionPolicy::new(self.default_action);
+        for rule in rules {
+            let Some(action) = RedactionAction::parse(&rule.action) else {
+                continue;
+            };
+            
+            if let Some(pattern) = &rule.pattern {
+                policy.custom_detectors.retain(|d| d.name != rule.detector);
+                let detector = Detector::new(&rule.detector, &custom_label(&rule.detector), pattern)
+                    .with_context(|| format!("Invalid pattern for redaction rule {}", rule.id))?;
+                policy.custom_detectors.push(detector);
+            }
+            policy.actions.insert(rule.detector, action);
+        }
+        
+        let policy = Arc::new(policy);
+        self.redaction_cache.insert(org_id, (Instant::now(), policy.clone()));
+        
+        Ok(policy)
+    }
+    
+    pub async fn list_redaction_rules(&self, org_id: Option<Uuid>) -> Result<Vec<RedactionRule>> {
+        let rules = sqlx::query_as::<_, RedactionRule>(
+            r#"
+            SELECT * FROM redaction_policies
+            WHERE org_id IS NOT DISTINCT FROM $1
+            ORDER BY detector
+            "#
+        )
+        .bind(org_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(rules)
+    }
+    
+    pub async fn upsert_redaction_rule(&self, request: UpsertRedactionRuleRequest) -> Result<RedactionRule> {
+        let action = match request.action {
+            RedactionAction::Block => "block",
+            RedactionAction::Mask => "mask",
+            RedactionAction::Allow => "allow",
+        };
+        
+        let rule = sqlx::query_as::<_, RedactionRule>(
+            r#"
+            INSERT INTO redaction_policies (org_id, detector, action, pattern)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (COALESCE(org_id, '00000000-0000-0000-0000-000000000000'::uuid), detector)
+            DO UPDATE SET action = EXCLUDED.action, pattern = EXCLUDED.pattern
+            RETURNING *
+            "#
+        )
+        .bind(request.org_id)
+        .bind(&request.detector)
+        .bind(action)
+        .bind(&request.pattern)
+        .fetch_one(&self.db)
+        .await?;
+        
+        self.invalidate(request.org_id);
+        
+        Ok(rule)
+    }
+    
+    pub async fn org_exists(&self, org_id: &Uuid) -> Result<bool> {
+        let exists = sqlx::query_scalar::<_, bool>("SELECT EXISTS(SELECT 1 FROM organizations WHERE id = $1)")
+            .bind(org_id)
+            .fetch_one(&self.db)
+            .await?;
+        
+        Ok(exists)
+    }
+    
+    pub async fn delete_redaction_rule(&self, rule_id: &Uuid) -> Result<Option<RedactionRule>> {
+        let rule = sqlx::query_as::<_, RedactionRule>(
+            r#"
+            DELETE FROM redaction_policies
+            WHERE id = $1
+            RETURNING *
+            "#
+        )
+        .bind(rule_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        if let Some(rule) = &rule {
+            self.invalidate(rule.org_id);
+        }
+        
+        Ok(rule)
+    }
+    
+    fn invalidate(&self, org_id: Option<Uuid>) {
+        // A global rule change affects every org's merged policy
+        if org_id.is_none() {
+            self.redaction_cache.clear();
+        } else {
+            self.redaction_cache.remove(&org_id);
+        }
+    }
+}
+
+pub fn is_builtin_detector(name: &str) -> bool {
+    builtin_detector_names().contains(&name)
+}
+
+fn custom_label(name: &str) -> String {
+    // Placeholder labels may only contain A-Z and underscores
+    let label: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+        .filter(|c| !c.is_ascii_digit())
+        .collect();
+    
+    if label.trim_matches('_').is_empty() { "CUSTOM".to_string() } else { label }
+}
@@ -0,0 +1,604 @@
+use crate::services::audit::{sha256_hex, AuditEvent};
+use crate::services::pubsub::{user_topic, TopicEvent};
+use crate::services::templates::PromptTemplate;
+use crate::services::user::User;
+use crate::services::{AuditService, ConversationService, ExportService, PubSubService, StreamBufferService};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+pub const ARCHIVE_FORMAT: &str = "chat-srv-data-export";
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DataExportStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataExportJob {
+    pub id: String,
+    pub user_id: String,
+    pub status: DataExportStatus,
+    #[serde(default)]
+    pub size_bytes: Option<usize>,
+    // SHA-256 of the archive, so a download can be checked
+    #[serde(default)]
+    pub sha256: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ApiKeyMetadata {
+    pub id: Uuid,
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub is_active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct UsageRecord {
+    pub model: String,
+    pub prompt_tokens: i32,
+    pub completion_tokens: i32,
+    pub total_tokens: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Membership {
+    pub conversation_id: Uuid,
+    pub role: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ErasureReceipt {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub requested_by: Uuid,
+    pub erased: Value,
+    pub retained: Value,
+    pub verification: Value,
+    pub verified: bool,
+    pub receipt_hash: String,
+    pub started_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
+
+// Data subject requests: a downloadable archive of everything we hold on a
+// user, and erasure of it. Export jobs and archives live in Redis under the
+// user's id until they expire, like import jobs
+pub struct PrivacyService {
+    db: PgPool,
+    redis: ConnectionManager,
+    conversations: Arc<ConversationService>,
+    export: Arc<ExportService>,
+    pubsub: Arc<PubSubService>,
+    audit: Arc<AuditService>,
+    stream_buffer: Arc<StreamBufferService>,
+    export_ttl_secs: u64,
+}
+
+impl PrivacyService {
+    pub fn new(
+        db: PgPool,
+        redis: ConnectionManager,
+        conversations: Arc<ConversationService>,
+        export: Arc<ExportService>,
+        pubsub: Arc<PubSubService>,
+        audit: Arc<AuditService>,
+        stream_buffer: Arc<StreamBufferService>,
+        export_ttl_secs: u64,
+    ) -> Self {
+        Self {
+            db,
+            redis,
+            conversations,
+            export,
+            pubsub,
+            audit,
+            stream_buffer,
+            export_ttl_secs,
+        }
+    }
+    
+    pub async fn start_export(self: &Arc<Self>, user_id: &Uuid) -> Result<DataExportJob> {
+        let now = Utc::now();
+        let job = DataExportJob {
+            id: Uuid::new_v4().to_string(),
+            user_id: user_id.to_string(),
+            status: DataExportStatus::Running,
+            size_bytes: None,
+            sha256: None,
+            error: None,
+            created_at: now,
+            updated_at: now,
+            expires_at: now + chrono::Duration::seconds(self.export_ttl_secs as i64),
+        };
+        self.save(&job).await?;
+        
+        let exporter = self.clone();
+        let running = job.clone();
+        let user_id = *user_id;
+        tokio::spawn(async move {
+            exporter.run_export(running, user_id).await;
+        });
+        
+        Ok(job)
+    }
+    
+    pub async fn get_export(&self, user_id: &Uuid, job_id: &str) -> Result<Option<DataExportJob>> {
+        let mut conn = self.redis.clone();
+        let stored: Option<String> = conn.get(job_key(&user_id.to_string(), job_id)).await?;
+        
+        Ok(stored.map(|s| serde_json::from_str(&s)).transpose()?)
+    }
+    
+    pub async fn archive(&self, user_id: &Uuid, job_id: &str) -> Result<Option<Vec<u8>>> {
+        let mut conn = self.redis.clone();
+        let archive: Option<Vec<u8>> = conn.get(archive_key(&user_id.to_string(), job_id)).await?;
+        
+        Ok(archive)
+    }
+    
+    async fn run_export(&self, mut job: DataExportJob, user_id: Uuid) {
+        match self.build_archive(&user_id).await {
+            Ok(archive) => {
+                job.size_bytes = Some(archive.len());
+                job.sha256 = Some(sha256_hex(&archive));
+                match self.save_archive(&job, archive).await {
+                    Ok(()) => job.status = DataExportStatus::Completed,
+                    Err(e) => {
+                        error!("Failed to store data export {}: {}", job.id, e);
+                        job.status = DataExportStatus::Failed;
+                        job.error = Some("Failed to store the archive".to_string());
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Data export {} failed: {}", job.id, e);
+                job.status = DataExportStatus::Failed;
+                job.error = Some("Failed to collect account data".to_string());
+            }
+        }
+        
+        job.updated_at = Utc::now();
+        if let Err(e) = self.save(&job).await {
+            error!("Failed to save data export {}: {}", job.id, e);
+        }
+        info!("Data export {} finished: {:?}", job.id, job.status);
+        
+        self.pubsub.publish(TopicEvent {
+            topic: user_topic(&job.user_id, "exports"),
+            origin: None,
+            data: json!({ "type": "exportFinished", "job": job }),
+        }).await;
+    }
+    
+    // A zip of JSON files. conversations.json is a native export document,
+    // so it can be imported again
+    async fn build_archive(&self, user_id: &Uuid) -> Result<Vec<u8>> {
+        let user = sqlx::query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_optional(&self.db)
+            .await?
+            .context("User not found")?;
+        let (org_id, search_index_consent) = sqlx::query_as::<_, (Option<Uuid>, bool)>(
+            "SELECT org_id, search_index_consent FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_one(&self.db)
+        .await?;
+        
+        let memberships = sqlx::query_as::<_, Membership>(
+            r#"
+            SELECT conversation_id, role, created_at FROM conversation_members
+            WHERE user_id = $1 AND role <> 'owner'
+            ORDER BY created_at
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let api_keys = sqlx::query_as::<_, ApiKeyMetadata>(
+            r#"
+            SELECT id, name, created_at, last_used_at, expires_at, is_active FROM api_keys
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let usage = sqlx::query_as::<_, UsageRecord>(
+            r#"
+            SELECT model, prompt_tokens, completion_tokens, total_tokens, created_at FROM token_usage
+            WHERE user_id = $1
+            ORDER BY created_at
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let templates = sqlx::query_as::<_, PromptTemplate>(
+            r#"
+            SELECT t.id, t.scope, t.owner_id, t.org_id, t.name, t.description, t.current_version,
+                   v.content, v.variables, t.created_at, t.updated_at
+            FROM prompt_templates t
+            JOIN prompt_template_versions v ON v.template_id = t.id AND v.version = t.current_version
+            WHERE t.owner_id = $1
+            ORDER BY t.created_at
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let conversations = self.export.export_account(user_id).await?;
+        
+        let files: Vec<(&'static str, Vec<u8>)> = vec![
+            ("profile.json", serde_json::to_vec_pretty(&json!({
+                "user": user,
+                "org_id": org_id,
+                "search_index_consent": search_index_consent,
+            }))?),
+            ("conversations.json", serde_json::to_vec_pretty(&conversations)?),
+            ("memberships.json", serde_json::to_vec_pretty(&memberships)?),
+            ("api_keys.json", serde_json::to_vec_pretty(&api_keys)?),
+            ("usage.json", serde_json::to_vec_pretty(&usage)?),
+            ("prompt_templates.json", serde_json::to_vec_pretty(&templates)?),
+        ];
+        let manifest = json!({
+            "format": ARCHIVE_FORMAT,
+            "version": ARCHIVE_VERSION,
+            "user_id": user_id,
+            "exported_at": Utc::now(),
+            "files": files
+                .iter()
+                .map(|(name, contents)| (name.to_string(), json!({ "bytes": contents.len(), "sha256": sha256_hex(contents) })))
+                .collect::<BTreeMap<_, _>>(),
+        });
+        let manifest = serde_json::to_vec_pretty(&manifest)?;
+        
+        tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+            let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+            let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+            for (name, contents) in std::iter::once(("manifest.json", manifest)).chain(files) {
+                zip.start_file(name, options)?;
+                zip.write_all(&contents)?;
+            }
+            
+            Ok(zip.finish()?.into_inner())
+        })
+        .await?
+    }
+    
+    // Conversations under legal hold block erasure until the hold is released
+    pub async fn held_conversations(&self, user_id: &Uuid) -> Result<i64> {
+        let held = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM conversations WHERE user_id = $1 AND legal_hold",
+        )
+        .bind(user_id)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(held)
+    }
+    
+    // Deletes what identifies the user and pseudonymizes what has to stay:
+    // the user row keeps its id for billing totals and the audit chain, and
+    // messages they wrote in other people's conversations lose their author.
+    // Safe to run again when a previous run did not verify
+    pub async fn erase(&self, user: &User, requested_by: &Uuid) -> Result<ErasureReceipt> {
+        let started_at = Utc::now();
+        let mut erased: BTreeMap<&str, u64> = BTreeMap::new();
+        
+        // Through ConversationService so its caches forget them too
+        let owned = sqlx::query_as::<_, (Uuid, Vec<Uuid>)>(
+            r#"
+            SELECT c.id, ARRAY(
+                SELECT m.user_id FROM conversation_members m
+                WHERE m.conversation_id = c.id AND m.user_id <> c.user_id
+            )
+            FROM conversations c
+            WHERE c.user_id = $1
+            "#
+        )
+        .bind(user.id)
+        .fetch_all(&self.db)
+        .await?;
+        for (conversation_id, members) in &owned {
+            let conversation_id = conversation_id.to_string();
+            self.conversations.delete_conversation(&conversation_id).await?;
+            
+            let deleted = json!({ "type": "sessionDeleted", "sessionId": conversation_id });
+            for member in members {
+                self.pubsub.publish(TopicEvent {
+                    topic: user_topic(&member.to_string(), "sessions"),
+                    origin: None,
+                    data: deleted.clone(),
+                }).await;
+            }
+        }
+        erased.insert("conversations", owned.len() as u64);
+        
+        let mut tx = self.db.begin().await?;
+        
+        let rolled_up = sqlx::query(
+            r#"
+            INSERT INTO token_usage_rollups (
+                user_id, org_id, model, month, prompt_tokens, completion_tokens, total_tokens, requests
+            )
+            SELECT t.user_id, u.org_id, t.model, date_trunc('month', t.created_at)::date,
+                   SUM(t.prompt_tokens), SUM(t.completion_tokens), SUM(t.total_tokens), COUNT(*)
+            FROM token_usage t
+            JOIN users u ON u.id = t.user_id
+            WHERE t.user_id = $1
+            GROUP BY t.user_id, u.org_id, t.model, date_trunc('month', t.created_at)
+            ON CONFLICT (user_id, model, month) DO UPDATE
+            SET prompt_tokens = token_usage_rollups.prompt_tokens + EXCLUDED.prompt_tokens,
+                completion_tokens = token_usage_rollups.completion_tokens + EXCLUDED.completion_tokens,
+                total_tokens = token_usage_rollups.total_tokens + EXCLUDED.total_tokens,
+                requests = token_usage_rollups.requests + EXCLUDED.requests
+            "#
+        )
+        .bind(user.id)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        
+        // Runs before the profile is pseudonymized, while invitations can
+        // still be matched by email
+        let statements: [(&str, &str); 9] = [
+            ("token_usage", "DELETE FROM token_usage WHERE user_id = $1"),
+            ("api_keys", "DELETE FROM api_keys WHERE user_id = $1"),
+            ("quota_boosts", "DELETE FROM quota_boosts WHERE user_id = $1"),
+            ("memberships", "DELETE FROM conversation_members WHERE user_id = $1"),
+            (
+                "invitations",
+                r#"
+                DELETE FROM conversation_invitations
+                WHERE invitee_id = $1 OR LOWER(email) = (SELECT LOWER(email) FROM users WHERE id = $1)
+                "#,
+            ),
+            ("authored_messages", "UPDATE messages SET author_id = NULL WHERE author_id = $1"),
+            ("search_index", "DELETE FROM message_search_index WHERE user_id = $1"),
+            // Org templates the user wrote stay with the org
+            ("prompt_templates", "DELETE FROM prompt_templates WHERE scope = 'personal' AND owner_id = $1"),
+            // Without its key, any ciphertext left behind in backups is unreadable
+            (
+                "data_keys",
+                r#"
+                DELETE FROM data_keys d
+                WHERE d.owner_type = 'user' AND d.owner_id = $1
+                  AND NOT EXISTS (SELECT 1 FROM messages m WHERE m.data_key_id = d.id)
+                "#,
+            ),
+        ];
+        for (name, statement) in statements {
+            let affected = sqlx::query(statement)
+                .bind(user.id)
+                .execute(&mut *tx)
+                .await?
+                .rows_affected();
+            erased.insert(name, affected);
+        }
+        
+        sqlx::query(
+            r#"
+            UPDATE users
+            SET email = $2, name = 'Erased user', metadata = '{}'::jsonb, is_active = false,
+                search_index_consent = false, erased_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(user.id)
+        .bind(pseudonymous_email(&user.id))
+        .execute(&mut *tx)
+        .await?;
+        erased.insert("profile", 1);
+        
+        tx.commit().await?;
+        
+        // Resume buffers are keyed by response id, so the scan below misses them
+        erased.insert("stream_buffers", self.stream_buffer.clear_user(&user.id.to_string()).await?);
+        erased.insert("redis_keys", self.delete_redis_keys(&user.id).await?);
+        
+        let retained = self.retained(&user.id, rolled_up).await?;
+        let verification = self.verify(&user.id).await?;
+        let verified = verification.values().all(|remaining| *remaining == 0);
+        
+        let body = json!({
+            "user_id": user.id,
+            "requested_by": requested_by,
+            "erased": erased,
+            "retained": retained,
+            "verification": verification,
+            "verified": verified,
+            "started_at": started_at,
+        });
+        let receipt_hash = sha256_hex(&serde_json::to_vec(&body)?);
+        
+        let receipt = sqlx::query_as::<_, ErasureReceipt>(
+            r#"
+            INSERT INTO erasure_receipts (user_id, requested_by, erased, retained, verification, verified, receipt_hash, started_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+            "#
+        )
+        .bind(user.id)
+        .bind(requested_by)
+        .bind(json!(erased))
+        .bind(json!(retained))
+        .bind(json!(verification))
+        .bind(verified)
+        .bind(&receipt_hash)
+        .bind(started_at)
+        .fetch_one(&self.db)
+        .await?;
+        
+        self.audit.record(AuditEvent {
+            event_type: "user.erase".to_string(),
+            actor_id: Some(*requested_by),
+            outcome: if verified { "success" } else { "unverified" }.to_string(),
+            details: json!({
+                "user_id": user.id,
+                "receipt_id": receipt.id,
+                "receipt_hash": receipt.receipt_hash,
+            }),
+            ..Default::default()
+        }).await?;
+        
+        Ok(receipt)
+    }
+    
+    pub async fn latest_receipt(&self, user_id: &Uuid) -> Result<Option<ErasureReceipt>> {
+        let receipt = sqlx::query_as::<_, ErasureReceipt>(
+            r#"
+            SELECT * FROM erasure_receipts
+            WHERE user_id = $1
+            ORDER BY completed_at DESC
+            LIMIT 1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(receipt)
+    }
+    
+    // Every other Redis key we write per user carries the user id: quota
+    // boosts, session registry sets, data export jobs
+    async fn delete_redis_keys(&self, user_id: &Uuid) -> Result<u64> {
+        let keys = self.redis_keys(user_id).await?;
+        if keys.is_empty() {
+            return Ok(0);
+        }
+        
+        let mut conn = self.redis.clone();
+        let deleted: u64 = conn.del(&keys).await?;
+        
+        Ok(deleted)
+    }
+    
+    async fn redis_keys(&self, user_id: &Uuid) -> Result<Vec<String>> {
+        let mut conn = self.redis.clone();
+        let mut iter = conn.scan_match::<_, String>(format!("*{}*", user_id)).await?;
+        let mut keys = Vec::new();
+        while let Some(key) = iter.next_item().await {
+            keys.push(key);
+        }
+        
+        Ok(keys)
+    }
+    
+    // Kept on purpose: billing totals, and audit records, which only the
+    // retention purge may remove without breaking the chain
+    async fn retained(&self, user_id: &Uuid, rolled_up: u64) -> Result<BTreeMap<&'static str, i64>> {
+        let (audit_records, admin_actions, org_templates) = sqlx::query_as::<_, (i64, i64, i64)>(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM audit_log WHERE actor_id = $1),
+                (SELECT COUNT(*) FROM admin_audit_log WHERE target_type = 'user' AND target_id = $1::text),
+                (SELECT COUNT(*) FROM prompt_templates WHERE scope = 'org' AND owner_id = $1)
+            "#
+        )
+        .bind(user_id)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(BTreeMap::from([
+            ("usage_rollups", rolled_up as i64),
+            ("audit_records", audit_records),
+            ("admin_actions", admin_actions),
+            ("org_prompt_templates", org_templates),
+        ]))
+    }
+    
+    // What still links to the user after erasure
+    async fn verify(&self, user_id: &Uuid) -> Result<BTreeMap<&'static str, i64>> {
+        let counts = sqlx::query_as::<_, (i64, i64, i64, i64, i64, i64, i64, i64, i64, i64)>(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM conversations WHERE user_id = $1),
+                (SELECT COUNT(*) FROM conversation_members WHERE user_id = $1),
+                (SELECT COUNT(*) FROM conversation_invitations WHERE invitee_id = $1),
+                (SELECT COUNT(*) FROM messages WHERE author_id = $1),
+                (SELECT COUNT(*) FROM message_search_index WHERE user_id = $1),
+                (SELECT COUNT(*) FROM token_usage WHERE user_id = $1),
+                (SELECT COUNT(*) FROM api_keys WHERE user_id = $1),
+                (SELECT COUNT(*) FROM data_keys WHERE owner_type = 'user' AND owner_id = $1),
+                (SELECT COUNT(*) FROM users WHERE id = $1 AND (erased_at IS NULL OR email <> $2)),
+                (SELECT COUNT(*) FROM prompt_templates WHERE scope = 'personal' AND owner_id = $1)
+            "#
+        )
+        .bind(user_id)
+        .bind(pseudonymous_email(user_id))
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(BTreeMap::from([
+            ("conversations", counts.0),
+            ("memberships", counts.1),
+            ("invitations", counts.2),
+            ("authored_messages", counts.3),
+            ("search_index", counts.4),
+            ("token_usage", counts.5),
+            ("api_keys", counts.6),
+            ("data_keys", counts.7),
+            ("profile", counts.8),
+            ("prompt_templates", counts.9),
+            ("redis_keys", self.redis_keys(user_id).await?.len() as i64),
+            ("stream_buffers", self.stream_buffer.buffered_for_user(&user_id.to_string()).await? as i64),
+        ]))
+    }
+    
+    async fn save(&self, job: &DataExportJob) -> Result<()> {
+        let mut conn = self.redis.clone();
+        conn.set_ex::<_, _, ()>(job_key(&job.user_id, &job.id), serde_json::to_string(job)?, self.export_ttl_secs).await?;
+        
+        Ok(())
+    }
+    
+    async fn save_archive(&self, job: &DataExportJob, archive: Vec<u8>) -> Result<()> {
+        let mut conn = self.redis.clone();
+        conn.set_ex::<_, _, ()>(archive_key(&job.user_id, &job.id), archive, self.export_ttl_secs).await?;
+        
+        Ok(())
+    }
+}
+
+// Unique like the email it replaces, and tied to nothing but the user id
+fn pseudonymous_email(user_id: &Uuid) -> String {
+    format!("erased-{}@erased.invalid", user_id.simple())
+}
+
+fn job_key(user_id: &str, job_id: &str) -> String {
+    format!("data_export:{}:{}", user_id, job_id)
+}
+
+fn archive_key(user_id: &str, job_id: &str) -> String {
+    format!("data_export:{}:{}:archive", user_id, job_id)
+}
@@ -0,0 +1,229 @@
+use dashmap::DashMap;
+use futures::StreamExt;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{error, info, warn};
+
+// Events a slow socket may fall behind by before it starts losing them
+const TOPIC_CAPACITY: usize = 256;
+// Events waiting to be published before new ones are dropped
+const PUBLISH_QUEUE_CAPACITY: usize = 4096;
+const CHANNEL_PREFIX: &str = "chat:events:";
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TopicEvent {
+    pub topic: String,
+    // Socket session that caused the event; it already has it and is skipped
+    pub origin: Option<String>,
+    pub data: serde_json::Value,
+}
+
+// Changes to the Redis channels this replica listens on
+#[derive(Debug)]
+enum Listen {
+    Subscribe(String),
+    Unsubscribe(String),
+}
+
+// Fans events out to every socket subscribed to a topic, on every replica.
+// Topics are either `session:<conversation id>` or per user,
+// `user:<user id>:<name>`. Each topic is a Redis channel; a replica only
+// listens on the channels of topics it has local receivers for, and hands
+// their messages to those receivers
+pub struct PubSubService {
+    redis: ConnectionManager,
+    topics: DashMap<String, broadcast::Sender<TopicEvent>>,
+    listen_tx: mpsc::UnboundedSender<Listen>,
+    listen_rx: Mutex<Option<mpsc::UnboundedReceiver<Listen>>>,
+    publish_tx: mpsc::Sender<(String, String)>,
+    publish_rx: Mutex<Option<mpsc::Receiver<(String, String)>>>,
+}
+
+impl PubSubService {
+    pub fn new(redis: ConnectionManager) -> Self {
+        let (listen_tx, listen_rx) = mpsc::unbounded_channel();
+        let (publish_tx, publish_rx) = mpsc::channel(PUBLISH_QUEUE_CAPACITY);
+        
+        Self {
+            redis,
+            topics: DashMap::new(),
+            listen_tx,
+            listen_rx: Mutex::new(Some(listen_rx)),
+            publish_tx,
+            publish_rx: Mutex::new(Some(publish_rx)),
+        }
+    }
+    
+    // The first receiver of a topic starts listening on its channel
+    pub fn subscribe(self: &Arc<Self>, topic: &str) -> TopicReceiver {
+        let events = self.topics
+            .entry(topic.to_string())
+            .or_insert_with(|| {
+                let _ = self.listen_tx.send(Listen::Subscribe(channel(topic)));
+                broadcast::channel(TOPIC_CAPACITY).0
+            })
+            .subscribe();
+        
+        TopicReceiver {
+            pubsub: self.clone(),
+            topic: topic.to_string(),
+            events: Some(events),
+        }
+    }
+    
+    // Best effort: live events are a convenience on top of what is persisted.
+    // Queued for the publisher task, so callers on the streaming path never
+    // wait on Redis; when the queue is full the event is dropped
+    pub async fn publish(&self, event: TopicEvent) {
+        let payload = match serde_json::to_string(&event) {
+            Ok(payload) => payload,
+            Err(e) => {
+                error!("Failed to encode event for {}: {}", event.topic, e);
+                return;
+            }
+        };
+        
+        if let Err(e) = self.publish_tx.try_send((channel(&event.topic), payload)) {
+            warn!("Failed to queue event on {}: {}", event.topic, e);
+        }
+    }
+    
+    fn deliver(&self, event: TopicEvent) {
+        if let Some(sender) = self.topics.get(&event.topic) {
+            let _ = sender.send(event);
+        }
+    }
+    
+    // Called as a receiver goes away; the last one stops listening on the channel
+    fn release(&self, topic: &str, events: broadcast::Receiver<TopicEvent>) {
+        let removed = self.topics.remove_if(topic, |_, sender| {
+            drop(events);
+            sender.receiver_count() == 0
+        });
+        if removed.is_some() {
+            let _ = self.listen_tx.send(Listen::Unsubscribe(channel(topic)));
+        }
+    }
+}
+
+// A socket's subscription to one topic
+pub struct TopicReceiver {
+    pubsub: Arc<PubSubService>,
+    topic: String,
+    // Only None while dropping
+    events: Option<broadcast::Receiver<TopicEvent>>,
+}
+
+impl TopicReceiver {
+    pub async fn recv(&mut self) -> Result<TopicEvent, broadcast::error::RecvError> {
+        match &mut self.events {
+            Some(events) => events.recv().await,
+            None => Err(broadcast::error::RecvError::Closed),
+        }
+    }
+}
+
+impl Drop for TopicReceiver {
+    fn drop(&mut self) {
+        if let Some(events) = self.events.take() {
+            self.pubsub.release(&self.topic, events);
+        }
+    }
+}
+
+// Starts the listener for this replica's channels and the publisher that
+// drains the publish queue, both reconnecting if their connection drops
+pub fn spawn_listener(pubsub: Arc<PubSubService>, client: redis::Client) {
+    let Some(mut listen_rx) = pubsub.listen_rx.lock().unwrap().take() else {
+        warn!("Event listener is already running");
+        return;
+    };
+    let publish_rx = pubsub.publish_rx.lock().unwrap().take();
+    
+    if let Some(publish_rx) = publish_rx {
+        tokio::spawn(publish_queued(pubsub.redis.clone(), publish_rx));
+    }
+    tokio::spawn(async move {
+        loop {
+            match listen(&pubsub, &client, &mut listen_rx).await {
+                Ok(()) => warn!("Event listener connection closed; reconnecting"),
+                Err(e) => error!("Event listener failed: {}; reconnecting", e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn publish_queued(mut redis: ConnectionManager, mut queue: mpsc::Receiver<(String, String)>) {
+    while let Some((channel, payload)) = queue.recv().await {
+        if let Err(e) = redis.publish::<_, _, ()>(&channel, payload).await {
+            warn!("Failed to publish event on {}: {}", channel, e);
+        }
+    }
+}
+
+async fn listen(
+    pubsub: &PubSubService,
+    client: &redis::Client,
+    listen_rx: &mut mpsc::UnboundedReceiver<Listen>,
+) -> redis::RedisResult<()> {
+    let mut conn = client.get_async_connection().await?.into_pubsub();
+    // Changes queued before this snapshot are replayed below; repeating a
+    // (un)subscribe is harmless, and they arrive in order
+    let channels: Vec<String> = pubsub.topics.iter().map(|entry| channel(entry.key())).collect();
+    for channel in &channels {
+        conn.subscribe(channel).await?;
+    }
+    info!("Listening for events on {} channels", channels.len());
+    
+    loop {
+        // Channels can only change while no message stream is borrowed
+        let change = {
+            let mut messages = conn.on_message();
+            loop {
+                tokio::select! {
+                    msg = messages.next() => {
+                        let Some(msg) = msg else {
+                            return Ok(());
+                        };
+                        let payload: String = match msg.get_payload() {
+                            Ok(payload) => payload,
+                            Err(e) => {
+                                warn!("Unreadable event on {}: {}", msg.get_channel_name(), e);
+                                continue;
+                            }
+                        };
+                        match serde_json::from_str::<TopicEvent>(&payload) {
+                            Ok(event) => pubsub.deliver(event),
+                            Err(e) => warn!("Malformed event on {}: {}", msg.get_channel_name(), e),
+                        }
+                    }
+                    change = listen_rx.recv() => break change,
+                }
+            }
+        };
+        
+        match change {
+            Some(Listen::Subscribe(channel)) => conn.subscribe(&channel).await?,
+            Some(Listen::Unsubscribe(channel)) => conn.unsubscribe(&channel).await?,
+            None => return Ok(()),
+        }
+    }
+}
+
+fn channel(topic: &str) -> String {
+    format!("{}{}", CHANNEL_PREFIX, topic)
+}
+
+pub fn session_topic(conversation_id: &str) -> String {
+    format!("session:{}", conversation_id)
+}
+
+pub fn user_topic(user_id: &str, name: &str) -> String {
+    format!("user:{}:{}", user_id, name)
+}
@@ -0,0 +1,478 @@
+use crate::services::audit::{AuditEvent, PurgeReport};
+use crate::services::pubsub::{user_topic, TopicEvent};
+use crate::services::{AuditService, ConversationService, PubSubService};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const PURGE_BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct RetentionPolicy {
+    pub id: Uuid,
+    pub org_id: Option<Uuid>,
+    pub message_days: Option<i32>,
+    pub token_usage_days: Option<i32>,
+    pub audit_days: Option<i32>,
+    pub restore_window_days: Option<i32>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Unset fields fall back to the global policy, then the deployment default
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpsertRetentionPolicyRequest {
+    pub org_id: Option<Uuid>,
+    pub message_days: Option<i32>,
+    pub token_usage_days: Option<i32>,
+    pub audit_days: Option<i32>,
+    pub restore_window_days: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TrashedConversation {
+    pub id: Uuid,
+    pub title: Option<String>,
+    pub model: String,
+    pub deleted_at: DateTime<Utc>,
+    pub restorable_until: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct LegalHold {
+    pub conversation_id: Uuid,
+    pub legal_hold: bool,
+    pub reason: Option<String>,
+    pub set_by: Option<Uuid>,
+    pub set_at: Option<DateTime<Utc>>,
+}
+
+// What one purge run removed. A failing step is reported and does not stop
+// the steps after it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RetentionReport {
+    pub started_at: DateTime<Utc>,
+    // Trashed conversations past their restore window
+    pub trashed_conversations: u64,
+    // Conversations with no message inside the message retention period
+    pub expired_conversations: u64,
+    // Conversations that would have been purged but are under legal hold
+    pub held_conversations: i64,
+    pub token_usage_records: u64,
+    pub audit: Option<PurgeReport>,
+    pub failures: Vec<String>,
+}
+
+impl RetentionReport {
+    pub fn deleted_anything(&self) -> bool {
+        self.trashed_conversations > 0
+            || self.expired_conversations > 0
+            || self.token_usage_records > 0
+            || self.audit.as_ref().map_or(false, |audit| audit.deleted > 0)
+    }
+}
+
+// Soft delete, retention policies and the purge job. Policies resolve per
+// user through the `user_retention` view (011_retention)
+pub struct RetentionService {
+    db: PgPool,
+    conversations: Arc<ConversationService>,
+    pubsub: Arc<PubSubService>,
+    audit: Arc<AuditService>,
+    default_restore_window_days: i32,
+    default_audit_days: u32,
+}
+
+impl RetentionService {
+    pub fn new(
+        db: PgPool,
+        conversations: Arc<ConversationService>,
+        pubsub: Arc<PubSubService>,
+        audit: Arc<AuditService>,
+        default_restore_window_days: u32,
+        default_audit_days: u32,
+    ) -> Self {
+        Self {
+            db,
+            conversations,
+            pubsub,
+            audit,
+            default_restore_window_days: default_restore_window_days as i32,
+            default_audit_days,
+        }
+    }
+    
+    // Moves a conversation to the trash; false when it already is there
+    pub async fn soft_delete(&self, conversation_id: &str, deleted_by: &Uuid) -> Result<bool> {
+        let deleted = sqlx::query(
+            r#"
+            UPDATE conversations SET deleted_at = NOW(), deleted_by = $2
+            WHERE id = $1 AND deleted_at IS NULL
+            "#
+        )
+        .bind(Uuid::parse_str(conversation_id)?)
+        .bind(deleted_by)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        
+        Ok(deleted > 0)
+    }
+    
+    // Only the owner restores, and only inside the restore window
+    pub async fn restore(&self, conversation_id: &str, owner_id: &Uuid) -> Result<bool> {
+        let Ok(conversation_id) = Uuid::parse_str(conversation_id) else {
+            return Ok(false);
+        };
+        let restored = sqlx::query(
+            r#"
+            UPDATE conversations c SET deleted_at = NULL, deleted_by = NULL
+            FROM user_retention r
+            WHERE c.id = $1 AND c.user_id = $2 AND r.user_id = c.user_id
+              AND c.deleted_at > NOW() - make_interval(days => COALESCE(r.restore_window_days, $3))
+            "#
+        )
+        .bind(conversation_id)
+        .bind(owner_id)
+        .bind(self.default_restore_window_days)
+        .execute(&self.db)
+        .await?
+        .rows_affected();
+        
+        Ok(restored > 0)
+    }
+    
+    // The owner's trashed conversations, most recently deleted first
+    pub async fn trash(&self, owner_id: &Uuid) -> Result<Vec<TrashedConversation>> {
+        let trashed = sqlx::query_as::<_, TrashedConversation>(
+            r#"
+            SELECT c.id, c.title, c.model, c.deleted_at,
+                   c.deleted_at + make_interval(days => COALESCE(r.restore_window_days, $2)) AS restorable_until
+            FROM conversations c
+            JOIN user_retention r ON r.user_id = c.user_id
+            WHERE c.user_id = $1 AND c.deleted_at IS NOT NULL
+            ORDER BY c.deleted_at DESC
+            "#
+        )
+        .bind(owner_id)
+        .bind(self.default_restore_window_days)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(trashed)
+    }
+    
+    pub async fn trashed_ids(&self, owner_id: &str) -> Result<HashSet<String>> {
+        let ids = sqlx::query_scalar::<_, Uuid>(
+            "SELECT id FROM conversations WHERE user_id = $1 AND deleted_at IS NOT NULL",
+        )
+        .bind(Uuid::parse_str(owner_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(ids.into_iter().map(|id| id.to_string()).collect())
+    }
+    
+    // None when the conversation does not exist, trashed or not
+    pub async fn set_legal_hold(
+        &self,
+        conversation_id: &Uuid,
+        hold: bool,
+        reason: Option<&str>,
+        set_by: &Uuid,
+    ) -> Result<Option<LegalHold>> {
+        let legal_hold = sqlx::query_as::<_, LegalHold>(
+            r#"
+            UPDATE conversations
+            SET legal_hold = $2, legal_hold_reason = $3, legal_hold_set_by = $4, legal_hold_set_at = NOW()
+            WHERE id = $1
+            RETURNING id AS conversation_id, legal_hold, legal_hold_reason AS reason,
+                      legal_hold_set_by AS set_by, legal_hold_set_at AS set_at
+            "#
+        )
+        .bind(conversation_id)
+        .bind(hold)
+        .bind(reason)
+        .bind(set_by)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(legal_hold)
+    }
+    
+    pub async fn list_policies(&self) -> Result<Vec<RetentionPolicy>> {
+        let policies = sqlx::query_as::<_, RetentionPolicy>(
+            "SELECT * FROM retention_policies ORDER BY org_id NULLS FIRST",
+        )
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(policies)
+    }
+    
+    pub async fn upsert_policy(&self, request: &UpsertRetentionPolicyRequest) -> Result<RetentionPolicy> {
+        let policy = sqlx::query_as::<_, RetentionPolicy>(
+            r#"
+            INSERT INTO retention_policies (org_id, message_days, token_usage_days, audit_days, restore_window_days)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (COALESCE(org_id, '00000000-0000-0000-0000-000000000000'::uuid))
+            DO UPDATE SET message_days = EXCLUDED.message_days,
+                          token_usage_days = EXCLUDED.token_usage_days,
+                          audit_days = EXCLUDED.audit_days,
+                          restore_window_days = EXCLUDED.restore_window_days
+            RETURNING *
+            "#
+        )
+        .bind(request.org_id)
+        .bind(request.message_days)
+        .bind(request.token_usage_days)
+        .bind(request.audit_days)
+        .bind(request.restore_window_days)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(policy)
+    }
+    
+    pub async fn delete_policy(&self, policy_id: &Uuid) -> Result<Option<RetentionPolicy>> {
+        let policy = sqlx::query_as::<_, RetentionPolicy>(
+            "DELETE FROM retention_policies WHERE id = $1 RETURNING *",
+        )
+        .bind(policy_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(policy)
+    }
+    
+    pub async fn purge(&self) -> RetentionReport {
+        let mut report = RetentionReport {
+            started_at: Utc::now(),
+            trashed_conversations: 0,
+            expired_conversations: 0,
+            held_conversations: 0,
+            token_usage_records: 0,
+            audit: None,
+            failures: Vec::new(),
+        };
+        
+        match self.purge_trash().await {
+            Ok(purged) => report.trashed_conversations = purged,
+            Err(e) => report.failures.push(format!("trash: {}", e)),
+        }
+        match self.purge_expired_conversations().await {
+            Ok(purged) => report.expired_conversations = purged,
+            Err(e) => report.failures.push(format!("messages: {}", e)),
+        }
+        match self.count_held().await {
+            Ok(held) => report.held_conversations = held,
+            Err(e) => report.failures.push(format!("legal hold: {}", e)),
+        }
+        match self.purge_token_usage().await {
+            Ok(deleted) => report.token_usage_records = deleted,
+            Err(e) => report.failures.push(format!("token usage: {}", e)),
+        }
+        match self.audit_days().await {
+            Ok(Some(days)) => match self.audit.purge_older_than(days).await {
+                Ok(purged) => report.audit = Some(purged),
+                Err(e) => report.failures.push(format!("audit: {}", e)),
+            },
+            Ok(None) => {}
+            Err(e) => report.failures.push(format!("audit: {}", e)),
+        }
+        
+        if report.deleted_anything() || !report.failures.is_empty() {
+            let outcome = if report.failures.is_empty() { "success" } else { "partial" };
+            if let Err(e) = self.audit.record(AuditEvent {
+                event_type: "retention.purge".to_string(),
+                outcome: outcome.to_string(),
+                details: json!(report),
+                ..Default::default()
+            }).await {
+                error!("Failed to record retention purge: {}", e);
+            }
+        }
+        
+        report
+    }
+    
+    // Goes through ConversationService so its caches forget the conversation
+    async fn purge_trash(&self) -> Result<u64> {
+        let mut purged = 0;
+        loop {
+            let ids = sqlx::query_scalar::<_, Uuid>(
+                r#"
+                SELECT c.id FROM conversations c
+                JOIN user_retention r ON r.user_id = c.user_id
+                WHERE c.deleted_at < NOW() - make_interval(days => COALESCE(r.restore_window_days, $1))
+                  AND NOT c.legal_hold
+                LIMIT $2
+                "#
+            )
+            .bind(self.default_restore_window_days)
+            .bind(PURGE_BATCH_SIZE)
+            .fetch_all(&self.db)
+            .await?;
+            if ids.is_empty() {
+                return Ok(purged);
+            }
+            
+            for id in ids {
+                self.conversations.delete_conversation(&id.to_string()).await?;
+                purged += 1;
+            }
+        }
+    }
+    
+    // Messages form a tree and deleting one takes its replies with it, so
+    // retention applies to whole conversations: one goes once its newest
+    // message is older than the retention period
+    async fn purge_expired_conversations(&self) -> Result<u64> {
+        let mut purged = 0;
+        loop {
+            let expired = sqlx::query_as::<_, (Uuid, Vec<Uuid>)>(
+                r#"
+                SELECT c.id, ARRAY(SELECT m.user_id FROM conversation_members m WHERE m.conversation_id = c.id)
+                FROM conversations c
+                JOIN user_retention r ON r.user_id = c.user_id
+                WHERE r.message_days IS NOT NULL AND NOT c.legal_hold
+                  AND c.created_at < NOW() - make_interval(days => r.message_days)
+                  AND NOT EXISTS (
+                      SELECT 1 FROM messages m
+                      WHERE m.conversation_id = c.id
+                        AND m.created_at >= NOW() - make_interval(days => r.message_days)
+                  )
+                LIMIT $1
+                "#
+            )
+            .bind(PURGE_BATCH_SIZE)
+            .fetch_all(&self.db)
+            .await?;
+            if expired.is_empty() {
+                return Ok(purged);
+            }
+            
+            for (id, members) in expired {
+                let conversation_id = id.to_string();
+                self.conversations.delete_conversation(&conversation_id).await?;
+                purged += 1;
+                
+                let deleted = json!({ "type": "sessionDeleted", "sessionId": conversation_id });
+                for member in members {
+                    self.pubsub.publish(TopicEvent {
+                        topic: user_topic(&member.to_string(), "sessions"),
+                        origin: None,
+                        data: deleted.clone(),
+                    }).await;
+                }
+            }
+        }
+    }
+    
+    async fn count_held(&self) -> Result<i64> {
+        let held = sqlx::query_scalar::<_, i64>(
+            r#"
+            SELECT COUNT(*) FROM conversations c
+            JOIN user_retention r ON r.user_id = c.user_id
+            WHERE c.legal_hold AND (
+                c.deleted_at < NOW() - make_interval(days => COALESCE(r.restore_window_days, $1))
+                OR (
+                    r.message_days IS NOT NULL
+                    AND c.created_at < NOW() - make_interval(days => r.message_days)
+                    AND NOT EXISTS (
+                        SELECT 1 FROM messages m
+                        WHERE m.conversation_id = c.id
+                          AND m.created_at >= NOW() - make_interval(days => r.message_days)
+                    )
+                )
+            )
+            "#
+        )
+        .bind(self.default_restore_window_days)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(held)
+    }
+    
+    async fn purge_token_usage(&self) -> Result<u64> {
+        let mut deleted = 0;
+        loop {
+            let batch = sqlx::query(
+                r#"
+                DELETE FROM token_usage
+                WHERE id IN (
+                    SELECT t.id FROM token_usage t
+                    JOIN user_retention r ON r.user_id = t.user_id
+                    WHERE r.token_usage_days IS NOT NULL
+                      AND t.created_at < NOW() - make_interval(days => r.token_usage_days)
+                    LIMIT $1
+                )
+                "#
+            )
+            .bind(PURGE_BATCH_SIZE)
+            .execute(&self.db)
+            .await?
+            .rows_affected();
+            
+            deleted += batch;
+            if batch < PURGE_BATCH_SIZE as u64 {
+                return Ok(deleted);
+            }
+        }
+    }
+    
+    // The audit chain is shared by every org and only a prefix can be
+    // purged, so it keeps records as long as the longest policy asks. None
+    // when some user's records are to be kept indefinitely
+    async fn audit_days(&self) -> Result<Option<u32>> {
+        let configured = sqlx::query_scalar::<_, Option<i32>>(
+            "SELECT DISTINCT audit_days FROM user_retention",
+        )
+        .fetch_all(&self.db)
+        .await?;
+        
+        let days: Vec<u32> = configured
+            .into_iter()
+            .map(|days| days.map_or(self.default_audit_days, |days| days as u32))
+            .collect();
+        
+        // AUDIT_RETENTION_DAYS=0 keeps records indefinitely
+        if days.contains(&0) {
+            return Ok(None);
+        }
+        
+        Ok(days.into_iter().max().or(Some(self.default_audit_days)).filter(|days| *days > 0))
+    }
+}
+
+pub fn spawn_purge_job(retention: Arc<RetentionService>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(24 * 60 * 60));
+        loop {
+            interval.tick().await;
+            
+            let report = retention.purge().await;
+            if report.deleted_anything() {
+                info!(
+                    "Retention purge removed {} trashed and {} expired conversations, {} token usage records and {} audit records; {} held",
+                    report.trashed_conversations,
+                    report.expired_conversations,
+                    report.token_usage_records,
+                    report.audit.as_ref().map_or(0, |audit| audit.deleted),
+                    report.held_conversations,
+                );
+            }
+            for failure in &report.failures {
+                error!("Retention purge failed: {}", failure);
+            }
+        }
+    });
+}
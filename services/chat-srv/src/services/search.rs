@@ -0,0 +1,486 @@
+use crate::llm::LLMClient;
+use crate::redaction;
+use crate::services::{EncryptionService, PolicyService};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+// Embedding inputs are cut to this many characters, well inside the
+// embedding models' input limit
+const EMBED_MAX_CHARS: usize = 8000;
+const SNIPPET_MAX_CHARS: usize = 240;
+// Reciprocal rank fusion constant; larger flattens the advantage of the top ranks
+const RRF_K: f64 = 60.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchMode {
+    Keyword,
+    Semantic,
+    // Keyword and semantic results merged by reciprocal rank fusion
+    Hybrid,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    // Hybrid when semantic search is available, keyword otherwise
+    #[serde(default)]
+    pub mode: Option<SearchMode>,
+    #[serde(default)]
+    pub conversation_id: Option<Uuid>,
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    #[serde(default = "default_limit")]
+    pub limit: i64,
+}
+
+fn default_limit() -> i64 {
+    20
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SearchHit {
+    pub message_id: Uuid,
+    pub conversation_id: Uuid,
+    pub conversation_title: Option<String>,
+    pub role: String,
+    pub snippet: String,
+    pub score: f64,
+    pub created_at: DateTime<Utc>,
+    // Where the client can open the message in its conversation
+    #[sqlx(skip)]
+    pub link: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub mode: SearchMode,
+    // False when encryption at rest is on and the user has not consented to
+    // a plaintext index, so nothing of theirs is searchable
+    pub indexed: bool,
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct IndexReport {
+    pub scanned: u64,
+    pub indexed: u64,
+    pub failed_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct PendingMessage {
+    id: Uuid,
+    conversation_id: Uuid,
+    user_id: Uuid,
+    content: Option<String>,
+    content_ciphertext: Option<Vec<u8>>,
+    data_key_id: Option<Uuid>,
+    aad_version: i16,
+}
+
+// Keyword (tsvector) and semantic (pgvector) search over the caller's
+// messages. Messages reach `message_search_index` through a background
+// indexer, which also embeds them; with encryption at rest only users who
+// consented are indexed. Text sent for embedding goes through the owner's
+// redaction policy first
+pub struct SearchService {
+    db: PgPool,
+    encryption: Option<Arc<EncryptionService>>,
+    policy: Arc<PolicyService>,
+    embedder: Arc<dyn LLMClient>,
+    embedding_model: String,
+}
+
+impl SearchService {
+    pub fn new(
+        db: PgPool,
+        encryption: Option<Arc<EncryptionService>>,
+        policy: Arc<PolicyService>,
+        embedder: Arc<dyn LLMClient>,
+        embedding_model: String,
+    ) -> Self {
+        Self {
+            db,
+            encryption,
+            policy,
+            embedder,
+            embedding_model,
+        }
+    }
+    
+    pub fn semantic_enabled(&self) -> bool {
+        !self.embedding_model.is_empty()
+    }
+    
+    // EMBEDDING_MODEL is free-form, so make sure at startup that its vectors
+    // fit the index column. An unreachable provider is not fatal; every
+    // batch is checked again before it is stored
+    pub async fn check_embedding_dimensions(&self) -> Result<()> {
+        if !self.semantic_enabled() {
+            return Ok(());
+        }
+        let expected = self.index_dimensions().await?;
+        match self.embedder.embed(&self.embedding_model, vec!["dimension check".to_string()]).await {
+            Ok(embeddings) => {
+                let actual = embeddings.first().map(Vec::len).unwrap_or(0);
+                if actual != expected {
+                    anyhow::bail!(
+                        "EMBEDDING_MODEL {} produces {}-dimension vectors, but message_search_index.embedding holds {}",
+                        self.embedding_model, actual, expected,
+                    );
+                }
+            }
+            Err(e) => warn!("Could not check the dimensions of EMBEDDING_MODEL {}: {}", self.embedding_model, e),
+        }
+        
+        Ok(())
+    }
+    
+    // pgvector keeps the dimension in the column's type modifier
+    async fn index_dimensions(&self) -> Result<usize> {
+        let dimensions = sqlx::query_scalar::<_, i32>(
+            r#"
+            SELECT atttypmod FROM pg_attribute
+            WHERE attrelid = 'message_search_index'::regclass AND attname = 'embedding'
+            "#
+        )
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(dimensions as usize)
+    }
+    
+    pub async fn search(&self, user_id: &Uuid, query: &SearchQuery) -> Result<SearchResults> {
+        let indexed = match &self.encryption {
+            Some(encryption) => encryption.has_search_consent(user_id).await?,
+            None => true,
+        };
+        let mode = match query.mode {
+            Some(mode) => mode,
+            None if self.semantic_enabled() => SearchMode::Hybrid,
+            None => SearchMode::Keyword,
+        };
+        if !indexed || query.q.trim().is_empty() {
+            return Ok(SearchResults { mode, indexed, hits: Vec::new() });
+        }
+        
+        let mut hits = match mode {
+            SearchMode::Keyword => self.keyword(user_id, query).await?,
+            SearchMode::Semantic => self.semantic(user_id, query).await?,
+            SearchMode::Hybrid => {
+                let keyword = self.keyword(user_id, query).await?;
+                // A provider outage degrades hybrid search to keyword search
+                let semantic = match self.semantic(user_id, query).await {
+                    Ok(hits) => hits,
+                    Err(e) => {
+                        warn!("Semantic search failed, using keyword results only: {}", e);
+                        Vec::new()
+                    }
+                };
+                fuse(keyword, semantic, query.limit as usize)
+            }
+        };
+        for hit in &mut hits {
+            hit.link = format!("/api/v1/conversations/{}/messages#{}", hit.conversation_id, hit.message_id);
+        }
+        
+        Ok(SearchResults { mode, indexed, hits })
+    }
+    
+    async fn keyword(&self, user_id: &Uuid, query: &SearchQuery) -> Result<Vec<SearchHit>> {
+        let hits = sqlx::query_as::<_, SearchHit>(
+            r#"
+            SELECT s.message_id, s.conversation_id, c.title AS conversation_title, m.role, m.created_at,
+                   ts_headline('english', s.content, q, 'MaxFragments=2, MaxWords=20, MinWords=8, StartSel=<mark>, StopSel=</mark>') AS snippet,
+                   ts_rank_cd(s.content_tsv, q)::float8 AS score
+            FROM message_search_index s
+            JOIN messages m ON m.id = s.message_id
+            JOIN conversations c ON c.id = s.conversation_id
+            CROSS JOIN websearch_to_tsquery('english', $2) AS q
+            WHERE s.user_id = $1 AND c.user_id = $1 AND c.deleted_at IS NULL
+              AND s.content_tsv @@ q
+              AND ($3::uuid IS NULL OR s.conversation_id = $3)
+              AND ($4::timestamptz IS NULL OR m.created_at >= $4)
+              AND ($5::timestamptz IS NULL OR m.created_at < $5)
+            ORDER BY score DESC, m.created_at DESC
+            LIMIT $6
+            "#
+        )
+        .bind(user_id)
+        .bind(&query.q)
+        .bind(query.conversation_id)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(query.limit)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(hits)
+    }
+    
+    async fn semantic(&self, user_id: &Uuid, query: &SearchQuery) -> Result<Vec<SearchHit>> {
+        if !self.semantic_enabled() {
+            anyhow::bail!("Semantic search is not configured");
+        }
+        let embedding = self.embedder
+            .embed(&self.embedding_model, vec![query.q.clone()])
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Embedding provider returned no vector"))?;
+        
+        let hits = sqlx::query_as::<_, SearchHit>(
+            r#"
+            SELECT s.message_id, s.conversation_id, c.title AS conversation_title, m.role, m.created_at,
+                   left(s.content, $7) AS snippet,
+                   (1 - (s.embedding <=> $2::vector))::float8 AS score
+            FROM message_search_index s
+            JOIN messages m ON m.id = s.message_id
+            JOIN conversations c ON c.id = s.conversation_id
+            WHERE s.user_id = $1 AND c.user_id = $1 AND c.deleted_at IS NULL
+              AND s.embedding IS NOT NULL AND s.embedding_model = $8
+              AND ($3::uuid IS NULL OR s.conversation_id = $3)
+              AND ($4::timestamptz IS NULL OR m.created_at >= $4)
+              AND ($5::timestamptz IS NULL OR m.created_at < $5)
+            ORDER BY s.embedding <=> $2::vector
+            LIMIT $6
+            "#
+        )
+        .bind(user_id)
+        .bind(vector_literal(&embedding))
+        .bind(query.conversation_id)
+        .bind(query.from)
+        .bind(query.to)
+        .bind(query.limit)
+        .bind(SNIPPET_MAX_CHARS as i32)
+        .bind(&self.embedding_model)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(hits)
+    }
+    
+    // Copies plaintext of messages not yet indexed into the search index,
+    // decrypting where needed. Rows in `skip` (earlier failures) are passed
+    // over so they cannot hold up the messages behind them
+    pub async fn index_pending(&self, batch_size: i64, skip: &[Uuid]) -> Result<IndexReport> {
+        let pending = sqlx::query_as::<_, PendingMessage>(
+            r#"
+            SELECT m.id, m.conversation_id, c.user_id, m.content, m.content_ciphertext, m.data_key_id, m.aad_version
+            FROM messages m
+            JOIN conversations c ON c.id = m.conversation_id
+            JOIN users u ON u.id = c.user_id
+            LEFT JOIN message_search_index s ON s.message_id = m.id
+            WHERE s.message_id IS NULL
+              AND m.role IN ('user', 'assistant')
+              AND (u.search_index_consent OR NOT $2)
+              AND m.id <> ALL($3)
+            ORDER BY m.created_at, m.id
+            LIMIT $1
+            "#
+        )
+        .bind(batch_size)
+        .bind(self.encryption.is_some())
+        .bind(skip)
+        .fetch_all(&self.db)
+        .await?;
+        
+        let mut report = IndexReport {
+            scanned: pending.len() as u64,
+            ..Default::default()
+        };
+        for message in pending {
+            let content = match (&message.content_ciphertext, message.data_key_id, &self.encryption) {
+                (Some(ciphertext), Some(data_key_id), Some(encryption)) => {
+                    match encryption.decrypt_message(&message.conversation_id, &message.id, message.aad_version, &data_key_id, ciphertext, None).await {
+                        Ok((content, _)) => content,
+                        Err(e) => {
+                            error!("Failed to decrypt message {} for indexing: {}", message.id, e);
+                            report.failed_ids.push(message.id);
+                            continue;
+                        }
+                    }
+                }
+                _ => match message.content {
+                    Some(content) => content,
+                    None => {
+                        warn!("Message {} has no readable content to index", message.id);
+                        report.failed_ids.push(message.id);
+                        continue;
+                    }
+                },
+            };
+            
+            sqlx::query(
+                r#"
+                INSERT INTO message_search_index (message_id, conversation_id, user_id, content)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (message_id) DO NOTHING
+                "#
+            )
+            .bind(message.id)
+            .bind(message.conversation_id)
+            .bind(message.user_id)
+            .bind(content)
+            .execute(&self.db)
+            .await?;
+            report.indexed += 1;
+        }
+        
+        Ok(report)
+    }
+    
+    // Embeds indexed messages that have no vector yet, or one from another
+    // model. Returns how many were embedded
+    pub async fn embed_pending(&self, batch_size: i64) -> Result<usize> {
+        if !self.semantic_enabled() {
+            return Ok(0);
+        }
+        
+        let pending = sqlx::query_as::<_, (Uuid, Uuid, String)>(
+            r#"
+            SELECT message_id, user_id, content FROM message_search_index
+            WHERE embedding IS NULL OR embedding_model <> $2
+            ORDER BY created_at
+            LIMIT $1
+            "#
+        )
+        .bind(batch_size)
+        .bind(&self.embedding_model)
+        .fetch_all(&self.db)
+        .await?;
+        if pending.is_empty() {
+            return Ok(0);
+        }
+        
+        let mut inputs = Vec::with_capacity(pending.len());
+        for (_, user_id, content) in &pending {
+            let content: String = content.chars().take(EMBED_MAX_CHARS).collect();
+            inputs.push(self.redact(user_id, content).await?);
+        }
+        let embeddings = self.embedder.embed(&self.embedding_model, inputs).await?;
+        
+        let expected = self.index_dimensions().await?;
+        if let Some(embedding) = embeddings.iter().find(|e| e.len() != expected) {
+            anyhow::bail!(
+                "EMBEDDING_MODEL {} returned a {}-dimension vector, but the index holds {}",
+                self.embedding_model, embedding.len(), expected,
+            );
+        }
+        
+        for ((message_id, _, _), embedding) in pending.iter().zip(&embeddings) {
+            sqlx::query(
+                r#"
+                UPDATE message_search_index
+                SET embedding = $2::vector, embedding_model = $3
+                WHERE message_id = $1
+                "#
+            )
+            .bind(message_id)
+            .bind(vector_literal(embedding))
+            .bind(&self.embedding_model)
+            .execute(&self.db)
+            .await?;
+        }
+        
+        Ok(embeddings.len())
+    }
+    
+    // Like chat prompts, text leaves for the embedding provider redacted by
+    // the owner's policy. Nobody is there to refuse, so block rules mask
+    async fn redact(&self, user_id: &Uuid, content: String) -> Result<String> {
+        let Some(policy) = self.policy.redaction_policy_for_user(user_id).await? else {
+            return Ok(content);
+        };
+        let redacted = redaction::redact(&[content.as_str()], &policy.masking_blocked())
+            .map_err(|e| anyhow!("Unexpected block while redacting for embedding: {}", e))?;
+        
+        Ok(redacted.texts.into_iter().next().unwrap_or_default())
+    }
+}
+
+// Keeps the search index and its embeddings up to date with new messages
+pub fn spawn_indexer(search: Arc<SearchService>, interval_secs: u64, batch_size: i64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+        loop {
+            interval.tick().await;
+            
+            // Failing rows are skipped for the rest of this run, so it ends
+            // once the backlog is drained; they are retried on the next tick
+            let mut indexed = 0;
+            let mut failed = Vec::new();
+            loop {
+                match search.index_pending(batch_size, &failed).await {
+                    Ok(batch) if batch.scanned == 0 => break,
+                    Ok(batch) => {
+                        indexed += batch.indexed;
+                        failed.extend(batch.failed_ids);
+                    }
+                    Err(e) => {
+                        error!("Search indexing failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            
+            let mut embedded = 0;
+            loop {
+                match search.embed_pending(batch_size).await {
+                    Ok(0) => break,
+                    Ok(count) => embedded += count,
+                    Err(e) => {
+                        error!("Search embedding failed: {}", e);
+                        break;
+                    }
+                }
+            }
+            
+            if indexed > 0 || embedded > 0 || !failed.is_empty() {
+                info!("Indexed {} and embedded {} messages for search, {} failed", indexed, embedded, failed.len());
+            }
+        }
+    });
+}
+
+// Reciprocal rank fusion: each list contributes 1 / (k + rank) per hit, so
+// messages found both ways rise to the top. Keyword snippets win, as they
+// highlight the matched terms
+fn fuse(keyword: Vec<SearchHit>, semantic: Vec<SearchHit>, limit: usize) -> Vec<SearchHit> {
+    let mut fused: HashMap<Uuid, SearchHit> = HashMap::new();
+    let mut scores: HashMap<Uuid, f64> = HashMap::new();
+    
+    for hits in [keyword, semantic] {
+        for (rank, hit) in hits.into_iter().enumerate() {
+            *scores.entry(hit.message_id).or_default() += 1.0 / (RRF_K + rank as f64 + 1.0);
+            fused.entry(hit.message_id).or_insert(hit);
+        }
+    }
+    
+    let mut hits: Vec<SearchHit> = fused
+        .into_values()
+        .map(|mut hit| {
+            hit.score = scores[&hit.message_id];
+            hit
+        })
+        .collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then(b.created_at.cmp(&a.created_at)));
+    hits.truncate(limit);
+    hits
+}
+
+// pgvector's text form, bound as a string and cast to `vector` in SQL
+fn vector_literal(values: &[f32]) -> String {
+    let items: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", items.join(","))
+}
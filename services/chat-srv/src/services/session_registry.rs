@@ -0,0 +1,340 @@
+use crate::state::SessionControl;
+use anyhow::Result;
+use dashmap::DashMap;
+use futures::StreamExt;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+const REPLICAS_KEY: &str = "ws:replicas";
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegisteredSession {
+    pub session_id: String,
+    pub replica_id: String,
+    pub user_id: String,
+    pub conversation_id: Option<String>,
+    pub connected_at: i64,
+    pub heartbeat_at: i64,
+}
+
+// What travels on a replica's control channel
+#[derive(Debug, Serialize, Deserialize)]
+struct ControlMessage {
+    session_id: String,
+    control: SessionControl,
+}
+
+// Records every authenticated socket in Redis (which replica holds it, whose
+// it is, what it is chatting in) so any replica can find a session and steer
+// it. Sessions on this replica are controlled directly; the rest through the
+// owning replica's `ws:control:<replica id>` channel
+pub struct SessionRegistryService {
+    redis: ConnectionManager,
+    replica_id: String,
+    stale_after_secs: u64,
+    local: DashMap<String, mpsc::Sender<SessionControl>>,
+    // Local sessions whose entry could not be written, by user id; retried
+    // on each heartbeat
+    unregistered: DashMap<String, String>,
+}
+
+impl SessionRegistryService {
+    pub fn new(redis: ConnectionManager, replica_id: String, stale_after_secs: u64) -> Self {
+        Self {
+            redis,
+            replica_id,
+            stale_after_secs,
+            local: DashMap::new(),
+            unregistered: DashMap::new(),
+        }
+    }
+    
+    // The session is controllable from this replica even if this fails; the
+    // entry other replicas need is then written by a later heartbeat
+    pub async fn register(&self, session_id: &str, user_id: &str, control: mpsc::Sender<SessionControl>) -> Result<()> {
+        self.local.insert(session_id.to_string(), control);
+        
+        if let Err(e) = self.write_entry(session_id, user_id).await {
+            self.unregistered.insert(session_id.to_string(), user_id.to_string());
+            return Err(e);
+        }
+        
+        Ok(())
+    }
+    
+    async fn write_entry(&self, session_id: &str, user_id: &str) -> Result<()> {
+        let mut conn = self.redis.clone();
+        let key = session_key(session_id);
+        let now = chrono::Utc::now().timestamp();
+        
+        redis::pipe()
+            .atomic()
+            .hset_multiple(&key, &[
+                ("replica_id", self.replica_id.clone()),
+                ("user_id", user_id.to_string()),
+                ("connected_at", now.to_string()),
+                ("heartbeat_at", now.to_string()),
+            ])
+            .ignore()
+            .expire(&key, self.stale_after_secs as i64)
+            .ignore()
+            .sadd(user_sessions_key(user_id), session_id)
+            .ignore()
+            .sadd(replica_sessions_key(&self.replica_id), session_id)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn set_conversation(&self, session_id: &str, conversation_id: &str) -> Result<()> {
+        let mut conn = self.redis.clone();
+        let key = session_key(session_id);
+        
+        redis::pipe()
+            .hset(&key, "conversation_id", conversation_id)
+            .ignore()
+            .expire(&key, self.stale_after_secs as i64)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn unregister(&self, session_id: &str, user_id: &str) -> Result<()> {
+        self.local.remove(session_id);
+        self.unregistered.remove(session_id);
+        
+        let mut conn = self.redis.clone();
+        redis::pipe()
+            .atomic()
+            .del(session_key(session_id))
+            .ignore()
+            .srem(user_sessions_key(user_id), session_id)
+            .ignore()
+            .srem(replica_sessions_key(&self.replica_id), session_id)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        
+        Ok(())
+    }
+    
+    // Keeps this replica and its sessions alive; anything not refreshed
+    // within `stale_after_secs` is treated as gone
+    pub async fn heartbeat(&self) -> Result<()> {
+        let pending: Vec<(String, String)> = self.unregistered
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        for (session_id, user_id) in pending {
+            self.write_entry(&session_id, &user_id).await?;
+            // It disconnected while the entry was written; take the entry back out
+            if self.unregistered.remove(&session_id).is_none() {
+                self.unregister(&session_id, &user_id).await?;
+                continue;
+            }
+            info!("Registered session {} after an earlier failure", session_id);
+        }
+        
+        let mut conn = self.redis.clone();
+        let now = chrono::Utc::now().timestamp();
+        
+        let mut pipe = redis::pipe();
+        pipe.zadd(REPLICAS_KEY, &self.replica_id, now).ignore();
+        for entry in self.local.iter() {
+            let key = session_key(entry.key());
+            pipe.hset(&key, "heartbeat_at", now)
+                .ignore()
+                .expire(&key, self.stale_after_secs as i64)
+                .ignore();
+        }
+        pipe.query_async::<_, ()>(&mut conn).await?;
+        
+        Ok(())
+    }
+    
+    pub async fn get(&self, session_id: &str) -> Result<Option<RegisteredSession>> {
+        let mut conn = self.redis.clone();
+        let fields: HashMap<String, String> = conn.hgetall(session_key(session_id)).await?;
+        
+        // A bare `conversation_id` written after the entry expired is not a session
+        if !fields.contains_key("replica_id") {
+            return Ok(None);
+        }
+        
+        let field = |name: &str| fields.get(name).cloned().unwrap_or_default();
+        Ok(Some(RegisteredSession {
+            session_id: session_id.to_string(),
+            replica_id: field("replica_id"),
+            user_id: field("user_id"),
+            conversation_id: fields.get("conversation_id").cloned(),
+            connected_at: field("connected_at").parse().unwrap_or_default(),
+            heartbeat_at: field("heartbeat_at").parse().unwrap_or_default(),
+        }))
+    }
+    
+    // Every live session of the user on any replica; ids whose entry has
+    // expired are pruned on the way
+    pub async fn sessions_for_user(&self, user_id: &str) -> Result<Vec<RegisteredSession>> {
+        let mut conn = self.redis.clone();
+        let session_ids: Vec<String> = conn.smembers(user_sessions_key(user_id)).await?;
+        
+        let mut sessions = Vec::with_capacity(session_ids.len());
+        for session_id in session_ids {
+            match self.get(&session_id).await? {
+                Some(session) => sessions.push(session),
+                None => {
+                    conn.srem::<_, _, ()>(user_sessions_key(user_id), &session_id).await?;
+                }
+            }
+        }
+        
+        Ok(sessions)
+    }
+    
+    // Hands a control message to the session wherever it lives. Returns false
+    // if the session is unknown or nobody was listening for it
+    pub async fn send(&self, session_id: &str, control: SessionControl) -> Result<bool> {
+        let local = self.local.get(session_id).map(|entry| entry.value().clone());
+        if let Some(sender) = local {
+            return Ok(sender.send(control).await.is_ok());
+        }
+        
+        let Some(session) = self.get(session_id).await? else {
+            return Ok(false);
+        };
+        
+        let payload = serde_json::to_string(&ControlMessage {
+            session_id: session_id.to_string(),
+            control,
+        })?;
+        let mut conn = self.redis.clone();
+        let receivers: usize = conn.publish(control_channel(&session.replica_id), payload).await?;
+        
+        Ok(receivers > 0)
+    }
+    
+    // Drops the sessions of replicas that stopped heartbeating, so a crashed
+    // pod does not leave its users looking connected
+    pub async fn reap_dead_replicas(&self) -> Result<usize> {
+        let mut conn = self.redis.clone();
+        let cutoff = chrono::Utc::now().timestamp() - self.stale_after_secs as i64;
+        let dead: Vec<String> = conn.zrangebyscore(REPLICAS_KEY, "-inf", cutoff).await?;
+        
+        let mut reaped = 0;
+        for replica_id in dead.into_iter().filter(|id| *id != self.replica_id) {
+            let session_ids: Vec<String> = conn.smembers(replica_sessions_key(&replica_id)).await?;
+            for session_id in &session_ids {
+                let user_id: Option<String> = conn.hget(session_key(session_id), "user_id").await?;
+                let mut pipe = redis::pipe();
+                pipe.del(session_key(session_id)).ignore();
+                if let Some(user_id) = user_id {
+                    pipe.srem(user_sessions_key(&user_id), session_id).ignore();
+                }
+                pipe.query_async::<_, ()>(&mut conn).await?;
+            }
+            
+            redis::pipe()
+                .del(replica_sessions_key(&replica_id))
+                .ignore()
+                .zrem(REPLICAS_KEY, &replica_id)
+                .ignore()
+                .query_async::<_, ()>(&mut conn)
+                .await?;
+            
+            warn!("Reaped {} sessions of dead replica {}", session_ids.len(), replica_id);
+            reaped += session_ids.len();
+        }
+        
+        Ok(reaped)
+    }
+    
+    fn deliver(&self, message: ControlMessage) {
+        let Some(sender) = self.local.get(&message.session_id).map(|entry| entry.value().clone()) else {
+            return;
+        };
+        if sender.try_send(message.control).is_err() {
+            warn!("Session {} is not taking control messages", message.session_id);
+        }
+    }
+}
+
+// Heartbeats this replica's sessions and reaps those of dead replicas
+pub fn spawn_registry_jobs(registry: Arc<SessionRegistryService>, heartbeat_secs: u64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(heartbeat_secs));
+        loop {
+            interval.tick().await;
+            
+            if let Err(e) = registry.heartbeat().await {
+                error!("Session registry heartbeat failed: {}", e);
+            }
+            if let Err(e) = registry.reap_dead_replicas().await {
+                error!("Failed to reap dead replicas: {}", e);
+            }
+        }
+    });
+}
+
+// Receives control messages other replicas address to this one's sessions,
+// reconnecting if the Redis connection drops
+pub fn spawn_control_listener(registry: Arc<SessionRegistryService>, client: redis::Client) {
+    tokio::spawn(async move {
+        loop {
+            match listen(&registry, &client).await {
+                Ok(()) => warn!("Session control connection closed; reconnecting"),
+                Err(e) => error!("Session control listener failed: {}; reconnecting", e),
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+        }
+    });
+}
+
+async fn listen(registry: &SessionRegistryService, client: &redis::Client) -> redis::RedisResult<()> {
+    let mut conn = client.get_async_connection().await?.into_pubsub();
+    conn.subscribe(control_channel(&registry.replica_id)).await?;
+    info!("Listening for session control as replica {}", registry.replica_id);
+    
+    let mut messages = conn.on_message();
+    while let Some(msg) = messages.next().await {
+        let payload: String = match msg.get_payload() {
+            Ok(payload) => payload,
+            Err(e) => {
+                warn!("Unreadable session control message: {}", e);
+                continue;
+            }
+        };
+        match serde_json::from_str::<ControlMessage>(&payload) {
+            Ok(message) => registry.deliver(message),
+            Err(e) => warn!("Malformed session control message: {}", e),
+        }
+    }
+    
+    Ok(())
+}
+
+fn session_key(session_id: &str) -> String {
+    format!("ws:session:{}", session_id)
+}
+
+fn user_sessions_key(user_id: &str) -> String {
+    format!("ws:user:{}:sessions", user_id)
+}
+
+fn replica_sessions_key(replica_id: &str) -> String {
+    format!("ws:replica:{}:sessions", replica_id)
+}
+
+fn control_channel(replica_id: &str) -> String {
+    format!("ws:control:{}", replica_id)
+}
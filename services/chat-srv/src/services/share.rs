@@ -0,0 +1,405 @@
+use crate::models::Conversation;
+use crate::redaction;
+use crate::services::branches::on_path;
+use crate::services::{BranchService, ConversationService, PolicyService};
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD as BASE64_URL, Engine};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareVisibility {
+    // Anyone with the link
+    Public,
+    // Signed-in members of the owner's org
+    Org,
+}
+
+impl ShareVisibility {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ShareVisibility::Public => "public",
+            ShareVisibility::Org => "org",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ShareRecord {
+    pub id: Uuid,
+    pub conversation_id: Uuid,
+    pub owner_id: Uuid,
+    pub visibility: String,
+    pub org_id: Option<Uuid>,
+    pub message_ids: Vec<Uuid>,
+    pub title: Option<String>,
+    pub redact: bool,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
+    pub view_count: i64,
+    pub last_viewed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl ShareRecord {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at.map_or(true, |expires_at| expires_at > Utc::now())
+    }
+}
+
+// What the owner sees, including the signed link
+#[derive(Debug, Clone, Serialize)]
+pub struct Share {
+    #[serde(flatten)]
+    pub record: ShareRecord,
+    pub token: String,
+    pub url: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CreateShareRequest {
+    pub visibility: ShareVisibility,
+    // First and last message of the range on the active branch; the whole
+    // branch when both are absent
+    #[serde(default)]
+    pub from_message_id: Option<String>,
+    #[serde(default)]
+    pub to_message_id: Option<String>,
+    // Never expires when absent
+    #[serde(default)]
+    pub expires_in_secs: Option<i64>,
+    #[serde(default = "default_redact")]
+    pub redact: bool,
+}
+
+fn default_redact() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedMessage {
+    pub role: String,
+    pub content: String,
+    pub model: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// The public view of a share. Deliberately carries no ids of the owner,
+// the conversation or its messages
+#[derive(Debug, Clone, Serialize)]
+pub struct SharedConversation {
+    pub title: Option<String>,
+    pub model: String,
+    pub shared_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub redacted: bool,
+    pub messages: Vec<SharedMessage>,
+}
+
+// Share links are `<share id>.<signature>`; the signature is an HMAC of the
+// id, so ids cannot be guessed or enumerated into valid links
+pub struct ShareService {
+    db: PgPool,
+    conversations: Arc<ConversationService>,
+    branches: Arc<BranchService>,
+    policy: Arc<PolicyService>,
+    secret: Vec<u8>,
+    base_url: String,
+}
+
+impl ShareService {
+    pub fn new(
+        db: PgPool,
+        conversations: Arc<ConversationService>,
+        branches: Arc<BranchService>,
+        policy: Arc<PolicyService>,
+        secret: &str,
+        base_url: &str,
+    ) -> Self {
+        Self {
+            db,
+            conversations,
+            branches,
+            policy,
+            secret: secret.as_bytes().to_vec(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+    
+    // `org_id` is the owner's org for org shares. Returns Ok(None) when the
+    // range is not on the active branch
+    pub async fn create(
+        &self,
+        owner_id: &Uuid,
+        org_id: Option<Uuid>,
+        conversation: &Conversation,
+        request: &CreateShareRequest,
+    ) -> Result<Option<Share>> {
+        let path = self.branches.path(&conversation.id, None).await?;
+        let Some(message_ids) = range(&path, request.from_message_id.as_deref(), request.to_message_id.as_deref()) else {
+            return Ok(None);
+        };
+        let message_ids = message_ids
+            .iter()
+            .map(|id| Uuid::parse_str(id))
+            .collect::<Result<Vec<_>, _>>()?;
+        
+        let expires_at = request
+            .expires_in_secs
+            .map(|secs| Utc::now() + chrono::Duration::seconds(secs));
+        
+        let record = sqlx::query_as::<_, ShareRecord>(
+            r#"
+            INSERT INTO conversation_shares (
+                conversation_id, owner_id, visibility, org_id, message_ids, title, redact, expires_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::parse_str(&conversation.id)?)
+        .bind(owner_id)
+        .bind(request.visibility.as_str())
+        .bind(org_id)
+        .bind(&message_ids)
+        .bind(&conversation.title)
+        .bind(request.redact)
+        .bind(expires_at)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(Some(self.share(record)))
+    }
+    
+    pub async fn list(&self, owner_id: &Uuid, conversation_id: &str) -> Result<Vec<Share>> {
+        let records = sqlx::query_as::<_, ShareRecord>(
+            r#"
+            SELECT * FROM conversation_shares
+            WHERE owner_id = $1 AND conversation_id = $2
+            ORDER BY created_at DESC
+            "#
+        )
+        .bind(owner_id)
+        .bind(Uuid::parse_str(conversation_id)?)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(records.into_iter().map(|record| self.share(record)).collect())
+    }
+    
+    // Revoking twice is a no-op; returns None for another owner's share
+    pub async fn revoke(&self, owner_id: &Uuid, share_id: &Uuid) -> Result<Option<Share>> {
+        let record = sqlx::query_as::<_, ShareRecord>(
+            r#"
+            UPDATE conversation_shares
+            SET revoked_at = COALESCE(revoked_at, NOW())
+            WHERE id = $1 AND owner_id = $2
+            RETURNING *
+            "#
+        )
+        .bind(share_id)
+        .bind(owner_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(record.map(|record| self.share(record)))
+    }
+    
+    // Looks up the share a link points at. None for malformed or forged
+    // links and for trashed conversations; expiry and visibility are left
+    // to the caller
+    pub async fn resolve(&self, token: &str) -> Result<Option<ShareRecord>> {
+        let Some(share_id) = verify(&self.secret, token) else {
+            return Ok(None);
+        };
+        
+        let record = sqlx::query_as::<_, ShareRecord>(
+            r#"
+            SELECT s.* FROM conversation_shares s
+            JOIN conversations c ON c.id = s.conversation_id
+            WHERE s.id = $1 AND c.deleted_at IS NULL
+            "#
+        )
+        .bind(share_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(record)
+    }
+    
+    // Renders the frozen messages and counts the view
+    pub async fn view(&self, record: &ShareRecord) -> Result<SharedConversation> {
+        let conversation_id = record.conversation_id.to_string();
+        let conversation = self.conversations
+            .get_conversation(&conversation_id)
+            .await?
+            .ok_or_else(|| anyhow!("Conversation {} of share {} is gone", conversation_id, record.id))?;
+        
+        let path: Vec<String> = record.message_ids.iter().map(|id| id.to_string()).collect();
+        let messages = on_path(self.conversations.get_messages(&conversation_id).await?, &path);
+        let messages = self.branches.decrypt(&conversation_id, messages).await?;
+        
+        let mut contents: Vec<String> = messages.iter().map(|m| m.content.clone()).collect();
+        let mut title = record.title.clone();
+        if record.redact {
+            if let Some(policy) = self.policy.redaction_policy_for_user(&record.owner_id).await? {
+                // A snapshot cannot be refused like a prompt, so block rules mask too
+                let policy = policy.masking_blocked();
+                let mut texts: Vec<&str> = contents.iter().map(String::as_str).collect();
+                texts.extend(title.as_deref());
+                let mut redacted = redaction::redact(&texts, &policy)
+                    .map_err(|e| anyhow!("Unexpected block while redacting share: {}", e))?
+                    .texts;
+                if title.is_some() {
+                    title = redacted.pop();
+                }
+                contents = redacted;
+            }
+        }
+        
+        sqlx::query(
+            r#"
+            UPDATE conversation_shares
+            SET view_count = view_count + 1, last_viewed_at = NOW()
+            WHERE id = $1
+            "#
+        )
+        .bind(record.id)
+        .execute(&self.db)
+        .await?;
+        
+        Ok(SharedConversation {
+            title,
+            model: conversation.model,
+            shared_at: record.created_at,
+            expires_at: record.expires_at,
+            redacted: record.redact,
+            messages: messages
+                .into_iter()
+                .zip(contents)
+                .map(|(m, content)| SharedMessage {
+                    role: m.role,
+                    content,
+                    model: m.model,
+                    created_at: m.created_at,
+                })
+                .collect(),
+        })
+    }
+    
+    fn share(&self, record: ShareRecord) -> Share {
+        let token = sign(&self.secret, &record.id);
+        Share {
+            url: format!("{}/share/{}", self.base_url, token),
+            active: record.is_active(),
+            token,
+            record,
+        }
+    }
+}
+
+fn mac(secret: &[u8], share_id: &Uuid) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(secret)
+        .expect("HMAC accepts keys of any length");
+    mac.update(b"chat-srv/share/");
+    mac.update(share_id.as_bytes());
+    mac
+}
+
+fn sign(secret: &[u8], share_id: &Uuid) -> String {
+    let signature = mac(secret, share_id).finalize().into_bytes();
+    format!("{}.{}", share_id.simple(), BASE64_URL.encode(signature))
+}
+
+fn verify(secret: &[u8], token: &str) -> Option<Uuid> {
+    let (id, signature) = token.split_once('.')?;
+    let share_id = Uuid::parse_str(id).ok()?;
+    let signature = BASE64_URL.decode(signature).ok()?;
+    
+    // Constant-time comparison
+    mac(secret, &share_id).verify_slice(&signature).ok()?;
+    Some(share_id)
+}
+
+// Ids of `path` from `from` through `to`, inclusive. None when either end is
+// not on the path or they are out of order
+fn range(path: &[String], from: Option<&str>, to: Option<&str>) -> Option<Vec<String>> {
+    let position = |id: &str| path.iter().position(|p| p == id);
+    let start = match from {
+        Some(id) => position(id)?,
+        None => 0,
+    };
+    let end = match to {
+        Some(id) => position(id)? + 1,
+        None => path.len(),
+    };
+    
+    (start < end).then(|| path[start..end].to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SECRET: &[u8] = b"share-secret";
+    
+    #[test]
+    fn signed_token_verifies() {
+        let share_id = Uuid::new_v4();
+        let token = sign(SECRET, &share_id);
+        
+        assert!(token.starts_with(&format!("{}.", share_id.simple())));
+        assert_eq!(verify(SECRET, &token), Some(share_id));
+    }
+    
+    #[test]
+    fn token_from_another_secret_is_rejected() {
+        let token = sign(b"other-secret", &Uuid::new_v4());
+        assert_eq!(verify(SECRET, &token), None);
+    }
+    
+    #[test]
+    fn signature_does_not_carry_over_to_another_id() {
+        let token = sign(SECRET, &Uuid::new_v4());
+        let (_, signature) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4().simple(), signature);
+        assert_eq!(verify(SECRET, &forged), None);
+    }
+    
+    #[test]
+    fn malformed_tokens_are_rejected() {
+        let share_id = Uuid::new_v4();
+        let token = sign(SECRET, &share_id);
+        let (id, signature) = token.split_once('.').unwrap();
+        
+        for bad in [
+            String::new(),
+            id.to_string(),
+            format!("{}.", id),
+            format!("not-a-uuid.{}", signature),
+            format!("{}.{}", id, &signature[..signature.len() - 2]),
+            format!("{}.{}!", id, signature),
+        ] {
+            assert_eq!(verify(SECRET, &bad), None, "{:?}", bad);
+        }
+    }
+    
+    #[test]
+    fn range_covers_the_path_between_its_ends() {
+        let path: Vec<String> = ["a", "b", "c", "d"].iter().map(|id| id.to_string()).collect();
+        
+        assert_eq!(range(&path, None, None), Some(path.clone()));
+        assert_eq!(range(&path, Some("b"), Some("c")), Some(vec!["b".to_string(), "c".to_string()]));
+        assert_eq!(range(&path, Some("c"), Some("c")), Some(vec!["c".to_string()]));
+        assert_eq!(range(&path, Some("c"), Some("b")), None);
+        assert_eq!(range(&path, Some("x"), None), None);
+        assert_eq!(range(&[], None, None), None);
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::Result;
+use redis::aio::ConnectionManager;
+use redis::streams::StreamRangeReply;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// How much longer than the upstream stream timeout a running generation may
+// stay silent before it is treated as lost (its replica died or was cut
+// off), so resuming clients stop waiting
+const STALE_GRACE_SECS: u64 = 15;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseMeta {
+    pub user_id: String,
+    // Socket running the generation and its request there, so a stop can be
+    // routed to it from any device
+    pub session_id: String,
+    pub request_key: String,
+    pub conversation_id: String,
+    pub model: String,
+    pub status: ResponseStatus,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseStatus {
+    Running,
+    Done,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct BufferedChunk {
+    pub seq: u64,
+    pub content: String,
+    pub finish_reason: Option<String>,
+}
+
+// Buffers every generated chunk in a Redis stream keyed by response id, so a
+// client that reconnects (to any replica) can replay from its last seq.
+// Chunks are what the user saw, with redacted values restored, so they
+// expire after `ttl_secs` (capped by config) and are deleted on erasure
+// through the per-user index
+pub struct StreamBufferService {
+    redis: ConnectionManager,
+    ttl_secs: u64,
+    stale_after_secs: u64,
+}
+
+impl StreamBufferService {
+    pub fn new(redis: ConnectionManager, ttl_secs: u64, upstream_timeout_secs: u64) -> Self {
+        Self {
+            redis,
+            ttl_secs,
+            stale_after_secs: upstream_timeout_secs + STALE_GRACE_SECS,
+        }
+    }
+    
+    // The generating replica gives up after the upstream timeout, so a
+    // running response silent for longer will not get another chunk
+    pub fn is_stale(&self, meta: &ResponseMeta) -> bool {
+        meta.status == ResponseStatus::Running
+            && chrono::Utc::now().timestamp() - meta.updated_at > self.stale_after_secs as i64
+    }
+    
+    pub async fn start(
+        &self,
+        response_id: &str,
+        user_id: &str,
+        session_id: &str,
+        request_key: &str,
+        conversation_id: &str,
+        model: &str,
+    ) -> Result<()> {
+        let mut conn = self.redis.clone();
+        let meta_key = meta_key(response_id);
+        let user_key = user_key(user_id);
+        
+        redis::pipe()
+            .atomic()
+            .sadd(&user_key, response_id)
+            .ignore()
+            .expire(&user_key, self.ttl_secs as i64)
+            .ignore()
+            .hset_multiple(&meta_key, &[
+                ("user_id", user_id.to_string()),
+                ("session_id", session_id.to_string()),
+                ("request_key", request_key.to_string()),
+                ("conversation_id", conversation_id.to_string()),
+                ("model", model.to_string()),
+                ("status", "running".to_string()),
+                ("updated_at", chrono::Utc::now().timestamp().to_string()),
+            ])
+            .ignore()
+            .expire(&meta_key, self.ttl_secs as i64)
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn append(
+        &self,
+        response_id: &str,
+        seq: u64,
+        content: &str,
+        finish_reason: Option<&str>,
+    ) -> Result<()> {
+        let mut conn = self.redis.clone();
+        let chunks_key = chunks_key(response_id);
+        let meta_key = meta_key(response_id);
+        
+        // Explicit "0-<seq>" entry ids make the stream id the sequence number
+        redis::pipe()
+            .atomic()
+            .xadd(&chunks_key, format!("0-{}", seq), &[
+                ("content", content),
+                ("finish_reason", finish_reason.unwrap_or("")),
+            ])
+            .ignore()
+            .expire(&chunks_key, self.ttl_secs as i64)
+            .ignore()
+            .hset(&meta_key, "updated_at", chrono::Utc::now().timestamp())
+            .ignore()
+            .query_async::<_, ()>(&mut conn)
+            .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn finish(&self, response_id: &str) -> Result<()> {
+        let mut conn = self.redis.clone();
+        conn.hset::<_, _, _, ()>(meta_key(response_id), "status", "done").await?;
+        
+        Ok(())
+    }
+    
+    pub async fn meta(&self, response_id: &str) -> Result<Option<ResponseMeta>> {
+        let mut conn = self.redis.clone();
+        let fields: HashMap<String, String> = conn.hgetall(meta_key(response_id)).await?;
+        
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        
+        let field = |name: &str| fields.get(name).cloned().unwrap_or_default();
+        Ok(Some(ResponseMeta {
+            user_id: field("user_id"),
+            session_id: field("session_id"),
+            request_key: field("request_key"),
+            conversation_id: field("conversation_id"),
+            model: field("model"),
+            status: if field("status") == "done" { ResponseStatus::Done } else { ResponseStatus::Running },
+            updated_at: field("updated_at").parse().unwrap_or_default(),
+        }))
+    }
+    
+    // Deletes every buffered response of the user; returns how many keys went
+    pub async fn clear_user(&self, user_id: &str) -> Result<u64> {
+        let mut conn = self.redis.clone();
+        let response_ids: Vec<String> = conn.smembers(user_key(user_id)).await?;
+        
+        let mut keys = vec![user_key(user_id)];
+        for response_id in &response_ids {
+            keys.push(chunks_key(response_id));
+            keys.push(meta_key(response_id));
+        }
+        let deleted: u64 = conn.del(&keys).await?;
+        
+        Ok(deleted)
+    }
+    
+    // Responses of the user still buffered, for erasure verification
+    pub async fn buffered_for_user(&self, user_id: &str) -> Result<u64> {
+        let mut conn = self.redis.clone();
+        let response_ids: Vec<String> = conn.smembers(user_key(user_id)).await?;
+        
+        let mut buffered = 0;
+        for response_id in &response_ids {
+            if conn.exists::<_, bool>(meta_key(response_id)).await? {
+                buffered += 1;
+            }
+        }
+        
+        Ok(buffered)
+    }
+    
+    // Chunks with seq greater than `after_seq`, oldest first
+    pub async fn read_after(&self, response_id: &str, after_seq: u64, limit: usize) -> Result<Vec<BufferedChunk>> {
+        let mut conn = self.redis.clone();
+        let reply: StreamRangeReply = conn
+            .xrange_count(chunks_key(response_id), format!("0-{}", after_seq + 1), "+", limit)
+            .await?;
+        
+        let chunks = reply.ids
+            .into_iter()
+            .filter_map(|entry| {
+                let seq = entry.id.strip_prefix("0-")?.parse().ok()?;
+                let content: String = entry.get("content").unwrap_or_default();
+                let finish_reason: String = entry.get("finish_reason").unwrap_or_default();
+                Some(BufferedChunk {
+                    seq,
+                    content,
+                    finish_reason: Some(finish_reason).filter(|r| !r.is_empty()),
+                })
+            })
+            .collect();
+        
+        Ok(chunks)
+    }
+}
+
+fn chunks_key(response_id: &str) -> String {
+    format!("resume:{}:chunks", response_id)
+}
+
+fn meta_key(response_id: &str) -> String {
+    format!("resume:{}:meta", response_id)
+}
+
+fn user_key(user_id: &str) -> String {
+    format!("resume:user:{}", user_id)
+}
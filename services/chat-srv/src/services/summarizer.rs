@@ -0,0 +1,342 @@
+use crate::llm::{ChatCompletionRequest, ChatMessage, LLMClient, LLMProvider};
+use crate::metrics::Metrics;
+use crate::models::Message;
+use crate::redaction::{self, RedactionVault};
+use crate::services::pubsub::{user_topic, TopicEvent};
+use crate::services::branches::on_path;
+use crate::services::encryption::AAD_VERSION;
+use crate::services::{BranchService, ConversationService, EncryptionService, PolicyService, PubSubService, TokenMeterService};
+use anyhow::{anyhow, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use dashmap::DashMap;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::sync::Arc;
+use tracing::{error, info};
+use uuid::Uuid;
+
+const TITLE_MAX_CHARS: usize = 80;
+// Per message, so one long paste does not crowd out the rest of the prompt
+const EXCERPT_MAX_CHARS: usize = 2000;
+
+const TITLE_PROMPT: &str = "Write a title of at most six words for the conversation below. \
+    Reply with the title only, without quotes or trailing punctuation.";
+const SUMMARY_PROMPT: &str = "Maintain a running summary of a conversation. Given the summary so far \
+    and the messages that followed it, write an updated summary of at most 200 words that keeps every \
+    fact, decision and open question a reader would need to continue the conversation.";
+
+#[derive(Debug, Clone)]
+pub struct SummarizerSettings {
+    pub titles_enabled: bool,
+    pub model: String,
+    pub summary_after_messages: usize,
+    pub system_account_id: String,
+}
+
+// Titles new conversations after their first exchange and keeps a rolling
+// summary of long ones in `conversations.metadata`. Runs in the background
+// on a cheap model, billed to the system account rather than the user
+pub struct SummarizerService {
+    db: PgPool,
+    conversations: Arc<ConversationService>,
+    branches: Arc<BranchService>,
+    encryption: Option<Arc<EncryptionService>>,
+    policy: Arc<PolicyService>,
+    token_meter: Arc<TokenMeterService>,
+    pubsub: Arc<PubSubService>,
+    metrics: Arc<Metrics>,
+    client: Arc<dyn LLMClient>,
+    provider: LLMProvider,
+    settings: SummarizerSettings,
+    running: DashMap<String, ()>,
+}
+
+impl SummarizerService {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        db: PgPool,
+        conversations: Arc<ConversationService>,
+        branches: Arc<BranchService>,
+        encryption: Option<Arc<EncryptionService>>,
+        policy: Arc<PolicyService>,
+        token_meter: Arc<TokenMeterService>,
+        pubsub: Arc<PubSubService>,
+        metrics: Arc<Metrics>,
+        (provider, client): (LLMProvider, Arc<dyn LLMClient>),
+        settings: SummarizerSettings,
+    ) -> Self {
+        Self {
+            db,
+            conversations,
+            branches,
+            encryption,
+            policy,
+            token_meter,
+            pubsub,
+            metrics,
+            client,
+            provider,
+            settings,
+            running: DashMap::new(),
+        }
+    }
+    
+    // Called once an exchange is saved; at most one run per conversation at a time
+    pub fn after_exchange(self: &Arc<Self>, user_id: &str, conversation_id: &str) {
+        if self.running.insert(conversation_id.to_string(), ()).is_some() {
+            return;
+        }
+        
+        let summarizer = self.clone();
+        let user_id = user_id.to_string();
+        let conversation_id = conversation_id.to_string();
+        tokio::spawn(async move {
+            if let Err(e) = summarizer.run(&user_id, &conversation_id).await {
+                error!("Failed to title or summarize conversation {}: {}", conversation_id, e);
+            }
+            summarizer.running.remove(&conversation_id);
+        });
+    }
+    
+    async fn run(&self, user_id: &str, conversation_id: &str) -> Result<()> {
+        let Some(conversation) = self.conversations.get_conversation(conversation_id).await? else {
+            return Ok(());
+        };
+        // Only the active branch is titled and summarized
+        let path = self.branches.path(conversation_id, None).await?;
+        let messages = on_path(self.conversations.get_messages(conversation_id).await?, &path);
+        let messages = self.branches.decrypt(conversation_id, messages).await?;
+        
+        if self.settings.titles_enabled && conversation.title.is_none() {
+            self.title(user_id, conversation_id, &messages).await?;
+        }
+        if self.settings.summary_after_messages > 0 && messages.len() >= self.settings.summary_after_messages {
+            self.summarize(user_id, conversation_id, &messages).await?;
+        }
+        
+        Ok(())
+    }
+    
+    async fn title(&self, user_id: &str, conversation_id: &str, messages: &[Message]) -> Result<()> {
+        let first_user = messages.iter().find(|m| m.role == "user");
+        let first_reply = messages.iter().find(|m| m.role == "assistant");
+        let (Some(question), Some(answer)) = (first_user, first_reply) else {
+            return Ok(());
+        };
+        
+        // Redacted before excerpting, so a secret cut at the limit is still found
+        let (redacted, vault) = self.redact(conversation_id, &[question.content.as_str(), answer.content.as_str()]).await?;
+        let transcript = format!("User: {}\n\nAssistant: {}", excerpt(&redacted[0]), excerpt(&redacted[1]));
+        let title = vault.restore(&self.complete(TITLE_PROMPT, transcript, 24).await?);
+        let title: String = title
+            .trim()
+            .trim_matches(|c: char| c == '"' || c == '\'' || c == '.')
+            .chars()
+            .take(TITLE_MAX_CHARS)
+            .collect();
+        if title.is_empty() {
+            return Ok(());
+        }
+        
+        self.conversations.set_title(conversation_id, &title).await?;
+        info!("Titled conversation {}", conversation_id);
+        
+        self.pubsub.publish(TopicEvent {
+            topic: user_topic(user_id, "sessions"),
+            origin: None,
+            data: json!({
+                "type": "sessionUpdated",
+                "session": { "id": conversation_id, "title": title },
+            }),
+        }).await;
+        
+        Ok(())
+    }
+    
+    // The latest half of the threshold stays verbatim; everything before it
+    // is folded into the summary once enough of it is new
+    async fn summarize(&self, user_id: &str, conversation_id: &str, messages: &[Message]) -> Result<()> {
+        let keep_recent = self.settings.summary_after_messages / 2;
+        let covered = messages.len().saturating_sub(keep_recent);
+        
+        let path: Vec<String> = messages.iter().map(|m| m.id.clone()).collect();
+        let previous = self.summary(conversation_id, &path).await?;
+        let previous_count = previous.as_ref().map(|(_, count)| *count).unwrap_or(0);
+        if covered < previous_count + keep_recent.max(1) {
+            return Ok(());
+        }
+        
+        let new_messages = &messages[previous_count.min(covered)..covered];
+        let mut texts: Vec<&str> = new_messages.iter().map(|m| m.content.as_str()).collect();
+        if let Some((summary, _)) = &previous {
+            texts.push(summary);
+        }
+        let (mut redacted, vault) = self.redact(conversation_id, &texts).await?;
+        
+        let mut transcript = match &previous {
+            Some(_) => format!("Summary so far:\n{}\n\nMessages since:\n", redacted.pop().unwrap_or_default()),
+            None => "Summary so far: (none)\n\nMessages:\n".to_string(),
+        };
+        for (message, content) in new_messages.iter().zip(&redacted) {
+            transcript.push_str(&format!("{}: {}\n\n", message.role, excerpt(content)));
+        }
+        
+        // The summary is redacted again whenever it is sent upstream as context
+        let summary = vault.restore(&self.complete(SUMMARY_PROMPT, transcript, 400).await?);
+        self.store_summary(user_id, conversation_id, summary.trim(), &messages[covered - 1].id, covered).await?;
+        info!("Summarized {} messages of conversation {}", covered, conversation_id);
+        
+        Ok(())
+    }
+    
+    // The rolling summary and how many of the oldest messages it covers, if
+    // it was written for the branch `path` (message ids, root first)
+    pub async fn summary(&self, conversation_id: &str, path: &[String]) -> Result<Option<(String, usize)>> {
+        let id = Uuid::parse_str(conversation_id)?;
+        let stored = sqlx::query_scalar::<_, Option<Value>>(
+            "SELECT metadata->'summary' FROM conversations WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.db)
+        .await?
+        .flatten();
+        
+        let Some(stored) = stored else {
+            return Ok(None);
+        };
+        let count = stored["message_count"].as_u64().unwrap_or(0) as usize;
+        // Summaries from before branching carry no id; their conversation had only one branch
+        if let Some(through) = stored["through_message_id"].as_str() {
+            if count == 0 || path.get(count - 1).map(String::as_str) != Some(through) {
+                return Ok(None);
+            }
+        }
+        
+        let text = match (stored["ciphertext"].as_str(), stored["data_key_id"].as_str(), &self.encryption) {
+            (Some(ciphertext), Some(data_key_id), Some(encryption)) => {
+                let sealed = BASE64.decode(ciphertext).context("Summary ciphertext is not base64")?;
+                // Sealed to the last message it covers; older summaries only to the conversation
+                let through = stored["through_message_id"].as_str().map(Uuid::parse_str).transpose()?;
+                let aad_version = stored["aad_version"].as_i64().unwrap_or(1) as i16;
+                let (text, _) = encryption
+                    .decrypt_message(
+                        &id,
+                        &through.unwrap_or_default(),
+                        aad_version,
+                        &Uuid::parse_str(data_key_id)?,
+                        &sealed,
+                        None,
+                    )
+                    .await?;
+                text
+            }
+            _ => match stored["text"].as_str() {
+                Some(text) => text.to_string(),
+                None => return Ok(None),
+            },
+        };
+        
+        Ok(Some((text, count)))
+    }
+    
+    // Sealed like message content when encryption at rest is on
+    async fn store_summary(
+        &self,
+        user_id: &str,
+        conversation_id: &str,
+        summary: &str,
+        through_message_id: &str,
+        covered: usize,
+    ) -> Result<()> {
+        let id = Uuid::parse_str(conversation_id)?;
+        
+        let stored = match &self.encryption {
+            Some(encryption) => {
+                let sealed = encryption
+                    .encrypt_message(&Uuid::parse_str(user_id)?, &id, &Uuid::parse_str(through_message_id)?, summary, &json!({}))
+                    .await?;
+                json!({
+                    "ciphertext": BASE64.encode(sealed.content),
+                    "data_key_id": sealed.data_key_id,
+                    "aad_version": AAD_VERSION,
+                    "through_message_id": through_message_id,
+                    "message_count": covered,
+                    "updated_at": chrono::Utc::now(),
+                })
+            }
+            None => json!({
+                "text": summary,
+                "through_message_id": through_message_id,
+                "message_count": covered,
+                "updated_at": chrono::Utc::now(),
+            }),
+        };
+        
+        sqlx::query(
+            "UPDATE conversations SET metadata = COALESCE(metadata, '{}'::jsonb) || jsonb_build_object('summary', $2::jsonb) WHERE id = $1",
+        )
+        .bind(id)
+        .bind(stored)
+        .execute(&self.db)
+        .await?;
+        
+        Ok(())
+    }
+    
+    // Transcripts go through the conversation owner's redaction policy, like
+    // chat prompts. A background run cannot be refused, so block rules mask
+    async fn redact(&self, conversation_id: &str, texts: &[&str]) -> Result<(Vec<String>, RedactionVault)> {
+        let owner_id = sqlx::query_scalar::<_, Uuid>("SELECT user_id FROM conversations WHERE id = $1")
+            .bind(Uuid::parse_str(conversation_id)?)
+            .fetch_one(&self.db)
+            .await?;
+        let Some(policy) = self.policy.redaction_policy_for_user(&owner_id).await? else {
+            return Ok((texts.iter().map(|t| t.to_string()).collect(), RedactionVault::default()));
+        };
+        
+        let redacted = redaction::redact(texts, &policy.masking_blocked())
+            .map_err(|e| anyhow!("Unexpected block while redacting transcript: {}", e))?;
+        
+        Ok((redacted.texts, redacted.vault))
+    }
+    
+    async fn complete(&self, instructions: &str, input: String, max_tokens: u32) -> Result<String> {
+        let model = &self.settings.model;
+        let response = self.client
+            .chat_completion(ChatCompletionRequest {
+                model: model.clone(),
+                messages: vec![
+                    ChatMessage { role: "system".to_string(), content: instructions.to_string() },
+                    ChatMessage { role: "user".to_string(), content: input },
+                ],
+                temperature: Some(0.2),
+                max_tokens: Some(max_tokens),
+                stream: false,
+            })
+            .await?;
+        
+        let usage = &response.usage;
+        if let Err(e) = self.token_meter.record_usage(
+            &self.settings.system_account_id,
+            model,
+            usage.prompt_tokens,
+            usage.completion_tokens,
+        ).await {
+            error!("Failed to record summarizer token usage: {}", e);
+        }
+        self.metrics.record_tokens(self.provider.as_str(), model, usage.prompt_tokens, usage.completion_tokens);
+        
+        response.choices
+            .into_iter()
+            .next()
+            .map(|choice| choice.message.content)
+            .context("Model returned no choices")
+    }
+}
+
+fn excerpt(content: &str) -> String {
+    match content.char_indices().nth(EXCERPT_MAX_CHARS) {
+        Some((end, _)) => format!("{}…", &content[..end]),
+        None => content.to_string(),
+    }
+}
@@ -0,0 +1,422 @@
+use crate::tiers::{Tiers, CUSTOM_SYSTEM_PROMPTS, DEFAULT_TIER};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Longest template content or one-off system prompt, in characters
+pub const MAX_TEMPLATE_CHARS: usize = 16_000;
+
+// `{{name}}`, with optional spaces inside the braces
+static VARIABLE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_]*)\s*\}\}").expect("variable pattern is valid")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateScope {
+    // Only its owner sees it
+    Personal,
+    // Members of the owner's org
+    Org,
+    // Everyone; managed by admins
+    Global,
+}
+
+impl TemplateScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemplateScope::Personal => "personal",
+            TemplateScope::Org => "org",
+            TemplateScope::Global => "global",
+        }
+    }
+}
+
+impl Default for TemplateScope {
+    fn default() -> Self {
+        TemplateScope::Personal
+    }
+}
+
+// A template with the content of its current version
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct PromptTemplate {
+    pub id: Uuid,
+    pub scope: String,
+    pub owner_id: Option<Uuid>,
+    pub org_id: Option<Uuid>,
+    pub name: String,
+    pub description: Option<String>,
+    pub current_version: i32,
+    pub content: String,
+    pub variables: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PromptTemplate {
+    // Personal and org templates are written by users; global ones are curated
+    pub fn is_custom(&self) -> bool {
+        self.scope != TemplateScope::Global.as_str()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct TemplateVersion {
+    pub template_id: Uuid,
+    pub version: i32,
+    pub content: String,
+    pub variables: Vec<String>,
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateTemplateRequest {
+    #[serde(default)]
+    pub scope: TemplateScope,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub content: String,
+}
+
+// A new `content` becomes the next version; name and description are
+// edited in place
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateTemplateRequest {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub content: Option<String>,
+}
+
+// What a user's tier lets them do with system prompts
+#[derive(Debug, Clone, Serialize)]
+pub struct PromptAccess {
+    pub tier: String,
+    // Global templates
+    pub system_prompts: bool,
+    // Their own prompts, and personal and org templates
+    pub custom_prompts: bool,
+}
+
+// A template rendered for one chat message; recorded in the reply's metadata
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedPrompt {
+    pub template_id: Option<Uuid>,
+    pub version: Option<i32>,
+    #[serde(default)]
+    pub variables: HashMap<String, String>,
+    pub prompt: String,
+}
+
+// Variable names used in `content`, in order of first use
+pub fn parse_variables(content: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for captures in VARIABLE.captures_iter(content) {
+        let name = &captures[1];
+        if !names.iter().any(|n| n == name) {
+            names.push(name.to_string());
+        }
+    }
+    names
+}
+
+// Fills every `{{name}}`; Err names the variables without a value. Values are
+// inserted as they are, so a value containing braces is not expanded again
+pub fn render(content: &str, variables: &HashMap<String, String>) -> Result<String, Vec<String>> {
+    let missing: Vec<String> = parse_variables(content)
+        .into_iter()
+        .filter(|name| !variables.contains_key(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    
+    Ok(VARIABLE
+        .replace_all(content, |captures: &regex::Captures| variables[&captures[1]].clone())
+        .into_owned())
+}
+
+// Prompt library: personal, org and global templates with versioned content.
+// Visibility is by scope: a user sees their own, their org's and global ones
+pub struct TemplateService {
+    db: PgPool,
+    tiers: Tiers,
+}
+
+impl TemplateService {
+    pub fn new(db: PgPool, tiers: Tiers) -> Self {
+        Self { db, tiers }
+    }
+    
+    // The tier is set in the user's metadata, else in their org's
+    pub async fn access(&self, user_id: &Uuid) -> Result<PromptAccess> {
+        let tier = sqlx::query_scalar::<_, Option<String>>(
+            r#"
+            SELECT COALESCE(u.metadata->>'tier', o.metadata->>'tier')
+            FROM users u
+            LEFT JOIN organizations o ON o.id = u.org_id
+            WHERE u.id = $1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?
+        .flatten()
+        .unwrap_or_else(|| DEFAULT_TIER.to_string());
+        
+        let system_prompts = self.tiers.allows_system_prompts(&tier);
+        Ok(PromptAccess {
+            custom_prompts: system_prompts && self.tiers.has_feature(&tier, CUSTOM_SYSTEM_PROMPTS),
+            system_prompts,
+            tier,
+        })
+    }
+    
+    pub async fn list_visible(&self, user_id: &Uuid, org_id: Option<Uuid>) -> Result<Vec<PromptTemplate>> {
+        let templates = sqlx::query_as::<_, PromptTemplate>(
+            r#"
+            SELECT t.id, t.scope, t.owner_id, t.org_id, t.name, t.description, t.current_version,
+                   v.content, v.variables, t.created_at, t.updated_at
+            FROM prompt_templates t
+            JOIN prompt_template_versions v ON v.template_id = t.id AND v.version = t.current_version
+            WHERE (t.scope = 'personal' AND t.owner_id = $1)
+               OR (t.scope = 'org' AND t.org_id = $2)
+               OR t.scope = 'global'
+            ORDER BY t.scope, t.name
+            "#
+        )
+        .bind(user_id)
+        .bind(org_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(templates)
+    }
+    
+    pub async fn list_global(&self) -> Result<Vec<PromptTemplate>> {
+        let templates = sqlx::query_as::<_, PromptTemplate>(
+            r#"
+            SELECT t.id, t.scope, t.owner_id, t.org_id, t.name, t.description, t.current_version,
+                   v.content, v.variables, t.created_at, t.updated_at
+            FROM prompt_templates t
+            JOIN prompt_template_versions v ON v.template_id = t.id AND v.version = t.current_version
+            WHERE t.scope = 'global'
+            ORDER BY t.name
+            "#
+        )
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(templates)
+    }
+    
+    pub async fn get(&self, template_id: &Uuid) -> Result<Option<PromptTemplate>> {
+        let template = sqlx::query_as::<_, PromptTemplate>(
+            r#"
+            SELECT t.id, t.scope, t.owner_id, t.org_id, t.name, t.description, t.current_version,
+                   v.content, v.variables, t.created_at, t.updated_at
+            FROM prompt_templates t
+            JOIN prompt_template_versions v ON v.template_id = t.id AND v.version = t.current_version
+            WHERE t.id = $1
+            "#
+        )
+        .bind(template_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(template)
+    }
+    
+    // None when it does not exist or the user cannot see it
+    pub async fn get_visible(&self, template_id: &Uuid, user_id: &Uuid, org_id: Option<Uuid>) -> Result<Option<PromptTemplate>> {
+        let template = self.get(template_id).await?.filter(|template| match template.scope.as_str() {
+            "personal" => template.owner_id.as_ref() == Some(user_id),
+            "org" => org_id.is_some() && template.org_id == org_id,
+            _ => true,
+        });
+        
+        Ok(template)
+    }
+    
+    pub async fn versions(&self, template_id: &Uuid) -> Result<Vec<TemplateVersion>> {
+        let versions = sqlx::query_as::<_, TemplateVersion>(
+            r#"
+            SELECT * FROM prompt_template_versions
+            WHERE template_id = $1
+            ORDER BY version DESC
+            "#
+        )
+        .bind(template_id)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(versions)
+    }
+    
+    pub async fn version(&self, template_id: &Uuid, version: i32) -> Result<Option<TemplateVersion>> {
+        let version = sqlx::query_as::<_, TemplateVersion>(
+            "SELECT * FROM prompt_template_versions WHERE template_id = $1 AND version = $2",
+        )
+        .bind(template_id)
+        .bind(version)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(version)
+    }
+    
+    // `owner_id` is None for global templates; `org_id` is set for org ones
+    pub async fn create(
+        &self,
+        owner_id: Option<&Uuid>,
+        org_id: Option<Uuid>,
+        created_by: &Uuid,
+        request: &CreateTemplateRequest,
+    ) -> Result<PromptTemplate> {
+        let mut tx = self.db.begin().await?;
+        
+        let template_id = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO prompt_templates (scope, owner_id, org_id, name, description)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id
+            "#
+        )
+        .bind(request.scope.as_str())
+        .bind(owner_id)
+        .bind(org_id)
+        .bind(request.name.trim())
+        .bind(&request.description)
+        .fetch_one(&mut *tx)
+        .await?;
+        
+        sqlx::query(
+            r#"
+            INSERT INTO prompt_template_versions (template_id, version, content, variables, created_by)
+            VALUES ($1, 1, $2, $3, $4)
+            "#
+        )
+        .bind(template_id)
+        .bind(&request.content)
+        .bind(parse_variables(&request.content))
+        .bind(created_by)
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        
+        self.get(&template_id).await?.ok_or_else(|| anyhow::anyhow!("Template {} vanished after insert", template_id))
+    }
+    
+    pub async fn update(&self, template_id: &Uuid, request: &UpdateTemplateRequest, edited_by: &Uuid) -> Result<Option<PromptTemplate>> {
+        let mut tx = self.db.begin().await?;
+        
+        // Locks the template so concurrent edits get consecutive versions
+        let current = sqlx::query_scalar::<_, i32>(
+            "SELECT current_version FROM prompt_templates WHERE id = $1 FOR UPDATE",
+        )
+        .bind(template_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(current) = current else {
+            return Ok(None);
+        };
+        
+        let mut version = current;
+        if let Some(content) = &request.content {
+            version = current + 1;
+            sqlx::query(
+                r#"
+                INSERT INTO prompt_template_versions (template_id, version, content, variables, created_by)
+                VALUES ($1, $2, $3, $4, $5)
+                "#
+            )
+            .bind(template_id)
+            .bind(version)
+            .bind(content)
+            .bind(parse_variables(content))
+            .bind(edited_by)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        sqlx::query(
+            r#"
+            UPDATE prompt_templates
+            SET name = COALESCE($2, name), description = COALESCE($3, description), current_version = $4
+            WHERE id = $1
+            "#
+        )
+        .bind(template_id)
+        .bind(request.name.as_deref().map(str::trim))
+        .bind(&request.description)
+        .bind(version)
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        
+        self.get(template_id).await
+    }
+    
+    pub async fn delete(&self, template_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM prompt_templates WHERE id = $1")
+            .bind(template_id)
+            .execute(&self.db)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn variables(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+    
+    #[test]
+    fn variables_are_listed_once_in_order_of_first_use() {
+        assert_eq!(
+            parse_variables("{{ tone }} reply to {{name}}, {{tone}} again, {{ 1bad }} {name}"),
+            vec!["tone", "name"],
+        );
+        assert!(parse_variables("No variables here").is_empty());
+    }
+    
+    #[test]
+    fn render_fills_every_variable() {
+        let rendered = render(
+            "You are {{ role }}. Answer {{name}} in {{role}}'s voice.",
+            &variables(&[("role", "a pirate"), ("name", "Sam"), ("unused", "x")]),
+        );
+        assert_eq!(rendered.unwrap(), "You are a pirate. Answer Sam in a pirate's voice.");
+    }
+    
+    #[test]
+    fn render_names_missing_variables() {
+        let missing = render("{{greeting}}, {{name}} from {{place}}", &variables(&[("name", "Sam")])).unwrap_err();
+        assert_eq!(missing, vec!["greeting", "place"]);
+    }
+    
+    #[test]
+    fn values_are_not_expanded_again() {
+        let rendered = render("Hello {{name}}", &variables(&[("name", "{{secret}}"), ("secret", "leaked")]));
+        assert_eq!(rendered.unwrap(), "Hello {{secret}}");
+    }
+    
+    #[test]
+    fn content_without_variables_is_unchanged() {
+        let content = "Be concise. Use {single} braces freely.";
+        assert_eq!(render(content, &HashMap::new()).unwrap(), content);
+    }
+}
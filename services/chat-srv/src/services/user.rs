@@ -1,5 +1,5 @@
 use anyhow::Result;
-use sqlx::PgPool;
+use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -22,6 +22,13 @@ pub struct CreateUserRequest {
     pub metadata: Option<serde_json::Value>,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UpdateUserRequest {
+    pub email: Option<String>,
+    pub name: Option<String>,
+    pub metadata: Option<serde_json::Value>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct ApiKey {
     pub id: Uuid,
@@ -43,7 +50,9 @@ impl UserService {
         Self { db }
     }
     
-    pub async fn create_user(&self, request: CreateUserRequest) -> Result<User> {
+    // Account changes run in the caller's transaction, so the admin audit
+    // entry for them commits or rolls back with the change
+    pub async fn create_user(&self, tx: &mut Transaction<'_, Postgres>, request: CreateUserRequest) -> Result<User> {
         let user = sqlx::query_as::<_, User>(
             r#"
             INSERT INTO users (email, name, metadata)
@@ -54,7 +63,7 @@ impl UserService {
         .bind(&request.email)
         .bind(&request.name)
         .bind(request.metadata.unwrap_or(serde_json::json!({})))
-        .fetch_one(&self.db)
+        .fetch_one(&mut **tx)
         .await?;
         
         // Create default rate limits
@@ -65,7 +74,7 @@ impl UserService {
             "#
         )
         .bind(&user.id)
-        .execute(&self.db)
+        .execute(&mut **tx)
         .await?;
         
         Ok(user)
@@ -99,6 +108,107 @@ impl UserService {
         Ok(user)
     }
     
+    // Whether another account, active or not, already has this email
+    pub async fn email_in_use(&self, email: &str, except: &Uuid) -> Result<bool> {
+        let in_use = sqlx::query_scalar::<_, bool>(
+            "SELECT EXISTS (SELECT 1 FROM users WHERE email = $1 AND id <> $2)",
+        )
+        .bind(email)
+        .bind(except)
+        .fetch_one(&self.db)
+        .await?;
+        
+        Ok(in_use)
+    }
+    
+    pub async fn get_user_including_inactive(&self, user_id: &Uuid) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            SELECT * FROM users
+            WHERE id = $1
+            "#
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db)
+        .await?;
+        
+        Ok(user)
+    }
+    
+    pub async fn list_users(&self, limit: i64, offset: i64, include_inactive: bool) -> Result<Vec<User>> {
+        let users = sqlx::query_as::<_, User>(
+            r#"
+            SELECT * FROM users
+            WHERE is_active = true OR $3
+            ORDER BY created_at DESC
+            LIMIT $1 OFFSET $2
+            "#
+        )
+        .bind(limit)
+        .bind(offset)
+        .bind(include_inactive)
+        .fetch_all(&self.db)
+        .await?;
+        
+        Ok(users)
+    }
+    
+    pub async fn update_user(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+        request: UpdateUserRequest,
+    ) -> Result<Option<User>> {
+        let user = sqlx::query_as::<_, User>(
+            r#"
+            UPDATE users
+            SET email = COALESCE($2, email),
+                name = COALESCE($3, name),
+                metadata = COALESCE($4, metadata)
+            WHERE id = $1
+            RETURNING *
+            "#
+        )
+        .bind(user_id)
+        .bind(request.email)
+        .bind(request.name)
+        .bind(request.metadata)
+        .fetch_optional(&mut **tx)
+        .await?;
+        
+        Ok(user)
+    }
+    
+    pub async fn set_user_active(&self, tx: &mut Transaction<'_, Postgres>, user_id: &Uuid, is_active: bool) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE users
+            SET is_active = $2
+            WHERE id = $1
+            "#
+        )
+        .bind(user_id)
+        .bind(is_active)
+        .execute(&mut **tx)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    pub async fn delete_user(&self, tx: &mut Transaction<'_, Postgres>, user_id: &Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM users
+            WHERE id = $1
+            "#
+        )
+        .bind(user_id)
+        .execute(&mut **tx)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
     pub async fn validate_api_key(&self, key_hash: &str) -> Result<Option<User>> {
         let result = sqlx::query_as::<_, (Uuid, Option<DateTime<Utc>>)>(
             r#"
@@ -1,12 +1,28 @@
 use crate::config::Config;
-use crate::llm::{AnthropicClient, LLMProvider, OpenAIClient};
-use crate::services::{ConversationService, TokenMeterService, UserService};
+use crate::crypto::{FileKeyProvider, LocalKmsProvider, MasterKeyProvider};
+use crate::guardrails::{Guardrails, GuardrailsConfig};
+use crate::lifecycle::Lifecycle;
+use crate::llm::{AnthropicClient, LLMClient, LLMProvider, OpenAIClient};
+use crate::metrics::Metrics;
+use crate::redaction::RedactionAction;
+use crate::models::Conversation;
+use crate::services::encryption::KeyScope;
+use crate::services::members::MemberRole;
+use crate::services::summarizer::SummarizerSettings;
+use crate::services::{
+    AdminService, AuditService, BranchService, ConversationService, EncryptionService, ExportService, ImportService,
+    MemberService, PolicyService, PrivacyService, PubSubService, RetentionService, SearchService, SessionRegistryService, ShareService, StreamBufferService, SummarizerService, TemplateService, TokenMeterService, UserService,
+};
+use crate::tiers::{QuotasConfig, Tiers};
 use anyhow::Result;
 use dashmap::DashMap;
 use redis::aio::ConnectionManager;
+use serde::{Deserialize, Serialize};
 use sqlx::{PgPool, postgres::PgPoolOptions};
+use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
+use uuid::Uuid;
 
 pub struct AppState {
     pub config: Config,
@@ -16,7 +32,28 @@ pub struct AppState {
     pub anthropic_client: Arc<AnthropicClient>,
     pub user_service: Arc<UserService>,
     pub conversation_service: Arc<ConversationService>,
+    pub branches: Arc<BranchService>,
+    pub members: Arc<MemberService>,
     pub token_meter_service: Arc<TokenMeterService>,
+    pub admin_service: Arc<AdminService>,
+    pub audit_service: Arc<AuditService>,
+    pub policy_service: Arc<PolicyService>,
+    pub stream_buffer: Arc<StreamBufferService>,
+    pub pubsub: Arc<PubSubService>,
+    pub session_registry: Arc<SessionRegistryService>,
+    pub summarizer: Arc<SummarizerService>,
+    pub search: Arc<SearchService>,
+    pub export_service: Arc<ExportService>,
+    pub import_service: Arc<ImportService>,
+    pub share_service: Arc<ShareService>,
+    pub retention: Arc<RetentionService>,
+    pub privacy: Arc<PrivacyService>,
+    pub templates: Arc<TemplateService>,
+    pub guardrails: Arc<Guardrails>,
+    pub encryption_service: Option<Arc<EncryptionService>>,
+    pub metrics: Arc<Metrics>,
+    pub lifecycle: Arc<Lifecycle>,
+    // Sessions on this replica; `session_registry` knows every replica's
     pub active_sessions: DashMap<String, SessionState>,
     pub model_status: Arc<RwLock<ModelStatusCache>>,
 }
@@ -25,8 +62,25 @@ pub struct AppState {
 pub struct SessionState {
     pub user_id: String,
     pub conversation_id: Option<String>,
+    // Conversations this socket follows live, across all of the user's devices
+    pub subscriptions: HashSet<String>,
     pub last_activity: chrono::DateTime<chrono::Utc>,
     pub provider: LLMProvider,
+    pub control: mpsc::Sender<SessionControl>,
+}
+
+// Sent to a session by its own replica or, through the registry, by any other
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SessionControl {
+    Disconnect { reason: String },
+    // Cancels one request, or every request on the socket
+    Stop { request_id: Option<String> },
+    // Delivers an event as if it arrived on a subscribed topic
+    Push { topic: String, data: serde_json::Value },
+    // Ends a subscription the user may no longer see, such as a conversation
+    // they were removed from
+    Revoke { topic: String },
 }
 
 #[derive(Default)]
@@ -76,15 +130,146 @@ impl AppState {
             config.anthropic_base_url.clone(),
         ));
         
+        // Initialize envelope encryption for message content
+        let encryption_service = if config.encryption_enabled {
+            let provider: Arc<dyn MasterKeyProvider> = match config.master_key_source.as_str() {
+                "kms" => Arc::new(LocalKmsProvider::new(
+                    config.kms_secret.as_deref().unwrap_or_default(),
+                    &config.kms_key_id,
+                )?),
+                _ => Arc::new(FileKeyProvider::from_path(
+                    config.master_key_path.as_deref().unwrap_or_default(),
+                )?),
+            };
+            let scope = KeyScope::parse(&config.data_key_scope).unwrap_or(KeyScope::User);
+            Some(Arc::new(EncryptionService::new(db.clone(), provider, scope)))
+        } else {
+            tracing::warn!("Encryption at rest is disabled; message content is stored in plaintext");
+            None
+        };
+        
         // Initialize services
         let user_service = Arc::new(UserService::new(db.clone()));
         let conversation_service = Arc::new(ConversationService::new(db.clone(), redis.clone()));
+        let branches = Arc::new(BranchService::new(db.clone(), encryption_service.clone()));
+        let members = Arc::new(MemberService::new(db.clone(), config.invitation_ttl_days));
         let token_meter_service = Arc::new(TokenMeterService::new(
             db.clone(),
             redis.clone(),
             config.max_tokens_per_day,
             config.max_tokens_per_month,
         ));
+        let audit_service = Arc::new(AuditService::new(db.clone()));
+        let admin_service = Arc::new(AdminService::new(db.clone(), redis.clone(), audit_service.clone()));
+        let policy_service = Arc::new(PolicyService::new(
+            db.clone(),
+            config.redaction_enabled,
+            RedactionAction::parse(&config.redaction_default_action).unwrap_or(RedactionAction::Mask),
+        ));
+        let stream_buffer = Arc::new(StreamBufferService::new(
+            redis.clone(),
+            config.stream_resume_ttl_secs,
+            config.llm_stream_timeout_secs,
+        ));
+        let pubsub = Arc::new(PubSubService::new(redis.clone()));
+        let session_registry = Arc::new(SessionRegistryService::new(
+            redis.clone(),
+            config.replica_id.clone(),
+            config.session_stale_secs,
+        ));
+        
+        // Load guardrails; a broken config should stop startup rather than run unguarded
+        let guardrails = match &config.guardrails_config_path {
+            // Config::validate rejects enabled guardrails without a path
+            Some(path) if config.guardrails_enabled => {
+                Guardrails::from_config(&GuardrailsConfig::from_file(path)?)?
+            }
+            _ => Guardrails::disabled(),
+        };
+        let guardrails = Arc::new(guardrails);
+        
+        // Tier features come from token-meter's quota config, so both services
+        // agree on what each tier may do
+        let tiers = match &config.quotas_config_path {
+            Some(path) => Tiers::from_config(QuotasConfig::from_file(path)?),
+            None => {
+                tracing::warn!("QUOTAS_CONFIG_PATH is not set; tier features are not enforced");
+                Tiers::unrestricted()
+            }
+        };
+        let templates = Arc::new(TemplateService::new(db.clone(), tiers));
+        
+        let metrics = Arc::new(Metrics::new(&config.known_models())?);
+        metrics.ws_outbound_limit_bytes.set(config.ws_outbound_max_bytes as i64);
+        
+        let summary_client: (LLMProvider, Arc<dyn LLMClient>) = if config.summary_model.starts_with("claude") {
+            (LLMProvider::Anthropic, anthropic_client.clone())
+        } else {
+            (LLMProvider::OpenAI, openai_client.clone())
+        };
+        let summarizer = Arc::new(SummarizerService::new(
+            db.clone(),
+            conversation_service.clone(),
+            branches.clone(),
+            encryption_service.clone(),
+            policy_service.clone(),
+            token_meter_service.clone(),
+            pubsub.clone(),
+            metrics.clone(),
+            summary_client,
+            SummarizerSettings {
+                titles_enabled: config.auto_title_enabled,
+                model: config.summary_model.clone(),
+                summary_after_messages: config.summary_after_messages,
+                system_account_id: config.system_account_id.clone(),
+            },
+        ));
+        
+        // Embeddings come from OpenAI; Anthropic has no embeddings endpoint
+        let search = Arc::new(SearchService::new(
+            db.clone(),
+            encryption_service.clone(),
+            policy_service.clone(),
+            openai_client.clone(),
+            config.embedding_model.clone(),
+        ));
+        search.check_embedding_dimensions().await?;
+        
+        let export_service = Arc::new(ExportService::new(db.clone(), conversation_service.clone(), branches.clone()));
+        let import_service = Arc::new(ImportService::new(
+            db.clone(),
+            redis.clone(),
+            conversation_service.clone(),
+            branches.clone(),
+            pubsub.clone(),
+            config.default_openai_model.clone(),
+        ));
+        let share_service = Arc::new(ShareService::new(
+            db.clone(),
+            conversation_service.clone(),
+            branches.clone(),
+            policy_service.clone(),
+            &config.share_link_secret,
+            &config.share_base_url,
+        ));
+        let retention = Arc::new(RetentionService::new(
+            db.clone(),
+            conversation_service.clone(),
+            pubsub.clone(),
+            audit_service.clone(),
+            config.restore_window_days,
+            config.audit_retention_days,
+        ));
+        let privacy = Arc::new(PrivacyService::new(
+            db.clone(),
+            redis.clone(),
+            conversation_service.clone(),
+            export_service.clone(),
+            pubsub.clone(),
+            audit_service.clone(),
+            stream_buffer.clone(),
+            config.data_export_ttl_secs,
+        ));
         
         Ok(Self {
             config,
@@ -94,7 +279,27 @@ impl AppState {
             anthropic_client,
             user_service,
             conversation_service,
+            branches,
+            members,
             token_meter_service,
+            admin_service,
+            audit_service,
+            policy_service,
+            stream_buffer,
+            pubsub,
+            session_registry,
+            summarizer,
+            search,
+            export_service,
+            import_service,
+            share_service,
+            retention,
+            privacy,
+            templates,
+            guardrails,
+            encryption_service,
+            metrics,
+            lifecycle: Arc::new(Lifecycle::new()),
             active_sessions: DashMap::new(),
             model_status: Arc::new(RwLock::new(ModelStatusCache::default())),
         })
@@ -147,6 +352,66 @@ impl AppState {
         Ok(())
     }
     
+    pub fn llm_client(&self, model: &str) -> (LLMProvider, Arc<dyn LLMClient>) {
+        if model.starts_with("claude") {
+            (LLMProvider::Anthropic, self.anthropic_client.clone())
+        } else {
+            (LLMProvider::OpenAI, self.openai_client.clone())
+        }
+    }
+    
+    // The conversation and the user's role in it; None when it does not
+    // exist or the user is not a member
+    pub async fn conversation_access(&self, conversation_id: &str, user_id: &Uuid) -> Result<Option<(Conversation, MemberRole)>> {
+        let Some(role) = self.members.role(conversation_id, user_id).await? else {
+            return Ok(None);
+        };
+        let conversation = self.conversation_service.get_conversation(conversation_id).await?;
+        
+        Ok(conversation.map(|conversation| (conversation, role)))
+    }
+    
+    pub async fn disconnect_user(&self, user_id: &str, reason: &str) -> usize {
+        self.control_user(user_id, SessionControl::Disconnect { reason: reason.to_string() }).await
+    }
+    
+    // Reaches the user's sockets on every replica; returns how many took it.
+    // Sockets on this replica are reached directly, so they still get it when
+    // the registry is down or never recorded them
+    pub async fn control_user(&self, user_id: &str, control: SessionControl) -> usize {
+        let local: Vec<(String, mpsc::Sender<SessionControl>)> = self.active_sessions
+            .iter()
+            .filter(|entry| entry.user_id == user_id)
+            .map(|entry| (entry.key().clone(), entry.control.clone()))
+            .collect();
+        let sessions = match self.session_registry.sessions_for_user(user_id).await {
+            Ok(sessions) => sessions,
+            Err(e) => {
+                tracing::error!("Failed to look up sessions of user {}, reaching this replica's only: {}", user_id, e);
+                Vec::new()
+            }
+        };
+        
+        let mut delivered = 0;
+        for (_, sender) in &local {
+            if sender.send(control.clone()).await.is_ok() {
+                delivered += 1;
+            }
+        }
+        for session in sessions {
+            if local.iter().any(|(session_id, _)| *session_id == session.session_id) {
+                continue;
+            }
+            match self.session_registry.send(&session.session_id, control.clone()).await {
+                Ok(true) => delivered += 1,
+                Ok(false) => {}
+                Err(e) => tracing::warn!("Failed to reach session {}: {}", session.session_id, e),
+            }
+        }
+        
+        delivered
+    }
+    
     fn get_model_max_tokens(&self, model_id: &str) -> u32 {
         match model_id {
             "gpt-4-turbo-preview" | "gpt-4-0125-preview" => 128000,
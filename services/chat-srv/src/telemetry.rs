@@ -0,0 +1,94 @@
+use anyhow::Result;
+use axum::http::{HeaderMap, Request};
+use opentelemetry::propagation::{Extractor, TextMapPropagator};
+use opentelemetry::{global, Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use std::collections::HashMap;
+use tracing::{info_span, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+const SERVICE_NAME: &str = "chat-srv";
+
+// OTLP/gRPC batch exporter; the returned tracer backs the tracing layer
+pub fn init_tracer(otlp_endpoint: &str) -> Result<trace::Tracer> {
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(otlp_endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![
+            KeyValue::new("service.name", SERVICE_NAME),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ])))
+        .install_batch(runtime::Tokio)?;
+    
+    Ok(tracer)
+}
+
+pub fn init_propagator() {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+}
+
+// Flushes spans still buffered in the batch exporter
+pub fn shutdown() {
+    global::shutdown_tracer_provider();
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+    
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|k| k.as_str()).collect()
+    }
+}
+
+// Remote parent from `traceparent`/`tracestate` request headers
+pub fn context_from_headers(headers: &HeaderMap) -> Context {
+    global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)))
+}
+
+// Remote parent from a `traceparent` sent inside a WebSocket message
+pub fn context_from_traceparent(traceparent: &str, tracestate: Option<&str>) -> Context {
+    let mut carrier = HashMap::new();
+    carrier.insert("traceparent".to_string(), traceparent.to_string());
+    if let Some(tracestate) = tracestate {
+        carrier.insert("tracestate".to_string(), tracestate.to_string());
+    }
+    
+    TraceContextPropagator::new().extract(&carrier)
+}
+
+// W3C headers for the current span, for calls to providers and other services
+pub fn trace_headers() -> HashMap<String, String> {
+    let context = Span::current().context();
+    let mut headers = HashMap::new();
+    global::get_text_map_propagator(|propagator| propagator.inject_context(&context, &mut headers));
+    headers
+}
+
+pub fn inject_trace_headers(mut request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    for (name, value) in trace_headers() {
+        request = request.header(name, value);
+    }
+    request
+}
+
+// Root span for REST requests, continuing the caller's trace when present
+pub fn make_http_span<B>(request: &Request<B>) -> Span {
+    let span = info_span!(
+        "http.request",
+        otel.kind = "server",
+        http.method = %request.method(),
+        http.target = %request.uri().path(),
+    );
+    span.set_parent(context_from_headers(request.headers()));
+    span
+}
@@ -0,0 +1,89 @@
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+pub const DEFAULT_TIER: &str = "free";
+
+// Lets a user write their own system prompts, directly or as personal and
+// org templates
+pub const CUSTOM_SYSTEM_PROMPTS: &str = "custom_system_prompts";
+
+// The parts of token-meter's quotas.yaml this service enforces; everything
+// else in the file belongs to token-meter and is ignored here
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct QuotasConfig {
+    #[serde(default)]
+    pub tiers: HashMap<String, TierConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TierConfig {
+    #[serde(default)]
+    pub features: Features,
+    #[serde(default)]
+    pub restrictions: TierRestrictions,
+}
+
+// A list of feature names, or "*" for all of them
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Features {
+    All(String),
+    List(Vec<String>),
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features::List(Vec::new())
+    }
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TierRestrictions {
+    // No system prompts at all, not even global templates
+    #[serde(default)]
+    pub no_system_prompts: bool,
+}
+
+impl QuotasConfig {
+    pub fn from_file(path: &str) -> Result<Self> {
+        let raw = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read quotas config {}", path))?;
+        serde_yaml::from_str(&raw).with_context(|| format!("Invalid quotas config {}", path))
+    }
+}
+
+// Feature gating by tier. Without a quotas config every tier may do
+// everything, as before tiers were enforced here
+pub struct Tiers {
+    tiers: Option<HashMap<String, TierConfig>>,
+}
+
+impl Tiers {
+    pub fn from_config(config: QuotasConfig) -> Self {
+        Self { tiers: Some(config.tiers) }
+    }
+    
+    pub fn unrestricted() -> Self {
+        Self { tiers: None }
+    }
+    
+    // Unknown tiers get nothing, so a typo in a user's tier fails closed
+    pub fn has_feature(&self, tier: &str, feature: &str) -> bool {
+        let Some(tiers) = &self.tiers else {
+            return true;
+        };
+        match tiers.get(tier).map(|tier| &tier.features) {
+            Some(Features::All(all)) => all == "*",
+            Some(Features::List(features)) => features.iter().any(|f| f == feature),
+            None => false,
+        }
+    }
+    
+    pub fn allows_system_prompts(&self, tier: &str) -> bool {
+        let Some(tiers) = &self.tiers else {
+            return true;
+        };
+        tiers.get(tier).map_or(false, |tier| !tier.restrictions.no_system_prompts)
+    }
+}
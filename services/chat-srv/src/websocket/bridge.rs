@@ -0,0 +1,380 @@
+use super::{ClientMessage, Incoming, Outgoing, ServerMessage};
+use crate::llm::context::ContextStrategy;
+use crate::services::pubsub::session_topic;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// Subprotocol requested by the Flutter `WebSocketBridge`; a socket that
+// negotiates it speaks envelopes instead of `type`-tagged messages
+pub const SUBPROTOCOL: &str = "v1.taas.websocket";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum EnvelopeType {
+    Connect,
+    Disconnect,
+    Authenticate,
+    Subscribe,
+    Unsubscribe,
+    Publish,
+    Request,
+    Response,
+    Error,
+    Ping,
+    Pong,
+    StreamStart,
+    StreamData,
+    StreamEnd,
+}
+
+// Mirrors `WebSocketMessage` in websocket_bridge.dart; `id` and `timestamp`
+// are required by the client's parser, so every outgoing envelope has both
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Envelope {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: EnvelopeType,
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub data: Option<Value>,
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default = "Utc::now")]
+    pub timestamp: DateTime<Utc>,
+}
+
+impl Envelope {
+    fn new(id: Option<String>, kind: EnvelopeType) -> Self {
+        Self {
+            id: id.unwrap_or_else(|| Uuid::new_v4().to_string()),
+            kind,
+            channel: None,
+            data: None,
+            error: None,
+            timestamp: Utc::now(),
+        }
+    }
+    
+    fn with_data(mut self, data: Value) -> Self {
+        self.data = Some(data);
+        self
+    }
+    
+    fn with_error(mut self, error: impl Into<String>) -> Self {
+        self.error = Some(error.into());
+        self
+    }
+    
+    fn publish(channel: String, data: Value) -> Self {
+        let mut envelope = Self::new(None, EnvelopeType::Publish).with_data(data);
+        envelope.channel = Some(channel);
+        envelope
+    }
+}
+
+// A bridge frame becomes a message for the shared handler, a reply to send
+// straight away, or both
+#[derive(Default)]
+pub struct Decoded {
+    pub message: Option<Incoming>,
+    pub reply: Option<Outgoing>,
+}
+
+impl Decoded {
+    fn message(id: String, message: ClientMessage) -> Self {
+        Self {
+            message: Some(Incoming {
+                request_id: Some(id),
+                message,
+            }),
+            reply: None,
+        }
+    }
+    
+    fn reply(id: String, message: ServerMessage) -> Self {
+        Self {
+            message: None,
+            reply: Some(Outgoing {
+                request_id: Some(id),
+                message,
+            }),
+        }
+    }
+    
+    fn error(id: String, message: impl Into<String>) -> Self {
+        Self::reply(id, ServerMessage::Error { message: message.into() })
+    }
+}
+
+impl From<Incoming> for Decoded {
+    fn from(incoming: Incoming) -> Self {
+        Self {
+            message: Some(incoming),
+            reply: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestData {
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+pub fn decode(text: &str) -> Result<Decoded, serde_json::Error> {
+    let envelope: Envelope = serde_json::from_str(text)?;
+    let data = envelope.data.unwrap_or(Value::Null);
+    let id = envelope.id;
+    
+    let decoded = match envelope.kind {
+        EnvelopeType::Connect => {
+            // "1.0.0" -> [1]; capabilities use the same names as `Feature`
+            let versions = data["version"]
+                .as_str()
+                .and_then(|v| v.split('.').next())
+                .and_then(|major| major.parse().ok())
+                .into_iter()
+                .collect();
+            let features = serde_json::from_value(data["capabilities"].clone()).unwrap_or_default();
+            Decoded::message(id, ClientMessage::Hello { versions, features })
+        }
+        EnvelopeType::Authenticate => match data["token"].as_str() {
+            Some(token) => Decoded::message(id, ClientMessage::Auth { token: token.to_string() }),
+            None => Decoded::error(id, "Missing token"),
+        },
+        EnvelopeType::Ping => Decoded::message(id, ClientMessage::Ping),
+        EnvelopeType::Request => {
+            let request: RequestData = serde_json::from_value(data)?;
+            decode_request(id, request)
+        }
+        // The client closes the socket itself right after
+        EnvelopeType::Disconnect => Decoded::default(),
+        EnvelopeType::Subscribe | EnvelopeType::Unsubscribe | EnvelopeType::Publish => {
+            let Some(topic) = envelope.channel else {
+                return Ok(Decoded::error(id, "Missing channel"));
+            };
+            let message = match envelope.kind {
+                EnvelopeType::Subscribe => ClientMessage::Subscribe { topic },
+                EnvelopeType::Unsubscribe => ClientMessage::Unsubscribe { topic },
+                _ => ClientMessage::Publish { topic, data },
+            };
+            Decoded::message(id, message)
+        }
+        kind => Decoded::error(id, format!("Unexpected message type {:?}", kind)),
+    };
+    
+    Ok(decoded)
+}
+
+fn decode_request(id: String, request: RequestData) -> Decoded {
+    let params = request.params;
+    
+    match request.method.as_str() {
+        "sendMessage" => {
+            let message = &params["message"];
+            match message["content"].as_str() {
+                Some(content) => Decoded::message(id, ClientMessage::Chat {
+                    message: content.to_string(),
+                    model: message["model"].as_str().map(str::to_string),
+                    conversation_id: params["sessionId"].as_str().map(str::to_string),
+                    temperature: None,
+                    max_tokens: None,
+                    edit_message_id: params["editMessageId"].as_str().map(str::to_string),
+                    regenerate_message_id: None,
+                    context_strategy: params["contextStrategy"].as_str().and_then(ContextStrategy::parse),
+                    pinned_message_ids: serde_json::from_value(params["pinnedMessageIds"].clone()).unwrap_or_default(),
+                    template_id: params["templateId"].as_str().map(str::to_string),
+                    template_version: params["templateVersion"].as_i64().and_then(|v| i32::try_from(v).ok()),
+                    variables: serde_json::from_value(params["variables"].clone()).unwrap_or_default(),
+                    system_prompt: params["systemPrompt"].as_str().map(str::to_string),
+                    traceparent: None,
+                    tracestate: None,
+                }),
+                None => Decoded::error(id, "Missing message content"),
+            }
+        }
+        // Answered like `sendMessage`, once the new reply starts
+        "regenerateMessage" => match (params["sessionId"].as_str(), params["messageId"].as_str()) {
+            (Some(session_id), Some(message_id)) => Decoded::message(id, ClientMessage::Chat {
+                message: String::new(),
+                model: params["model"].as_str().map(str::to_string),
+                conversation_id: Some(session_id.to_string()),
+                temperature: None,
+                max_tokens: None,
+                edit_message_id: None,
+                regenerate_message_id: Some(message_id.to_string()),
+                context_strategy: params["contextStrategy"].as_str().and_then(ContextStrategy::parse),
+                pinned_message_ids: serde_json::from_value(params["pinnedMessageIds"].clone()).unwrap_or_default(),
+                template_id: params["templateId"].as_str().map(str::to_string),
+                template_version: params["templateVersion"].as_i64().and_then(|v| i32::try_from(v).ok()),
+                variables: serde_json::from_value(params["variables"].clone()).unwrap_or_default(),
+                system_prompt: params["systemPrompt"].as_str().map(str::to_string),
+                traceparent: None,
+                tracestate: None,
+            }),
+            _ => Decoded::error(id, "Missing sessionId or messageId"),
+        },
+        // The bridge stops by session rather than by request id, and its app
+        // drives one session per socket, so this stops everything on the socket
+        "stopGeneration" => Decoded {
+            message: Some(Incoming {
+                request_id: None,
+                message: ClientMessage::Stop { response_id: None },
+            }),
+            reply: Some(Outgoing {
+                request_id: Some(id),
+                message: ServerMessage::Stopped { request_ids: Vec::new() },
+            }),
+        },
+        // Session management and anything newer goes to the RPC layer
+        method => Decoded::message(id, ClientMessage::Rpc {
+            method: method.to_string(),
+            params,
+        }),
+    }
+}
+
+// Turns server messages into envelopes. Generations are reported the way
+// chat_websocket_service.dart expects: the `sendMessage` request is answered
+// once the response starts, and text flows as messageStart / messageChunk /
+// messageEnd publishes on `session:<conversation id>`
+#[derive(Default)]
+pub struct Encoder {
+    // Conversation of each running generation, by request id and response id
+    by_request: HashMap<String, String>,
+    by_response: HashMap<String, String>,
+}
+
+impl Encoder {
+    pub fn encode(&mut self, outgoing: Outgoing) -> Vec<Envelope> {
+        let Outgoing { request_id, message } = outgoing;
+        
+        match message {
+            ServerMessage::Connected { session_id } => {
+                vec![Envelope::new(None, EnvelopeType::Connect).with_data(json!({ "sessionId": session_id }))]
+            }
+            ServerMessage::Welcome { protocol_version, features, session_id } => {
+                vec![Envelope::new(request_id, EnvelopeType::Connect).with_data(json!({
+                    "version": format!("{}.0.0", protocol_version),
+                    "capabilities": features,
+                    "sessionId": session_id,
+                }))]
+            }
+            ServerMessage::Authenticated { user_id } => {
+                let kind = if request_id.is_some() { EnvelopeType::Response } else { EnvelopeType::Authenticate };
+                vec![Envelope::new(request_id, kind).with_data(json!({ "userId": user_id }))]
+            }
+            ServerMessage::ResponseStarted { response_id, conversation_id, model, context } => {
+                let mut envelopes = Vec::new();
+                if let Some(request_id) = request_id {
+                    self.by_request.insert(request_id.clone(), conversation_id.clone());
+                    envelopes.push(Envelope::new(Some(request_id), EnvelopeType::Response).with_data(json!({
+                        "sessionId": conversation_id,
+                        "messageId": response_id,
+                        "context": context,
+                    })));
+                }
+                self.by_response.insert(response_id.clone(), conversation_id.clone());
+                envelopes.push(Envelope::publish(session_topic(&conversation_id), json!({
+                    "type": "messageStart",
+                    "messageId": response_id,
+                    "model": model,
+                })));
+                envelopes
+            }
+            ServerMessage::Chunk { response_id, content, finish_reason, .. } => {
+                let Some(conversation_id) = self.by_response.get(&response_id).cloned() else {
+                    return Vec::new();
+                };
+                let channel = session_topic(&conversation_id);
+                
+                let mut envelopes = Vec::new();
+                if !content.is_empty() {
+                    envelopes.push(Envelope::publish(channel.clone(), json!({
+                        "type": "messageChunk",
+                        "messageId": response_id,
+                        "chunk": content,
+                    })));
+                }
+                if let Some(finish_reason) = finish_reason {
+                    self.by_response.remove(&response_id);
+                    if let Some(request_id) = &request_id {
+                        self.by_request.remove(request_id);
+                    }
+                    envelopes.push(Envelope::publish(channel, json!({
+                        "type": "messageEnd",
+                        "messageId": response_id,
+                        "finishReason": finish_reason,
+                    })));
+                }
+                envelopes
+            }
+            ServerMessage::Usage { prompt_tokens, completion_tokens, total_tokens, remaining_daily, remaining_monthly } => {
+                let Some(conversation_id) = request_id.as_ref().and_then(|id| self.by_request.get(id)) else {
+                    return Vec::new();
+                };
+                vec![Envelope::publish(session_topic(conversation_id), json!({
+                    "type": "usage",
+                    "promptTokens": prompt_tokens,
+                    "completionTokens": completion_tokens,
+                    "totalTokens": total_tokens,
+                    "remainingDaily": remaining_daily,
+                    "remainingMonthly": remaining_monthly,
+                }))]
+            }
+            ServerMessage::Annotation { guardrail, label, note } => {
+                let channel = match request_id.as_ref().and_then(|id| self.by_request.get(id)) {
+                    Some(conversation_id) => session_topic(conversation_id),
+                    None => "annotations".to_string(),
+                };
+                vec![Envelope::publish(channel, json!({
+                    "type": "annotation",
+                    "guardrail": guardrail,
+                    "label": label,
+                    "note": note,
+                }))]
+            }
+            ServerMessage::Error { message } => {
+                // Before a generation starts the error answers the request;
+                // afterwards the request has had its response already
+                let answered = request_id.as_ref().is_some_and(|id| self.by_request.contains_key(id));
+                let kind = match (&request_id, answered) {
+                    (Some(_), false) => EnvelopeType::Response,
+                    _ => EnvelopeType::Error,
+                };
+                vec![Envelope::new(request_id, kind).with_error(message)]
+            }
+            ServerMessage::Pong => vec![Envelope::new(request_id, EnvelopeType::Pong)],
+            ServerMessage::RpcResult { result } => {
+                vec![Envelope::new(request_id, EnvelopeType::Response).with_data(result)]
+            }
+            ServerMessage::RpcError { code, message } => {
+                vec![Envelope::new(request_id, EnvelopeType::Response)
+                    .with_data(json!({ "code": code }))
+                    .with_error(message)]
+            }
+            // Bridge subscriptions are fire-and-forget
+            ServerMessage::Subscribed { .. } => Vec::new(),
+            ServerMessage::Event { topic, data } => vec![Envelope::publish(topic, data)],
+            // Only acknowledgements of a bridge request are worth answering
+            ServerMessage::Stopped { request_ids } => match request_id {
+                Some(id) => vec![Envelope::new(Some(id), EnvelopeType::Response).with_data(json!({ "stopped": request_ids }))],
+                None => Vec::new(),
+            },
+            ServerMessage::Draining { reconnect_after } => {
+                vec![Envelope::publish("system".to_string(), json!({
+                    "type": "draining",
+                    "reconnectAfter": reconnect_after,
+                }))]
+            }
+            ServerMessage::Disconnected { reason } => {
+                vec![Envelope::new(None, EnvelopeType::Disconnect).with_data(json!({ "reason": reason }))]
+            }
+        }
+    }
+}
@@ -0,0 +1,2140 @@
+use crate::auth;
+use crate::lifecycle::Phase;
+use crate::guardrails::{Annotation, BlockedBy, CONTENT_FILTER_FINISH_REASON};
+use crate::llm::context::{self, BuiltContext, ContextBuilder, ContextReport, ContextStrategy, HistoryMessage, Summary};
+use crate::llm::LLMProvider;
+use crate::redaction::{self, RedactionFinding, RedactionVault, StreamRestorer};
+use crate::services::audit::{sha256_hex, AuditEvent};
+use crate::services::branches::{on_path, BranchNode};
+use crate::services::members::MemberRole;
+use crate::services::pubsub::{self, TopicEvent, TopicReceiver};
+use crate::services::stream_buffer::ResponseStatus;
+use crate::services::templates::{self, RenderedPrompt, MAX_TEMPLATE_CHARS};
+use crate::state::{AppState, SessionControl, SessionState};
+use crate::telemetry;
+use axum::extract::ws::{Message, WebSocket};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use futures::{sink::SinkExt, stream::StreamExt};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::task::JoinHandle;
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use uuid::Uuid;
+
+mod bridge;
+mod outbox;
+mod protocol;
+mod rpc;
+
+pub use bridge::SUBPROTOCOL as BRIDGE_SUBPROTOCOL;
+use outbox::{Outbox, OutboxLimits, OverflowPolicy, Received};
+use protocol::{Dialect, Feature, Negotiated};
+use rpc::RpcErrorCode;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    // Optional first message: protocol versions the client speaks and the
+    // features it would like; clients that skip it get protocol version 1
+    #[serde(rename = "hello")]
+    Hello {
+        #[serde(default)]
+        versions: Vec<u32>,
+        #[serde(default)]
+        features: Vec<String>,
+    },
+    
+    #[serde(rename = "auth")]
+    Auth { token: String },
+    
+    #[serde(rename = "chat")]
+    Chat {
+        // Not needed when regenerating, which reuses the stored prompt
+        #[serde(default)]
+        message: String,
+        model: Option<String>,
+        conversation_id: Option<String>,
+        temperature: Option<f32>,
+        max_tokens: Option<u32>,
+        // Sends `message` as an edit of this earlier user message, on a new branch
+        #[serde(default)]
+        edit_message_id: Option<String>,
+        // Answers again the prompt of this assistant message, on a new branch
+        #[serde(default)]
+        regenerate_message_id: Option<String>,
+        // How earlier messages are fitted into the model's context window;
+        // the server's default strategy when absent
+        #[serde(default)]
+        context_strategy: Option<ContextStrategy>,
+        // Earlier messages to keep in context whatever the strategy drops
+        #[serde(default)]
+        pinned_message_ids: Vec<String>,
+        // System prompt from a prompt template, filled with `variables`;
+        // `template_version` pins a version, else the current one is used
+        #[serde(default)]
+        template_id: Option<String>,
+        #[serde(default)]
+        template_version: Option<i32>,
+        #[serde(default)]
+        variables: HashMap<String, String>,
+        // A one-off system prompt instead of a template
+        #[serde(default)]
+        system_prompt: Option<String>,
+        // W3C trace context, so a client trace continues through this message
+        #[serde(default)]
+        traceparent: Option<String>,
+        #[serde(default)]
+        tracestate: Option<String>,
+    },
+    
+    // Replays chunks after `last_seq` and follows the generation if it is still running
+    #[serde(rename = "resume")]
+    Resume {
+        response_id: String,
+        #[serde(default)]
+        last_seq: u64,
+    },
+    
+    // Cancels the request named by the envelope `request_id`, or every
+    // request on the socket when none is given. With `response_id` it stops
+    // that generation instead, whichever of the user's sockets is running it
+    #[serde(rename = "stop")]
+    Stop {
+        #[serde(default)]
+        response_id: Option<String>,
+    },
+    
+    // Request/response call, answered by `rpc_result` or `rpc_error`
+    #[serde(rename = "rpc")]
+    Rpc {
+        method: String,
+        #[serde(default)]
+        params: serde_json::Value,
+    },
+    
+    // Topics: `session:<conversation id>`, and the user-wide `sessions`,
+    // `messages`, `typing`, `presence`, `imports` and `invitations`
+    #[serde(rename = "subscribe")]
+    Subscribe { topic: String },
+    
+    #[serde(rename = "unsubscribe")]
+    Unsubscribe { topic: String },
+    
+    #[serde(rename = "publish")]
+    Publish {
+        topic: String,
+        #[serde(default)]
+        data: serde_json::Value,
+    },
+    
+    #[serde(rename = "ping")]
+    Ping,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    #[serde(rename = "connected")]
+    Connected { session_id: String },
+    
+    // Answer to `hello`: the chosen version and the features granted
+    #[serde(rename = "welcome")]
+    Welcome {
+        protocol_version: u32,
+        features: Vec<Feature>,
+        session_id: String,
+    },
+    
+    #[serde(rename = "authenticated")]
+    Authenticated { user_id: String },
+    
+    #[serde(rename = "response_started")]
+    ResponseStarted {
+        response_id: String,
+        conversation_id: String,
+        model: String,
+        // Which earlier messages the prompt was built from; not known on resume
+        #[serde(skip_serializing_if = "Option::is_none")]
+        context: Option<ContextReport>,
+    },
+    
+    #[serde(rename = "chunk")]
+    Chunk {
+        response_id: String,
+        seq: u64,
+        content: String,
+        model: String,
+        finish_reason: Option<String>,
+    },
+    
+    #[serde(rename = "error")]
+    Error { message: String },
+    
+    #[serde(rename = "usage")]
+    Usage {
+        prompt_tokens: u32,
+        completion_tokens: u32,
+        total_tokens: u32,
+        remaining_daily: u64,
+        remaining_monthly: u64,
+    },
+    
+    #[serde(rename = "pong")]
+    Pong,
+    
+    // Acknowledges `stop` with the requests that were signalled
+    #[serde(rename = "stopped")]
+    Stopped { request_ids: Vec<String> },
+    
+    #[serde(rename = "rpc_result")]
+    RpcResult { result: serde_json::Value },
+    
+    #[serde(rename = "rpc_error")]
+    RpcError { code: RpcErrorCode, message: String },
+    
+    #[serde(rename = "subscribed")]
+    Subscribed { topic: String },
+    
+    // Something published on a subscribed topic by another socket
+    #[serde(rename = "event")]
+    Event {
+        topic: String,
+        data: serde_json::Value,
+    },
+    
+    #[serde(rename = "disconnected")]
+    Disconnected { reason: String },
+    
+    // Server is shutting down; in-flight streams finish, new ones go elsewhere
+    #[serde(rename = "draining")]
+    Draining { reconnect_after: u64 },
+    
+    #[serde(rename = "annotation")]
+    Annotation {
+        guardrail: String,
+        label: String,
+        note: String,
+    },
+}
+
+// Wire envelope: any client message may carry a `request_id`, and every
+// reply it causes (response_started, chunk, usage, error, ...) echoes it so
+// parallel requests on one socket can be told apart
+#[derive(Debug, Deserialize)]
+struct Incoming {
+    #[serde(default)]
+    request_id: Option<String>,
+    #[serde(flatten)]
+    message: ClientMessage,
+}
+
+#[derive(Debug, Serialize)]
+struct Outgoing {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+    #[serde(flatten)]
+    message: ServerMessage,
+}
+
+const RESUME_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const RESUME_BATCH_SIZE: usize = 500;
+
+// Sends replies for one client message, tagged with its request id
+#[derive(Clone)]
+struct Responder {
+    tx: Outbox,
+    request_id: Option<String>,
+}
+
+impl Responder {
+    fn new(tx: &Outbox, request_id: Option<String>) -> Self {
+        Self {
+            tx: tx.clone(),
+            request_id,
+        }
+    }
+    
+    // False once the socket's send task has gone away or the client fell behind
+    async fn send(&self, message: ServerMessage) -> bool {
+        self.tx.send(Outgoing {
+            request_id: self.request_id.clone(),
+            message,
+        })
+    }
+    
+    async fn error(&self, message: impl Into<String>) {
+        self.send(ServerMessage::Error { message: message.into() }).await;
+    }
+}
+
+// In-flight requests on one socket, keyed by request id, holding their stop signal
+type RequestRegistry = Arc<DashMap<String, watch::Sender<bool>>>;
+
+// Registration of a running request; dropping it frees the concurrency slot
+struct RequestHandle {
+    key: String,
+    cancel: watch::Receiver<bool>,
+    registry: RequestRegistry,
+}
+
+impl RequestHandle {
+    fn register(registry: &RequestRegistry, request_id: Option<&str>, limit: usize) -> Result<Self, String> {
+        if registry.len() >= limit {
+            return Err(format!("Too many concurrent requests (limit {})", limit));
+        }
+        
+        // Requests without an id still count against the limit and stop-all
+        let key = match request_id {
+            Some(id) => id.to_string(),
+            None => Uuid::new_v4().to_string(),
+        };
+        
+        match registry.entry(key.clone()) {
+            Entry::Occupied(_) => Err(format!("Request {} is already in progress", key)),
+            Entry::Vacant(slot) => {
+                let (cancel_tx, cancel) = watch::channel(false);
+                slot.insert(cancel_tx);
+                Ok(Self {
+                    key,
+                    cancel,
+                    registry: registry.clone(),
+                })
+            }
+        }
+    }
+    
+    fn is_cancelled(&self) -> bool {
+        *self.cancel.borrow()
+    }
+    
+    // Resolves once the client sends a stop for this request
+    async fn cancelled(&mut self) {
+        if self.cancel.wait_for(|stopped| *stopped).await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+impl Drop for RequestHandle {
+    fn drop(&mut self) {
+        self.registry.remove(&self.key);
+    }
+}
+
+// Cancels the named request, or every request when none is named; None if
+// the named one is not running
+fn cancel_requests(requests: &RequestRegistry, request_id: Option<&str>) -> Option<Vec<String>> {
+    match request_id {
+        Some(id) => {
+            requests.get(id)?.send_replace(true);
+            Some(vec![id.to_string()])
+        }
+        None => Some(
+            requests
+                .iter()
+                .map(|entry| {
+                    entry.value().send_replace(true);
+                    entry.key().clone()
+                })
+                .collect(),
+        ),
+    }
+}
+
+// Topic forwarders of one socket, stopped when the socket goes away
+#[derive(Default)]
+struct Subscriptions(HashMap<String, JoinHandle<()>>);
+
+impl Subscriptions {
+    fn add(&mut self, topic: String, forwarder: JoinHandle<()>) {
+        if let Some(previous) = self.0.insert(topic, forwarder) {
+            previous.abort();
+        }
+    }
+    
+    fn remove(&mut self, topic: &str) -> bool {
+        match self.0.remove(topic) {
+            Some(forwarder) => {
+                forwarder.abort();
+                true
+            }
+            None => false,
+        }
+    }
+    
+    fn is_subscribed(&self, topic: &str) -> bool {
+        self.0.contains_key(topic)
+    }
+    
+    fn conversations(&self) -> Vec<String> {
+        self.0
+            .keys()
+            .filter_map(|topic| topic.strip_prefix("session:"))
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        for forwarder in self.0.values() {
+            forwarder.abort();
+        }
+    }
+}
+
+// Chat parameters from one `chat` message
+struct ChatRequest {
+    message: String,
+    model: Option<String>,
+    conversation_id: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+    branching: Branching,
+    context_strategy: Option<ContextStrategy>,
+    pinned_message_ids: Vec<String>,
+    system: PromptChoice,
+}
+
+// The system prompt a chat message asked for, before tier checks and
+// rendering; the server's default prompt when both are absent
+#[derive(Debug)]
+struct PromptChoice {
+    template_id: Option<String>,
+    template_version: Option<i32>,
+    variables: HashMap<String, String>,
+    system_prompt: Option<String>,
+}
+
+// Where a chat message goes in the conversation's message tree
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Branching {
+    // After the active leaf
+    Continue,
+    // As a sibling of this earlier user message
+    Edit(String),
+    // As a new reply to the prompt this assistant message answered
+    Regenerate(String),
+}
+
+// The branch a chat message builds on
+struct Turn {
+    history: Vec<HistoryMessage>,
+    summary: Option<Summary>,
+    // Set when the message forks off the active branch: the leaf to move to
+    // before saving, None for a new root
+    fork_from: Option<Option<String>>,
+    // The stored prompt a regenerated reply answers
+    prompt: Option<String>,
+}
+
+// Identity of an authenticated socket, carried into every chat request it makes
+#[derive(Debug, Clone)]
+struct ChatContext {
+    session_id: String,
+    user_id: String,
+    api_key_id: Option<Uuid>,
+    client_ip: Option<String>,
+}
+
+pub async fn handle_socket(
+    socket: WebSocket,
+    state: Arc<AppState>,
+    client_ip: Option<String>,
+    token: Option<String>,
+    trace_parent: opentelemetry::Context,
+) {
+    let session_id = Uuid::new_v4().to_string();
+    let dialect = match socket.protocol() {
+        Some(protocol) if protocol == BRIDGE_SUBPROTOCOL => Dialect::Bridge,
+        _ => Dialect::Tagged,
+    };
+    let (mut sender, mut receiver) = socket.split();
+    let (tx, mut rx) = outbox::outbox(
+        OutboxLimits {
+            messages: state.config.ws_outbound_buffer,
+            bytes: state.config.ws_outbound_max_bytes,
+            policy: OverflowPolicy::parse(&state.config.ws_outbound_policy).unwrap_or(OverflowPolicy::Coalesce),
+        },
+        state.metrics.clone(),
+    );
+    let (control_tx, mut control_rx) = mpsc::channel::<SessionControl>(8);
+    let requests: RequestRegistry = Arc::new(DashMap::new());
+    
+    // Session-level messages carry no request id
+    let session = Responder::new(&tx, None);
+    
+    // Send connection confirmation
+    session.send(ServerMessage::Connected {
+        session_id: session_id.clone(),
+    }).await;
+    
+    // Task to send messages to the client; it also pings, so that a quiet
+    // but live client keeps answering and a dead one goes idle
+    let tx_clone = tx.clone();
+    let send_metrics = state.metrics.clone();
+    let send_session_id = session_id.clone();
+    let ping_interval = Duration::from_secs(state.config.ws_ping_interval_secs);
+    let send_task = tokio::spawn(async move {
+        let mut encoder = bridge::Encoder::default();
+        let mut ping = tokio::time::interval_at(tokio::time::Instant::now() + ping_interval, ping_interval);
+        loop {
+            let msg = tokio::select! {
+                received = rx.recv() => match received {
+                    Some(Received::Message(msg)) => msg,
+                    Some(Received::Overflowed) => {
+                        warn!("Session {} fell too far behind; disconnecting", send_session_id);
+                        send_metrics.ws_disconnects.with_label_values(&["backpressure"]).inc();
+                        Outgoing {
+                            request_id: None,
+                            message: ServerMessage::Disconnected {
+                                reason: "Client is not keeping up".to_string(),
+                            },
+                        }
+                    }
+                    None => return,
+                },
+                _ = ping.tick() => {
+                    if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                        return;
+                    }
+                    continue;
+                }
+            };
+            let closing = matches!(msg.message, ServerMessage::Disconnected { .. });
+            
+            let frames = match dialect {
+                Dialect::Tagged => vec![serde_json::to_string(&msg)],
+                Dialect::Bridge => encoder.encode(msg).iter().map(serde_json::to_string).collect(),
+            };
+            for json in frames.into_iter().flatten() {
+                if sender.send(Message::Text(json)).await.is_err() {
+                    return;
+                }
+            }
+            if closing {
+                let _ = sender.send(Message::Close(None)).await;
+                return;
+            }
+        }
+    });
+    
+    // Task to receive messages from the client
+    let recv_state = state.clone();
+    let recv_session_id = session_id.clone();
+    let recv_task = tokio::spawn(async move {
+        let state = recv_state;
+        let session_id = recv_session_id;
+        let max_requests = state.config.ws_max_concurrent_requests;
+        let mut context: Option<ChatContext> = None;
+        let mut negotiated = Negotiated::legacy();
+        let mut said_hello = false;
+        let mut subscriptions = Subscriptions::default();
+        let mut phase_rx = state.lifecycle.subscribe();
+        // Any frame counts as activity, pongs included
+        let idle_timeout = Duration::from_secs(state.config.ws_idle_timeout_secs);
+        let mut last_seen = Instant::now();
+        let mut idle_check = tokio::time::interval(Duration::from_secs(state.config.ws_ping_interval_secs));
+        
+        // A bearer token on the upgrade request authenticates up front, for
+        // clients that cannot send an `auth` message before their first request
+        if let Some(token) = token {
+            let auth_span = info_span!("ws.auth", session_id = %session_id);
+            auth_span.set_parent(trace_parent.clone());
+            match authenticate(&state, &session_id, &token, client_ip.clone(), &control_tx).instrument(auth_span).await {
+                Ok(ctx) => {
+                    session.send(ServerMessage::Authenticated {
+                        user_id: ctx.user_id.clone(),
+                    }).await;
+                    context = Some(ctx);
+                }
+                Err(e) => {
+                    session.error(format!("Authentication failed: {}", e)).await;
+                }
+            }
+        }
+        
+        loop {
+            let msg = tokio::select! {
+                msg = receiver.next() => match msg {
+                    Some(Ok(msg)) => msg,
+                    _ => break,
+                },
+                _ = idle_check.tick() => {
+                    if last_seen.elapsed() < idle_timeout {
+                        continue;
+                    }
+                    info!("Session {} idle for {:?}; disconnecting", session_id, last_seen.elapsed());
+                    state.metrics.ws_disconnects.with_label_values(&["idle"]).inc();
+                    session.send(ServerMessage::Disconnected {
+                        reason: "Idle timeout".to_string(),
+                    }).await;
+                    break;
+                }
+                Some(control) = control_rx.recv() => {
+                    match control {
+                        SessionControl::Disconnect { reason } => {
+                            info!("Disconnecting session {}: {}", session_id, reason);
+                            session.send(ServerMessage::Disconnected { reason }).await;
+                            break;
+                        }
+                        SessionControl::Stop { request_id } => {
+                            let stopped = cancel_requests(&requests, request_id.as_deref());
+                            info!("Stop for {:?} on session {} from elsewhere", stopped, session_id);
+                            continue;
+                        }
+                        SessionControl::Push { topic, data } => {
+                            session.send(ServerMessage::Event { topic, data }).await;
+                            continue;
+                        }
+                        SessionControl::Revoke { topic } => {
+                            if !subscriptions.remove(&topic) {
+                                continue;
+                            }
+                            if let Some(conversation_id) = topic.strip_prefix("session:") {
+                                if let Some(mut entry) = state.active_sessions.get_mut(&session_id) {
+                                    entry.subscriptions.remove(conversation_id);
+                                }
+                            }
+                            session.send(ServerMessage::Event {
+                                topic,
+                                data: serde_json::json!({ "type": "accessRevoked" }),
+                            }).await;
+                            continue;
+                        }
+                    }
+                }
+                Ok(()) = phase_rx.changed() => {
+                    let phase = *phase_rx.borrow_and_update();
+                    match phase {
+                        Phase::Draining => {
+                            session.send(ServerMessage::Draining {
+                                reconnect_after: state.config.shutdown_readiness_delay_secs,
+                            }).await;
+                            continue;
+                        }
+                        Phase::Closing => {
+                            session.send(ServerMessage::Disconnected {
+                                reason: "Server shutting down".to_string(),
+                            }).await;
+                            break;
+                        }
+                        Phase::Serving => continue,
+                    }
+                }
+            };
+            
+            last_seen = Instant::now();
+            if let Some(mut entry) = state.active_sessions.get_mut(&session_id) {
+                entry.last_activity = chrono::Utc::now();
+            }
+            
+            let text = match msg {
+                Message::Text(text) => text,
+                Message::Binary(bytes) if negotiated.allows(Feature::Binary) => match String::from_utf8(bytes) {
+                    Ok(text) => text,
+                    Err(_) => {
+                        session.error("Binary frames must carry UTF-8 JSON").await;
+                        continue;
+                    }
+                },
+                Message::Binary(_) => {
+                    session.error("Binary frames were not negotiated").await;
+                    continue;
+                }
+                Message::Close(_) => {
+                    info!("Client disconnected: {}", session_id);
+                    break;
+                }
+                _ => continue,
+            };
+            
+            let decoded = match dialect {
+                Dialect::Tagged => serde_json::from_str::<Incoming>(&text).map(bridge::Decoded::from),
+                Dialect::Bridge => bridge::decode(&text),
+            };
+            let decoded = match decoded {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    warn!("Failed to parse client message: {}", e);
+                    session.error("Invalid message format").await;
+                    continue;
+                }
+            };
+            if let Some(reply) = decoded.reply {
+                tx_clone.send(reply);
+            }
+            let Some(Incoming { request_id, message: client_msg }) = decoded.message else {
+                continue;
+            };
+            
+            let reply = Responder::new(&tx_clone, request_id.clone());
+            match client_msg {
+                ClientMessage::Hello { versions, features } => {
+                    if said_hello {
+                        reply.error("Protocol already negotiated").await;
+                        continue;
+                    }
+                    
+                    match protocol::negotiate(dialect, &versions, &features) {
+                        Ok(result) => {
+                            said_hello = true;
+                            reply.send(ServerMessage::Welcome {
+                                protocol_version: result.version,
+                                features: result.features.iter().copied().collect(),
+                                session_id: session_id.clone(),
+                            }).await;
+                            negotiated = result;
+                        }
+                        Err(reason) => {
+                            reply.error(reason).await;
+                            session.send(ServerMessage::Disconnected {
+                                reason: "Unsupported protocol version".to_string(),
+                            }).await;
+                            break;
+                        }
+                    }
+                }
+                
+                ClientMessage::Auth { token } => {
+                    // Validate JWT token
+                    let auth_span = info_span!("ws.auth", session_id = %session_id);
+                    auth_span.set_parent(trace_parent.clone());
+                    match authenticate(&state, &session_id, &token, client_ip.clone(), &control_tx).instrument(auth_span).await {
+                        Ok(ctx) => {
+                            reply.send(ServerMessage::Authenticated {
+                                user_id: ctx.user_id.clone(),
+                            }).await;
+                            context = Some(ctx);
+                        }
+                        Err(e) => {
+                            reply.error(format!("Authentication failed: {}", e)).await;
+                        }
+                    }
+                }
+                
+                ClientMessage::Chat {
+                    message,
+                    model,
+                    conversation_id,
+                    temperature,
+                    max_tokens,
+                    edit_message_id,
+                    regenerate_message_id,
+                    context_strategy,
+                    pinned_message_ids,
+                    template_id,
+                    template_version,
+                    variables,
+                    system_prompt,
+                    traceparent,
+                    tracestate,
+                } => {
+                    let Some(ctx) = &context else {
+                        reply.error("Not authenticated").await;
+                        continue;
+                    };
+                    
+                    if state.lifecycle.is_draining() {
+                        reply.error("Server is shutting down; reconnect to continue").await;
+                        continue;
+                    }
+                    
+                    let request = match RequestHandle::register(&requests, request_id.as_deref(), max_requests) {
+                        Ok(request) => request,
+                        Err(reason) => {
+                            reply.error(reason).await;
+                            continue;
+                        }
+                    };
+                    
+                    // One span tree per message, parented on the client's trace if it sent one
+                    let chat_span = info_span!(
+                        "ws.chat",
+                        otel.kind = "server",
+                        session_id = %ctx.session_id,
+                        user_id = %ctx.user_id,
+                    );
+                    chat_span.set_parent(match traceparent {
+                        Some(traceparent) => telemetry::context_from_traceparent(&traceparent, tracestate.as_deref()),
+                        None => trace_parent.clone(),
+                    });
+                    
+                    // Runs off the receive loop, which keeps reading stops and
+                    // further requests while this one prepares and streams
+                    let chat = ChatRequest {
+                        message,
+                        model,
+                        conversation_id,
+                        temperature,
+                        max_tokens,
+                        branching: match (edit_message_id, regenerate_message_id) {
+                            (_, Some(message_id)) => Branching::Regenerate(message_id),
+                            (Some(message_id), None) => Branching::Edit(message_id),
+                            (None, None) => Branching::Continue,
+                        },
+                        context_strategy,
+                        pinned_message_ids,
+                        system: PromptChoice {
+                            template_id,
+                            template_version,
+                            variables,
+                            system_prompt,
+                        },
+                    };
+                    let state = state.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        handle_chat_message(&state, &reply, &ctx, request, chat).instrument(chat_span).await;
+                    });
+                }
+                
+                ClientMessage::Resume { response_id, last_seq } => {
+                    let Some(ctx) = &context else {
+                        reply.error("Not authenticated").await;
+                        continue;
+                    };
+                    
+                    if !negotiated.allows(Feature::Resume) {
+                        reply.error("Resume was not negotiated").await;
+                        continue;
+                    }
+                    
+                    // A replay holds a slot too; stopping it leaves the generation running
+                    let request = match RequestHandle::register(&requests, request_id.as_deref(), max_requests) {
+                        Ok(request) => request,
+                        Err(reason) => {
+                            reply.error(reason).await;
+                            continue;
+                        }
+                    };
+                    
+                    tokio::spawn(resume_response(
+                        state.clone(),
+                        reply,
+                        request,
+                        ctx.user_id.clone(),
+                        response_id,
+                        last_seq,
+                    ));
+                }
+                
+                ClientMessage::Stop { response_id: Some(response_id) } => {
+                    let Some(ctx) = &context else {
+                        reply.error("Not authenticated").await;
+                        continue;
+                    };
+                    
+                    let meta = match state.stream_buffer.meta(&response_id).await {
+                        Ok(Some(meta)) if meta.user_id == ctx.user_id => meta,
+                        Ok(_) => {
+                            reply.error(format!("Unknown response {}", response_id)).await;
+                            continue;
+                        }
+                        Err(e) => {
+                            error!("Failed to look up response {}: {}", response_id, e);
+                            reply.error("Failed to stop response").await;
+                            continue;
+                        }
+                    };
+                    if meta.status == ResponseStatus::Done {
+                        reply.error(format!("Response {} has already finished", response_id)).await;
+                        continue;
+                    }
+                    
+                    // The generation may run on another device, possibly on another replica
+                    if meta.session_id == session_id {
+                        let stopped = cancel_requests(&requests, Some(&meta.request_key)).unwrap_or_default();
+                        reply.send(ServerMessage::Stopped { request_ids: stopped }).await;
+                        continue;
+                    }
+                    let control = SessionControl::Stop { request_id: Some(meta.request_key) };
+                    match state.session_registry.send(&meta.session_id, control).await {
+                        Ok(true) => {
+                            info!("Stop for response {} sent to session {}", response_id, meta.session_id);
+                            reply.send(ServerMessage::Stopped { request_ids: Vec::new() }).await;
+                        }
+                        Ok(false) => reply.error(format!("Response {} is no longer running", response_id)).await,
+                        Err(e) => {
+                            error!("Failed to route stop for response {}: {}", response_id, e);
+                            reply.error("Failed to stop response").await;
+                        }
+                    }
+                }
+                
+                ClientMessage::Stop { response_id: None } => {
+                    match cancel_requests(&requests, request_id.as_deref()) {
+                        Some(stopped) => {
+                            info!("Stop requested for {:?} on session {}", stopped, session_id);
+                            reply.send(ServerMessage::Stopped { request_ids: stopped }).await;
+                        }
+                        None => {
+                            let id = request_id.unwrap_or_default();
+                            reply.error(format!("No request {} in progress", id)).await;
+                        }
+                    }
+                }
+                
+                ClientMessage::Rpc { method, params } => {
+                    let Some(ctx) = &context else {
+                        reply.error("Not authenticated").await;
+                        continue;
+                    };
+                    
+                    let rpc_span = info_span!("ws.rpc", method = %method, session_id = %session_id);
+                    rpc_span.set_parent(trace_parent.clone());
+                    let state = state.clone();
+                    let ctx = ctx.clone();
+                    tokio::spawn(async move {
+                        let message = match rpc::dispatch(&state, &ctx, &method, params).await {
+                            Ok(result) => ServerMessage::RpcResult { result },
+                            Err(e) => ServerMessage::RpcError {
+                                code: e.code,
+                                message: e.message,
+                            },
+                        };
+                        reply.send(message).await;
+                    }.instrument(rpc_span));
+                }
+                
+                ClientMessage::Subscribe { topic } => {
+                    let Some(ctx) = &context else {
+                        reply.error("Not authenticated").await;
+                        continue;
+                    };
+                    
+                    match resolve_topic(&state, ctx, &topic).await {
+                        Ok(key) => {
+                            let events = state.pubsub.subscribe(&key);
+                            let forwarder = tokio::spawn(forward_events(events, topic.clone(), session_id.clone(), tx_clone.clone()));
+                            let resubscribed = subscriptions.is_subscribed(&topic);
+                            subscriptions.add(topic.clone(), forwarder);
+                            reply.send(ServerMessage::Subscribed { topic: topic.clone() }).await;
+                            
+                            if let Some(conversation_id) = topic.strip_prefix("session:") {
+                                if let Some(mut entry) = state.active_sessions.get_mut(&session_id) {
+                                    entry.subscriptions.insert(conversation_id.to_string());
+                                }
+                                if !resubscribed {
+                                    publish_presence(&state, ctx, Some(conversation_id), "joined").await;
+                                }
+                            }
+                        }
+                        Err(reason) => reply.error(reason).await,
+                    }
+                }
+                
+                ClientMessage::Unsubscribe { topic } => {
+                    let removed = subscriptions.remove(&topic);
+                    
+                    if let (Some(ctx), Some(conversation_id)) = (&context, topic.strip_prefix("session:")) {
+                        if let Some(mut entry) = state.active_sessions.get_mut(&session_id) {
+                            entry.subscriptions.remove(conversation_id);
+                        }
+                        if removed {
+                            publish_presence(&state, ctx, Some(conversation_id), "left").await;
+                        }
+                    }
+                }
+                
+                ClientMessage::Publish { topic, mut data } => {
+                    let Some(ctx) = &context else {
+                        reply.error("Not authenticated").await;
+                        continue;
+                    };
+                    
+                    // Clients may only announce typing; everything else is server-sent
+                    if topic != "typing" {
+                        reply.error(format!("Topic {} is read-only", topic)).await;
+                        continue;
+                    }
+                    let Some(fields) = data.as_object_mut() else {
+                        reply.error("Published data must be an object").await;
+                        continue;
+                    };
+                    fields.insert("userId".to_string(), serde_json::json!(ctx.user_id));
+                    
+                    // Also shown to everyone following the conversation, as long
+                    // as this socket follows it too
+                    let conversation_id = fields.get("sessionId").and_then(|v| v.as_str()).map(str::to_string);
+                    if let Some(conversation_id) = conversation_id {
+                        if subscriptions.is_subscribed(&pubsub::session_topic(&conversation_id)) {
+                            let mut session_data = data.clone();
+                            session_data["type"] = serde_json::json!("typing");
+                            state.pubsub.publish(TopicEvent {
+                                topic: pubsub::session_topic(&conversation_id),
+                                origin: Some(session_id.clone()),
+                                data: session_data,
+                            }).await;
+                        }
+                    }
+                    
+                    state.pubsub.publish(TopicEvent {
+                        topic: pubsub::user_topic(&ctx.user_id, &topic),
+                        origin: Some(session_id.clone()),
+                        data,
+                    }).await;
+                }
+                
+                ClientMessage::Ping => {
+                    reply.send(ServerMessage::Pong).await;
+                }
+            }
+        }
+        
+        // Let the user's other devices know this one is gone
+        if let Some(ctx) = &context {
+            for conversation_id in subscriptions.conversations() {
+                publish_presence(&state, ctx, Some(&conversation_id), "left").await;
+            }
+            publish_presence(&state, ctx, None, "offline").await;
+        }
+    });
+    
+    // Wait for tasks to complete
+    tokio::select! {
+        _ = send_task => {},
+        _ = recv_task => {},
+    }
+    
+    // Clean up session
+    if let Some((_, entry)) = state.active_sessions.remove(&session_id) {
+        state.metrics.active_sessions.dec();
+        if let Err(e) = state.session_registry.unregister(&session_id, &entry.user_id).await {
+            warn!("Failed to unregister session {}: {}", session_id, e);
+        }
+    }
+    info!("WebSocket session {} closed", session_id);
+}
+
+// Validates the token and registers the socket as the user's session
+async fn authenticate(
+    state: &AppState,
+    session_id: &str,
+    token: &str,
+    client_ip: Option<String>,
+    control: &mpsc::Sender<SessionControl>,
+) -> Result<ChatContext, String> {
+    let claims = validate_token(state, token).await?;
+    
+    let previous = state.active_sessions.insert(session_id.to_string(), SessionState {
+        user_id: claims.sub.clone(),
+        conversation_id: None,
+        subscriptions: Default::default(),
+        last_activity: chrono::Utc::now(),
+        provider: LLMProvider::OpenAI,
+        control: control.clone(),
+    });
+    if previous.is_none() {
+        state.metrics.active_sessions.inc();
+    }
+    // Without the registry entry the socket is controlled from this replica
+    // only, until a heartbeat manages to write it
+    if let Err(e) = state.session_registry.register(session_id, &claims.sub, control.clone()).await {
+        warn!("Failed to register session {}, retrying on the next heartbeat: {}", session_id, e);
+    }
+    
+    let ctx = ChatContext {
+        session_id: session_id.to_string(),
+        user_id: claims.sub,
+        api_key_id: claims.api_key_id,
+        client_ip,
+    };
+    publish_presence(state, &ctx, None, "online").await;
+    
+    Ok(ctx)
+}
+
+// Maps a client topic to the pub/sub topic behind it, checking the user may see it
+async fn resolve_topic(state: &AppState, ctx: &ChatContext, topic: &str) -> Result<String, String> {
+    if let Some(conversation_id) = topic.strip_prefix("session:") {
+        rpc::member_conversation(state, ctx, conversation_id, MemberRole::Viewer).await.map_err(|e| e.message)?;
+        return Ok(pubsub::session_topic(conversation_id));
+    }
+    
+    match topic {
+        "sessions" | "messages" | "typing" | "presence" | "imports" | "exports" | "invitations" => Ok(pubsub::user_topic(&ctx.user_id, topic)),
+        _ => Err(format!("Unknown topic: {}", topic)),
+    }
+}
+
+async fn forward_events(
+    mut events: TopicReceiver,
+    topic: String,
+    session_id: String,
+    tx: Outbox,
+) {
+    loop {
+        let event = match events.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Session {} missed {} events on {}", session_id, missed, topic);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        
+        if event.origin.as_deref() == Some(session_id.as_str()) {
+            continue;
+        }
+        let sent = tx.send(Outgoing {
+            request_id: None,
+            message: ServerMessage::Event {
+                topic: topic.clone(),
+                data: event.data,
+            },
+        });
+        if !sent {
+            return;
+        }
+    }
+}
+
+// Tells the other devices of every member about a complete message; the
+// author is whoever sent the prompt that led to it
+async fn publish_message(state: &AppState, ctx: &ChatContext, conversation_id: &str, message: serde_json::Value) {
+    let origin = Some(ctx.session_id.clone());
+    
+    state.pubsub.publish(TopicEvent {
+        topic: pubsub::session_topic(conversation_id),
+        origin: origin.clone(),
+        data: serde_json::json!({
+            "type": "message",
+            "sessionId": conversation_id,
+            "authorId": ctx.user_id,
+            "message": message,
+        }),
+    }).await;
+    
+    let members = state.members.member_ids(conversation_id).await.unwrap_or_else(|e| {
+        warn!("Failed to load members of conversation {}: {}", conversation_id, e);
+        vec![ctx.user_id.clone()]
+    });
+    for member in members {
+        state.pubsub.publish(TopicEvent {
+            topic: pubsub::user_topic(&member, "messages"),
+            origin: origin.clone(),
+            data: serde_json::json!({
+                "sessionId": conversation_id,
+                "authorId": ctx.user_id,
+                "message": message,
+            }),
+        }).await;
+    }
+}
+
+// Device presence: user-wide online/offline, and joined/left per conversation
+async fn publish_presence(state: &AppState, ctx: &ChatContext, conversation_id: Option<&str>, status: &str) {
+    let (topic, data) = match conversation_id {
+        Some(conversation_id) => (
+            pubsub::session_topic(conversation_id),
+            serde_json::json!({
+                "type": "presence",
+                "sessionId": conversation_id,
+                "userId": ctx.user_id,
+                "deviceId": ctx.session_id,
+                "status": status,
+            }),
+        ),
+        None => (
+            pubsub::user_topic(&ctx.user_id, "presence"),
+            serde_json::json!({
+                "type": "presence",
+                "userId": ctx.user_id,
+                "deviceId": ctx.session_id,
+                "status": status,
+            }),
+        ),
+    };
+    
+    state.pubsub.publish(TopicEvent {
+        topic,
+        origin: Some(ctx.session_id.clone()),
+        data,
+    }).await;
+}
+
+async fn validate_token(state: &AppState, token: &str) -> Result<auth::Claims, String> {
+    let claims = auth::decode_token(&state.config.jwt_secret, token)
+        .map_err(|e| e.to_string())?;
+    let user_id = Uuid::parse_str(&claims.sub)
+        .map_err(|_| "Invalid token subject".to_string())?;
+    
+    match state.user_service.get_user(&user_id).await {
+        Ok(Some(_)) => Ok(claims),
+        Ok(None) => Err("User not found or inactive".to_string()),
+        Err(e) => {
+            error!("Failed to load user {}: {}", user_id, e);
+            Err("Internal error".to_string())
+        }
+    }
+}
+
+async fn handle_chat_message(
+    state: &Arc<AppState>,
+    reply: &Responder,
+    ctx: &ChatContext,
+    mut request: RequestHandle,
+    chat: ChatRequest,
+) {
+    let ChatRequest {
+        message,
+        model,
+        conversation_id,
+        temperature,
+        max_tokens,
+        branching,
+        context_strategy,
+        pinned_message_ids,
+        system,
+    } = chat;
+    let user_id = ctx.user_id.as_str();
+    
+    // Earlier messages of the branch this message builds on, and their summary
+    // if the strategy uses one
+    let strategy = context_strategy
+        .or_else(|| ContextStrategy::parse(&state.config.context_strategy))
+        .unwrap_or(ContextStrategy::SummarizeOlder);
+    let turn = match &conversation_id {
+        Some(id) => load_turn(state, ctx, id, &branching, strategy).instrument(info_span!("db.load_history")).await,
+        None if branching == Branching::Continue => Ok(Turn {
+            history: Vec::new(),
+            summary: None,
+            fork_from: None,
+            prompt: None,
+        }),
+        None => Err("conversation_id is required to edit or regenerate a message".to_string()),
+    };
+    let Turn { history, summary, fork_from, prompt: stored_prompt } = match turn {
+        Ok(turn) => turn,
+        Err(reason) => {
+            reply.error(reason).await;
+            return;
+        }
+    };
+    let regenerating = matches!(branching, Branching::Regenerate(_));
+    let message = stored_prompt.unwrap_or(message);
+    if message.trim().is_empty() {
+        reply.error("Message is empty").await;
+        return;
+    }
+    
+    // Determine model and provider
+    let model = model.unwrap_or_else(|| state.config.default_openai_model.clone());
+    let (provider, client) = state.llm_client(&model);
+    let provider = provider.as_str();
+    let model_label = state.metrics.model_label(&model).to_string();
+    
+    // Check token limits, unless an admin granted this user or the request's
+    // API key a quota bypass
+    let uid = Uuid::parse_str(user_id).ok();
+    let bypass = match &uid {
+        Some(uid) => state.admin_service.has_quota_bypass(uid, ctx.api_key_id.as_ref()).await.unwrap_or_else(|e| {
+            error!("Failed to check quota bypass: {}", e);
+            false
+        }),
+        None => false,
+    };
+    
+    if !bypass {
+        let quota_check = check_quota(state, uid.as_ref(), user_id)
+            .instrument(info_span!("quota.check"))
+            .await;
+        match quota_check {
+            Ok(false) => {
+                state.metrics.quota_rejections.with_label_values(&[provider, &model_label]).inc();
+                audit_chat(state, ctx, &model, &message, "quota_exceeded", None, serde_json::json!({}));
+                reply.error("Token limit exceeded").await;
+                return;
+            }
+            Err(e) => {
+                error!("Failed to check token limits: {}", e);
+                reply.error("Internal error").await;
+                return;
+            }
+            _ => {}
+        }
+    }
+    
+    // Render the requested system prompt, if the user's tier allows it
+    let system = match resolve_system_prompt(state, ctx, system).instrument(info_span!("templates.render")).await {
+        Ok(system) => system,
+        Err(reason) => {
+            reply.error(reason).await;
+            return;
+        }
+    };
+    
+    // Redact secrets and PII before anything leaves the service; history,
+    // summary and a custom system prompt share the new message's placeholders
+    let mut texts: Vec<&str> = history.iter().map(|m| m.content.as_str()).collect();
+    if let Some(summary) = &summary {
+        texts.push(&summary.text);
+    }
+    if let Some(system) = &system {
+        texts.push(&system.prompt);
+    }
+    texts.push(&message);
+    let (mut redacted, vault) = match redact_prompt(state, ctx, &model, &texts).instrument(info_span!("redaction.apply")).await {
+        Ok(redacted) => redacted,
+        Err(reason) => {
+            reply.error(reason).await;
+            return;
+        }
+    };
+    let prompt = redacted.pop().unwrap_or_default();
+    let system_prompt = match &system {
+        Some(_) => redacted.pop(),
+        None => state.config.system_prompt.clone(),
+    };
+    let summary = summary.map(|summary| Summary {
+        text: redacted.pop().unwrap_or_default(),
+        covers: summary.covers,
+    });
+    let history: Vec<HistoryMessage> = history
+        .into_iter()
+        .zip(redacted)
+        .map(|(entry, content)| HistoryMessage { content, ..entry })
+        .collect();
+    
+    // Run input guardrails on the redacted prompt and any requested template
+    // or custom system prompt, which is what goes upstream; the configured
+    // default is the operator's own and is not checked
+    let checked = state.guardrails
+        .check_request(user_id, &model, system.as_ref().and(system_prompt.as_deref()), &prompt)
+        .instrument(info_span!("guardrails.input"))
+        .await;
+    send_annotations(reply, checked.annotations).await;
+    if let Some(blocked) = checked.blocked {
+        audit_guardrail(state, ctx, &model, &message, "input", &blocked);
+        reply.error(format!("Message blocked by content policy: {}", blocked.reason)).await;
+        return;
+    }
+    let prompt = checked.prompt;
+    let system_prompt = if system.is_some() { checked.system_prompt } else { system_prompt };
+    
+    // Fit everything into the model's context window
+    let window = state.config.context_window(&model);
+    let built = ContextBuilder::new(&model, window, strategy, max_tokens.unwrap_or(state.config.context_reserve_tokens))
+        .system_prompt(system_prompt)
+        .window_messages(state.config.context_window_messages)
+        .pin(pinned_message_ids)
+        .summary(summary)
+        .history(history)
+        .build(&prompt);
+    let BuiltContext { messages: context, report } = match built {
+        Ok(built) => built,
+        Err(reason) => {
+            reply.error(reason).await;
+            return;
+        }
+    };
+    
+    // A stop during preparation ends the request before anything is saved
+    if request.is_cancelled() {
+        return;
+    }
+    
+    // Create or get conversation
+    let conv_id = match conversation_id {
+        Some(id) => id,
+        None => {
+            let created = state.conversation_service
+                .create_conversation(user_id, &model)
+                .instrument(info_span!("db.create_conversation"))
+                .await;
+            match created {
+                Ok(conv) => conv.id,
+                Err(e) => {
+                    error!("Failed to create conversation: {}", e);
+                    reply.error("Failed to create conversation").await;
+                    return;
+                }
+            }
+        }
+    };
+    
+    // Move to where the new branch starts; what follows is saved under it
+    if let Some(leaf) = &fork_from {
+        if let Err(e) = state.branches.set_active_leaf(&conv_id, leaf.as_deref()).await {
+            error!("Failed to fork conversation {}: {}", conv_id, e);
+            reply.error("Internal error").await;
+            return;
+        }
+        rpc::publish_branch_switched(state, ctx, &conv_id, leaf.as_deref()).await;
+    }
+    
+    // Add user message to conversation; a regenerated reply answers the stored
+    // one. The reply is saved under its prompt, wherever the branch is by then
+    let fork_leaf = fork_from.flatten();
+    let reply_parent = if regenerating {
+        fork_leaf
+    } else {
+        let saved = state.branches
+            .add_message(&conv_id, fork_leaf.as_deref(), Some(user_id), "user", &message, &serde_json::json!({}))
+            .instrument(info_span!("db.add_message", role = "user"))
+            .await;
+        match saved {
+            Ok(message_id) => {
+                publish_message(
+                    state,
+                    ctx,
+                    &conv_id,
+                    rpc::chat_message_json(&message_id, "user", &message, None, chrono::Utc::now()),
+                ).await;
+                Some(message_id)
+            }
+            Err(e) => {
+                error!("Failed to add user message: {}", e);
+                None
+            }
+        }
+    };
+    if let Some(mut session) = state.active_sessions.get_mut(&ctx.session_id) {
+        session.conversation_id = Some(conv_id.clone());
+        session.last_activity = chrono::Utc::now();
+    }
+    if let Err(e) = state.session_registry.set_conversation(&ctx.session_id, &conv_id).await {
+        warn!("Failed to record conversation of session {}: {}", ctx.session_id, e);
+    }
+    
+    // Stream response; provider headers and token counts hang off this span
+    let llm_span = info_span!(
+        "llm.stream",
+        otel.kind = "client",
+        gen_ai.system = provider,
+        gen_ai.request.model = %model,
+        gen_ai.usage.input_tokens = field::Empty,
+        gen_ai.usage.output_tokens = field::Empty,
+        llm.time_to_first_token_ms = field::Empty,
+        llm.outcome = field::Empty,
+    );
+    let state = state.clone();
+    let ctx = ctx.clone();
+    let reply = reply.clone();
+    let in_flight = state.lifecycle.track_stream();
+    tokio::spawn(async move {
+        // Shutdown waits on this until the response and usage are persisted
+        let _in_flight = in_flight;
+        let mut phase_rx = state.lifecycle.subscribe();
+        let user_id = ctx.user_id.as_str();
+        let mut completion_tokens = 0u32;
+        let mut assistant_message = String::new();
+        let mut outcome = "success";
+        let mut restorer = StreamRestorer::new(vault);
+        let mut guard = state.guardrails.stream_guard(&model);
+        let mut blocked: Option<BlockedBy> = None;
+        let started = Instant::now();
+        let stream_timeout = Duration::from_secs(state.config.llm_stream_timeout_secs);
+        let mut first_token = true;
+        let prompt_tokens = report.prompt_tokens;
+        let mut emitter = ResponseEmitter::start(&state, &reply, &ctx, &request.key, &conv_id, &model, report).await;
+        
+        match client.stream_chat(
+            &model,
+            context,
+            temperature,
+            max_tokens,
+        ).await {
+            Ok(mut stream) => {
+                loop {
+                    let chunk = tokio::select! {
+                        chunk = stream.next() => match chunk {
+                            Some(chunk) => chunk,
+                            None => break,
+                        },
+                        // Resuming clients give up on a silent response shortly after this too
+                        _ = tokio::time::sleep(stream_timeout) => {
+                            error!("Upstream stream sent nothing for {:?}", stream_timeout);
+                            state.metrics.llm_upstream_errors.with_label_values(&[provider, &model_label, "timeout"]).inc();
+                            outcome = "upstream_timeout";
+                            reply.error("Stream timed out").await;
+                            break;
+                        }
+                        // Shutdown grace period ran out; keep what we have
+                        _ = phase_rx.wait_for(|phase| *phase == Phase::Closing) => {
+                            outcome = "interrupted";
+                            break;
+                        }
+                        _ = request.cancelled() => {
+                            outcome = "cancelled";
+                            state.metrics.stream_cancellations.with_label_values(&[provider, &model_label]).inc();
+                            break;
+                        }
+                    };
+                    
+                    match chunk {
+                        Ok(text) => {
+                            if first_token {
+                                first_token = false;
+                                state.metrics.llm_time_to_first_token
+                                    .with_label_values(&[provider, &model_label])
+                                    .observe(started.elapsed().as_secs_f64());
+                                Span::current().record("llm.time_to_first_token_ms", started.elapsed().as_millis() as u64);
+                            }
+                            completion_tokens += estimate_tokens(&text);
+                            
+                            // Guardrails see the model's own text, so redacted values
+                            // never reach a remote moderation service
+                            let checked = guard.check_chunk(&text).await;
+                            send_annotations(&reply, checked.annotations).await;
+                            if let Some(by) = checked.blocked {
+                                blocked = Some(by);
+                                break;
+                            }
+                            
+                            // Put redacted values back before the user sees them
+                            let text = restorer.push(&checked.content);
+                            if text.is_empty() {
+                                continue;
+                            }
+                            assistant_message.push_str(&text);
+                            
+                            // A dropped client can resume from the buffer, so keep generating
+                            // unless there is nowhere left to deliver the rest
+                            emitter.chunk(text, None).await;
+                            if emitter.abandoned() {
+                                outcome = "cancelled";
+                                state.metrics.stream_cancellations.with_label_values(&[provider, &model_label]).inc();
+                                break;
+                            }
+                        }
+                        Err(e) => {
+                            error!("Stream error: {}", e);
+                            state.metrics.llm_upstream_errors.with_label_values(&[provider, &model_label, e.kind()]).inc();
+                            outcome = "stream_error";
+                            reply.error("Stream error").await;
+                            break;
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Failed to start stream: {}", e);
+                state.metrics.llm_upstream_errors.with_label_values(&[provider, &model_label, e.kind()]).inc();
+                state.metrics.record_request(provider, &model_label, "upstream_error", started.elapsed().as_secs_f64());
+                Span::current().record("llm.outcome", "upstream_error");
+                audit_chat(&state, &ctx, &model, &message, "upstream_error", None, serde_json::json!({ "error": e.to_string() }));
+                reply.error("Failed to start stream").await;
+                emitter.chunk(String::new(), Some("error")).await;
+                emitter.finish().await;
+                return;
+            }
+        }
+        
+        // Flush text held back while waiting for a placeholder to complete;
+        // the guardrails already passed it
+        let tail = restorer.finish();
+        if !tail.is_empty() && blocked.is_none() {
+            assistant_message.push_str(&tail);
+            emitter.chunk(tail, None).await;
+        }
+        
+        if let Some(by) = &blocked {
+            outcome = "content_filter";
+            audit_guardrail(&state, &ctx, &model, &message, "output", by);
+        }
+        
+        // Save assistant message, with the system prompt it was generated with
+        if !assistant_message.is_empty() {
+            let metadata = match &system {
+                Some(system) => serde_json::json!({ "system_prompt": system }),
+                None => serde_json::json!({}),
+            };
+            let saved = state.branches
+                .add_message(&conv_id, reply_parent.as_deref(), None, "assistant", &assistant_message, &metadata)
+                .instrument(info_span!("db.add_message", role = "assistant"))
+                .await;
+            // Published under its stored id, which is what later edits and branches name
+            match saved {
+                Ok(message_id) => {
+                    publish_message(
+                        &state,
+                        &ctx,
+                        &conv_id,
+                        rpc::chat_message_json(&message_id, "assistant", &assistant_message, Some(&model), chrono::Utc::now()),
+                    ).await;
+                    state.summarizer.after_exchange(user_id, &conv_id);
+                }
+                Err(e) => error!("Failed to save assistant message: {}", e),
+            }
+        }
+        
+        // Update token usage
+        let total_tokens = prompt_tokens + completion_tokens;
+        
+        if let Err(e) = state.token_meter_service.record_usage(
+            user_id,
+            &model,
+            prompt_tokens,
+            completion_tokens,
+        ).await {
+            error!("Failed to record token usage: {}", e);
+        }
+        
+        state.metrics.record_tokens(provider, &model_label, prompt_tokens, completion_tokens);
+        Span::current()
+            .record("gen_ai.usage.input_tokens", prompt_tokens)
+            .record("gen_ai.usage.output_tokens", completion_tokens)
+            .record("llm.outcome", outcome);
+        state.metrics.record_request(provider, &model_label, outcome, started.elapsed().as_secs_f64());
+        
+        audit_chat(
+            &state,
+            &ctx,
+            &model,
+            &message,
+            outcome,
+            Some((prompt_tokens, completion_tokens)),
+            serde_json::json!({
+                "conversation_id": conv_id,
+                "response_hash": sha256_hex(assistant_message.as_bytes()),
+                "template_id": system.as_ref().and_then(|system| system.template_id),
+                "template_version": system.as_ref().and_then(|system| system.version),
+            }),
+        );
+        
+        // Get remaining limits
+        match state.token_meter_service.get_remaining_tokens(user_id).await {
+            Ok((daily, monthly)) => {
+                reply.send(ServerMessage::Usage {
+                    prompt_tokens,
+                    completion_tokens,
+                    total_tokens,
+                    remaining_daily: daily,
+                    remaining_monthly: monthly,
+                }).await;
+            }
+            Err(e) => {
+                error!("Failed to get remaining tokens: {}", e);
+            }
+        }
+        
+        // Send completion signal; a guardrail block ends the stream as filtered,
+        // a shutdown cut as interrupted and a client stop as cancelled
+        let finish_reason = match (&blocked, outcome) {
+            (Some(_), _) => CONTENT_FILTER_FINISH_REASON,
+            (None, "interrupted") => "interrupted",
+            (None, "cancelled") => "cancelled",
+            (None, _) => "stop",
+        };
+        emitter.chunk(String::new(), Some(finish_reason)).await;
+        emitter.finish().await;
+    }.instrument(llm_span));
+}
+
+// Delivers a generation's chunks to the socket, mirrors them into the resume
+// buffer under increasing sequence numbers, and streams them live to the
+// user's other devices following the conversation
+struct ResponseEmitter {
+    state: Arc<AppState>,
+    reply: Responder,
+    response_id: String,
+    conversation_id: String,
+    origin: String,
+    model: String,
+    seq: u64,
+    buffered: bool,
+    connected: bool,
+}
+
+impl ResponseEmitter {
+    async fn start(
+        state: &Arc<AppState>,
+        reply: &Responder,
+        ctx: &ChatContext,
+        request_key: &str,
+        conversation_id: &str,
+        model: &str,
+        context: ContextReport,
+    ) -> Self {
+        let response_id = Uuid::new_v4().to_string();
+        
+        let buffered = match state.stream_buffer
+            .start(&response_id, &ctx.user_id, &ctx.session_id, request_key, conversation_id, model)
+            .await
+        {
+            Ok(()) => true,
+            Err(e) => {
+                warn!("Resume buffer unavailable for response {}: {}", response_id, e);
+                false
+            }
+        };
+        
+        let connected = reply.send(ServerMessage::ResponseStarted {
+            response_id: response_id.clone(),
+            conversation_id: conversation_id.to_string(),
+            model: model.to_string(),
+            context: Some(context),
+        }).await;
+        
+        let emitter = Self {
+            state: state.clone(),
+            reply: reply.clone(),
+            response_id,
+            conversation_id: conversation_id.to_string(),
+            origin: ctx.session_id.clone(),
+            model: model.to_string(),
+            seq: 0,
+            buffered,
+            connected,
+        };
+        emitter.publish(serde_json::json!({
+            "type": "messageStart",
+            "messageId": emitter.response_id,
+            "model": emitter.model,
+        })).await;
+        emitter
+    }
+    
+    // Shapes follow the session topic events in chat_websocket_service.dart
+    async fn publish(&self, mut data: serde_json::Value) {
+        data["sessionId"] = serde_json::json!(self.conversation_id);
+        self.state.pubsub.publish(TopicEvent {
+            topic: pubsub::session_topic(&self.conversation_id),
+            origin: Some(self.origin.clone()),
+            data,
+        }).await;
+    }
+    
+    async fn chunk(&mut self, content: String, finish_reason: Option<&str>) {
+        self.seq += 1;
+        
+        if self.buffered {
+            if let Err(e) = self.state.stream_buffer.append(&self.response_id, self.seq, &content, finish_reason).await {
+                warn!("Failed to buffer chunk {} of response {}: {}", self.seq, self.response_id, e);
+                self.buffered = false;
+            }
+        }
+        
+        if !content.is_empty() {
+            self.publish(serde_json::json!({
+                "type": "messageChunk",
+                "messageId": self.response_id,
+                "seq": self.seq,
+                "chunk": content,
+            })).await;
+        }
+        if let Some(finish_reason) = finish_reason {
+            self.publish(serde_json::json!({
+                "type": "messageEnd",
+                "messageId": self.response_id,
+                "seq": self.seq,
+                "finishReason": finish_reason,
+            })).await;
+        }
+        
+        if self.connected {
+            self.connected = self.reply.send(ServerMessage::Chunk {
+                response_id: self.response_id.clone(),
+                seq: self.seq,
+                content,
+                model: self.model.clone(),
+                finish_reason: finish_reason.map(str::to_string),
+            }).await;
+        }
+    }
+    
+    // Nobody is listening and nothing can be resumed
+    fn abandoned(&self) -> bool {
+        !self.connected && !self.buffered
+    }
+    
+    async fn finish(&self) {
+        if self.buffered {
+            if let Err(e) = self.state.stream_buffer.finish(&self.response_id).await {
+                warn!("Failed to mark response {} finished: {}", self.response_id, e);
+            }
+        }
+    }
+}
+
+async fn resume_response(
+    state: Arc<AppState>,
+    reply: Responder,
+    mut request: RequestHandle,
+    user_id: String,
+    response_id: String,
+    last_seq: u64,
+) {
+    // Other users' response ids look exactly like expired ones
+    let meta = match state.stream_buffer.meta(&response_id).await {
+        Ok(Some(meta)) if meta.user_id == user_id => meta,
+        Ok(_) => {
+            reply.error("Response not found or expired").await;
+            return;
+        }
+        Err(e) => {
+            error!("Failed to load response {}: {}", response_id, e);
+            reply.error("Internal error").await;
+            return;
+        }
+    };
+    
+    let started = reply.send(ServerMessage::ResponseStarted {
+        response_id: response_id.clone(),
+        conversation_id: meta.conversation_id.clone(),
+        model: meta.model.clone(),
+        context: None,
+    }).await;
+    if !started {
+        return;
+    }
+    
+    let mut last_seq = last_seq;
+    loop {
+        let chunks = match state.stream_buffer.read_after(&response_id, last_seq, RESUME_BATCH_SIZE).await {
+            Ok(chunks) => chunks,
+            Err(e) => {
+                error!("Failed to read response {}: {}", response_id, e);
+                reply.error("Internal error").await;
+                return;
+            }
+        };
+        
+        if chunks.is_empty() {
+            // Done is set after the final chunk is appended, so nothing is left
+            match state.stream_buffer.meta(&response_id).await {
+                Ok(Some(meta)) if meta.status == ResponseStatus::Done => return,
+                Ok(Some(meta)) if !state.stream_buffer.is_stale(&meta) => {}
+                Ok(_) => {
+                    reply.error("Response generation was interrupted").await;
+                    return;
+                }
+                Err(e) => {
+                    error!("Failed to load response {}: {}", response_id, e);
+                    return;
+                }
+            }
+            tokio::select! {
+                _ = tokio::time::sleep(RESUME_POLL_INTERVAL) => continue,
+                _ = request.cancelled() => return,
+            }
+        }
+        
+        for chunk in chunks {
+            last_seq = chunk.seq;
+            let finished = chunk.finish_reason.is_some();
+            let sent = reply.send(ServerMessage::Chunk {
+                response_id: response_id.clone(),
+                seq: chunk.seq,
+                content: chunk.content,
+                model: meta.model.clone(),
+                finish_reason: chunk.finish_reason,
+            }).await;
+            if !sent || finished {
+                return;
+            }
+        }
+    }
+}
+
+// The branch a message builds on, in a conversation the user owns: its
+// earlier messages oldest first, and their rolling summary when the strategy
+// can use one
+async fn load_turn(
+    state: &AppState,
+    ctx: &ChatContext,
+    conversation_id: &str,
+    branching: &Branching,
+    strategy: ContextStrategy,
+) -> Result<Turn, String> {
+    // Any editor may continue, edit or regenerate; usage is charged to them
+    rpc::member_conversation(state, ctx, conversation_id, MemberRole::Editor).await.map_err(|e| e.message)?;
+    
+    let internal = |e: anyhow::Error| {
+        error!("Failed to load history of conversation {}: {}", conversation_id, e);
+        "Internal error".to_string()
+    };
+    // The leaf the history ends at, and whether the turn leaves the active branch
+    let (leaf, fork_from) = match branching {
+        Branching::Continue => (state.branches.active_leaf(conversation_id).await.map_err(internal)?, None),
+        Branching::Edit(message_id) => {
+            let edited = branch_node(state, conversation_id, message_id).await?;
+            if edited.role != "user" {
+                return Err("Only user messages can be edited".to_string());
+            }
+            (edited.parent_id.clone(), Some(edited.parent_id))
+        }
+        Branching::Regenerate(message_id) => {
+            let answer = branch_node(state, conversation_id, message_id).await?;
+            let prompt = match (answer.role.as_str(), answer.parent_id) {
+                ("assistant", Some(parent_id)) => branch_node(state, conversation_id, &parent_id).await?,
+                ("assistant", None) => return Err("Message has no prompt to answer again".to_string()),
+                _ => return Err("Only assistant messages can be regenerated".to_string()),
+            };
+            if prompt.role != "user" {
+                return Err("Message has no prompt to answer again".to_string());
+            }
+            (Some(prompt.id.clone()), Some(Some(prompt.id)))
+        }
+    };
+    
+    let path = match &leaf {
+        Some(leaf) => state.branches.path(conversation_id, Some(leaf)).await.map_err(internal)?,
+        None => Vec::new(),
+    };
+    let messages = state.conversation_service.get_messages(conversation_id).await.map_err(internal)?;
+    let mut messages = state.branches.decrypt(conversation_id, on_path(messages, &path)).await.map_err(internal)?;
+    
+    let prompt = match branching {
+        Branching::Regenerate(_) => messages.pop().map(|m| m.content),
+        _ => None,
+    };
+    let ids: Vec<String> = messages.iter().map(|m| m.id.clone()).collect();
+    let history = messages
+        .into_iter()
+        .map(|m| HistoryMessage {
+            id: m.id,
+            role: m.role,
+            content: m.content,
+        })
+        .collect();
+    
+    let summary = if strategy == ContextStrategy::SummarizeOlder {
+        match state.summarizer.summary(conversation_id, &ids).await {
+            Ok(summary) => summary.map(|(text, covers)| Summary { text, covers }),
+            Err(e) => {
+                warn!("Failed to load summary of conversation {}: {}", conversation_id, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    
+    Ok(Turn {
+        history,
+        summary,
+        fork_from,
+        prompt,
+    })
+}
+
+async fn branch_node(state: &AppState, conversation_id: &str, message_id: &str) -> Result<BranchNode, String> {
+    match state.branches.node(conversation_id, message_id).await {
+        Ok(Some(node)) => Ok(node),
+        Ok(None) => Err("Message not found".to_string()),
+        Err(e) => {
+            error!("Failed to load message {}: {}", message_id, e);
+            Err("Internal error".to_string())
+        }
+    }
+}
+
+// An active boost scales the user's limits; without one the token meter
+// decides on its own
+async fn check_quota(state: &AppState, uid: Option<&Uuid>, user_id: &str) -> anyhow::Result<bool> {
+    if let Some(uid) = uid {
+        let multiplier = state.admin_service.effective_boost(uid).await?;
+        if multiplier > 1.0 {
+            if let Some(within) = state.admin_service.within_boosted_limits(uid, multiplier).await? {
+                return Ok(within);
+            }
+        }
+    }
+    
+    state.token_meter_service.check_limits(user_id).await
+}
+
+// The system prompt for one message: a visible template rendered with the
+// message's variables, or a one-off prompt. Personal and org templates and
+// one-off prompts need the tier's custom prompt feature; global templates
+// only need a tier that allows system prompts at all
+async fn resolve_system_prompt(
+    state: &AppState,
+    ctx: &ChatContext,
+    choice: PromptChoice,
+) -> Result<Option<RenderedPrompt>, String> {
+    let PromptChoice { template_id, template_version, variables, system_prompt } = choice;
+    let internal = |e: anyhow::Error| {
+        error!("Failed to resolve system prompt: {}", e);
+        "Internal error".to_string()
+    };
+    
+    let (template_id, system_prompt) = match (template_id, system_prompt) {
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => return Err("Send either template_id or system_prompt, not both".to_string()),
+        choice => choice,
+    };
+    let user_id = Uuid::parse_str(&ctx.user_id).map_err(|_| "Invalid user".to_string())?;
+    let access = state.templates.access(&user_id).await.map_err(internal)?;
+    
+    let Some(template_id) = template_id else {
+        let prompt = system_prompt.unwrap_or_default();
+        if !access.custom_prompts {
+            return Err(format!("Custom system prompts are not available on the {} tier", access.tier));
+        }
+        if prompt.chars().count() > MAX_TEMPLATE_CHARS {
+            return Err(format!("system_prompt is longer than {} characters", MAX_TEMPLATE_CHARS));
+        }
+        return Ok(Some(RenderedPrompt {
+            template_id: None,
+            version: None,
+            variables: HashMap::new(),
+            prompt,
+        }));
+    };
+    
+    let not_found = || "Template not found".to_string();
+    let template_id = Uuid::parse_str(&template_id).map_err(|_| not_found())?;
+    let org_id = state.policy_service.user_org(&user_id).await.map_err(internal)?;
+    let template = state.templates
+        .get_visible(&template_id, &user_id, org_id)
+        .await
+        .map_err(internal)?
+        .ok_or_else(not_found)?;
+    let allowed = if template.is_custom() { access.custom_prompts } else { access.system_prompts };
+    if !allowed {
+        return Err(format!("This template is not available on the {} tier", access.tier));
+    }
+    
+    let (version, content) = match template_version {
+        Some(version) if version != template.current_version => {
+            let stored = state.templates.version(&template_id, version).await.map_err(internal)?;
+            let stored = stored.ok_or_else(|| format!("Template has no version {}", version))?;
+            (stored.version, stored.content)
+        }
+        _ => (template.current_version, template.content),
+    };
+    let prompt = templates::render(&content, &variables)
+        .map_err(|missing| format!("Missing template variables: {}", missing.join(", ")))?;
+    
+    Ok(Some(RenderedPrompt {
+        template_id: Some(template_id),
+        version: Some(version),
+        variables,
+        prompt,
+    }))
+}
+
+// The last of `texts` is the new message; audit records are keyed by its hash
+async fn redact_prompt(
+    state: &AppState,
+    ctx: &ChatContext,
+    model: &str,
+    texts: &[&str],
+) -> Result<(Vec<String>, RedactionVault), String> {
+    let message = texts.last().copied().unwrap_or_default();
+    let unchanged = || Ok((texts.iter().map(|t| t.to_string()).collect(), RedactionVault::default()));
+    
+    let Ok(user_id) = Uuid::parse_str(&ctx.user_id) else {
+        return unchanged();
+    };
+    
+    // Fail closed: if the policy cannot be loaded, nothing is sent upstream
+    let policy = match state.policy_service.redaction_policy_for_user(&user_id).await {
+        Ok(Some(policy)) => policy,
+        Ok(None) => return unchanged(),
+        Err(e) => {
+            error!("Failed to load redaction policy: {}", e);
+            return Err("Internal error".to_string());
+        }
+    };
+    
+    match redaction::redact(texts, &policy) {
+        Ok(redacted) => {
+            if !redacted.findings.is_empty() {
+                audit_redaction(state, ctx, model, message, redacted.outcome(), &redacted.findings);
+            }
+            Ok((redacted.texts, redacted.vault))
+        }
+        Err(blocked) => {
+            audit_redaction(state, ctx, model, message, "blocked", &blocked.findings);
+            Err(blocked.to_string())
+        }
+    }
+}
+
+fn audit_redaction(
+    state: &AppState,
+    ctx: &ChatContext,
+    model: &str,
+    prompt: &str,
+    outcome: &str,
+    findings: &[RedactionFinding],
+) {
+    // Findings carry detector names and counts only, never the matched values
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: "chat.redaction".to_string(),
+        actor_id: Uuid::parse_str(&ctx.user_id).ok(),
+        session_id: Some(ctx.session_id.clone()),
+        api_key_id: ctx.api_key_id,
+        model: Some(model.to_string()),
+        request_hash: Some(sha256_hex(prompt.as_bytes())),
+        outcome: outcome.to_string(),
+        client_ip: ctx.client_ip.clone(),
+        details: serde_json::json!({ "findings": findings }),
+        ..Default::default()
+    });
+}
+
+async fn send_annotations(reply: &Responder, annotations: Vec<Annotation>) {
+    for annotation in annotations {
+        reply.send(ServerMessage::Annotation {
+            guardrail: annotation.guardrail,
+            label: annotation.label,
+            note: annotation.note,
+        }).await;
+    }
+}
+
+fn audit_guardrail(
+    state: &AppState,
+    ctx: &ChatContext,
+    model: &str,
+    prompt: &str,
+    stage: &str,
+    blocked: &BlockedBy,
+) {
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: "chat.guardrail".to_string(),
+        actor_id: Uuid::parse_str(&ctx.user_id).ok(),
+        session_id: Some(ctx.session_id.clone()),
+        api_key_id: ctx.api_key_id,
+        model: Some(model.to_string()),
+        request_hash: Some(sha256_hex(prompt.as_bytes())),
+        outcome: "blocked".to_string(),
+        client_ip: ctx.client_ip.clone(),
+        details: serde_json::json!({
+            "stage": stage,
+            "guardrail": blocked.guardrail,
+            "reason": blocked.reason,
+        }),
+        ..Default::default()
+    });
+}
+
+fn audit_chat(
+    state: &AppState,
+    ctx: &ChatContext,
+    model: &str,
+    prompt: &str,
+    outcome: &str,
+    tokens: Option<(u32, u32)>,
+    details: serde_json::Value,
+) {
+    // Only a hash of the prompt is kept; the content itself lives in the conversation
+    state.audit_service.record_in_background(AuditEvent {
+        event_type: "chat.completion".to_string(),
+        actor_id: Uuid::parse_str(&ctx.user_id).ok(),
+        session_id: Some(ctx.session_id.clone()),
+        api_key_id: ctx.api_key_id,
+        model: Some(model.to_string()),
+        request_hash: Some(sha256_hex(prompt.as_bytes())),
+        prompt_tokens: tokens.map(|(p, _)| p as i32),
+        completion_tokens: tokens.map(|(_, c)| c as i32),
+        outcome: outcome.to_string(),
+        client_ip: ctx.client_ip.clone(),
+        details,
+    });
+}
+
+fn estimate_tokens(text: &str) -> u32 {
+    context::count_tokens(text)
+}
\ No newline at end of file
@@ -0,0 +1,341 @@
+use super::{Outgoing, ServerMessage};
+use crate::metrics::Metrics;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+// What to do when a client reads slower than the server writes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    // Merge queued chunks of the same response and drop live events; only
+    // replies that still do not fit disconnect the client
+    Coalesce,
+    // Disconnect as soon as anything does not fit
+    Disconnect,
+}
+
+impl OverflowPolicy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "coalesce" => Some(OverflowPolicy::Coalesce),
+            "disconnect" => Some(OverflowPolicy::Disconnect),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OutboxLimits {
+    pub messages: usize,
+    pub bytes: usize,
+    pub policy: OverflowPolicy,
+}
+
+pub enum Received {
+    Message(Outgoing),
+    // The client fell too far behind; the queue has been discarded
+    Overflowed,
+}
+
+struct Queued {
+    message: Outgoing,
+    bytes: usize,
+}
+
+#[derive(Default)]
+struct Queue {
+    items: VecDeque<Queued>,
+    bytes: usize,
+    senders: usize,
+    overflowed: bool,
+    closed: bool,
+}
+
+struct Shared {
+    queue: Mutex<Queue>,
+    notify: Notify,
+    limits: OutboxLimits,
+    metrics: Arc<Metrics>,
+}
+
+impl Shared {
+    fn discard(&self, queue: &mut Queue) {
+        self.metrics.ws_outbound_queued_bytes.sub(queue.bytes as i64);
+        queue.items.clear();
+        queue.bytes = 0;
+    }
+}
+
+// Outbound queue of one socket. Unlike a bounded channel, sending never
+// waits on the client, so a slow reader cannot hold up generation; it is
+// bounded by `OutboxLimits` instead
+pub struct Outbox {
+    shared: Arc<Shared>,
+}
+
+pub struct OutboxReceiver {
+    shared: Arc<Shared>,
+}
+
+pub fn outbox(limits: OutboxLimits, metrics: Arc<Metrics>) -> (Outbox, OutboxReceiver) {
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(Queue {
+            senders: 1,
+            ..Default::default()
+        }),
+        notify: Notify::new(),
+        limits,
+        metrics,
+    });
+    
+    (Outbox { shared: shared.clone() }, OutboxReceiver { shared })
+}
+
+impl Outbox {
+    // False once the socket is closing or has overflowed
+    pub fn send(&self, message: Outgoing) -> bool {
+        let shared = &self.shared;
+        let limits = shared.limits;
+        let mut queue = shared.queue.lock().unwrap();
+        if queue.closed || queue.overflowed {
+            return false;
+        }
+        
+        if limits.policy == OverflowPolicy::Coalesce {
+            let room = limits.bytes.saturating_sub(queue.bytes);
+            if let Some(added) = coalesce(&mut queue.items, &message, room) {
+                queue.bytes += added;
+                shared.metrics.ws_outbound_queued_bytes.add(added as i64);
+                shared.metrics.ws_outbound_coalesced.inc();
+                return true;
+            }
+        }
+        
+        let bytes = serde_json::to_vec(&message).map(|json| json.len()).unwrap_or_default();
+        if queue.bytes + bytes > limits.bytes || queue.items.len() >= limits.messages {
+            // Live events are a convenience; the client can catch up on reconnect
+            if limits.policy == OverflowPolicy::Coalesce && matches!(message.message, ServerMessage::Event { .. }) {
+                shared.metrics.ws_outbound_dropped.inc();
+                return true;
+            }
+            
+            queue.overflowed = true;
+            shared.discard(&mut queue);
+            shared.notify.notify_one();
+            return false;
+        }
+        
+        queue.items.push_back(Queued { message, bytes });
+        queue.bytes += bytes;
+        shared.metrics.ws_outbound_queued_bytes.add(bytes as i64);
+        shared.notify.notify_one();
+        true
+    }
+}
+
+impl Clone for Outbox {
+    fn clone(&self) -> Self {
+        self.shared.queue.lock().unwrap().senders += 1;
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Drop for Outbox {
+    fn drop(&mut self) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.senders -= 1;
+        if queue.senders == 0 {
+            self.shared.notify.notify_one();
+        }
+    }
+}
+
+impl OutboxReceiver {
+    // None once every sender is gone and the queue is drained
+    pub async fn recv(&mut self) -> Option<Received> {
+        loop {
+            {
+                let mut queue = self.shared.queue.lock().unwrap();
+                if queue.overflowed {
+                    return Some(Received::Overflowed);
+                }
+                if let Some(queued) = queue.items.pop_front() {
+                    queue.bytes -= queued.bytes;
+                    self.shared.metrics.ws_outbound_queued_bytes.sub(queued.bytes as i64);
+                    return Some(Received::Message(queued.message));
+                }
+                if queue.senders == 0 {
+                    return None;
+                }
+            }
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for OutboxReceiver {
+    fn drop(&mut self) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        queue.closed = true;
+        self.shared.discard(&mut queue);
+    }
+}
+
+// Appends a chunk to the chunk of the same response waiting at the back of
+// the queue, if its content fits in `room` bytes; returns the bytes added.
+// The merged chunk carries the later seq, so a client resuming from it
+// misses nothing
+fn coalesce(items: &mut VecDeque<Queued>, message: &Outgoing, room: usize) -> Option<usize> {
+    let ServerMessage::Chunk { response_id, seq, content, finish_reason, .. } = &message.message else {
+        return None;
+    };
+    if content.len() > room {
+        return None;
+    }
+    let last = items.back_mut()?;
+    if last.message.request_id != message.request_id {
+        return None;
+    }
+    let ServerMessage::Chunk {
+        response_id: queued_response_id,
+        seq: queued_seq,
+        content: queued_content,
+        finish_reason: queued_finish_reason,
+        ..
+    } = &mut last.message.message
+    else {
+        return None;
+    };
+    if queued_response_id != response_id || queued_finish_reason.is_some() {
+        return None;
+    }
+    
+    queued_content.push_str(content);
+    *queued_seq = *seq;
+    *queued_finish_reason = finish_reason.clone();
+    last.bytes += content.len();
+    Some(content.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn chunk(request_id: &str, response_id: &str, seq: u64, content: &str, finish_reason: Option<&str>) -> Outgoing {
+        Outgoing {
+            request_id: Some(request_id.to_string()),
+            message: ServerMessage::Chunk {
+                response_id: response_id.to_string(),
+                seq,
+                content: content.to_string(),
+                model: "gpt-4o".to_string(),
+                finish_reason: finish_reason.map(str::to_string),
+            },
+        }
+    }
+    
+    fn event() -> Outgoing {
+        Outgoing {
+            request_id: None,
+            message: ServerMessage::Event {
+                topic: "user:1".to_string(),
+                data: serde_json::json!({}),
+            },
+        }
+    }
+    
+    fn queued(message: Outgoing) -> Queued {
+        Queued { message, bytes: 100 }
+    }
+    
+    fn chunk_parts(queued: &Queued) -> (u64, &str, Option<&str>) {
+        match &queued.message.message {
+            ServerMessage::Chunk { seq, content, finish_reason, .. } => (*seq, content, finish_reason.as_deref()),
+            _ => panic!("not a chunk"),
+        }
+    }
+    
+    fn limits(messages: usize, bytes: usize, policy: OverflowPolicy) -> OutboxLimits {
+        OutboxLimits { messages, bytes, policy }
+    }
+    
+    fn metrics() -> Arc<Metrics> {
+        Arc::new(Metrics::new(&[]).unwrap())
+    }
+    
+    #[test]
+    fn merged_chunk_carries_the_later_seq() {
+        let mut items = VecDeque::from([queued(chunk("r1", "resp", 3, "Hel", None))]);
+        
+        assert_eq!(coalesce(&mut items, &chunk("r1", "resp", 4, "lo", None), 1000), Some(2));
+        assert_eq!(coalesce(&mut items, &chunk("r1", "resp", 5, "!", Some("stop")), 1000), Some(1));
+        
+        assert_eq!(items.len(), 1);
+        assert_eq!(chunk_parts(&items[0]), (5, "Hello!", Some("stop")));
+        assert_eq!(items[0].bytes, 103);
+    }
+    
+    #[test]
+    fn nothing_merges_into_a_finished_chunk() {
+        let mut items = VecDeque::from([queued(chunk("r1", "resp", 3, "done", Some("stop")))]);
+        
+        assert_eq!(coalesce(&mut items, &chunk("r1", "resp", 4, "more", None), 1000), None);
+        assert_eq!(chunk_parts(&items[0]), (3, "done", Some("stop")));
+    }
+    
+    #[test]
+    fn only_chunks_of_the_same_response_merge() {
+        let mut items = VecDeque::from([queued(chunk("r1", "resp", 3, "a", None))]);
+        
+        assert_eq!(coalesce(&mut items, &chunk("r2", "resp", 4, "b", None), 1000), None);
+        assert_eq!(coalesce(&mut items, &chunk("r1", "other", 4, "b", None), 1000), None);
+        assert_eq!(coalesce(&mut items, &event(), 1000), None);
+        assert_eq!(chunk_parts(&items[0]), (3, "a", None));
+    }
+    
+    #[test]
+    fn chunks_merge_only_at_the_back_of_the_queue() {
+        let mut items = VecDeque::from([queued(chunk("r1", "resp", 3, "a", None)), queued(event())]);
+        
+        assert_eq!(coalesce(&mut items, &chunk("r1", "resp", 4, "b", None), 1000), None);
+    }
+    
+    #[test]
+    fn chunks_that_do_not_fit_are_not_merged() {
+        let mut items = VecDeque::from([queued(chunk("r1", "resp", 3, "a", None))]);
+        
+        assert_eq!(coalesce(&mut items, &chunk("r1", "resp", 4, "bcd", None), 2), None);
+        assert_eq!(chunk_parts(&items[0]), (3, "a", None));
+    }
+    
+    #[tokio::test]
+    async fn slow_client_receives_coalesced_chunks() {
+        let (tx, mut rx) = outbox(limits(1, 10_000, OverflowPolicy::Coalesce), metrics());
+        
+        assert!(tx.send(chunk("r1", "resp", 1, "a", None)));
+        assert!(tx.send(chunk("r1", "resp", 2, "b", None)));
+        assert!(tx.send(chunk("r1", "resp", 3, "c", Some("stop"))));
+        // Dropped rather than overflowing
+        assert!(tx.send(event()));
+        drop(tx);
+        
+        let Some(Received::Message(message)) = rx.recv().await else {
+            panic!("expected a message");
+        };
+        assert_eq!(chunk_parts(&Queued { message, bytes: 0 }), (3, "abc", Some("stop")));
+        assert!(rx.recv().await.is_none());
+    }
+    
+    #[tokio::test]
+    async fn disconnect_policy_overflows_instead_of_merging() {
+        let (tx, mut rx) = outbox(limits(1, 10_000, OverflowPolicy::Disconnect), metrics());
+        
+        assert!(tx.send(chunk("r1", "resp", 1, "a", None)));
+        assert!(!tx.send(chunk("r1", "resp", 2, "b", None)));
+        assert!(!tx.send(chunk("r1", "resp", 3, "c", None)));
+        
+        assert!(matches!(rx.recv().await, Some(Received::Overflowed)));
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+// Version 1 is the original `type`-tagged protocol, spoken by clients that
+// never send `hello`; version 2 adds the handshake and request ids
+pub const TAGGED_VERSIONS: (u32, u32) = (1, 2);
+// The Flutter bridge envelope; its `connect` carries a semver whose major is this
+pub const BRIDGE_VERSIONS: (u32, u32) = (1, 1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Feature {
+    // Replay and follow a generation by response id
+    Resume,
+    // Tool calls streamed alongside text
+    Tools,
+    // Client messages in binary frames (UTF-8 JSON)
+    Binary,
+    // Compressed frames
+    Compression,
+}
+
+// Features this server grants when asked; tools and compression are
+// recognised so clients can ask, but not offered yet
+const SUPPORTED_FEATURES: &[Feature] = &[Feature::Resume, Feature::Binary];
+
+impl Feature {
+    // Unknown names are skipped so newer clients can still connect
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "resume" => Some(Feature::Resume),
+            "tools" => Some(Feature::Tools),
+            "binary" => Some(Feature::Binary),
+            "compression" => Some(Feature::Compression),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    // `{"type": "chat", ...}` messages, see `ClientMessage`
+    Tagged,
+    // `{"id", "type": "request", "data": {"method", "params"}}` envelopes
+    Bridge,
+}
+
+impl Dialect {
+    fn versions(self) -> (u32, u32) {
+        match self {
+            Dialect::Tagged => TAGGED_VERSIONS,
+            Dialect::Bridge => BRIDGE_VERSIONS,
+        }
+    }
+}
+
+// Outcome of the handshake for one socket
+#[derive(Debug, Clone)]
+pub struct Negotiated {
+    pub version: u32,
+    pub features: BTreeSet<Feature>,
+}
+
+impl Negotiated {
+    // What a client that skips `hello` gets: everything version 1 offered
+    pub fn legacy() -> Self {
+        Self {
+            version: 1,
+            features: BTreeSet::from([Feature::Resume]),
+        }
+    }
+    
+    pub fn allows(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+// Picks the highest version both sides speak and the requested features the
+// server supports. An empty `versions` means the client takes the newest
+pub fn negotiate(dialect: Dialect, versions: &[u32], features: &[String]) -> Result<Negotiated, String> {
+    let (min, max) = dialect.versions();
+    
+    let version = if versions.is_empty() {
+        max
+    } else {
+        versions
+            .iter()
+            .copied()
+            .filter(|v| (min..=max).contains(v))
+            .max()
+            .ok_or_else(|| format!("Unsupported protocol version; server speaks {} to {}", min, max))?
+    };
+    
+    let features = features
+        .iter()
+        .filter_map(|name| Feature::parse(name))
+        .filter(|feature| SUPPORTED_FEATURES.contains(feature))
+        .collect();
+    
+    Ok(Negotiated { version, features })
+}
@@ -0,0 +1,302 @@
+use super::ChatContext;
+use crate::handlers::retention;
+use crate::models::{Conversation, Message};
+use crate::services::branches::on_path;
+use crate::services::members::MemberRole;
+use crate::services::pubsub::{session_topic, user_topic, TopicEvent};
+use crate::state::AppState;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tracing::error;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RpcErrorCode {
+    UnknownMethod,
+    InvalidParams,
+    NotFound,
+    Forbidden,
+    Internal,
+}
+
+#[derive(Debug)]
+pub struct RpcError {
+    pub code: RpcErrorCode,
+    pub message: String,
+}
+
+impl RpcError {
+    fn new(code: RpcErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+    
+    fn internal(context: &str, e: anyhow::Error) -> Self {
+        error!("{}: {}", context, e);
+        Self::new(RpcErrorCode::Internal, "Internal error")
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateSessionParams {
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    settings: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SessionParams {
+    session_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SwitchBranchParams {
+    session_id: String,
+    // Any message of the branch; its most recent leaf becomes active
+    message_id: String,
+}
+
+// Session calls from the Flutter bridge ("sessions" are conversations);
+// `sendMessage` and `stopGeneration` go through the chat path instead
+pub async fn dispatch(state: &AppState, ctx: &ChatContext, method: &str, params: Value) -> Result<Value, RpcError> {
+    match method {
+        "getSessions" => get_sessions(state, ctx).await,
+        "createSession" => create_session(state, ctx, parse_params(params)?).await,
+        "deleteSession" => delete_session(state, ctx, parse_params(params)?).await,
+        "getBranches" => get_branches(state, ctx, parse_params(params)?).await,
+        "switchBranch" => switch_branch(state, ctx, parse_params(params)?).await,
+        _ => Err(RpcError::new(RpcErrorCode::UnknownMethod, format!("Unknown method: {}", method))),
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(RpcErrorCode::InvalidParams, e.to_string()))
+}
+
+// The user's own conversations outside the trash, then those other users
+// shared with them
+async fn get_sessions(state: &AppState, ctx: &ChatContext) -> Result<Value, RpcError> {
+    let trashed = state.retention
+        .trashed_ids(&ctx.user_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to list trashed conversations", e))?;
+    let mut conversations: Vec<(Conversation, MemberRole)> = state.conversation_service
+        .list_conversations(&ctx.user_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to list conversations", e))?
+        .into_iter()
+        .filter(|conversation| !trashed.contains(&conversation.id))
+        .map(|conversation| (conversation, MemberRole::Owner))
+        .collect();
+    
+    let shared = state.members
+        .shared_with(&ctx.user_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to list shared conversations", e))?;
+    if let Ok(user_id) = Uuid::parse_str(&ctx.user_id) {
+        for conversation_id in shared {
+            let access = state
+                .conversation_access(&conversation_id, &user_id)
+                .await
+                .map_err(|e| RpcError::internal("Failed to load shared conversation", e))?;
+            conversations.extend(access);
+        }
+    }
+    
+    let mut sessions = Vec::with_capacity(conversations.len());
+    for (conversation, role) in &conversations {
+        let messages = active_messages(state, &conversation.id).await?;
+        sessions.push(session_json(conversation, *role, &messages, &authors(state, &conversation.id).await?));
+    }
+    
+    Ok(json!({ "sessions": sessions }))
+}
+
+async fn authors(state: &AppState, conversation_id: &str) -> Result<HashMap<String, String>, RpcError> {
+    state.members
+        .authors(conversation_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to load message authors", e))
+}
+
+// Messages of the active branch, root first
+async fn active_messages(state: &AppState, conversation_id: &str) -> Result<Vec<Message>, RpcError> {
+    let path = state.branches
+        .path(conversation_id, None)
+        .await
+        .map_err(|e| RpcError::internal("Failed to load active branch", e))?;
+    let messages = state.conversation_service
+        .get_messages(conversation_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to load messages", e))?;
+    
+    state.branches
+        .decrypt(conversation_id, on_path(messages, &path))
+        .await
+        .map_err(|e| RpcError::internal("Failed to decrypt messages", e))
+}
+
+async fn create_session(state: &AppState, ctx: &ChatContext, params: CreateSessionParams) -> Result<Value, RpcError> {
+    let model = params.settings
+        .as_ref()
+        .and_then(|settings| settings["model"].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| state.config.default_openai_model.clone());
+    
+    let mut conversation = state.conversation_service
+        .create_conversation(&ctx.user_id, &model)
+        .await
+        .map_err(|e| RpcError::internal("Failed to create conversation", e))?;
+    
+    if let Some(title) = params.title.filter(|t| !t.trim().is_empty()) {
+        state.conversation_service
+            .set_title(&conversation.id, &title)
+            .await
+            .map_err(|e| RpcError::internal("Failed to set conversation title", e))?;
+        conversation.title = Some(title);
+    }
+    
+    let session = session_json(&conversation, MemberRole::Owner, &[], &HashMap::new());
+    publish_sessions_event(state, ctx, json!({ "type": "sessionCreated", "session": session })).await;
+    
+    Ok(json!({ "session": session }))
+}
+
+async fn delete_session(state: &AppState, ctx: &ChatContext, params: SessionParams) -> Result<Value, RpcError> {
+    member_conversation(state, ctx, &params.session_id, MemberRole::Owner).await?;
+    let user_id = Uuid::parse_str(&ctx.user_id).map_err(|e| RpcError::internal("Invalid user id", e.into()))?;
+    
+    // Goes to the trash like a REST delete, and can be restored from there
+    retention::move_to_trash(state, &params.session_id, &user_id, Some(ctx.session_id.clone()))
+        .await
+        .map_err(|e| RpcError::internal("Failed to delete conversation", e))?;
+    
+    Ok(json!({}))
+}
+
+async fn get_branches(state: &AppState, ctx: &ChatContext, params: SessionParams) -> Result<Value, RpcError> {
+    member_conversation(state, ctx, &params.session_id, MemberRole::Viewer).await?;
+    
+    let tree = state.branches
+        .tree(&params.session_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to load branches", e))?;
+    
+    Ok(json!({
+        "activeLeafId": tree.active_leaf_id,
+        "path": tree.path,
+        "forks": tree.forks.iter().map(|fork| json!({
+            "parentId": fork.parent_id,
+            "messageIds": fork.message_ids,
+            "activeId": fork.active_id,
+        })).collect::<Vec<_>>(),
+        "branches": tree.branches.iter().map(|branch| json!({
+            "leafId": branch.leaf_id,
+            "messageCount": branch.message_count,
+            "updatedAt": branch.updated_at,
+            "active": branch.active,
+        })).collect::<Vec<_>>(),
+    }))
+}
+
+async fn switch_branch(state: &AppState, ctx: &ChatContext, params: SwitchBranchParams) -> Result<Value, RpcError> {
+    let (conversation, role) = member_conversation(state, ctx, &params.session_id, MemberRole::Editor).await?;
+    
+    let leaf = state.branches
+        .switch(&params.session_id, &params.message_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to switch branch", e))?
+        .ok_or_else(|| RpcError::new(RpcErrorCode::NotFound, "Message not found"))?;
+    publish_branch_switched(state, ctx, &params.session_id, Some(&leaf)).await;
+    
+    let messages = active_messages(state, &params.session_id).await?;
+    let authors = authors(state, &params.session_id).await?;
+    Ok(json!({ "session": session_json(&conversation, role, &messages, &authors) }))
+}
+
+// Other devices reload the conversation when its active branch changes
+pub async fn publish_branch_switched(state: &AppState, ctx: &ChatContext, conversation_id: &str, leaf_id: Option<&str>) {
+    state.pubsub.publish(TopicEvent {
+        topic: session_topic(conversation_id),
+        origin: Some(ctx.session_id.clone()),
+        data: json!({
+            "type": "branchSwitched",
+            "sessionId": conversation_id,
+            "leafId": leaf_id,
+        }),
+    }).await;
+}
+
+// The conversation and the user's role in it. One the user is not a member
+// of is reported exactly like a missing one; members without the role are
+// forbidden
+pub async fn member_conversation(
+    state: &AppState,
+    ctx: &ChatContext,
+    conversation_id: &str,
+    required: MemberRole,
+) -> Result<(Conversation, MemberRole), RpcError> {
+    let Ok(user_id) = Uuid::parse_str(&ctx.user_id) else {
+        return Err(RpcError::new(RpcErrorCode::NotFound, "Session not found"));
+    };
+    let access = state
+        .conversation_access(conversation_id, &user_id)
+        .await
+        .map_err(|e| RpcError::internal("Failed to load conversation", e))?;
+    
+    match access {
+        Some((conversation, role)) if role.allows(required) => Ok((conversation, role)),
+        Some((_, role)) => Err(RpcError::new(
+            RpcErrorCode::Forbidden,
+            format!("A {} of this session cannot do that", role.as_str()),
+        )),
+        None => Err(RpcError::new(RpcErrorCode::NotFound, "Session not found")),
+    }
+}
+
+async fn publish_sessions_event(state: &AppState, ctx: &ChatContext, data: Value) {
+    state.pubsub.publish(TopicEvent {
+        topic: user_topic(&ctx.user_id, "sessions"),
+        origin: Some(ctx.session_id.clone()),
+        data,
+    }).await;
+}
+
+// Shapes follow `ChatSession` / `ChatMessage` in chat_websocket_service.dart
+fn session_json(conversation: &Conversation, role: MemberRole, messages: &[Message], authors: &HashMap<String, String>) -> Value {
+    json!({
+        "id": conversation.id,
+        "title": conversation.title.clone().unwrap_or_else(|| "New chat".to_string()),
+        "messages": messages.iter().map(|m| message_json(m, authors.get(&m.id))).collect::<Vec<_>>(),
+        "ownerId": conversation.user_id,
+        "role": role,
+        "createdAt": conversation.created_at,
+        "updatedAt": conversation.updated_at,
+        "settings": { "model": conversation.model },
+    })
+}
+
+fn message_json(message: &Message, author_id: Option<&String>) -> Value {
+    let mut json = chat_message_json(&message.id, &message.role, &message.content, message.model.as_deref(), message.created_at);
+    json["authorId"] = json!(author_id);
+    json
+}
+
+pub fn chat_message_json(id: &str, role: &str, content: &str, model: Option<&str>, timestamp: DateTime<Utc>) -> Value {
+    json!({
+        "id": id,
+        "content": content,
+        "role": role,
+        "model": model,
+        "timestamp": timestamp,
+    })
+}